@@ -0,0 +1,64 @@
+//! Logical names for scene files, for pipelines whose output names
+//! carry hashes or versions. Set in the config's `[aliases]` table, from
+//! the file name on disk to the alias:
+//!
+//! ```toml
+//! [aliases]
+//! "out_final_v12.obj" = "turret"
+//! ```
+//!
+//! Listings and events carry a file's alias next to its name, and
+//! filters, the control API and the per-file routes accept either. When
+//! the pipeline moves on to `out_final_v13.obj`, pointing `turret` at it
+//! keeps links and scripts working.
+
+use crate::config::Config;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default)]
+pub struct Aliases {
+  /// File name -> alias
+  by_file: BTreeMap<String, String>,
+  /// Alias -> file name
+  by_alias: BTreeMap<String, String>,
+}
+
+impl Aliases {
+  pub fn from_config(config: &Config) -> Result<Aliases, String> {
+    let mut aliases = Aliases::default();
+    let Some(table) = config.table(&["aliases"]) else { return Ok(aliases) };
+    for (file, value) in table {
+      let alias = value.as_str()
+        .ok_or_else(|| format!("aliases: {} must be a string", file))?;
+      if alias.is_empty() || alias.contains(['/', '\\']) {
+        return Err(format!("aliases: invalid alias {:?} for {}", alias, file));
+      }
+      if table.contains_key(alias) {
+        return Err(format!("aliases: {} is also an aliased file", alias));
+      }
+      if let Some(other) =
+          aliases.by_alias.insert(alias.to_string(), file.clone()) {
+        return Err(format!("aliases: {} and {} are both called {}",
+          other, file, alias));
+      }
+      aliases.by_file.insert(file.clone(), alias.to_string());
+    }
+    Ok(aliases)
+  }
+
+  /// The alias of a file on disk, if it has one.
+  pub fn alias(&self, file: &str) -> Option<&str> {
+    self.by_file.get(file).map(String::as_str)
+  }
+
+  /// The file on disk that `name` stands for: the aliased file if it is
+  /// an alias, otherwise `name` itself.
+  pub fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+    self.by_alias.get(name).map_or(name, String::as_str)
+  }
+
+  /// File name -> alias, for clients that map names themselves.
+  pub fn map(&self) -> &BTreeMap<String, String> {
+    &self.by_file
+  }
+}
@@ -0,0 +1,228 @@
+//! Types in the HTTP API's responses, shared by the server and the
+//! client module.
+
+use crate::{git, mesh, provenance, uv};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileInfo {
+  pub name: String,
+  /// Logical name from the config's `[aliases]`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub alias: Option<String>,
+  /// Axis-aligned bounds, absent if the file fails to parse
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bounds: Option<mesh::Bounds>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub triangles: Option<usize>,
+  /// How the texture coordinates use the texture, absent for files
+  /// without any
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub uv: Option<uv::UvStats>,
+  /// Whether the vertices carry colours, for the viewer to show them
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub vertex_colors: bool,
+  /// Number of points in a point cloud, which has no triangles
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub points: Option<usize>,
+  /// Tags from the scene manifest
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+  /// Last change in milliseconds since the Unix epoch, absent for
+  /// files read from `--source-url`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mtime: Option<u64>,
+  /// File size, absent for files read from `--source-url`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub bytes: Option<u64>,
+  /// Lowercase file extension, e.g. `obj`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub format: Option<String>,
+  /// Content hash, absent if the file couldn't be read
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub hash: Option<String>,
+  /// The generator run that produced the file, if it was uploaded with
+  /// one and hasn't changed since
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provenance: Option<provenance::Provenance>,
+  /// Absent unless the scene directory is in a git work tree
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub git: Option<git::FileStatus>,
+  /// Still open for writing by another program; load it once a
+  /// `modified` event says it's done
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub busy: bool,
+  /// `#rrggbb` from the server's palette
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub color: Option<String>,
+  /// Why the server couldn't parse the file, if it couldn't
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+impl FileInfo {
+  /// Names of the fields that `retain` can keep. `name` is always kept.
+  pub const FIELDS: &'static [&'static str] = &["name", "alias", "bounds",
+    "triangles", "uv", "vertex_colors", "points", "tags", "mtime", "bytes",
+    "format", "hash", "provenance", "git", "busy", "color", "error"];
+
+  /// Clear every field not in `fields`, for listings that ask for only
+  /// some.
+  pub fn retain(&mut self, fields: &[String]) {
+    let keep = |field: &str| fields.iter().any(|f| f == field);
+    if !keep("alias") { self.alias = None }
+    if !keep("bounds") { self.bounds = None }
+    if !keep("triangles") { self.triangles = None }
+    if !keep("uv") { self.uv = None }
+    if !keep("vertex_colors") { self.vertex_colors = false }
+    if !keep("points") { self.points = None }
+    if !keep("tags") { self.tags.clear() }
+    if !keep("mtime") { self.mtime = None }
+    if !keep("bytes") { self.bytes = None }
+    if !keep("format") { self.format = None }
+    if !keep("hash") { self.hash = None }
+    if !keep("provenance") { self.provenance = None }
+    if !keep("git") { self.git = None }
+    if !keep("busy") { self.busy = false }
+    if !keep("color") { self.color = None }
+    if !keep("error") { self.error = None }
+  }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileListResponse {
+  /// Number of matching files before `offset`/`limit` are applied
+  pub total: usize,
+  pub files: Vec<FileInfo>,
+  /// Current git branch of the scene directory, if it's in a work tree
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub branch: Option<String>,
+}
+
+/// What a server speaks, from `/api/version` and the WebSocket hello.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VersionInfo {
+  pub server_version: String,
+  pub protocol_version: u32,
+  pub capabilities: Vec<String>,
+  /// WebSocket subprotocols, most preferred first
+  pub subprotocols: Vec<String>,
+}
+
+/// What went wrong, in the `code` of an [`ErrorResponse`]. Most follow
+/// from the HTTP status; a few say more than the status can. Every code
+/// is in [`ERROR_CODES`], with its status and meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+  BadRequest,
+  Unauthorized,
+  Forbidden,
+  ReadOnly,
+  CsrfRejected,
+  NotFound,
+  MethodNotAllowed,
+  RequestTimeout,
+  Conflict,
+  PayloadTooLarge,
+  UnsupportedMediaType,
+  Unprocessable,
+  Locked,
+  InternalError,
+  InternalPanic,
+  FilesystemTimeout,
+  /// A code from a newer server
+  #[serde(other)]
+  Unknown,
+}
+
+impl ErrorCode {
+  /// The generic code for an error status.
+  pub fn for_status(status: u16) -> ErrorCode {
+    match status {
+      401 => ErrorCode::Unauthorized,
+      403 => ErrorCode::Forbidden,
+      404 => ErrorCode::NotFound,
+      405 => ErrorCode::MethodNotAllowed,
+      408 => ErrorCode::RequestTimeout,
+      409 => ErrorCode::Conflict,
+      413 => ErrorCode::PayloadTooLarge,
+      415 => ErrorCode::UnsupportedMediaType,
+      422 => ErrorCode::Unprocessable,
+      423 => ErrorCode::Locked,
+      504 => ErrorCode::FilesystemTimeout,
+      400..=499 => ErrorCode::BadRequest,
+      _ => ErrorCode::InternalError,
+    }
+  }
+}
+
+/// An error code's status and meaning, as `/api/capabilities` lists
+/// them.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ErrorCodeInfo {
+  pub code: ErrorCode,
+  pub status: u16,
+  pub meaning: &'static str,
+  /// Fields of the error's `details`, if it has any
+  #[serde(skip_serializing_if = "<[_]>::is_empty")]
+  pub details: &'static [&'static str],
+}
+
+const fn code(code: ErrorCode, status: u16, meaning: &'static str,
+    details: &'static [&'static str]) -> ErrorCodeInfo {
+  ErrorCodeInfo { code, status, meaning, details }
+}
+
+/// Every code the server sends.
+pub const ERROR_CODES: &[ErrorCodeInfo] = &[
+  code(ErrorCode::BadRequest, 400, "a parameter is missing or invalid", &[]),
+  code(ErrorCode::Unauthorized, 401, "no valid token was sent", &[]),
+  code(ErrorCode::Forbidden, 403,
+    "the token's role isn't enough for the route, or a grant doesn't \
+    cover it", &["role", "needed"]),
+  code(ErrorCode::ReadOnly, 403,
+    "the server was started with --read-only", &[]),
+  code(ErrorCode::CsrfRejected, 403,
+    "a browser request lacked the CSRF token", &[]),
+  code(ErrorCode::NotFound, 404, "no such file, route or history entry", &[]),
+  code(ErrorCode::MethodNotAllowed, 405,
+    "the route doesn't take this method", &[]),
+  code(ErrorCode::RequestTimeout, 408,
+    "the request ran over its time limit", &["timeout_secs"]),
+  code(ErrorCode::Conflict, 409, "the target exists, or the scene is remote",
+    &[]),
+  code(ErrorCode::PayloadTooLarge, 413,
+    "the body is over the route's size limit", &["max_bytes"]),
+  code(ErrorCode::UnsupportedMediaType, 415,
+    "the body isn't of the expected type", &[]),
+  code(ErrorCode::Unprocessable, 422,
+    "the body or mesh is well-formed but unusable", &[]),
+  code(ErrorCode::Locked, 423, "someone else holds the scene lock",
+    &["lock"]),
+  code(ErrorCode::InternalError, 500, "reading or writing files failed", &[]),
+  code(ErrorCode::InternalPanic, 500, "a bug in the server", &[]),
+  code(ErrorCode::FilesystemTimeout, 504,
+    "the filesystem didn't answer in time", &[]),
+];
+
+impl fmt::Display for ErrorCode {
+  // The name on the wire, e.g. `read_only`
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = serde_json::to_value(self).ok();
+    f.write_str(name.as_ref().and_then(|v| v.as_str()).unwrap_or("unknown"))
+  }
+}
+
+/// The body of every error response from the `/api` routes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+  pub code: ErrorCode,
+  /// For people; don't match on it
+  pub message: String,
+  /// More about the error, shaped by its code, e.g. the size limit for
+  /// `payload_too_large`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub details: Option<serde_json::Value>,
+}
@@ -0,0 +1,393 @@
+//! Users and roles from the `[users.<name>]` tables of the config file.
+//! Each user has a secret token and one of three roles:
+//!
+//! - `viewer`: look at the scene
+//! - `editor`: also change scene files and drive viewers
+//! - `admin`: also manage storage and shut the server down
+//!
+//! With no users configured, the server is open to everyone.
+//!
+//! Which role each group of routes needs can be changed in a `[policy]`
+//! table, e.g. `read = "anyone"` to let people look without a token:
+//!
+//! ```toml
+//! [policy]
+//! read = "anyone"
+//! mutate = "editor"
+//! control = "editor"
+//! admin = "admin"
+//! ```
+//!
+//! For people outside the team, admins mint grants: read-only tokens
+//! that expire, optionally limited to some files or to one snapshot.
+
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+  Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+  Viewer,
+  Editor,
+  Admin,
+}
+
+impl FromStr for Role {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Role, String> {
+    match s {
+      "viewer" => Ok(Role::Viewer),
+      "editor" => Ok(Role::Editor),
+      "admin" => Ok(Role::Admin),
+      _ => Err(format!(
+        "unknown role {:?}, expected viewer, editor or admin", s)),
+    }
+  }
+}
+
+impl fmt::Display for Role {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      Role::Viewer => "viewer",
+      Role::Editor => "editor",
+      Role::Admin => "admin",
+    })
+  }
+}
+
+/// Routes grouped by what they can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+  /// Looking at the scene
+  Read,
+  /// Changing scene files
+  Mutate,
+  /// Driving connected viewers
+  Control,
+  /// Storage management and shutdown
+  Admin,
+}
+
+impl RouteGroup {
+  pub const ALL: [RouteGroup; 4] =
+    [RouteGroup::Read, RouteGroup::Mutate, RouteGroup::Control,
+      RouteGroup::Admin];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      RouteGroup::Read => "read",
+      RouteGroup::Mutate => "mutate",
+      RouteGroup::Control => "control",
+      RouteGroup::Admin => "admin",
+    }
+  }
+}
+
+/// The least role each route group needs; None lets anyone in, with
+/// or without a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Policy {
+  pub read: Option<Role>,
+  pub mutate: Option<Role>,
+  pub control: Option<Role>,
+  pub admin: Option<Role>,
+}
+
+impl Default for Policy {
+  fn default() -> Policy {
+    Policy {
+      read: Some(Role::Viewer),
+      mutate: Some(Role::Editor),
+      control: Some(Role::Editor),
+      admin: Some(Role::Admin),
+    }
+  }
+}
+
+impl Policy {
+  /// Everything open, as when there are no users.
+  pub fn open() -> Policy {
+    Policy { read: None, mutate: None, control: None, admin: None }
+  }
+
+  /// The defaults, overridden by the config's `[policy]` table. Each
+  /// value is a role name or `anyone`.
+  pub fn from_config(config: &Config) -> Result<Policy, String> {
+    let mut policy = Policy::default();
+    let Some(table) = config.table(&["policy"]) else { return Ok(policy) };
+    for (key, value) in table {
+      let group = RouteGroup::ALL.into_iter()
+        .find(|group| group.name() == key)
+        .ok_or_else(|| format!(
+          "policy: unknown route group {}, expected read, mutate, control \
+          or admin", key))?;
+      let required = match value.as_str() {
+        Some("anyone") => None,
+        Some(role) =>
+          Some(role.parse().map_err(|e| format!("policy: {}", e))?),
+        None => return Err(format!("policy: {} must be a string", key)),
+      };
+      *policy.slot(group) = required;
+    }
+    Ok(policy)
+  }
+
+  pub fn required(&self, group: RouteGroup) -> Option<Role> {
+    match group {
+      RouteGroup::Read => self.read,
+      RouteGroup::Mutate => self.mutate,
+      RouteGroup::Control => self.control,
+      RouteGroup::Admin => self.admin,
+    }
+  }
+
+  /// Whether someone with `role` (None without a token) may use the
+  /// group's routes.
+  pub fn allows(&self, group: RouteGroup, role: Option<Role>) -> bool {
+    match self.required(group) {
+      None => true,
+      Some(needed) => role.is_some_and(|role| role >= needed),
+    }
+  }
+
+  fn slot(&mut self, group: RouteGroup) -> &mut Option<Role> {
+    match group {
+      RouteGroup::Read => &mut self.read,
+      RouteGroup::Mutate => &mut self.mutate,
+      RouteGroup::Control => &mut self.control,
+      RouteGroup::Admin => &mut self.admin,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct User {
+  pub name: String,
+  pub role: Role,
+  /// Set for grants, which may only read, and only what this allows
+  pub scope: Option<Scope>,
+  token: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Users {
+  users: Vec<User>,
+}
+
+impl Users {
+  /// Users from `[users.<name>]` tables, each with a `token` and a
+  /// `role` (default `viewer`).
+  pub fn from_config(config: &Config) -> Result<Users, String> {
+    let mut users: Vec<User> = Vec::new();
+    for (name, table) in config.children("users") {
+      let token = table.get("token").and_then(|v| v.as_str())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| format!("user {} needs a token", name))?;
+      let role = match table.get("role") {
+        None => Role::Viewer,
+        Some(value) => value.as_str()
+          .ok_or_else(|| format!("user {}: role must be a string", name))?
+          .parse()
+          .map_err(|e| format!("user {}: {}", name, e))?,
+      };
+      if users.iter().any(|user| user.token == token) {
+        return Err(format!("user {} has the same token as another", name));
+      }
+      users.push(User {
+        name: name.to_string(),
+        role,
+        scope: None,
+        token: token.into(),
+      });
+    }
+    Ok(Users { users })
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.users.is_empty()
+  }
+
+  /// The user with this token, if any.
+  pub fn authenticate(&self, token: &str) -> Option<&User> {
+    // Compare every token in full, so timing doesn't give one away
+    let mut found = None;
+    for user in &self.users {
+      if constant_time_eq(user.token.as_bytes(), token.as_bytes()) {
+        found = Some(user);
+      }
+    }
+    found
+  }
+}
+
+/// What a grant may read, beyond the viewer page itself.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scope {
+  /// Only these scene files; None for the whole scene
+  #[serde(default)]
+  pub files: Option<Vec<String>>,
+  /// Only this snapshot, which the link to the viewer opens
+  #[serde(default)]
+  pub snapshot: Option<String>,
+}
+
+impl Scope {
+  /// Whether the whole scene is open, as opposed to some of it.
+  pub fn is_whole_scene(&self) -> bool {
+    self.files.is_none() && self.snapshot.is_none()
+  }
+
+  pub fn allows_file(&self, name: &str) -> bool {
+    self.files.as_ref().is_none_or(|files| files.iter().any(|f| f == name))
+  }
+
+  /// Snapshots show files outside a limited scope, so only the shared
+  /// one is allowed there.
+  pub fn allows_snapshot(&self, name: &str) -> bool {
+    self.is_whole_scene() || self.snapshot.as_deref() == Some(name)
+  }
+}
+
+/// A token minted through the admin API.
+#[derive(Clone, Debug, Serialize)]
+pub struct Grant {
+  /// Names the grant for listing and revoking; not a secret
+  pub id: String,
+  /// Who the grant is for, shown as its user name
+  pub label: String,
+  /// The admin who minted it
+  pub created_by: String,
+  /// Milliseconds since the Unix epoch
+  pub created: u64,
+  pub expires: u64,
+  pub scope: Scope,
+  #[serde(skip)]
+  token: String,
+}
+
+/// Grants currently in force. They're held in memory only, so
+/// restarting the server revokes them all.
+#[derive(Debug, Default)]
+pub struct Grants {
+  grants: Mutex<Vec<Grant>>,
+}
+
+impl Grants {
+  /// A new grant lasting `lifetime_ms` from `now`, with its token.
+  pub fn mint(&self, label: &str, created_by: &str, scope: Scope, now: u64,
+      lifetime_ms: u64) -> (Grant, String) {
+    let token = random_token();
+    let id = random_token()[..8].to_string();
+    let grant = Grant {
+      label: if label.is_empty() { format!("grant-{}", id) } else {
+        label.to_string()
+      },
+      id,
+      created_by: created_by.to_string(),
+      created: now,
+      expires: now.saturating_add(lifetime_ms),
+      scope,
+      token: token.clone(),
+    };
+    let mut grants = self.grants.lock().unwrap();
+    grants.retain(|grant| grant.expires > now);
+    grants.push(grant.clone());
+    (grant, token)
+  }
+
+  /// The read-only user a grant's token stands for, if it hasn't
+  /// expired.
+  pub fn authenticate(&self, token: &str, now: u64) -> Option<User> {
+    let grants = self.grants.lock().unwrap();
+    let mut found = None;
+    for grant in grants.iter() {
+      if constant_time_eq(grant.token.as_bytes(), token.as_bytes())
+          && grant.expires > now {
+        found = Some(User {
+          name: grant.label.clone(),
+          role: Role::Viewer,
+          scope: Some(grant.scope.clone()),
+          token: grant.token.clone(),
+        });
+      }
+    }
+    found
+  }
+
+  /// Grants that haven't expired, oldest first.
+  pub fn list(&self, now: u64) -> Vec<Grant> {
+    let mut grants = self.grants.lock().unwrap();
+    grants.retain(|grant| grant.expires > now);
+    grants.clone()
+  }
+
+  /// Withdraw a grant by ID, returning whether there was one.
+  pub fn revoke(&self, id: &str) -> bool {
+    let mut grants = self.grants.lock().unwrap();
+    let before = grants.len();
+    grants.retain(|grant| grant.id != id);
+    grants.len() != before
+  }
+}
+
+/// A fresh random secret, as 32 hex digits.
+pub fn random_token() -> String {
+  let mut bytes = [0u8; 16];
+  getrandom::getrandom(&mut bytes).expect("no source of randomness");
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compare secrets without the time taken depending on where they
+/// differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+  a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reads_users_and_policy() {
+    let config = Config::parse("\
+      [users.alice]\ntoken = \"a-token\"\nrole = \"admin\"\n\
+      [users.bob]\ntoken = \"b-token\"\n\
+      [policy]\nread = \"anyone\"\nmutate = \"admin\"\n").unwrap();
+    let users = Users::from_config(&config).unwrap();
+    let alice = users.authenticate("a-token").unwrap();
+    assert_eq!((alice.name.as_str(), alice.role), ("alice", Role::Admin));
+    // Viewers by default
+    assert_eq!(users.authenticate("b-token").unwrap().role, Role::Viewer);
+    assert!(users.authenticate("a-toke").is_none());
+
+    let policy = Policy::from_config(&config).unwrap();
+    assert!(policy.allows(RouteGroup::Read, None));
+    assert!(!policy.allows(RouteGroup::Mutate, Some(Role::Editor)));
+    assert!(policy.allows(RouteGroup::Mutate, Some(Role::Admin)));
+  }
+
+  #[test]
+  fn rejects_bad_users() {
+    let users = |text: &str| Users::from_config(&Config::parse(text).unwrap());
+    assert!(users("[users.a]\nrole = \"admin\"\n").is_err());
+    assert!(users("[users.a]\ntoken = \"\"\n").is_err());
+    assert!(users("[users.a]\ntoken = \"t\"\nrole = \"root\"\n").is_err());
+    assert!(users("[users.a]\ntoken = \"t\"\nrole = 1\n").is_err());
+    assert!(users("[users.a]\ntoken = \"t\"\n[users.b]\ntoken = \"t\"\n")
+      .is_err());
+    assert!(users("").unwrap().is_empty());
+    let policy = |text: &str| Policy::from_config(&Config::parse(text)
+      .unwrap());
+    assert!(policy("[policy]\nwrite = \"admin\"\n").is_err());
+    assert!(policy("[policy]\nread = \"root\"\n").is_err());
+  }
+}
@@ -0,0 +1,140 @@
+//! `kitbash-viewer bench`: throughput of the server-side mesh pipeline
+//! on real files, for spotting parser performance regressions.
+
+use kitbash_viewer::manifest::Transform;
+use kitbash_viewer::{formats, glb, mesh};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Totals over every run of every file
+#[derive(Default)]
+struct Totals {
+  bytes: u64,
+  glb_bytes: u64,
+  triangles: u64,
+  // Triangles that came from triangulating OBJ polygons
+  triangulated: u64,
+  parse: Duration,
+  triangulate: Duration,
+  transcode: Duration,
+}
+
+/// Benchmark one scene file, or every one in a directory, printing
+/// one line per file and a summary. Returns false if nothing could be
+/// benchmarked.
+pub fn run(path: &Path, iterations: u32) -> bool {
+  let files = if path.is_dir() {
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.path())
+      .filter(|p| p.file_name().and_then(|n| n.to_str())
+        .is_some_and(formats::is_scene_file) && p.is_file())
+      .collect();
+    files.sort();
+    files
+  } else {
+    vec![path.to_path_buf()]
+  };
+  let iterations = iterations.max(1);
+
+  println!("Triangulation of OBJ faces is timed apart from parsing; other");
+  println!("formats parse straight to triangles. Transcode is mesh -> GLB.");
+  println!("{} run(s) per file\n", iterations);
+  println!("{:<32} {:>10} {:>10} {:>11} {:>12} {:>13} {:>11}",
+    "file", "size", "triangles", "parse MB/s", "parse tri/s",
+    "triangulate/s", "glb MB/s");
+
+  let mut totals = Totals::default();
+  for file in &files {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let bytes = match fs::read(file) {
+      Ok(bytes) => bytes,
+      Err(e) => {
+        eprintln!("{}: {}", name, e);
+        continue;
+      }
+    };
+
+    let mut file_totals = Totals::default();
+    for _ in 0..iterations {
+      let start = Instant::now();
+      let parsed = match mesh::parse_faces(&bytes) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+          eprintln!("{}: {}", name, e);
+          break;
+        }
+      };
+      file_totals.parse += start.elapsed();
+
+      let polygons = matches!(parsed, mesh::Parsed::Polygons(_));
+      let start = Instant::now();
+      let parsed = parsed.triangulate();
+      if polygons {
+        file_totals.triangulate += start.elapsed();
+        file_totals.triangulated += parsed.triangles.len() as u64;
+      }
+
+      let start = Instant::now();
+      let encoded = glb::encode(
+        &[(name.to_string(), &parsed, Transform::default())]);
+      file_totals.transcode += start.elapsed();
+
+      file_totals.bytes += bytes.len() as u64;
+      file_totals.glb_bytes += encoded.len() as u64;
+      file_totals.triangles += parsed.triangles.len() as u64;
+    }
+    if file_totals.bytes == 0 {
+      continue;
+    }
+
+    print_row(&name, &file_totals, iterations);
+    totals.bytes += file_totals.bytes;
+    totals.glb_bytes += file_totals.glb_bytes;
+    totals.triangles += file_totals.triangles;
+    totals.triangulated += file_totals.triangulated;
+    totals.parse += file_totals.parse;
+    totals.triangulate += file_totals.triangulate;
+    totals.transcode += file_totals.transcode;
+  }
+
+  if totals.bytes == 0 {
+    eprintln!("No scene files could be benchmarked in {:?}", path);
+    return false;
+  }
+  println!();
+  print_row("total", &totals, iterations);
+  true
+}
+
+fn print_row(name: &str, totals: &Totals, iterations: u32) {
+  let runs = iterations as u64;
+  // Files that were already triangles have nothing to show
+  let triangulate = match totals.triangulated {
+    0 => "-".to_string(),
+    n => format!("{:.0}", per_second(n as f64, totals.triangulate)),
+  };
+  println!("{:<32} {:>10} {:>10} {:>11.1} {:>12.0} {:>13} {:>11.1}",
+    name,
+    format_bytes(totals.bytes / runs),
+    totals.triangles / runs,
+    per_second(totals.bytes as f64 / 1e6, totals.parse),
+    per_second(totals.triangles as f64, totals.parse),
+    triangulate,
+    per_second(totals.glb_bytes as f64 / 1e6, totals.transcode));
+}
+
+fn per_second(amount: f64, elapsed: Duration) -> f64 {
+  amount / elapsed.as_secs_f64().max(1e-9)
+}
+
+fn format_bytes(bytes: u64) -> String {
+  match bytes {
+    b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1 << 20) as f64),
+    b if b >= 1 << 10 => format!("{:.1} KB", b as f64 / (1 << 10) as f64),
+    b => format!("{} B", b),
+  }
+}
@@ -0,0 +1,87 @@
+//! Whether another program still has a scene file open for writing, so
+//! a half-exported file isn't loaded. How that's found depends on the
+//! platform:
+//!
+//! - Linux: the file descriptors of every process in `/proc`
+//! - Windows: opening the file without sharing fails while it's open
+//! - elsewhere: the file changed within the last second
+
+use std::path::Path;
+
+/// Whether some other process is writing to `path`. Errors (say, the
+/// file is gone) count as not busy.
+pub fn is_busy(path: &Path) -> bool {
+  imp::is_busy(path)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+  use std::fs;
+  use std::path::Path;
+
+  pub fn is_busy(path: &Path) -> bool {
+    let Ok(path) = fs::canonicalize(path) else { return false };
+    let Ok(procs) = fs::read_dir("/proc") else { return false };
+    let own_pid = std::process::id().to_string();
+    for proc_entry in procs.flatten() {
+      let pid = proc_entry.file_name();
+      let Some(pid) = pid.to_str() else { continue };
+      if !pid.bytes().all(|b| b.is_ascii_digit()) || pid == own_pid {
+        continue;
+      }
+      // Other users' processes can't be inspected; skip them
+      let Ok(fds) = fs::read_dir(proc_entry.path().join("fd")) else {
+        continue
+      };
+      for fd in fds.flatten() {
+        if fs::read_link(fd.path()).is_ok_and(|target| target == path)
+            && opened_for_writing(pid, &fd.file_name().to_string_lossy()) {
+          return true;
+        }
+      }
+    }
+    false
+  }
+
+  // The access mode in fdinfo's octal "flags:" line
+  fn opened_for_writing(pid: &str, fd: &str) -> bool {
+    let info = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd))
+      .unwrap_or_default();
+    info.lines()
+      .find_map(|line| line.strip_prefix("flags:"))
+      .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+      .is_some_and(|flags| flags & 0o3 != 0)
+  }
+}
+
+#[cfg(windows)]
+mod imp {
+  use std::fs::OpenOptions;
+  use std::os::windows::fs::OpenOptionsExt;
+  use std::path::Path;
+
+  const ERROR_SHARING_VIOLATION: i32 = 32;
+
+  pub fn is_busy(path: &Path) -> bool {
+    match OpenOptions::new().read(true).share_mode(0).open(path) {
+      Ok(_) => false,
+      Err(e) => e.raw_os_error() == Some(ERROR_SHARING_VIOLATION),
+    }
+  }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod imp {
+  use std::path::Path;
+  use std::time::{Duration, SystemTime};
+
+  const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+  pub fn is_busy(path: &Path) -> bool {
+    std::fs::metadata(path)
+      .and_then(|meta| meta.modified())
+      .ok()
+      .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+      .is_some_and(|age| age < QUIET_PERIOD)
+  }
+}
@@ -0,0 +1,180 @@
+//! Parsed meshes cached by content hash, so repeated listings and
+//! scene queries only re-parse files whose bytes actually changed.
+
+use crate::mesh::{self, Fnv1a, Mesh};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+type ParseResult = Result<Arc<Mesh>, String>;
+
+pub struct Parsed {
+  pub result: ParseResult,
+  pub hash: String,
+  pub cached: bool,
+}
+
+/// How much the cache holds. Sizes are estimates of the parsed meshes,
+/// not counting allocator overhead.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CacheUsage {
+  pub entries: usize,
+  pub bytes: u64,
+}
+
+/// What a `collect` pass dropped.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Collected {
+  /// Parses of files that are gone or have changed since
+  pub stale: usize,
+  /// Least recently used parses dropped to get under the size cap
+  pub evicted: usize,
+}
+
+#[derive(Default)]
+pub struct MeshCache {
+  inner: Mutex<Inner>,
+  options: mesh::ParseOptions,
+}
+
+#[derive(Default)]
+struct Inner {
+  entries: HashMap<String, Entry>,
+  /// The content hash each file was last loaded with
+  names: HashMap<String, String>,
+  /// Ticks on every lookup, to order entries by last use
+  clock: u64,
+}
+
+struct Entry {
+  result: ParseResult,
+  bytes: u64,
+  last_used: u64,
+}
+
+impl MeshCache {
+  /// An empty cache that parses with `options`.
+  pub fn new(options: mesh::ParseOptions) -> Self {
+    MeshCache { options, ..Default::default() }
+  }
+
+  /// Read and parse a mesh file, reusing the cached parse when the
+  /// content hash matches. Failed parses are cached too.
+  pub fn load(&self, path: &Path) -> ParseResult {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    self.parse_named(&path.to_string_lossy(), bytes).result
+  }
+
+  /// Parse a mesh from file contents already in hand, e.g. from a
+  /// `SceneSource`.
+  pub fn parse(&self, bytes: Vec<u8>) -> ParseResult {
+    self.parse_traced(bytes).result
+  }
+
+  /// Like `parse`, also giving the content hash and whether the result
+  /// came from the cache.
+  pub fn parse_traced(&self, bytes: Vec<u8>) -> Parsed {
+    let hash = content_hash(&bytes);
+
+    if let Some(cached) = self.inner.lock().unwrap().get(&hash) {
+      return Parsed { result: cached, hash, cached: true };
+    }
+
+    let parsed = mesh::parse_file_with(&bytes, self.options).map(Arc::new);
+    self.inner.lock().unwrap().insert(hash.clone(), parsed.clone());
+    Parsed { result: parsed, hash, cached: false }
+  }
+
+  /// Like `parse_traced`, remembering that this is the current content
+  /// of the file `name`, so `collect` can tell when it goes stale.
+  pub fn parse_named(&self, name: &str, bytes: Vec<u8>) -> Parsed {
+    let parsed = self.parse_traced(bytes);
+    self.inner.lock().unwrap()
+      .names.insert(name.to_string(), parsed.hash.clone());
+    parsed
+  }
+
+  pub fn usage(&self) -> CacheUsage {
+    let inner = self.inner.lock().unwrap();
+    CacheUsage {
+      entries: inner.entries.len(),
+      bytes: inner.entries.values().map(|entry| entry.bytes).sum(),
+    }
+  }
+
+  /// Drop every cached parse, returning how many there were.
+  pub fn clear(&self) -> usize {
+    let mut inner = self.inner.lock().unwrap();
+    let count = inner.entries.len();
+    inner.entries.clear();
+    inner.names.clear();
+    count
+  }
+
+  /// Drop parses that no current file has: those of files for which
+  /// `is_current` is false, of earlier contents of files loaded since,
+  /// and of contents never loaded by name. Then, if the rest is larger
+  /// than `max_bytes`, drop the least recently used until it isn't.
+  pub fn collect(&self, is_current: impl Fn(&str) -> bool,
+      max_bytes: Option<u64>) -> Collected {
+    let mut inner = self.inner.lock().unwrap();
+    let before = inner.entries.len();
+    inner.names.retain(|name, _| is_current(name));
+    let live: HashSet<String> = inner.names.values().cloned().collect();
+    inner.entries.retain(|hash, _| live.contains(hash));
+    let stale = before - inner.entries.len();
+
+    let mut evicted = 0;
+    if let Some(max_bytes) = max_bytes {
+      let mut total: u64 = inner.entries.values().map(|e| e.bytes).sum();
+      let mut by_age: Vec<(u64, String)> = inner.entries.iter()
+        .map(|(hash, entry)| (entry.last_used, hash.clone()))
+        .collect();
+      by_age.sort();
+      for (_, hash) in by_age {
+        if total <= max_bytes {
+          break;
+        }
+        if let Some(entry) = inner.entries.remove(&hash) {
+          total -= entry.bytes;
+          evicted += 1;
+        }
+      }
+    }
+    Collected { stale, evicted }
+  }
+}
+
+impl Inner {
+  fn get(&mut self, hash: &str) -> Option<ParseResult> {
+    self.clock += 1;
+    let entry = self.entries.get_mut(hash)?;
+    entry.last_used = self.clock;
+    Some(entry.result.clone())
+  }
+
+  fn insert(&mut self, hash: String, result: ParseResult) {
+    self.clock += 1;
+    let bytes = result.as_ref().map_or(0, |mesh| mesh_bytes(mesh));
+    let entry = Entry { result, bytes, last_used: self.clock };
+    self.entries.insert(hash, entry);
+  }
+}
+
+// Rough memory held by a parsed mesh
+fn mesh_bytes(mesh: &Mesh) -> u64 {
+  let objects: usize = mesh.objects.iter()
+    .map(|o| o.name.len() + std::mem::size_of_val(o))
+    .sum();
+  (std::mem::size_of_val(mesh.positions.as_slice())
+    + std::mem::size_of_val(mesh.triangles.as_slice())
+    + objects) as u64
+}
+
+pub fn content_hash(bytes: &[u8]) -> String {
+  let mut hasher = Fnv1a::new();
+  hasher.write(bytes);
+  hasher.hex()
+}
@@ -0,0 +1,389 @@
+//! Per-file geometry checks.
+
+use crate::geom::{self, PointGrid};
+use crate::mesh::{Bounds, Mesh};
+use crate::uv;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Most asymmetric vertices listed per plane
+const MAX_REPORTED_VERTICES: usize = 100;
+
+#[derive(Serialize)]
+pub struct AsymmetricVertex {
+  index:     usize,
+  position:  [f64; 3],
+  deviation: f64,
+}
+
+#[derive(Serialize)]
+pub struct PlaneSymmetry {
+  /// Axis the mirror plane is perpendicular to
+  axis:   char,
+  /// Position of the plane along that axis (the bounds centre)
+  offset: f64,
+  max_deviation:  f64,
+  mean_deviation: f64,
+  symmetric:      bool,
+  /// Bounds of the vertices deviating by more than the tolerance
+  #[serde(skip_serializing_if = "Option::is_none")]
+  asymmetric_region: Option<Bounds>,
+  /// Worst offenders, largest deviation first
+  asymmetric_vertices: Vec<AsymmetricVertex>,
+}
+
+#[derive(Serialize)]
+pub struct SymmetryReport {
+  tolerance: f64,
+  planes:    Vec<PlaneSymmetry>,
+}
+
+/// Mirror the mesh across planes through its bounds centre and measure
+/// how far each mirrored vertex lands from the nearest real one.
+///
+/// `tolerance` defaults to a millionth of the bounding box diagonal.
+pub fn symmetry(
+    mesh: &Mesh,
+    axes: &[char],
+    tolerance: Option<f64>) -> Option<SymmetryReport> {
+  let bounds = mesh.bounds()?;
+  let diagonal = geom::length(geom::sub(bounds.max, bounds.min));
+  let tolerance = tolerance.unwrap_or(diagonal * 1e-6);
+  let grid = PointGrid::new(mesh.positions.clone());
+
+  let planes = axes.iter()
+    .filter_map(|&axis| {
+      let a = "xyz".find(axis)?;
+      let offset = (bounds.min[a] + bounds.max[a]) / 2.0;
+
+      let mut deviations: Vec<(usize, f64)> = mesh.positions.iter()
+        .enumerate()
+        .map(|(i, p)| {
+          let mut mirrored = *p;
+          mirrored[a] = 2.0 * offset - p[a];
+          (i, grid.nearest(mirrored).map_or(0.0, |(_, d)| d))
+        })
+        .collect();
+
+      let max_deviation = deviations.iter().map(|d| d.1).fold(0.0, f64::max);
+      let mean_deviation = deviations.iter().map(|d| d.1).sum::<f64>()
+        / deviations.len().max(1) as f64;
+
+      deviations.retain(|d| d.1 > tolerance);
+      deviations.sort_by(|x, y| y.1.total_cmp(&x.1));
+      let mut asymmetric_region: Option<Bounds> = None;
+      for &(i, _) in &deviations {
+        let p = mesh.positions[i];
+        asymmetric_region.get_or_insert(Bounds { min: p, max: p }).extend(p);
+      }
+
+      Some(PlaneSymmetry {
+        axis,
+        offset,
+        max_deviation,
+        mean_deviation,
+        symmetric: deviations.is_empty(),
+        asymmetric_region,
+        asymmetric_vertices: deviations.iter()
+          .take(MAX_REPORTED_VERTICES)
+          .map(|&(index, deviation)| AsymmetricVertex {
+            index,
+            position: mesh.positions[index],
+            deviation,
+          })
+          .collect(),
+      })
+    })
+    .collect();
+
+  Some(SymmetryReport { tolerance, planes })
+}
+
+#[derive(Serialize)]
+pub struct LintReport {
+  vertices:  usize,
+  triangles: usize,
+  /// Faces with (near) zero area
+  degenerate_faces: usize,
+  /// Edges used by only one face: holes in the surface
+  boundary_edges: usize,
+  /// Edges shared by more than two faces
+  non_manifold_edges: usize,
+  /// Edges whose two faces traverse them in the same direction, i.e.
+  /// neighbouring faces with opposite winding
+  inconsistent_edges: usize,
+  /// Pairs of non-adjacent faces that cross each other
+  self_intersections: usize,
+  /// Closed two-manifold surface
+  watertight: bool,
+  /// Watertight, consistently oriented and free of self-intersections:
+  /// safe to hand to a slicer
+  printable: bool,
+  /// Texture coordinate checks, if the mesh has any
+  #[serde(skip_serializing_if = "Option::is_none")]
+  uv: Option<UvLint>,
+}
+
+#[derive(Serialize)]
+pub struct UvLint {
+  /// Connected groups of faces in UV space
+  islands: usize,
+  /// Islands sharing texture space with another island or folding over
+  /// themselves, which bake to the same texels
+  overlapping_islands: usize,
+  /// Faces whose UVs wind clockwise, i.e. a mirrored texture
+  inverted_faces: usize,
+  texel_density: TexelDensity,
+  /// No overlaps, inverted faces or faces off the target density
+  bakeable: bool,
+}
+
+#[derive(Serialize)]
+pub struct TexelDensity {
+  texture_size: u32,
+  /// Texels per unit the faces are measured against
+  target: f64,
+  /// Over the whole surface, in texels per unit
+  mean: f64,
+  min: f64,
+  max: f64,
+  /// Area-weighted standard deviation over the mean: 0 if every face
+  /// gets the same resolution
+  variation: f64,
+  tolerance: f64,
+  /// Faces whose density differs from the target by more than the
+  /// tolerance, as a fraction of the target
+  off_target: usize,
+}
+
+/// How `uv_lint` measures texel density.
+pub struct DensityTarget {
+  /// Width and height of the texture in texels
+  pub texture_size: u32,
+  /// Texels per unit wanted, the mesh's mean if None
+  pub density: Option<f64>,
+  /// Allowed difference from the target, as a fraction of it
+  pub tolerance: f64,
+}
+
+/// Manifoldness and printability checks. Vertices at the same position
+/// are treated as one, since exporters often split them at seams. UV
+/// checks are added from `uv_lint` when the mesh has texture coordinates.
+pub fn lint(mesh: &Mesh, density: &DensityTarget) -> LintReport {
+  let triangles = welded_triangles(mesh);
+
+  let degenerate_faces = triangles.iter()
+    .filter(|t| {
+      let [a, b, c] = t.map(|vi| mesh.positions[vi]);
+      t[0] == t[1] || t[1] == t[2] || t[0] == t[2]
+        || geom::length(geom::cross(geom::sub(b, a), geom::sub(c, a))) < 1e-12
+    })
+    .count();
+
+  // Directed uses of each undirected edge
+  let mut edges: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+  for t in &triangles {
+    for (from, to) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+      if from == to {
+        continue;
+      }
+      let uses = edges.entry((from.min(to), from.max(to))).or_default();
+      if from < to { uses.0 += 1 } else { uses.1 += 1 }
+    }
+  }
+  let (mut boundary_edges, mut non_manifold_edges, mut inconsistent_edges) =
+    (0, 0, 0);
+  for (forward, backward) in edges.values() {
+    match forward + backward {
+      1 => boundary_edges += 1,
+      2 if *forward != 1 => inconsistent_edges += 1,
+      2 => {}
+      _ => non_manifold_edges += 1,
+    }
+  }
+
+  let self_intersections = count_self_intersections(mesh, &triangles);
+  let watertight = boundary_edges == 0 && non_manifold_edges == 0
+    && !triangles.is_empty();
+
+  LintReport {
+    vertices: mesh.positions.len(),
+    triangles: triangles.len(),
+    degenerate_faces,
+    boundary_edges,
+    non_manifold_edges,
+    inconsistent_edges,
+    self_intersections,
+    watertight,
+    printable: watertight && inconsistent_edges == 0 && self_intersections == 0,
+    uv: uv_lint(mesh, density),
+  }
+}
+
+/// Texture bake checks: overlapping islands, mirrored faces and how
+/// evenly texels are spread over the surface. None without UVs.
+pub fn uv_lint(mesh: &Mesh, density: &DensityTarget) -> Option<UvLint> {
+  if mesh.uv_triangles.is_empty() {
+    return None;
+  }
+  // Faces with UVs, as (face, UV corners)
+  let faces: Vec<(usize, [[f64; 2]; 3])> = mesh.uv_triangles.iter()
+    .enumerate()
+    .filter_map(|(t, corners)| {
+      Some((t, corners.as_ref()?.map(|i| mesh.uvs[i])))
+    })
+    .collect();
+
+  // Islands are faces joined by shared UV corners; as with positions,
+  // corners at the same place count as shared
+  let mut canonical: HashMap<[i64; 2], usize> = HashMap::new();
+  let mut parent: Vec<usize> = Vec::new();
+  let mut face_corners = Vec::with_capacity(faces.len());
+  for (_, corners) in &faces {
+    face_corners.push(corners.map(|uv| {
+      *canonical.entry(uv.map(|v| (v * 1e6).round() as i64))
+        .or_insert_with(|| {
+          parent.push(parent.len());
+          parent.len() - 1
+        })
+    }));
+  }
+  for c in &face_corners {
+    union(&mut parent, c[0], c[1]);
+    union(&mut parent, c[0], c[2]);
+  }
+  let mut island_ids: HashMap<usize, usize> = HashMap::new();
+  let island: Vec<usize> = face_corners.iter()
+    .map(|c| {
+      let root = find(&mut parent, c[0]);
+      let next = island_ids.len();
+      *island_ids.entry(root).or_insert(next)
+    })
+    .collect();
+
+  // An island overlaps if any cell it covers is covered twice
+  let coverage = uv::rasterize(mesh);
+  let mut overlapping = vec![false; island_ids.len()];
+  for (&(_, corners), &i) in faces.iter().zip(&island) {
+    if !overlapping[i] {
+      uv::cover(corners, |cell| {
+        overlapping[i] |= coverage.counts[cell] > 1;
+      });
+    }
+  }
+  let overlapping_islands = overlapping.iter().filter(|&&o| o).count();
+
+  let inverted_faces = faces.iter()
+    .filter(|(_, [a, b, c])| uv::cross(*a, *b, *c) < 0.0)
+    .count();
+
+  // Per face: texels per unit and surface area
+  let texels = density.texture_size as f64;
+  let densities: Vec<(f64, f64)> = faces.iter()
+    .filter_map(|&(t, [a, b, c])| {
+      let [p, q, r] = mesh.triangles[t].map(|vi| mesh.positions[vi]);
+      let area = geom::length(geom::cross(geom::sub(q, p), geom::sub(r, p)));
+      let uv_area = uv::cross(a, b, c).abs() * texels * texels;
+      (area > 1e-12).then(|| ((uv_area / area).sqrt(), area))
+    })
+    .collect();
+  let total_area: f64 = densities.iter().map(|d| d.1).sum();
+  let mean = if total_area > 0.0 {
+    densities.iter().map(|(d, area)| d * area).sum::<f64>() / total_area
+  } else {
+    0.0
+  };
+  let variation = if mean > 0.0 {
+    let variance = densities.iter()
+      .map(|(d, area)| (d - mean).powi(2) * area)
+      .sum::<f64>() / total_area;
+    variance.sqrt() / mean
+  } else {
+    0.0
+  };
+  let target = density.density.unwrap_or(mean);
+  let off_target = densities.iter()
+    .filter(|(d, _)| target > 0.0
+      && ((d - target) / target).abs() > density.tolerance)
+    .count();
+
+  Some(UvLint {
+    islands: island_ids.len(),
+    overlapping_islands,
+    inverted_faces,
+    texel_density: TexelDensity {
+      texture_size: density.texture_size,
+      target,
+      mean,
+      min: if densities.is_empty() {
+        0.0
+      } else {
+        densities.iter().map(|d| d.0).fold(f64::INFINITY, f64::min)
+      },
+      max: densities.iter().map(|d| d.0).fold(0.0, f64::max),
+      variation,
+      tolerance: density.tolerance,
+      off_target,
+    },
+    bakeable: overlapping_islands == 0 && inverted_faces == 0
+      && off_target == 0,
+  })
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+  while parent[i] != i {
+    parent[i] = parent[parent[i]];
+    i = parent[i];
+  }
+  i
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+  let (a, b) = (find(parent, a), find(parent, b));
+  parent[a] = b;
+}
+
+// Triangles re-indexed so coincident vertices share one index
+fn welded_triangles(mesh: &Mesh) -> Vec<[usize; 3]> {
+  let mut canonical: HashMap<[i64; 3], usize> = HashMap::new();
+  let remap: Vec<usize> = mesh.positions.iter()
+    .enumerate()
+    .map(|(i, p)| *canonical.entry(p.map(|v| (v * 1e6).round() as i64))
+      .or_insert(i))
+    .collect();
+  mesh.triangles.iter().map(|t| t.map(|vi| remap[vi])).collect()
+}
+
+// Sweep along x over triangle bounds, testing only pairs whose bounds
+// overlap and which share no vertex
+fn count_self_intersections(mesh: &Mesh, triangles: &[[usize; 3]]) -> usize {
+  let mut boxes: Vec<(Bounds, usize)> = triangles.iter()
+    .enumerate()
+    .map(|(i, t)| {
+      let p = mesh.positions[t[0]];
+      let mut b = Bounds { min: p, max: p };
+      b.extend(mesh.positions[t[1]]);
+      b.extend(mesh.positions[t[2]]);
+      (b, i)
+    })
+    .collect();
+  boxes.sort_by(|a, b| a.0.min[0].total_cmp(&b.0.min[0]));
+
+  let mut count = 0;
+  for (i, (box_a, ta)) in boxes.iter().enumerate() {
+    for (box_b, tb) in &boxes[i + 1..] {
+      if box_b.min[0] > box_a.max[0] {
+        break;
+      }
+      let (ta, tb) = (triangles[*ta], triangles[*tb]);
+      if ta.iter().any(|v| tb.contains(v)) || box_a.intersection(box_b).is_none() {
+        continue;
+      }
+      if geom::triangles_intersect(ta.map(|vi| mesh.positions[vi]),
+                                   tb.map(|vi| mesh.positions[vi])) {
+        count += 1;
+      }
+    }
+  }
+  count
+}
@@ -0,0 +1,368 @@
+//! Typed access to a running viewer, for generators and scripts that
+//! push files into a scene, follow its events or drive its viewers.
+//! Enabled by the `client` feature.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), kitbash_viewer::client::Error> {
+//! use kitbash_viewer::client::Client;
+//! use kitbash_viewer::events::ControlCommand;
+//!
+//! let client = Client::new("localhost:8080");
+//! let triangle = b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+//! client.upload("part.obj", triangle).await?;
+//! client.control(&ControlCommand::Frame { files: vec![] }).await?;
+//!
+//! let mut events = client.events().await?;
+//! while let Some(event) = events.next().await {
+//!   println!("{:?}", event?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::{
+  ErrorCode, ErrorResponse, FileInfo, FileListResponse, VersionInfo,
+};
+use crate::events::{ControlCommand, FileEvent};
+use crate::filter::FileFilter;
+use crate::http;
+use crate::lock::Lock;
+use crate::provenance::Provenance;
+use futures::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  /// The server answered with an error status; `code` is None if the
+  /// body wasn't an error envelope (say, from a proxy)
+  Http { status: u16, code: Option<ErrorCode>, message: String },
+  /// The server's response couldn't be understood
+  Protocol(String),
+  Json(serde_json::Error),
+  WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Io(e) => write!(f, "{}", e),
+      Error::Http { status, code: Some(code), message } =>
+        write!(f, "HTTP {} ({}): {}", status, code, message),
+      Error::Http { status, code: None, message } =>
+        write!(f, "HTTP {}: {}", status, message),
+      Error::Protocol(message) => write!(f, "protocol error: {}", message),
+      Error::Json(e) => write!(f, "invalid JSON: {}", e),
+      Error::WebSocket(e) => write!(f, "WebSocket: {}", e),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+  fn from(e: std::io::Error) -> Self {
+    Error::Io(e)
+  }
+}
+
+impl From<serde_json::Error> for Error {
+  fn from(e: serde_json::Error) -> Self {
+    Error::Json(e)
+  }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+  fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+    Error::WebSocket(Box::new(e))
+  }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A viewer server, addressed as `host:port` (an `http://` prefix is
+/// accepted too). Every call makes its own connection.
+#[derive(Clone, Debug)]
+pub struct Client {
+  addr: String,
+  /// Sent with every request, so the server lets through changes while
+  /// this client holds the scene lock
+  lock_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ControlResponse {
+  viewers: usize,
+}
+
+#[derive(Serialize)]
+struct LockRequest<'a> {
+  holder: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  note: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct LockResponse {
+  key: String,
+  lock: Lock,
+}
+
+impl Client {
+  pub fn new(addr: &str) -> Self {
+    let addr = addr.trim_start_matches("http://").trim_end_matches('/');
+    Client { addr: addr.to_string(), lock_key: None }
+  }
+
+  /// Claim exclusive write access to the scene for `expires_in`
+  /// seconds (the server's default if None), or extend it. Until it's
+  /// released or runs out, changes from anyone else fail with
+  /// [`ErrorCode::Locked`]. `holder` names the job on servers without
+  /// users; with users, the lock is the token's user's.
+  pub async fn lock(&mut self, holder: &str, note: Option<&str>,
+      expires_in: Option<u64>) -> Result<Lock> {
+    let body = serde_json::to_vec(&LockRequest { holder, note, expires_in })?;
+    let response: LockResponse =
+      self.json("POST", "/api/lock", Some(&body)).await?;
+    self.lock_key = Some(response.key);
+    Ok(response.lock)
+  }
+
+  /// Release the scene lock this client holds.
+  pub async fn unlock(&mut self) -> Result<()> {
+    self.request("DELETE", "/api/lock", None, None).await?;
+    self.lock_key = None;
+    Ok(())
+  }
+
+  /// Every scene file, with bounds, triangle counts and tags.
+  pub async fn files(&self) -> Result<Vec<FileInfo>> {
+    let response: FileListResponse =
+      self.json("GET", "/api/files", None).await?;
+    Ok(response.files)
+  }
+
+  pub async fn version(&self) -> Result<VersionInfo> {
+    self.json("GET", "/api/version", None).await
+  }
+
+  /// Raw contents of a scene file.
+  pub async fn download(&self, name: &str) -> Result<Vec<u8>> {
+    let path = format!("/scene/{}", http::encode_path(name));
+    self.request("GET", &path, None, None).await
+  }
+
+  /// Write an OBJ into the scene directory, replacing any file of that
+  /// name. Connected viewers load it like any other change.
+  pub async fn upload(&self, name: &str, contents: &[u8]) -> Result<()> {
+    let path = format!("/api/files/{}", http::encode_path(name));
+    self.request("PUT", &path, Some(contents), None).await?;
+    Ok(())
+  }
+
+  /// Upload a file, saying which generator run produced it. The server
+  /// shows this with the file and in its timeline.
+  pub async fn upload_generated(&self, name: &str, contents: &[u8],
+      provenance: &Provenance) -> Result<()> {
+    let path = format!("/api/files/{}?{}", http::encode_path(name),
+      provenance.query());
+    self.request("PUT", &path, Some(contents), None).await?;
+    Ok(())
+  }
+
+  /// Remove a file from the scene directory. The server keeps its
+  /// contents in history.
+  pub async fn delete(&self, name: &str) -> Result<()> {
+    let path = format!("/api/files/{}", http::encode_path(name));
+    self.request("DELETE", &path, None, None).await?;
+    Ok(())
+  }
+
+  /// Send a command to every connected viewer, returning how many
+  /// received it.
+  pub async fn control(&self, command: &ControlCommand) -> Result<usize> {
+    let body = serde_json::to_vec(command)?;
+    let response: ControlResponse = self
+      .json("POST", "/api/control", Some(&body)).await?;
+    Ok(response.viewers)
+  }
+
+  /// Connect to the event stream. The scene's current files are in
+  /// `snapshot`; events that follow are relative to it.
+  pub async fn events(&self) -> Result<EventStream> {
+    let url = format!("ws://{}/ws", self.addr);
+    let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let mut stream = EventStream {
+      socket,
+      version: None,
+      snapshot: Vec::new(),
+      pending: None,
+    };
+    // The hello comes first, then the snapshot unless the server failed
+    // to take one
+    let closed = || Error::Protocol(
+      "connection closed before the snapshot".to_string());
+    match stream.receive().await?.ok_or_else(closed)? {
+      Incoming::Hello(version) => stream.version = Some(version),
+      _ => return Err(Error::Protocol("expected a hello first".to_string())),
+    }
+    match stream.receive().await?.ok_or_else(closed)? {
+      Incoming::Snapshot { files } => stream.snapshot = files,
+      Incoming::Event(event) => stream.pending = Some(event),
+      Incoming::Hello(_) =>
+        return Err(Error::Protocol("repeated hello".to_string())),
+    }
+    Ok(stream)
+  }
+
+  async fn json<T: DeserializeOwned>(
+      &self,
+      method: &str,
+      path: &str,
+      body: Option<&[u8]>) -> Result<T> {
+    let response = self
+      .request(method, path, body, Some("application/json")).await?;
+    Ok(serde_json::from_slice(&response)?)
+  }
+
+  // One HTTP/1.1 request on a fresh connection, returning the body of a
+  // successful response
+  async fn request(
+      &self,
+      method: &str,
+      path: &str,
+      body: Option<&[u8]>,
+      content_type: Option<&str>) -> Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(&self.addr).await?;
+    let mut head = format!(
+      "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+      method, path, self.addr);
+    if let Some(body) = body {
+      head += &format!("Content-Length: {}\r\n", body.len());
+      if let Some(content_type) = content_type {
+        head += &format!("Content-Type: {}\r\n", content_type);
+      }
+    }
+    if let Some(key) = &self.lock_key {
+      head += &format!("X-Scene-Lock: {}\r\n", key);
+    }
+    head += "\r\n";
+    stream.write_all(head.as_bytes()).await?;
+    if let Some(body) = body {
+      stream.write_all(body).await?;
+    }
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = http::parse_response(&response).map_err(Error::Protocol)?;
+    if !(200..300).contains(&response.status) {
+      return Err(match serde_json::from_slice::<ErrorResponse>(&response.body) {
+        Ok(error) => Error::Http {
+          status: response.status,
+          code: Some(error.code),
+          message: error.message,
+        },
+        Err(_) => Error::Http {
+          status: response.status,
+          code: None,
+          message: String::from_utf8_lossy(&response.body).into_owned(),
+        },
+      });
+    }
+    Ok(response.body)
+  }
+}
+
+// A message from the server: one of the per-client messages, or a
+// broadcast event
+enum Incoming {
+  Hello(VersionInfo),
+  Snapshot { files: Vec<FileInfo> },
+  Event(FileEvent),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+  Hello(VersionInfo),
+  Snapshot { files: Vec<FileInfo> },
+}
+
+/// A live connection to the server's event stream.
+pub struct EventStream {
+  socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+  version: Option<VersionInfo>,
+  snapshot: Vec<FileInfo>,
+  /// An event that arrived while waiting for the snapshot
+  pending: Option<FileEvent>,
+}
+
+impl EventStream {
+  /// What the server announced in its hello.
+  pub fn version(&self) -> &VersionInfo {
+    self.version.as_ref().expect("hello is received on connect")
+  }
+
+  /// The scene's files as of connecting (and any later `subscribe`).
+  pub fn snapshot(&self) -> &[FileInfo] {
+    &self.snapshot
+  }
+
+  /// The next event, or None once the server closes the connection.
+  pub async fn next(&mut self) -> Option<Result<FileEvent>> {
+    if let Some(event) = self.pending.take() {
+      return Some(Ok(event));
+    }
+    loop {
+      match self.receive().await {
+        Ok(Some(Incoming::Event(event))) => return Some(Ok(event)),
+        Ok(Some(Incoming::Hello(version))) => self.version = Some(version),
+        Ok(Some(Incoming::Snapshot { files })) => self.snapshot = files,
+        Ok(None) => return None,
+        Err(e) => return Some(Err(e)),
+      }
+    }
+  }
+
+  /// Only receive events for files passing `filter`, and only of the
+  /// types in its `events` if set; an empty filter receives everything
+  /// again.
+  pub async fn subscribe(&mut self, filter: &FileFilter) -> Result<()> {
+    let mut message = serde_json::to_value(filter)?;
+    message["type"] = "subscribe".into();
+    self.socket.send(Message::Text(message.to_string())).await?;
+    Ok(())
+  }
+
+  async fn receive(&mut self) -> Result<Option<Incoming>> {
+    while let Some(frame) = self.socket.next().await {
+      let text = match frame? {
+        Message::Text(text) => text,
+        Message::Close(_) => break,
+        // Binary frames only come with compression or MessagePack,
+        // neither of which this client asks for
+        _ => continue,
+      };
+      let value: serde_json::Value = serde_json::from_str(&text)?;
+      let incoming = match value["type"].as_str() {
+        Some("hello" | "snapshot") =>
+          match serde_json::from_value(value)? {
+            ServerMessage::Hello(version) => Incoming::Hello(version),
+            ServerMessage::Snapshot { files } =>
+              Incoming::Snapshot { files },
+          },
+        _ => Incoming::Event(serde_json::from_value(value)?),
+      };
+      return Ok(Some(incoming));
+    }
+    Ok(None)
+  }
+}
@@ -0,0 +1,256 @@
+//! Connected viewers and how well their links keep up, for
+//! `/api/clients`. Each WebSocket connection is pinged every
+//! [`PING_INTERVAL`], and the pongs give its round-trip time. Mesh
+//! downloads sent with the viewer's `X-Kitbash-Client` header are timed
+//! from the first byte of the body to the last, for its throughput.
+//!
+//! A client is starved when its recent round trips average over
+//! [`SLOW_ROUND_TRIP`], or its recent big downloads come in under
+//! [`SLOW_THROUGHPUT`]; that's logged at most once a minute per client.
+
+use kitbash_viewer::auth;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Header tying a mesh download to the viewer's WebSocket connection.
+pub const CLIENT_HEADER: &str = "x-kitbash-client";
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(10);
+pub const SLOW_ROUND_TRIP: Duration = Duration::from_secs(1);
+/// Bytes per second
+pub const SLOW_THROUGHPUT: f64 = 256.0 * 1024.0;
+
+// Downloads smaller than this finish inside the socket buffers, so
+// their time says nothing about the link
+const TIMED_BYTES: u64 = 256 * 1024;
+// Samples kept for the recent averages
+const RECENT: usize = 20;
+const WARN_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Default)]
+pub struct Clients {
+  connected: Arc<Mutex<BTreeMap<String, Client>>>,
+}
+
+struct Client {
+  user: Option<String>,
+  user_agent: Option<String>,
+  /// Milliseconds since the Unix epoch
+  connected: u64,
+  xr: bool,
+  /// Payload of the ping awaiting its pong, and when it went out
+  ping: Option<(u64, Instant)>,
+  next_ping: u64,
+  round_trips: VecDeque<Duration>,
+  max_round_trip: Duration,
+  downloads: u64,
+  bytes: u64,
+  /// Bytes per second of recent timed downloads
+  throughputs: VecDeque<f64>,
+  warned: Option<Instant>,
+}
+
+#[derive(Serialize)]
+pub struct ClientReport {
+  pub id: String,
+  /// From the token it connected with, absent on an open server
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub user: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub user_agent: Option<String>,
+  /// Milliseconds since the Unix epoch
+  pub connected: u64,
+  /// Connected from `/xr`
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub xr: bool,
+  /// Absent until the first pong
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub round_trip: Option<RoundTrip>,
+  pub downloads: Downloads,
+  pub starved: bool,
+}
+
+#[derive(Serialize)]
+pub struct RoundTrip {
+  pub last_ms: f64,
+  /// Over the recent pings
+  pub mean_ms: f64,
+  /// Since it connected
+  pub max_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct Downloads {
+  pub count: u64,
+  pub bytes: u64,
+  /// Mean bytes per second over recent big downloads, absent until
+  /// there's been one
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub throughput: Option<f64>,
+}
+
+/// Keeps a client listed until dropped, when its socket closes.
+pub struct ClientGuard {
+  clients: Clients,
+  pub id: String,
+  /// Whether the client is an immersive (WebXR) viewer
+  pub xr: bool,
+}
+
+impl Drop for ClientGuard {
+  fn drop(&mut self) {
+    self.clients.connected.lock().unwrap().remove(&self.id);
+  }
+}
+
+impl Clients {
+  /// List a client under the id it asked for, or a fresh one if that's
+  /// missing, malformed or taken.
+  pub fn connect(&self, id: Option<&str>, user: Option<String>,
+      user_agent: Option<String>, xr: bool, now: u64) -> ClientGuard {
+    let mut connected = self.connected.lock().unwrap();
+    let id = id.filter(|id| valid_id(id) && !connected.contains_key(*id))
+      .map(str::to_string)
+      .unwrap_or_else(auth::random_token);
+    connected.insert(id.clone(), Client {
+      user,
+      user_agent,
+      connected: now,
+      xr,
+      ping: None,
+      next_ping: 0,
+      round_trips: VecDeque::new(),
+      max_round_trip: Duration::ZERO,
+      downloads: 0,
+      bytes: 0,
+      throughputs: VecDeque::new(),
+      warned: None,
+    });
+    ClientGuard { clients: self.clone(), id, xr }
+  }
+
+  /// The payload of a ping to send the client, timed from now.
+  pub fn ping(&self, id: &str) -> Vec<u8> {
+    let mut connected = self.connected.lock().unwrap();
+    let Some(client) = connected.get_mut(id) else { return Vec::new() };
+    // Never answered, so it took at least this long
+    if let Some((_, at)) = client.ping {
+      push_recent(&mut client.round_trips, at.elapsed());
+      warn_if_starved(id, client);
+    }
+    let payload = client.next_ping;
+    client.next_ping += 1;
+    client.ping = Some((payload, Instant::now()));
+    payload.to_be_bytes().to_vec()
+  }
+
+  /// A pong from the client; ones that don't answer the last ping are
+  /// ignored.
+  pub fn pong(&self, id: &str, payload: &[u8]) {
+    let mut connected = self.connected.lock().unwrap();
+    let Some(client) = connected.get_mut(id) else { return };
+    let Some((sent, at)) = client.ping else { return };
+    if payload != sent.to_be_bytes() {
+      return;
+    }
+    client.ping = None;
+    let round_trip = at.elapsed();
+    push_recent(&mut client.round_trips, round_trip);
+    client.max_round_trip = client.max_round_trip.max(round_trip);
+    warn_if_starved(id, client);
+  }
+
+  /// A finished download for the client, if it's connected.
+  pub fn download(&self, id: &str, bytes: u64, elapsed: Duration) {
+    let mut connected = self.connected.lock().unwrap();
+    let Some(client) = connected.get_mut(id) else { return };
+    client.downloads += 1;
+    client.bytes += bytes;
+    if bytes >= TIMED_BYTES && !elapsed.is_zero() {
+      push_recent(&mut client.throughputs,
+        bytes as f64 / elapsed.as_secs_f64());
+      warn_if_starved(id, client);
+    }
+  }
+
+  /// How many viewers are connected.
+  pub fn count(&self) -> usize {
+    self.connected.lock().unwrap().len()
+  }
+
+  pub fn list(&self) -> Vec<ClientReport> {
+    let connected = self.connected.lock().unwrap();
+    connected.iter().map(|(id, client)| ClientReport {
+      id: id.clone(),
+      user: client.user.clone(),
+      user_agent: client.user_agent.clone(),
+      connected: client.connected,
+      xr: client.xr,
+      round_trip: client.round_trips.back().map(|last| RoundTrip {
+        last_ms: millis(*last),
+        mean_ms: millis(client.mean_round_trip()),
+        max_ms: millis(client.max_round_trip),
+      }),
+      downloads: Downloads {
+        count: client.downloads,
+        bytes: client.bytes,
+        throughput: client.throughput(),
+      },
+      starved: client.starved().is_some(),
+    }).collect()
+  }
+}
+
+impl Client {
+  fn mean_round_trip(&self) -> Duration {
+    let total: Duration = self.round_trips.iter().sum();
+    total / self.round_trips.len().max(1) as u32
+  }
+
+  fn throughput(&self) -> Option<f64> {
+    (!self.throughputs.is_empty()).then(||
+      self.throughputs.iter().sum::<f64>() / self.throughputs.len() as f64)
+  }
+
+  // Why the client is starved, if it is
+  fn starved(&self) -> Option<String> {
+    let round_trip = self.mean_round_trip();
+    if round_trip > SLOW_ROUND_TRIP {
+      return Some(format!("round trips average {:.0} ms",
+        millis(round_trip)));
+    }
+    self.throughput().filter(|&t| t < SLOW_THROUGHPUT)
+      .map(|t| format!("downloads average {:.0} KiB/s", t / 1024.0))
+  }
+}
+
+fn warn_if_starved(id: &str, client: &mut Client) {
+  let Some(reason) = client.starved() else { return };
+  if client.warned.is_some_and(|at| at.elapsed() < WARN_EVERY) {
+    return;
+  }
+  client.warned = Some(Instant::now());
+  let who = client.user.as_deref()
+    .or(client.user_agent.as_deref())
+    .unwrap_or("unknown");
+  println!("Client {} ({}) is starved: {}", id, who, reason);
+}
+
+fn push_recent<T>(samples: &mut VecDeque<T>, sample: T) {
+  if samples.len() == RECENT {
+    samples.pop_front();
+  }
+  samples.push_back(sample);
+}
+
+// Viewers pick their own ids; keep them short and printable
+fn valid_id(id: &str) -> bool {
+  (1..=64).contains(&id.len())
+    && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+fn millis(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1000.0
+}
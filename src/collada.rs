@@ -0,0 +1,589 @@
+//! Server-side COLLADA (.dae) parsing. Like the other parsers this only
+//! keeps positions and triangles; the browser loads the file itself with
+//! three.js' ColladaLoader.
+//!
+//! Geometry is placed by the visual scene's node hierarchy, library
+//! nodes and skinned geometry included, and each node with geometry
+//! becomes a named object. As in ColladaLoader, a Z-up asset is turned
+//! Y-up and the asset's unit is applied. Triangles, polylists and
+//! polygons are read; lines, strips and fans are skipped.
+//!
+//! Instancing can multiply a small file many times over, so files that
+//! place more than [`mesh::MAX_TRIANGLES`] triangles, or a million
+//! nodes, are refused.
+
+use crate::mesh::{self, Mesh, SubObject};
+use crate::xml::{unescape, Tag, Tags};
+use std::collections::HashMap;
+
+// Instanced nodes nested deeper than this are taken to be a cycle
+const MAX_DEPTH: usize = 32;
+// Most nodes placed, counting each instance. Nodes that instance others
+// several times multiply at every level, geometry or not.
+const MAX_PLACED: usize = 1_000_000;
+
+/// A 4x4 matrix in row-major order, applied to column vectors, as
+/// COLLADA's `<matrix>` writes it.
+type Matrix = [f64; 16];
+
+const IDENTITY: Matrix = [
+  1.0, 0.0, 0.0, 0.0,
+  0.0, 1.0, 0.0, 0.0,
+  0.0, 0.0, 1.0, 0.0,
+  0.0, 0.0, 0.0, 1.0,
+];
+
+// `<triangles>`, `<polylist>` or `<polygons>`, before its inputs are
+// looked up
+struct Primitive {
+  /// Source of the VERTEX input, and its offset in `<p>`
+  vertices: Option<(String, usize)>,
+  /// Index values per vertex, the largest input offset plus one
+  stride: usize,
+  polygons: Polygons,
+  /// One list per `<p>`
+  indices: Vec<Vec<usize>>,
+}
+
+// How a primitive's corners make polygons
+enum Polygons {
+  Triangles,
+  /// Corners per polygon, from `<vcount>`
+  Counts(Vec<usize>),
+  /// Each `<p>` is one polygon
+  PerList,
+}
+
+#[derive(Default)]
+struct Node {
+  name: Option<String>,
+  transform: Option<Matrix>,
+  /// Ids of the geometry (or skin controllers) the node shows
+  geometries: Vec<String>,
+  /// Ids of library nodes placed in this one
+  instances: Vec<String>,
+  children: Vec<Node>,
+}
+
+// Everything read from the document, by id
+#[derive(Default)]
+struct Document {
+  arrays: HashMap<String, Vec<f64>>,
+  /// A `<source>`'s array and its stride
+  sources: HashMap<String, (String, usize)>,
+  /// A `<vertices>`' POSITION source
+  vertices: HashMap<String, String>,
+  geometries: HashMap<String, Vec<Primitive>>,
+  /// A skin controller's geometry
+  controllers: HashMap<String, String>,
+  library_nodes: HashMap<String, Node>,
+  visual_scenes: HashMap<String, Vec<Node>>,
+  /// The visual scene `<scene>` names, else the first one
+  scene: Option<String>,
+  z_up: bool,
+  /// Metres per unit
+  unit: f64,
+}
+
+/// Whether the contents look like a COLLADA document.
+pub fn is_dae(bytes: &[u8]) -> bool {
+  let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+  head.trim_start_matches('\u{feff}').trim_start().starts_with('<')
+    && head.contains("<COLLADA")
+}
+
+/// Parse a COLLADA document into an object per node with geometry.
+pub fn parse_dae(bytes: &[u8]) -> Result<Mesh, String> {
+  let xml = std::str::from_utf8(bytes)
+    .map_err(|_| "file is not valid UTF-8".to_string())?;
+  let document = read_document(xml)?;
+  let scene = document.scene.as_deref()
+    .and_then(|id| document.visual_scenes.get(id))
+    .or_else(|| document.visual_scenes.values().next())
+    .ok_or_else(|| "COLLADA file has no visual scene".to_string())?;
+
+  let mut root = scale(document.unit);
+  if document.z_up {
+    // -90 degrees about X
+    root = multiply(&[
+      1.0, 0.0, 0.0, 0.0,
+      0.0, 0.0, 1.0, 0.0,
+      0.0, -1.0, 0.0, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ], &root);
+  }
+  let mut mesh = Mesh::default();
+  let mut placed = 0;
+  for node in scene {
+    place(&document, node, &root, 0, &mut placed, &mut mesh)?;
+  }
+  Ok(mesh)
+}
+
+fn read_document(xml: &str) -> Result<Document, String> {
+  let mut document = Document { unit: 1.0, ..Document::default() };
+  let mut tags = Tags::new(xml);
+  // Ids of the element being read, where its contents need them
+  let mut source: Option<String> = None;
+  let mut vertices: Option<String> = None;
+  let mut geometry: Option<String> = None;
+  let mut controller: Option<String> = None;
+  let mut visual_scene: Option<String> = None;
+  let mut primitive: Option<Primitive> = None;
+  // Open nodes, with their ids and how deep they are
+  let mut nodes: Vec<(Option<String>, usize, Node)> = Vec::new();
+  // Elements open around the current tag
+  let mut depth: usize = 0;
+  while let Some(tag) = tags.next() {
+    let tag = tag?;
+    if tag.closing {
+      depth = depth.saturating_sub(1);
+      match tag.name {
+        "source" => source = None,
+        "vertices" => vertices = None,
+        "geometry" => geometry = None,
+        "controller" => controller = None,
+        "visual_scene" => visual_scene = None,
+        "triangles" | "polylist" | "polygons" => {
+          if let (Some(id), Some(done)) = (&geometry, primitive.take()) {
+            document.geometries.entry(id.clone()).or_default().push(done);
+          }
+        }
+        "node" => {
+          if let Some((id, _, node)) = nodes.pop() {
+            add_node(&mut document, &mut nodes, visual_scene.as_deref(), id,
+              node);
+          }
+        }
+        _ => {}
+      }
+      continue;
+    }
+    let text = if tag.empty { "" } else { tags.text() };
+    // The node this tag is directly inside, which transforms and
+    // instances belong to
+    let node = nodes.last_mut()
+      .filter(|(_, node_depth, _)| *node_depth + 1 == depth)
+      .map(|(_, _, node)| node);
+    match tag.name {
+      "unit" => {
+        if let Some(meter) = tag.get("meter") {
+          document.unit = meter.trim().parse()
+            .map_err(|_| format!("<unit> has invalid meter '{}'", meter))?;
+        }
+      }
+      "up_axis" => document.z_up = text.trim() == "Z_UP",
+      "float_array" => {
+        let id = tag.require("id")?.to_string();
+        document.arrays.insert(id, numbers(&tag, text)?);
+      }
+      "source" if !tag.empty => source = tag.get("id").map(str::to_string),
+      "accessor" => {
+        if let Some(id) = &source {
+          let stride = match tag.get("stride") {
+            Some(_) => tag.parse("stride")?,
+            None => 1,
+          };
+          let array = reference(tag.require("source")?);
+          document.sources.insert(id.clone(), (array, stride));
+        }
+      }
+      "vertices" if !tag.empty =>
+        vertices = tag.get("id").map(str::to_string),
+      "geometry" if !tag.empty =>
+        geometry = tag.get("id").map(str::to_string),
+      "controller" if !tag.empty =>
+        controller = tag.get("id").map(str::to_string),
+      "skin" => {
+        if let Some(id) = &controller {
+          document.controllers.insert(id.clone(),
+            reference(tag.require("source")?));
+        }
+      }
+      "triangles" | "polylist" | "polygons" if !tag.empty => {
+        primitive = Some(Primitive {
+          vertices: None,
+          stride: 1,
+          polygons: match tag.name {
+            "triangles" => Polygons::Triangles,
+            "polylist" => Polygons::Counts(Vec::new()),
+            _ => Polygons::PerList,
+          },
+          indices: Vec::new(),
+        });
+      }
+      "input" => {
+        let semantic = tag.require("semantic")?;
+        let target = reference(tag.require("source")?);
+        if let Some(primitive) = &mut primitive {
+          let offset: usize = match tag.get("offset") {
+            Some(_) => tag.parse("offset")?,
+            None => 0,
+          };
+          let stride = offset.checked_add(1)
+            .ok_or_else(|| format!("<input> offset {} is too large", offset))?;
+          primitive.stride = primitive.stride.max(stride);
+          if semantic == "VERTEX" {
+            primitive.vertices = Some((target, offset));
+          }
+        } else if let Some(id) = &vertices {
+          if semantic == "POSITION" {
+            document.vertices.insert(id.clone(), target);
+          }
+        }
+      }
+      "vcount" => {
+        if let Some(primitive) = &mut primitive {
+          primitive.polygons = Polygons::Counts(integers(&tag, text)?);
+        }
+      }
+      "p" => {
+        if let Some(primitive) = &mut primitive {
+          primitive.indices.push(integers(&tag, text)?);
+        }
+      }
+      "visual_scene" if !tag.empty => {
+        let id = tag.require("id")?.to_string();
+        document.visual_scenes.entry(id.clone()).or_default();
+        visual_scene = Some(id);
+      }
+      "instance_visual_scene" =>
+        document.scene = Some(reference(tag.require("url")?)),
+      "node" => {
+        let node = Node {
+          name: tag.get("name").or(tag.get("id")).map(unescape),
+          ..Node::default()
+        };
+        let id = tag.get("id").map(str::to_string);
+        if tag.empty {
+          add_node(&mut document, &mut nodes, visual_scene.as_deref(), id,
+            node);
+        } else {
+          nodes.push((id, depth, node));
+        }
+      }
+      "matrix" | "translate" | "rotate" | "scale" => {
+        if let Some(node) = node {
+          let step = node_transform(&tag, text)?;
+          node.transform = Some(match &node.transform {
+            Some(transform) => multiply(transform, &step),
+            None => step,
+          });
+        }
+      }
+      "instance_geometry" | "instance_controller" => {
+        if let Some(node) = node {
+          node.geometries.push(reference(tag.require("url")?));
+        }
+      }
+      "instance_node" => {
+        if let Some(node) = node {
+          node.instances.push(reference(tag.require("url")?));
+        }
+      }
+      _ => {}
+    }
+    if !tag.empty {
+      depth += 1;
+    }
+  }
+  if !nodes.is_empty() {
+    return Err("<node> is never closed".to_string());
+  }
+  Ok(document)
+}
+
+// File a finished node under its parent, its visual scene or the node
+// library
+fn add_node(document: &mut Document,
+    open: &mut [(Option<String>, usize, Node)], visual_scene: Option<&str>,
+    id: Option<String>, node: Node) {
+  if let Some((_, _, parent)) = open.last_mut() {
+    parent.children.push(node);
+  } else if let Some(scene) = visual_scene {
+    document.visual_scenes.entry(scene.to_string()).or_default().push(node);
+  } else if let Some(id) = id {
+    document.library_nodes.insert(id, node);
+  }
+}
+
+// Add a node's geometry, and its children's, to the mesh
+fn place(document: &Document, node: &Node, parent: &Matrix, depth: usize,
+    placed: &mut usize, mesh: &mut Mesh) -> Result<(), String> {
+  if depth > MAX_DEPTH {
+    return Err(format!("nodes nest over {} deep", MAX_DEPTH));
+  }
+  *placed += 1;
+  if *placed > MAX_PLACED {
+    return Err(format!("instances place over {} nodes", MAX_PLACED));
+  }
+  let transform = match &node.transform {
+    Some(transform) => multiply(parent, transform),
+    None => *parent,
+  };
+  let start = mesh.triangles.len();
+  for id in &node.geometries {
+    add_geometry(document, id, &transform, mesh)?;
+  }
+  if !node.geometries.is_empty() {
+    let name = node.name.clone()
+      .unwrap_or_else(|| format!("geometry {}", node.geometries[0]));
+    let triangles = start..mesh.triangles.len();
+    mesh.objects.push(SubObject { name, triangles });
+  }
+  for id in &node.instances {
+    let instance = document.library_nodes.get(id)
+      .ok_or_else(|| format!("instance of missing node {}", id))?;
+    place(document, instance, &transform, depth + 1, placed, mesh)?;
+  }
+  for child in &node.children {
+    place(document, child, &transform, depth + 1, placed, mesh)?;
+  }
+  Ok(())
+}
+
+fn add_geometry(document: &Document, id: &str, transform: &Matrix,
+    mesh: &mut Mesh) -> Result<(), String> {
+  let id = document.controllers.get(id).map_or(id, String::as_str);
+  let primitives = document.geometries.get(id)
+    .ok_or_else(|| format!("instance of missing geometry {}", id))?;
+  for primitive in primitives {
+    let Some((source, offset)) = &primitive.vertices else { continue };
+    // Usually a `<vertices>`, though a source will do
+    let source = document.vertices.get(source).unwrap_or(source);
+    let (array, stride) = document.sources.get(source)
+      .ok_or_else(|| format!("geometry {}: missing source {}", id, source))?;
+    let values = document.arrays.get(array)
+      .ok_or_else(|| format!("geometry {}: missing array {}", id, array))?;
+    if *stride < 3 {
+      return Err(format!("geometry {}: positions have stride {}", id,
+        stride));
+    }
+    let count = values.len() / stride;
+    let base = mesh.positions.len();
+    mesh.positions.extend(values.chunks_exact(*stride)
+      .map(|p| apply(transform, [p[0], p[1], p[2]])));
+    mesh::check_size(mesh)?;
+
+    for indices in &primitive.indices {
+      let corners: Vec<usize> = indices.chunks_exact(primitive.stride)
+        .map(|values| values.get(*offset).copied().unwrap_or(0))
+        .collect();
+      if let Some(&bad) = corners.iter().find(|&&v| v >= count) {
+        return Err(format!("geometry {}: vertex index {} out of range ({} \
+          vertices)", id, bad, count));
+      }
+      let sizes = match &primitive.polygons {
+        Polygons::Triangles => vec![3; corners.len() / 3],
+        Polygons::Counts(sizes) => sizes.clone(),
+        Polygons::PerList => vec![corners.len()],
+      };
+      let mut at = 0;
+      for size in sizes {
+        let Some(polygon) = corners.get(at..at + size) else {
+          return Err(format!("geometry {}: <vcount> runs past <p>", id));
+        };
+        at += size;
+        // A fan, as ColladaLoader triangulates
+        for i in 1..size.saturating_sub(1) {
+          mesh.triangles.push([polygon[0], polygon[i], polygon[i + 1]]
+            .map(|v| base + v));
+        }
+      }
+      mesh::check_size(mesh)?;
+    }
+  }
+  Ok(())
+}
+
+// One of a node's transformation elements, as a matrix
+fn node_transform(tag: &Tag, text: &str) -> Result<Matrix, String> {
+  let values = numbers(tag, text)?;
+  let expected = match tag.name {
+    "matrix" => 16,
+    "rotate" => 4,
+    _ => 3,
+  };
+  if values.len() != expected {
+    return Err(format!("<{}> needs {} numbers", tag.name, expected));
+  }
+  let v = &values;
+  Ok(match tag.name {
+    "matrix" => v.as_slice().try_into().unwrap(),
+    "translate" => [
+      1.0, 0.0, 0.0, v[0],
+      0.0, 1.0, 0.0, v[1],
+      0.0, 0.0, 1.0, v[2],
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    "scale" => [
+      v[0], 0.0, 0.0, 0.0,
+      0.0, v[1], 0.0, 0.0,
+      0.0, 0.0, v[2], 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    _ => rotation([v[0], v[1], v[2]], v[3].to_radians()),
+  })
+}
+
+// About an axis, by the right-hand rule
+fn rotation(axis: [f64; 3], angle: f64) -> Matrix {
+  let length = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2])
+    .sqrt();
+  if length == 0.0 {
+    return IDENTITY;
+  }
+  let [x, y, z] = axis.map(|a| a / length);
+  let (s, c) = angle.sin_cos();
+  let t = 1.0 - c;
+  [
+    t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0,
+    t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0,
+    t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+  ]
+}
+
+fn scale(factor: f64) -> Matrix {
+  let mut matrix = IDENTITY;
+  for i in [0, 5, 10] {
+    matrix[i] = factor;
+  }
+  matrix
+}
+
+// `a` applied after `b`
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+  let mut result = [0.0; 16];
+  for row in 0..4 {
+    for column in 0..4 {
+      result[row * 4 + column] = (0..4)
+        .map(|k| a[row * 4 + k] * b[k * 4 + column])
+        .sum();
+    }
+  }
+  result
+}
+
+fn apply(m: &Matrix, p: [f64; 3]) -> [f64; 3] {
+  [0, 1, 2].map(|row| m[row * 4] * p[0] + m[row * 4 + 1] * p[1]
+    + m[row * 4 + 2] * p[2] + m[row * 4 + 3])
+}
+
+// The id a `#id` URL points at
+fn reference(url: &str) -> String {
+  unescape(url.trim_start_matches('#'))
+}
+
+fn numbers(tag: &Tag, text: &str) -> Result<Vec<f64>, String> {
+  text.split_whitespace()
+    .map(|n| n.parse())
+    .collect::<Result<_, _>>()
+    .map_err(|_| format!("<{}> holds something other than numbers",
+      tag.name))
+}
+
+fn integers(tag: &Tag, text: &str) -> Result<Vec<usize>, String> {
+  text.split_whitespace()
+    .map(|n| n.parse())
+    .collect::<Result<_, _>>()
+    .map_err(|_| format!("<{}> holds something other than indices",
+      tag.name))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A document with one node showing a quad, given its `<p>`
+  fn dae(asset: &str, p: &str) -> String {
+    format!(r##"<?xml version="1.0" encoding="utf-8"?>
+<COLLADA version="1.4.1">
+  <asset>{}</asset>
+  <library_geometries>
+    <geometry id="quad-mesh">
+      <mesh>
+        <source id="quad-positions">
+          <float_array id="quad-array" count="12">
+            0 0 0 100 0 0 100 100 0 0 100 0
+          </float_array>
+          <technique_common>
+            <accessor source="#quad-array" count="4" stride="3"/>
+          </technique_common>
+        </source>
+        <vertices id="quad-vertices">
+          <input semantic="POSITION" source="#quad-positions"/>
+        </vertices>
+        <polylist count="1">
+          <input semantic="VERTEX" source="#quad-vertices" offset="0"/>
+          <vcount>4</vcount>
+          <p>{}</p>
+        </polylist>
+      </mesh>
+    </geometry>
+  </library_geometries>
+  <library_visual_scenes>
+    <visual_scene id="Scene">
+      <node name="Floor">
+        <matrix>1 0 0 -50 0 1 0 -50 0 0 1 0 0 0 0 1</matrix>
+        <instance_geometry url="#quad-mesh"/>
+      </node>
+    </visual_scene>
+  </library_visual_scenes>
+  <scene><instance_visual_scene url="#Scene"/></scene>
+</COLLADA>
+"##, asset, p)
+  }
+
+  #[test]
+  fn places_geometry() {
+    let bytes = dae("", "0 1 2 3").into_bytes();
+    assert!(is_dae(&bytes));
+    let mesh = parse_dae(&bytes).unwrap();
+    assert_eq!(mesh.objects.len(), 1);
+    assert_eq!(mesh.objects[0].name, "Floor");
+    assert_eq!(mesh.triangles.len(), 2);
+    assert_eq!(mesh.positions[0], [-50.0, -50.0, 0.0]);
+    assert_eq!(mesh.positions[2], [50.0, 50.0, 0.0]);
+  }
+
+  #[test]
+  fn applies_units_and_up_axis() {
+    let asset = r#"<unit meter="0.01"/><up_axis>Z_UP</up_axis>"#;
+    let mesh = parse_dae(dae(asset, "0 1 2 3").as_bytes()).unwrap();
+    assert_eq!(mesh.positions[2], [0.5, 0.0, -0.5]);
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    // Corners that aren't in the source
+    assert!(parse_dae(dae("", "0 1 2 9").as_bytes()).is_err());
+    assert!(parse_dae(dae("", "0 1 2").as_bytes()).is_err());
+    assert!(parse_dae(dae("", "0 1 two 3").as_bytes()).is_err());
+    let doc = dae("", "0 1 2 3");
+    assert!(parse_dae(&doc.as_bytes()[..doc.len() / 2]).is_err());
+    let offset = format!("offset=\"{}\"", usize::MAX);
+    assert!(parse_dae(doc.replace("offset=\"0\"", &offset).as_bytes())
+      .is_err());
+  }
+
+  #[test]
+  fn limits_nested_instances() {
+    // Each library node places the one before it twice, so the last is
+    // 2^30 nodes, even with no geometry in them
+    let mut nodes = String::from(r#"<node id="n0"/>"#);
+    for i in 1..=30 {
+      nodes.push_str(&format!(r##"<node id="n{}">
+        <instance_node url="#n{}"/><instance_node url="#n{}"/></node>"##,
+        i, i - 1, i - 1));
+    }
+    let doc = dae("", "0 1 2 3")
+      .replace("<library_visual_scenes>", &format!(
+        "<library_nodes>{}</library_nodes><library_visual_scenes>", nodes))
+      .replace(r##"<instance_geometry url="#quad-mesh"/>
+      </node>"##, r##"<instance_node url="#n30"/>
+      </node>"##);
+    let error = parse_dae(doc.as_bytes()).err().unwrap();
+    assert!(error.contains("over"), "{}", error);
+  }
+}
@@ -0,0 +1,317 @@
+//! The server's config file: a subset of TOML, enough for sections of
+//! plain values. Supported are `[table]` and `[dotted."quoted".table]`
+//! headers, `key = value` pairs, `#` comments, and strings (basic and
+//! literal), integers, floats, booleans and single-line arrays as
+//! values. Inline tables and multi-line strings are not.
+//!
+//! ```toml
+//! [users.alice]
+//! token = "s3cret"
+//! role = "admin"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  String(String),
+  Integer(i64),
+  Float(f64),
+  Bool(bool),
+  Array(Vec<Value>),
+}
+
+impl Value {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(s) => Some(s),
+      _ => None,
+    }
+  }
+
+  pub fn as_integer(&self) -> Option<i64> {
+    match self {
+      Value::Integer(n) => Some(*n),
+      _ => None,
+    }
+  }
+
+  /// Integers convert too.
+  pub fn as_float(&self) -> Option<f64> {
+    match self {
+      Value::Float(x) => Some(*x),
+      Value::Integer(n) => Some(*n as f64),
+      _ => None,
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Value::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&[Value]> {
+    match self {
+      Value::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+}
+
+/// The keys under one `[header]`.
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+  /// Keys before the first header are under the empty path
+  tables: BTreeMap<Vec<String>, Table>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+  /// 1-based; 0 when the file couldn't be read
+  pub line: usize,
+  pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.line {
+      0 => write!(f, "{}", self.message),
+      line => write!(f, "line {}: {}", line, self.message),
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+  pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError {
+      line: 0,
+      message: format!("can't read {}: {}", path.display(), e),
+    })?;
+    Config::parse(&text)
+  }
+
+  pub fn parse(text: &str) -> Result<Config, ConfigError> {
+    let mut config = Config::default();
+    let mut current: Vec<String> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+      let error = |message: String| ConfigError { line: i + 1, message };
+      let line = strip_comment(line).trim();
+      if line.is_empty() {
+        continue;
+      }
+      if let Some(header) = line.strip_prefix('[') {
+        let header = header.strip_suffix(']')
+          .ok_or_else(|| error("unclosed table header".to_string()))?;
+        current = parse_path(header).map_err(error)?;
+        if config.tables.contains_key(&current) {
+          return Err(error(format!("table [{}] defined twice", header)));
+        }
+        config.tables.insert(current.clone(), Table::new());
+        continue;
+      }
+      let (key, value) = line.split_once('=')
+        .ok_or_else(|| error("expected key = value".to_string()))?;
+      let key = match parse_path(key.trim()).map_err(error)?.as_slice() {
+        [key] => key.clone(),
+        _ => return Err(error("dotted keys aren't supported".to_string())),
+      };
+      let mut rest = value.trim();
+      let value = parse_value(&mut rest).map_err(error)?;
+      if !rest.trim().is_empty() {
+        return Err(error(format!("unexpected {:?} after value", rest)));
+      }
+      let table = config.tables.entry(current.clone()).or_default();
+      if table.insert(key.clone(), value).is_some() {
+        return Err(error(format!("{} set twice", key)));
+      }
+    }
+    Ok(config)
+  }
+
+  /// The table at a header path, e.g. `&["users", "alice"]`.
+  pub fn table(&self, path: &[&str]) -> Option<&Table> {
+    let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
+    self.tables.get(&path)
+  }
+
+  /// Tables one level below `parent`, by their last name: for `users`,
+  /// `[users.alice]` and `[users.bob]`.
+  pub fn children(&self, parent: &str) -> Vec<(&str, &Table)> {
+    self.tables.iter()
+      .filter_map(|(path, table)| match path.as_slice() {
+        [first, name] if first == parent => Some((name.as_str(), table)),
+        _ => None,
+      })
+      .collect()
+  }
+}
+
+// Everything before a `#` that isn't inside a string
+fn strip_comment(line: &str) -> &str {
+  let mut quote = None;
+  let mut escaped = false;
+  for (i, c) in line.char_indices() {
+    match quote {
+      Some('"') if escaped => escaped = false,
+      Some('"') if c == '\\' => escaped = true,
+      Some(q) if c == q => quote = None,
+      Some(_) => {}
+      None if c == '"' || c == '\'' => quote = Some(c),
+      None if c == '#' => return &line[..i],
+      None => {}
+    }
+  }
+  line
+}
+
+// `a.b."c.d"` -> ["a", "b", "c.d"]
+fn parse_path(text: &str) -> Result<Vec<String>, String> {
+  let mut parts = Vec::new();
+  let mut rest = text.trim();
+  loop {
+    let part = if rest.starts_with(['"', '\'']) {
+      let Value::String(s) = parse_value(&mut rest)? else { unreachable!() };
+      s
+    } else {
+      let end = rest.find(|c: char|
+          !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(rest.len());
+      let (bare, after) = rest.split_at(end);
+      rest = after;
+      bare.to_string()
+    };
+    if part.is_empty() {
+      return Err(format!("bad name {:?}", text));
+    }
+    parts.push(part);
+    rest = rest.trim_start();
+    match rest.strip_prefix('.') {
+      Some(after) => rest = after.trim_start(),
+      None if rest.is_empty() => return Ok(parts),
+      None => return Err(format!("bad name {:?}", text)),
+    }
+  }
+}
+
+// Parse a value off the front of `text`, leaving the rest
+fn parse_value(text: &mut &str) -> Result<Value, String> {
+  let s = text.trim_start();
+  if let Some(body) = s.strip_prefix('"') {
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+      match c {
+        '"' => {
+          *text = &body[i + 1..];
+          return Ok(Value::String(out));
+        }
+        '\\' => out.push(match chars.next().map(|(_, c)| c) {
+          Some('n') => '\n',
+          Some('t') => '\t',
+          Some('r') => '\r',
+          Some('\\') => '\\',
+          Some('"') => '"',
+          Some(c) => return Err(format!("unknown escape \\{}", c)),
+          None => break,
+        }),
+        c => out.push(c),
+      }
+    }
+    return Err("unterminated string".to_string());
+  }
+  if let Some(body) = s.strip_prefix('\'') {
+    let end = body.find('\'').ok_or("unterminated string")?;
+    *text = &body[end + 1..];
+    return Ok(Value::String(body[..end].to_string()));
+  }
+  if let Some(mut body) = s.strip_prefix('[') {
+    let mut items = Vec::new();
+    loop {
+      body = body.trim_start();
+      if let Some(after) = body.strip_prefix(']') {
+        *text = after;
+        return Ok(Value::Array(items));
+      }
+      items.push(parse_value(&mut body)?);
+      body = body.trim_start();
+      if let Some(after) = body.strip_prefix(',') {
+        body = after;
+      } else if !body.starts_with(']') {
+        return Err("expected , or ] in array".to_string());
+      }
+    }
+  }
+
+  let end = s.find([',', ']', ' ', '\t']).unwrap_or(s.len());
+  let (word, rest) = s.split_at(end);
+  *text = rest;
+  let plain = word.replace('_', "");
+  match word {
+    "true" => Ok(Value::Bool(true)),
+    "false" => Ok(Value::Bool(false)),
+    _ if plain.parse::<i64>().is_ok() =>
+      Ok(Value::Integer(plain.parse().unwrap())),
+    _ if plain.parse::<f64>().is_ok() =>
+      Ok(Value::Float(plain.parse().unwrap())),
+    _ => Err(format!("bad value {:?}", word)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_tables_and_values() {
+    let config = Config::parse(r#"
+      port = 8080  # top level
+      [users.alice]
+      token = "s3\"cr#t"
+      role = 'admin'
+      [aliases."parts.v2"]
+      scale = 1_000
+      ratio = 0.5
+      open = true
+      names = ["a", 'b', [1, 2] ]
+    "#).unwrap();
+    assert_eq!(config.table(&[]).unwrap()["port"], Value::Integer(8080));
+    let alice = config.table(&["users", "alice"]).unwrap();
+    assert_eq!(alice["token"].as_str(), Some("s3\"cr#t"));
+    assert_eq!(alice["role"].as_str(), Some("admin"));
+    let table = config.table(&["aliases", "parts.v2"]).unwrap();
+    assert_eq!(table["scale"].as_integer(), Some(1000));
+    assert_eq!(table["scale"].as_float(), Some(1000.0));
+    assert_eq!(table["ratio"].as_float(), Some(0.5));
+    assert_eq!(table["open"].as_bool(), Some(true));
+    assert_eq!(table["names"].as_array().unwrap()[2],
+      Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+    let children: Vec<&str> = config.children("users").into_iter()
+      .map(|(name, _)| name)
+      .collect();
+    assert_eq!(children, ["alice"]);
+  }
+
+  #[test]
+  fn reports_lines_of_errors() {
+    let line = |text: &str| Config::parse(text).unwrap_err().line;
+    assert_eq!(line("[a]\n[a]\n"), 2);
+    assert_eq!(line("a = 1\na = 2\n"), 2);
+    assert_eq!(line("\n\n[open\n"), 3);
+    assert_eq!(line("a.b = 1\n"), 1);
+    assert_eq!(line("a = \"open\n"), 1);
+    assert_eq!(line("a = [1 2]\n"), 1);
+    assert_eq!(line("a = 1 2\n"), 1);
+    assert_eq!(line("a = maybe\n"), 1);
+    assert_eq!(line("a = \"\\q\"\n"), 1);
+    assert_eq!(line("just words\n"), 1);
+  }
+}
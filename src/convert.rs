@@ -0,0 +1,280 @@
+//! The conversion pipeline: scene files that neither the server nor
+//! three.js read well, FBX from DCC tools and STEP or IGES from CAD,
+//! are converted to GLB by external tools such as FBX2glTF or a
+//! tessellator. Each extension has its own command (`--converter
+//! step=...`, or `--fbx-converter`), run without a shell, with
+//! `{input}` and `{output}` replaced by the paths of the file and the
+//! GLB to write.
+//!
+//! Results are kept in the scene's `converted` cache directory by
+//! content hash, so a file is converted once per version and not again
+//! after a restart. Viewers load them from `/scene-converted/<name>`,
+//! and the server parses them in place of the original. Conversions of
+//! versions no scene file has any more are swept away with the cache.
+//!
+//! Tessellating a CAD part can take minutes, so a conversion is
+//! announced with a `converting` event, followed by more as the tool
+//! prints percentages and by `converted` once the GLB is ready.
+
+use kitbash_viewer::cache::content_hash;
+use kitbash_viewer::events::FileEvent;
+use kitbash_viewer::rewrite::write_atomic;
+use kitbash_viewer::source::{IndexedSource, SceneSource};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Extensions of the files that are converted before use.
+pub const CONVERTED: &[&str] = &["fbx", "step", "stp", "iges", "igs"];
+
+// A converter that takes longer than this is stuck
+const TIMEOUT: Duration = Duration::from_secs(300);
+// How often a running converter's output is checked for progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct Pipeline {
+  /// The program, then its arguments, by lowercase extension
+  commands: HashMap<String, Vec<String>>,
+  dir: PathBuf,
+  /// A lock for each content hash being converted, held while it is so
+  /// one version isn't converted twice at once, while others are
+  running: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+  /// Where `converting` and `converted` events go
+  notify: Box<dyn Fn(FileEvent) + Send + Sync>,
+}
+
+/// Whether a scene file is converted before use.
+pub fn needs_conversion(name: &str) -> bool {
+  extension(name).is_some()
+}
+
+// The file's extension, lowercased, if it's one that's converted
+fn extension(name: &str) -> Option<String> {
+  let (_, extension) = name.rsplit_once('.')?;
+  let extension = extension.to_ascii_lowercase();
+  CONVERTED.contains(&extension.as_str()).then_some(extension)
+}
+
+/// Why a file that needs converting can't be shown.
+pub fn unconverted(name: &str) -> String {
+  let extension = extension(name).unwrap_or_default();
+  format!("{} files are only shown with --converter {}=<COMMAND>",
+    extension.to_ascii_uppercase(), extension)
+}
+
+/// A scene file's contents, as the GLB it converts to if it needs
+/// converting. Without a converter for it, such a file can't be read.
+pub fn read(pipeline: Option<&Pipeline>, source: &IndexedSource,
+    name: &str) -> io::Result<Vec<u8>> {
+  let bytes = source.read(name)?;
+  if !needs_conversion(name) {
+    return Ok(bytes);
+  }
+  match pipeline {
+    Some(pipeline) => pipeline.convert(name, &bytes)
+      .map_err(io::Error::other),
+    None => Err(io::Error::other(unconverted(name))),
+  }
+}
+
+impl Pipeline {
+  /// A pipeline running `commands`, given as `(extension, command)`,
+  /// that keeps its results in `dir` and reports progress to `notify`.
+  pub fn new(commands: &[(String, String)], dir: PathBuf,
+      notify: impl Fn(FileEvent) + Send + Sync + 'static)
+      -> Result<Pipeline, String> {
+    let mut parsed = HashMap::new();
+    for (extension, command) in commands {
+      let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+      if !CONVERTED.contains(&extension.as_str()) {
+        return Err(format!("{} files can't be converted (only {})",
+          extension, CONVERTED.join(", ")));
+      }
+      let command: Vec<String> =
+        command.split_whitespace().map(str::to_string).collect();
+      if command.is_empty() {
+        return Err(format!("the {} converter command is empty", extension));
+      }
+      if !command.iter().any(|arg| arg.contains("{input}"))
+          || !command.iter().any(|arg| arg.contains("{output}")) {
+        return Err(format!("the {} converter command needs {{input}} and \
+          {{output}}", extension));
+      }
+      parsed.insert(extension, command);
+    }
+    Ok(Pipeline {
+      commands: parsed,
+      dir,
+      running: Mutex::default(),
+      notify: Box::new(notify),
+    })
+  }
+
+  /// Where converted files are kept.
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Delete the conversions of versions that no scene file has any
+  /// more, returning how many were.
+  pub fn sweep(&self, source: &IndexedSource) -> usize {
+    let current: HashSet<String> = source.list().unwrap_or_default().iter()
+      .filter(|name| self.converts(name))
+      .filter_map(|name| source.read(name).ok())
+      .map(|bytes| content_hash(&bytes))
+      .collect();
+    // Held so that no conversion starts or finishes during the sweep
+    let running = self.running.lock().unwrap();
+    let mut removed = 0;
+    for entry in fs::read_dir(&self.dir).into_iter().flatten().flatten() {
+      let file_name = entry.file_name();
+      let file_name = file_name.to_string_lossy();
+      // The GLB, or the input, output and log of a running conversion
+      let hash = file_name.split('.').next().unwrap_or_default();
+      if current.contains(hash) || running.contains_key(hash) {
+        continue;
+      }
+      if fs::remove_file(entry.path()).is_ok() {
+        removed += 1;
+      }
+    }
+    removed
+  }
+
+  /// Whether there's a converter for the file.
+  pub fn converts(&self, name: &str) -> bool {
+    extension(name).is_some_and(|e| self.commands.contains_key(&e))
+  }
+
+  /// The GLB a scene file converts to, converting it unless that was
+  /// done before.
+  pub fn convert(&self, name: &str, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let extension = extension(name)
+      .ok_or_else(|| format!("{} isn't converted", name))?;
+    let command = self.commands.get(&extension)
+      .ok_or_else(|| unconverted(name))?;
+    let hash = content_hash(bytes);
+    let output = self.dir.join(format!("{}.glb", hash));
+    if let Ok(glb) = fs::read(&output) {
+      return Ok(glb);
+    }
+    let lock = self.running.lock().unwrap()
+      .entry(hash.clone()).or_default().clone();
+    let converted = {
+      let _running = lock.lock().unwrap();
+      self.convert_locked(name, command, &extension, &hash, &output, bytes)
+    };
+    // Clones are only taken with the map locked, so nothing else is
+    // waiting on this hash if there are no others
+    let mut running = self.running.lock().unwrap();
+    if Arc::strong_count(&lock) == 2 {
+      running.remove(&hash);
+    }
+    converted
+  }
+
+  // Convert, with the lock for the hash held
+  fn convert_locked(&self, name: &str, command: &[String], extension: &str,
+      hash: &str, output: &Path, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    // Converted while this waited
+    if let Ok(glb) = fs::read(output) {
+      return Ok(glb);
+    }
+    fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+    // With the original extension, which tools go by
+    let input = self.dir.join(format!("{}.{}", hash, extension));
+    let temp = self.dir.join(format!("{}.tmp.glb", hash));
+    write_atomic(&input, bytes).map_err(|e| e.to_string())?;
+    let log = self.dir.join(format!("{}.log", hash));
+    let started = Instant::now();
+    (self.notify)(FileEvent::Converting {
+      filename: name.to_string(),
+      progress: None,
+    });
+    let converted = self.run(name, command, &input, &temp, &log)
+      .and_then(|()| fs::read(&temp).map_err(|e| format!(
+        "the converter wrote no {}: {}", temp.display(), e)));
+    for path in [&input, &temp, &log] {
+      let _ = fs::remove_file(path);
+    }
+    let glb = converted?;
+    if !glb.starts_with(b"glTF") {
+      return Err("the converter's output isn't a GLB file".to_string());
+    }
+    write_atomic(output, &glb).map_err(|e| e.to_string())?;
+    (self.notify)(FileEvent::Converted {
+      filename: name.to_string(),
+      duration_ms: started.elapsed().as_millis() as u64,
+    });
+    Ok(glb)
+  }
+
+  // Run the command, its output going to `log`, which is watched for
+  // progress
+  fn run(&self, name: &str, command: &[String], input: &Path, output: &Path,
+      log: &Path) -> Result<(), String> {
+    let stderr = fs::File::create(log).map_err(|e| e.to_string())?;
+    let stdout = stderr.try_clone().map_err(|e| e.to_string())?;
+    let fill = |arg: &String| arg
+      .replace("{input}", &input.to_string_lossy())
+      .replace("{output}", &output.to_string_lossy());
+    let mut child = Command::new(&command[0])
+      .args(command[1..].iter().map(fill))
+      .stdin(Stdio::null())
+      .stdout(stdout)
+      .stderr(stderr)
+      .spawn()
+      .map_err(|e| format!("can't run {}: {}", command[0], e))?;
+    let started = Instant::now();
+    let mut checked = started;
+    let mut reported: Option<f64> = None;
+    let status = loop {
+      if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+        break status;
+      }
+      if started.elapsed() > TIMEOUT {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(format!("{} took over {}s", command[0],
+          TIMEOUT.as_secs()));
+      }
+      if checked.elapsed() >= PROGRESS_INTERVAL {
+        checked = Instant::now();
+        let progress = fs::read(log).ok()
+          .and_then(|text| last_percentage(&String::from_utf8_lossy(&text)));
+        // Whole percent steps, so a chatty tool doesn't flood viewers
+        if progress.is_some_and(|p| reported.is_none_or(|r| p - r >= 0.01)) {
+          reported = progress;
+          (self.notify)(FileEvent::Converting {
+            filename: name.to_string(),
+            progress,
+          });
+        }
+      }
+      std::thread::sleep(Duration::from_millis(50));
+    };
+    if status.success() {
+      return Ok(());
+    }
+    let log = fs::read_to_string(log).unwrap_or_default();
+    let last = log.lines().rev().find(|line| !line.trim().is_empty())
+      .unwrap_or("no error output");
+    Err(format!("{} failed ({}): {}", command[0], status, last.trim()))
+  }
+}
+
+// The last "NN%" or "NN.N%" a tool printed, as a fraction
+fn last_percentage(text: &str) -> Option<f64> {
+  text.rmatch_indices('%').find_map(|(at, _)| {
+    let before = &text[..at];
+    let start = before.rfind(|c: char| !c.is_ascii_digit() && c != '.')
+      .map_or(0, |i| i + 1);
+    before[start..].parse::<f64>().ok()
+      .filter(|p| (0.0..=100.0).contains(p))
+      .map(|p| p / 100.0)
+  })
+}
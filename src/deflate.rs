@@ -0,0 +1,413 @@
+//! Minimal raw DEFLATE (RFC 1951) encoder, for compressing large
+//! WebSocket payloads. Browsers decode it natively with
+//! `DecompressionStream('deflate-raw')`.
+//!
+//! Uses greedy LZ77 matching and the fixed Huffman code, in a single
+//! block. That gets most of the win on repetitive JSON without the
+//! bookkeeping of dynamic Huffman tables.
+//!
+//! The decoder reads any stream, dynamic tables included, since it's
+//! for archives other tools wrote (3MF files are zips). It decodes a
+//! bit at a time, which is slow but small.
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier positions to try per match; trades speed for ratio
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+  3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+  67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+  0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4,
+  5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+  769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+  0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10,
+  11, 11, 12, 12, 13, 13,
+];
+
+/// Compress `data` as a raw DEFLATE stream (no zlib or gzip header).
+pub fn compress(data: &[u8]) -> Vec<u8> {
+  let mut out = BitWriter::default();
+  // One final block using the fixed Huffman code
+  out.bits(1, 1);
+  out.bits(1, 2);
+
+  let mut head = vec![usize::MAX; 1 << HASH_BITS];
+  let mut prev = vec![usize::MAX; WINDOW];
+  let mut pos = 0;
+  while pos < data.len() {
+    let (length, distance) = longest_match(data, pos, &head, &prev);
+    let step = if length >= MIN_MATCH {
+      write_match(&mut out, length, distance);
+      length
+    } else {
+      write_symbol(&mut out, data[pos] as u16);
+      1
+    };
+    // Index every position we pass, so later matches can refer to it
+    for p in pos..(pos + step) {
+      if p + MIN_MATCH <= data.len() {
+        let h = hash(&data[p..]);
+        prev[p % WINDOW] = head[h];
+        head[h] = p;
+      }
+    }
+    pos += step;
+  }
+
+  write_symbol(&mut out, 256);
+  out.finish()
+}
+
+fn hash(bytes: &[u8]) -> usize {
+  let v = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+  (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+// Longest earlier match for the bytes at `pos`, as (length, distance)
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize])
+    -> (usize, usize) {
+  if pos + MIN_MATCH > data.len() {
+    return (0, 0);
+  }
+  let max = MAX_MATCH.min(data.len() - pos);
+  let (mut best_len, mut best_dist) = (0, 0);
+  let mut candidate = head[hash(&data[pos..])];
+  for _ in 0..MAX_CHAIN {
+    if candidate == usize::MAX || pos - candidate > WINDOW - 1 {
+      break;
+    }
+    let len = data[candidate..].iter()
+      .zip(&data[pos..pos + max])
+      .take_while(|(a, b)| a == b)
+      .count();
+    if len > best_len {
+      best_len = len;
+      best_dist = pos - candidate;
+      if len == max {
+        break;
+      }
+    }
+    let next = prev[candidate % WINDOW];
+    // Chains only ever go backwards; anything else is a stale slot
+    if next >= candidate {
+      break;
+    }
+    candidate = next;
+  }
+  (best_len, best_dist)
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+  let code = LENGTH_BASE.iter().rposition(|&b| b as usize <= length).unwrap();
+  write_symbol(out, 257 + code as u16);
+  out.bits((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code]);
+
+  let code = DIST_BASE.iter().rposition(|&b| b as usize <= distance).unwrap();
+  // Distance codes are a fixed 5 bits, sent most significant bit first
+  out.huffman(code as u32, 5);
+  out.bits((distance - DIST_BASE[code] as usize) as u32, DIST_EXTRA[code]);
+}
+
+// A literal/length symbol in the fixed Huffman code (RFC 1951 3.2.6)
+fn write_symbol(out: &mut BitWriter, symbol: u16) {
+  let symbol = symbol as u32;
+  match symbol {
+    0..=143 => out.huffman(0x30 + symbol, 8),
+    144..=255 => out.huffman(0x190 + symbol - 144, 9),
+    256..=279 => out.huffman(symbol - 256, 7),
+    _ => out.huffman(0xc0 + symbol - 280, 8),
+  }
+}
+
+#[derive(Default)]
+struct BitWriter {
+  bytes: Vec<u8>,
+  current: u32,
+  filled: u8,
+}
+
+impl BitWriter {
+  // Plain values go least significant bit first
+  fn bits(&mut self, value: u32, count: u8) {
+    for i in 0..count {
+      self.current |= ((value >> i) & 1) << self.filled;
+      self.filled += 1;
+      if self.filled == 8 {
+        self.bytes.push(self.current as u8);
+        self.current = 0;
+        self.filled = 0;
+      }
+    }
+  }
+
+  // Huffman codes go most significant bit first
+  fn huffman(&mut self, code: u32, length: u8) {
+    for i in (0..length).rev() {
+      self.bits((code >> i) & 1, 1);
+    }
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    if self.filled > 0 {
+      self.bytes.push(self.current as u8);
+    }
+    self.bytes
+  }
+}
+
+// Order in which code length code lengths are sent (RFC 1951 3.2.7)
+const CODE_LENGTH_ORDER: [usize; 19] =
+  [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Most bytes one byte of DEFLATE input can stand for: a 258-byte match
+/// in two bits, less block overhead.
+pub const MAX_RATIO: usize = 1032;
+
+/// Decompress a raw DEFLATE stream (no zlib or gzip header), failing as
+/// soon as it comes to more than `max` bytes, so that a small stream
+/// can't fill memory.
+pub fn decompress(data: &[u8], max: usize) -> Result<Vec<u8>, String> {
+  let mut input = BitReader { data, pos: 0, bit: 0 };
+  let mut out = Vec::new();
+  loop {
+    let last = input.bits(1)? == 1;
+    match input.bits(2)? {
+      0 => {
+        input.align();
+        let length = input.bits(16)?;
+        if input.bits(16)? != !length & 0xffff {
+          return Err("stored block length is corrupt".to_string());
+        }
+        let bytes = data.get(input.pos..input.pos + length as usize)
+          .ok_or_else(|| "DEFLATE stream ends early".to_string())?;
+        if out.len() + bytes.len() > max {
+          return Err(too_large(max));
+        }
+        out.extend_from_slice(bytes);
+        input.pos += length as usize;
+      }
+      1 => {
+        let (literals, distances) = fixed_codes();
+        inflate_block(&mut input, &mut out, max, &literals, &distances)?;
+      }
+      2 => {
+        let (literals, distances) = dynamic_codes(&mut input)?;
+        inflate_block(&mut input, &mut out, max, &literals, &distances)?;
+      }
+      _ => return Err("invalid DEFLATE block type".to_string()),
+    }
+    if last {
+      return Ok(out);
+    }
+  }
+}
+
+fn too_large(max: usize) -> String {
+  format!("DEFLATE stream inflates to more than {} bytes", max)
+}
+
+fn inflate_block(input: &mut BitReader, out: &mut Vec<u8>, max: usize,
+    literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+  loop {
+    let symbol = literals.decode(input)? as usize;
+    match symbol {
+      0..=255 if out.len() == max => return Err(too_large(max)),
+      0..=255 => out.push(symbol as u8),
+      256 => return Ok(()),
+      _ => {
+        let code = symbol - 257;
+        if code >= LENGTH_BASE.len() {
+          return Err(format!("invalid length symbol {}", symbol));
+        }
+        let length = LENGTH_BASE[code] as usize
+          + input.bits(LENGTH_EXTRA[code] as u32)? as usize;
+        let code = distances.decode(input)? as usize;
+        if code >= DIST_BASE.len() {
+          return Err(format!("invalid distance code {}", code));
+        }
+        let distance = DIST_BASE[code] as usize
+          + input.bits(DIST_EXTRA[code] as u32)? as usize;
+        if distance > out.len() {
+          return Err("distance reaches before the start".to_string());
+        }
+        if out.len() + length > max {
+          return Err(too_large(max));
+        }
+        // Byte by byte, as a match may overlap what it produces
+        for _ in 0..length {
+          out.push(out[out.len() - distance]);
+        }
+      }
+    }
+  }
+}
+
+// The fixed literal/length and distance codes (RFC 1951 3.2.6)
+fn fixed_codes() -> (Huffman, Huffman) {
+  let mut lengths = [0; 288];
+  lengths[..144].fill(8);
+  lengths[144..256].fill(9);
+  lengths[256..280].fill(7);
+  lengths[280..].fill(8);
+  (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+// The codes a dynamic block starts with (RFC 1951 3.2.7)
+fn dynamic_codes(input: &mut BitReader)
+    -> Result<(Huffman, Huffman), String> {
+  let literal_count = input.bits(5)? as usize + 257;
+  let distance_count = input.bits(5)? as usize + 1;
+  let length_count = input.bits(4)? as usize + 4;
+  let mut length_lengths = [0; 19];
+  for &i in &CODE_LENGTH_ORDER[..length_count] {
+    length_lengths[i] = input.bits(3)? as u8;
+  }
+  let length_code = Huffman::new(&length_lengths);
+
+  let total = literal_count + distance_count;
+  let mut lengths: Vec<u8> = Vec::with_capacity(total);
+  while lengths.len() < total {
+    let (length, repeat) = match length_code.decode(input)? {
+      symbol @ 0..=15 => (symbol as u8, 1),
+      16 => {
+        let previous = *lengths.last()
+          .ok_or_else(|| "length repeated before any".to_string())?;
+        (previous, 3 + input.bits(2)?)
+      }
+      17 => (0, 3 + input.bits(3)?),
+      _ => (0, 11 + input.bits(7)?),
+    };
+    lengths.extend(std::iter::repeat_n(length, repeat as usize));
+  }
+  if lengths.len() > total {
+    return Err("code lengths overrun their count".to_string());
+  }
+  Ok((Huffman::new(&lengths[..literal_count]),
+    Huffman::new(&lengths[literal_count..])))
+}
+
+// A canonical Huffman code, as the number of codes of each length and
+// the symbols in code order
+struct Huffman {
+  counts: [u16; 16],
+  symbols: Vec<u16>,
+}
+
+impl Huffman {
+  fn new(lengths: &[u8]) -> Huffman {
+    let mut counts = [0; 16];
+    for &length in lengths {
+      counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut offsets = [0; 16];
+    for length in 1..15 {
+      offsets[length + 1] = offsets[length] + counts[length];
+    }
+    let mut symbols = vec![0; lengths.len()];
+    for (symbol, &length) in lengths.iter().enumerate() {
+      if length != 0 {
+        symbols[offsets[length as usize] as usize] = symbol as u16;
+        offsets[length as usize] += 1;
+      }
+    }
+    Huffman { counts, symbols }
+  }
+
+  // Codes are read a bit at a time, most significant bit first
+  fn decode(&self, input: &mut BitReader) -> Result<u16, String> {
+    let (mut code, mut first, mut index) = (0, 0, 0);
+    for length in 1..16 {
+      code |= input.bits(1)? as i32;
+      let count = self.counts[length] as i32;
+      if code - first < count {
+        return Ok(self.symbols[(index + code - first) as usize]);
+      }
+      index += count;
+      first = (first + count) << 1;
+      code <<= 1;
+    }
+    Err("invalid Huffman code".to_string())
+  }
+}
+
+struct BitReader<'a> {
+  data: &'a [u8],
+  pos: usize,
+  bit: u8,
+}
+
+impl BitReader<'_> {
+  // Plain values come least significant bit first
+  fn bits(&mut self, count: u32) -> Result<u32, String> {
+    let mut value = 0;
+    for i in 0..count {
+      let byte = *self.data.get(self.pos)
+        .ok_or_else(|| "DEFLATE stream ends early".to_string())?;
+      value |= ((byte >> self.bit) as u32 & 1) << i;
+      self.bit += 1;
+      if self.bit == 8 {
+        self.bit = 0;
+        self.pos += 1;
+      }
+    }
+    Ok(value)
+  }
+
+  // Stored blocks start on a byte boundary
+  fn align(&mut self) {
+    if self.bit > 0 {
+      self.bit = 0;
+      self.pos += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips() {
+    let json = br#"{"type":"modified","filename":"a.obj"}"#.repeat(200);
+    let mut noise = Vec::new();
+    let mut x: u32 = 1;
+    for _ in 0..5000 {
+      x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+      noise.push((x >> 16) as u8);
+    }
+    for data in [&b""[..], b"a", &json, &noise] {
+      let compressed = compress(data);
+      assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+    assert!(compress(&json).len() < json.len() / 10);
+  }
+
+  #[test]
+  fn stops_at_the_limit() {
+    let data = vec![b'x'; 10_000];
+    let compressed = compress(&data);
+    assert!(decompress(&compressed, data.len() - 1).is_err());
+  }
+
+  #[test]
+  fn rejects_malformed_streams() {
+    let compressed = compress(b"hello hello hello hello");
+    assert!(decompress(&compressed[..compressed.len() / 2], 100).is_err());
+    // Block type 3 is reserved
+    assert!(decompress(&[0b111], 100).is_err());
+    // A stored block whose length check doesn't match
+    assert!(decompress(&[1, 5, 0, 5, 0, b'a'], 100).is_err());
+    assert!(decompress(&[], 100).is_err());
+  }
+}
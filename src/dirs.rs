@@ -0,0 +1,102 @@
+//! Where the server keeps its own files, outside the scene directory:
+//! scene directories are often generator output that gets wiped, and
+//! history or screenshots shouldn't go with them.
+//!
+//! - Linux and other Unixes: the XDG base directories
+//!   (`$XDG_CONFIG_HOME`, `$XDG_DATA_HOME`, `$XDG_CACHE_HOME`, falling
+//!   back to `~/.config`, `~/.local/share` and `~/.cache`)
+//! - macOS: `~/Library/Application Support` and `~/Library/Caches`
+//! - Windows: `%APPDATA%` and `%LOCALAPPDATA%`
+//!
+//! Each directory gets a `kitbash-viewer` folder. Per-scene data goes in
+//! `<data>/scenes/<key>`, with the key derived from the scene's path,
+//! and per-scene caches, such as converted files, in `<cache>/scenes/<key>`.
+
+use crate::cache::content_hash;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const APP: &str = "kitbash-viewer";
+
+/// Where the config file lives.
+pub fn config_dir() -> Option<PathBuf> {
+  base(Kind::Config).map(|dir| dir.join(APP))
+}
+
+/// Where history and screenshots go, under `scenes/<key>`.
+pub fn data_dir() -> Option<PathBuf> {
+  base(Kind::Data).map(|dir| dir.join(APP))
+}
+
+/// Where anything that can be rebuilt goes.
+pub fn cache_dir() -> Option<PathBuf> {
+  base(Kind::Cache).map(|dir| dir.join(APP))
+}
+
+/// Data folder of one scene directory, None if there's no home
+/// directory to put it in.
+pub fn scene_data_dir(scene_dir: &Path) -> Option<PathBuf> {
+  data_dir().map(|dir| dir.join("scenes").join(scene_key(scene_dir)))
+}
+
+/// Cache folder of one scene directory, None if there's no home
+/// directory to put it in.
+pub fn scene_cache_dir(scene_dir: &Path) -> Option<PathBuf> {
+  cache_dir().map(|dir| dir.join("scenes").join(scene_key(scene_dir)))
+}
+
+/// The scene directory's name plus a hash of its full path, e.g.
+/// `scene-1f2e3d4c5b6a7980`, readable yet distinct for same-named
+/// scenes.
+pub fn scene_key(scene_dir: &Path) -> String {
+  let path = fs::canonicalize(scene_dir)
+    .unwrap_or_else(|_| scene_dir.to_path_buf());
+  let name: String = path.file_name()
+    .map(|n| n.to_string_lossy().into_owned())
+    .unwrap_or_default()
+    .chars()
+    .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+    .collect();
+  let hash = content_hash(path.to_string_lossy().as_bytes());
+  if name.is_empty() { hash } else { format!("{}-{}", name, hash) }
+}
+
+enum Kind {
+  Config,
+  Data,
+  Cache,
+}
+
+#[cfg(windows)]
+fn base(kind: Kind) -> Option<PathBuf> {
+  let var = match kind {
+    Kind::Config | Kind::Data => "APPDATA",
+    Kind::Cache => "LOCALAPPDATA",
+  };
+  absolute_var(var)
+}
+
+#[cfg(target_os = "macos")]
+fn base(kind: Kind) -> Option<PathBuf> {
+  let home = absolute_var("HOME")?;
+  Some(match kind {
+    Kind::Config | Kind::Data => home.join("Library/Application Support"),
+    Kind::Cache => home.join("Library/Caches"),
+  })
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn base(kind: Kind) -> Option<PathBuf> {
+  let (var, fallback) = match kind {
+    Kind::Config => ("XDG_CONFIG_HOME", ".config"),
+    Kind::Data => ("XDG_DATA_HOME", ".local/share"),
+    Kind::Cache => ("XDG_CACHE_HOME", ".cache"),
+  };
+  absolute_var(var).or_else(|| absolute_var("HOME").map(|h| h.join(fallback)))
+}
+
+// Relative paths in these variables are invalid per the XDG spec
+fn absolute_var(name: &str) -> Option<PathBuf> {
+  env::var_os(name).map(PathBuf::from).filter(|path| path.is_absolute())
+}
@@ -0,0 +1,346 @@
+//! Server-side DXF parsing, for architectural parts exported from CAD.
+//! three.js has no DXF loader, so the viewer shows what this reads, as
+//! the GLB from `/api/files/:name/mesh.glb`.
+//!
+//! Only ASCII DXF is read, and only the surfaces in its ENTITIES
+//! section: `3DFACE`s, and `POLYLINE`s that are polyface or polygon
+//! meshes. Lines, 2D polylines, ACIS solids and block `INSERT`s are
+//! skipped. Each layer becomes a named object, and the drawing's Z-up
+//! axes are turned Y-up, as the viewer expects.
+
+use crate::mesh::{self, Mesh, SubObject};
+use std::collections::HashMap;
+
+const BINARY_SENTINEL: &[u8] = b"AutoCAD Binary DXF";
+
+// POLYLINE flags, group 70
+const CLOSED_M: i64 = 1;
+const POLYGON_MESH: i64 = 16;
+const CLOSED_N: i64 = 32;
+const POLYFACE_MESH: i64 = 64;
+// VERTEX flags, group 70
+const CONTROL_POINT: i64 = 16;
+const MESH_VERTEX: i64 = 64;
+const POLYFACE_VERTEX: i64 = 128;
+
+/// An entity's group codes and values, from its `0` group up to the
+/// next.
+struct Entity<'a> {
+  kind: &'a str,
+  /// Line of the `0` group, for errors
+  line: usize,
+  groups: Vec<(i32, &'a str)>,
+}
+
+impl Entity<'_> {
+  fn get(&self, code: i32) -> Option<&str> {
+    self.groups.iter().find(|(c, _)| *c == code).map(|(_, value)| *value)
+  }
+
+  // A number, which DXF takes to be 0 if it's left out
+  fn number(&self, code: i32) -> Result<f64, String> {
+    match self.get(code) {
+      Some(value) => value.parse().map_err(|_| format!("line {}: {} has \
+        invalid number '{}' for group {}", self.line, self.kind, value, code)),
+      None => Ok(0.0),
+    }
+  }
+
+  fn int(&self, code: i32) -> Result<i64, String> {
+    self.number(code).map(|n| n as i64)
+  }
+
+  // The point in groups `code`, `code + 10` and `code + 20`, turned Y-up
+  fn point(&self, code: i32) -> Result<[f64; 3], String> {
+    let [x, y, z] =
+      [self.number(code)?, self.number(code + 10)?, self.number(code + 20)?];
+    Ok([x, z, -y])
+  }
+
+  fn layer(&self) -> &str {
+    self.get(8).unwrap_or("0")
+  }
+}
+
+/// Whether the contents look like a DXF file: an ASCII one starting
+/// with a section, or a binary one, which is then refused.
+pub fn is_dxf(bytes: &[u8]) -> bool {
+  if bytes.starts_with(BINARY_SENTINEL) {
+    return true;
+  }
+  let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+  let mut lines = head.lines().map(str::trim);
+  loop {
+    match (lines.next(), lines.next()) {
+      // Comments may come first
+      (Some("999"), Some(_)) => continue,
+      (Some("0"), Some("SECTION")) => return true,
+      _ => return false,
+    }
+  }
+}
+
+/// Parse an ASCII DXF drawing's meshes into an object per layer.
+pub fn parse_dxf(bytes: &[u8]) -> Result<Mesh, String> {
+  if bytes.starts_with(BINARY_SENTINEL) {
+    return Err("binary DXF isn't supported; save the drawing as ASCII DXF"
+      .to_string());
+  }
+  let text = String::from_utf8_lossy(bytes);
+  let entities = entities(&text)?;
+
+  let mut positions = Vec::new();
+  let mut layers = Layers::default();
+  let mut entities = entities.iter();
+  while let Some(entity) = entities.next() {
+    match entity.kind {
+      "3DFACE" => {
+        let corners = [entity.point(10)?, entity.point(11)?,
+          entity.point(12)?, entity.point(13)?];
+        let base = positions.len();
+        // A triangle repeats its third corner as the fourth
+        let count = if corners[3] == corners[2] { 3 } else { 4 };
+        positions.extend_from_slice(&corners[..count]);
+        let face: Vec<usize> = (base..base + count).collect();
+        layers.add(entity.layer(), &face);
+      }
+      "POLYLINE" => {
+        let mut vertices = Vec::new();
+        for vertex in entities.by_ref() {
+          match vertex.kind {
+            "VERTEX" => vertices.push(vertex),
+            "SEQEND" => break,
+            _ => return Err(format!("line {}: {} inside a POLYLINE",
+              vertex.line, vertex.kind)),
+          }
+        }
+        polyline(entity, &vertices, &mut positions, &mut layers)?;
+      }
+      _ => {}
+    }
+  }
+  let mesh = layers.into_mesh(positions);
+  mesh::check_size(&mesh)?;
+  Ok(mesh)
+}
+
+// The ENTITIES section's entities
+fn entities(text: &str) -> Result<Vec<Entity<'_>>, String> {
+  let mut lines = text.lines().enumerate();
+  let mut entities = Vec::new();
+  let mut section: Option<&str> = None;
+  let mut current: Option<Entity> = None;
+  while let Some((index, code)) = lines.next() {
+    let line = index + 1;
+    let code: i32 = code.trim().parse()
+      .map_err(|_| format!("line {}: invalid group code '{}'", line,
+        code.trim()))?;
+    let (_, value) = lines.next()
+      .ok_or_else(|| format!("line {}: group {} has no value", line, code))?;
+    let value = value.trim();
+    if code != 0 {
+      match (&mut current, section) {
+        (Some(entity), _) => entity.groups.push((code, value)),
+        // The section's name
+        (None, Some("")) if code == 2 => section = Some(value),
+        _ => {}
+      }
+      continue;
+    }
+    if let Some(entity) = current.take() {
+      entities.push(entity);
+    }
+    match value {
+      "SECTION" => section = Some(""),
+      "ENDSEC" => section = None,
+      "EOF" => break,
+      kind if section == Some("ENTITIES") =>
+        current = Some(Entity { kind, line, groups: Vec::new() }),
+      _ => {}
+    }
+  }
+  Ok(entities)
+}
+
+// A polyline's faces, if it's a mesh
+fn polyline(polyline: &Entity, vertices: &[&Entity],
+    positions: &mut Vec<[f64; 3]>, layers: &mut Layers)
+    -> Result<(), String> {
+  let flags = polyline.int(70)?;
+  let base = positions.len();
+  if flags & POLYFACE_MESH != 0 {
+    // Vertices first, then face records of 1-based indices, negative
+    // for hidden edges and 0 for unused corners
+    let mut count = 0;
+    for vertex in vertices {
+      let vertex_flags = vertex.int(70)?;
+      if vertex_flags & POLYFACE_VERTEX == 0 {
+        continue;
+      }
+      if vertex_flags & MESH_VERTEX != 0 {
+        positions.push(vertex.point(10)?);
+        count += 1;
+        continue;
+      }
+      let mut face = Vec::new();
+      for code in 71..=74 {
+        let index = vertex.int(code)?.unsigned_abs() as usize;
+        if index == 0 {
+          continue;
+        }
+        if index > count {
+          return Err(format!("line {}: polyface face refers to vertex {} \
+            of {}", vertex.line, index, count));
+        }
+        face.push(base + index - 1);
+      }
+      layers.add(polyline.layer(), &face);
+    }
+  } else if flags & POLYGON_MESH != 0 {
+    // An M by N grid of vertices, row by row
+    let size = |code: i32| -> Result<usize, String> {
+      let size = polyline.int(code)?;
+      usize::try_from(size).ok().filter(|&size| size > 0)
+        .ok_or_else(|| format!("line {}: polygon mesh has size {} in group \
+          {}", polyline.line, size, code))
+    };
+    let (m, n) = (size(71)?, size(72)?);
+    for vertex in vertices {
+      if vertex.int(70)? & CONTROL_POINT == 0 {
+        positions.push(vertex.point(10)?);
+      }
+    }
+    if m.checked_mul(n) != Some(positions.len() - base) {
+      return Err(format!("line {}: polygon mesh has {} vertices, not {} by \
+        {}", polyline.line, positions.len() - base, m, n));
+    }
+    let rows = if flags & CLOSED_M != 0 { m } else { m.saturating_sub(1) };
+    let columns = if flags & CLOSED_N != 0 { n } else { n.saturating_sub(1) };
+    let at = |i: usize, j: usize| base + (i % m) * n + j % n;
+    for i in 0..rows {
+      for j in 0..columns {
+        layers.add(polyline.layer(),
+          &[at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j + 1)]);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Triangles by layer, in the order layers first appear.
+#[derive(Default)]
+struct Layers {
+  names: Vec<String>,
+  triangles: Vec<Vec<[usize; 3]>>,
+  index: HashMap<String, usize>,
+}
+
+impl Layers {
+  // Fan-triangulate a face onto its layer, dropping collapsed triangles
+  fn add(&mut self, layer: &str, face: &[usize]) {
+    let index = *self.index.entry(layer.to_string()).or_insert_with(|| {
+      self.names.push(layer.to_string());
+      self.triangles.push(Vec::new());
+      self.names.len() - 1
+    });
+    for i in 1..face.len().saturating_sub(1) {
+      let [a, b, c] = [face[0], face[i], face[i + 1]];
+      if a != b && b != c && a != c {
+        self.triangles[index].push([a, b, c]);
+      }
+    }
+  }
+
+  fn into_mesh(self, positions: Vec<[f64; 3]>) -> Mesh {
+    let mut triangles = Vec::new();
+    let mut objects = Vec::new();
+    for (name, layer) in self.names.into_iter().zip(self.triangles) {
+      if layer.is_empty() {
+        continue;
+      }
+      let start = triangles.len();
+      triangles.extend(layer);
+      objects.push(SubObject { name, triangles: start..triangles.len() });
+    }
+    Mesh { positions, triangles, objects, ..Mesh::default() }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Group code and value pairs, a line each
+  fn dxf(groups: &[(i32, &str)]) -> Vec<u8> {
+    let mut text = String::from("0\nSECTION\n2\nENTITIES\n");
+    for (code, value) in groups {
+      text.push_str(&format!("{}\n{}\n", code, value));
+    }
+    text.push_str("0\nENDSEC\n0\nEOF\n");
+    text.into_bytes()
+  }
+
+  #[test]
+  fn parses_faces_and_meshes() {
+    let bytes = dxf(&[
+      (0, "3DFACE"), (8, "Walls"),
+      (10, "0"), (20, "0"), (30, "0"),
+      (11, "1"), (21, "0"), (31, "0"),
+      (12, "1"), (22, "0"), (32, "2"),
+      (13, "0"), (23, "0"), (33, "2"),
+      // A triangle repeats its third corner
+      (0, "3DFACE"), (8, "Roof"),
+      (10, "0"), (20, "0"), (30, "2"),
+      (11, "1"), (21, "0"), (31, "2"),
+      (12, "0.5"), (22, "0"), (32, "3"),
+      (13, "0.5"), (23, "0"), (33, "3"),
+      // A polyface mesh of one quad
+      (0, "POLYLINE"), (8, "Floor"), (66, "1"), (70, "64"),
+      (0, "VERTEX"), (10, "0"), (20, "0"), (70, "192"),
+      (0, "VERTEX"), (10, "1"), (20, "0"), (70, "192"),
+      (0, "VERTEX"), (10, "1"), (20, "1"), (70, "192"),
+      (0, "VERTEX"), (10, "0"), (20, "1"), (70, "192"),
+      (0, "VERTEX"), (70, "128"), (71, "1"), (72, "2"), (73, "-3"),
+      (74, "4"),
+      (0, "SEQEND"),
+    ]);
+    assert!(is_dxf(&bytes));
+    let mesh = parse_dxf(&bytes).unwrap();
+    let objects: Vec<(&str, usize)> = mesh.objects.iter()
+      .map(|o| (o.name.as_str(), o.triangles.len()))
+      .collect();
+    assert_eq!(objects, [("Walls", 2), ("Roof", 1), ("Floor", 2)]);
+    // Z-up drawings are turned Y-up
+    assert!(mesh.positions.contains(&[1.0, 2.0, -0.0]));
+    assert!(mesh.positions.contains(&[0.0, 0.0, -1.0]));
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    // Binary files are recognized, to be refused
+    assert!(is_dxf(b"AutoCAD Binary DXF\r\n\x1a\0"));
+    assert!(parse_dxf(b"AutoCAD Binary DXF\r\n\x1a\0").is_err());
+    assert!(parse_dxf(&dxf(&[(0, "3DFACE"), (10, "one")])).is_err());
+    // A code without its value
+    assert!(parse_dxf(b"0\nSECTION\n2\nENTITIES\n0\n").is_err());
+    // A polyface face using a vertex that doesn't exist
+    assert!(parse_dxf(&dxf(&[
+      (0, "POLYLINE"), (66, "1"), (70, "64"),
+      (0, "VERTEX"), (10, "0"), (20, "0"), (70, "192"),
+      (0, "VERTEX"), (70, "128"), (71, "1"), (72, "5"), (73, "9"),
+      (0, "SEQEND"),
+    ])).is_err());
+    // A polygon mesh whose negative sizes multiply to its vertex count
+    let grid = |m: &'static str, n: &'static str| dxf(&[
+      (0, "POLYLINE"), (66, "1"), (70, "16"), (71, m), (72, n),
+      (0, "VERTEX"), (10, "0"), (20, "0"), (70, "64"),
+      (0, "VERTEX"), (10, "1"), (20, "0"), (70, "64"),
+      (0, "VERTEX"), (10, "0"), (20, "1"), (70, "64"),
+      (0, "VERTEX"), (10, "1"), (20, "1"), (70, "64"),
+      (0, "SEQEND"),
+    ]);
+    assert_eq!(parse_dxf(&grid("2", "2")).unwrap().triangles.len(), 2);
+    assert!(parse_dxf(&grid("-2", "-2")).is_err());
+    assert!(parse_dxf(&grid("0", "4")).is_err());
+  }
+}
@@ -0,0 +1,263 @@
+//! Scene change events, broadcast to WebSocket clients as JSON.
+
+use crate::{git, lock, materials};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FileEvent {
+  Added {
+    filename: String,
+    #[serde(flatten)]
+    change: Change,
+  },
+  Modified {
+    filename: String,
+    #[serde(flatten)]
+    change: Change,
+  },
+  Removed  { filename: String },
+  /// The file changed but another program still has it open for
+  /// writing, e.g. mid-export. `added` or `modified` follows once it's
+  /// closed.
+  Busy     { filename: String },
+  ManifestChanged,
+  ScaleWarning {
+    filename: String,
+    /// Largest dimension of the file's bounding box
+    size: f64,
+    /// Factors that would bring the mesh into the expected range
+    suggested_scales: Vec<f64>,
+  },
+  /// The server failed to process a file, so viewers can flag it
+  /// without loading it themselves. Cleared by the next `added`,
+  /// `modified` or `removed` for the file.
+  Error {
+    code: EventErrorCode,
+    /// Absent for errors not about one file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    message: String,
+  },
+  /// A command for the viewers, from `POST /api/control`
+  Control(ControlCommand),
+  /// The git branch or some files' git status changed. Only files whose
+  /// status changed are listed.
+  GitStatus {
+    branch: Option<String>,
+    files: BTreeMap<String, git::FileStatus>,
+  },
+  /// Someone claimed, renewed or released the scene lock. A lock that
+  /// runs out isn't announced; clients go by its `expires`.
+  LockChanged { lock: Option<lock::Lock> },
+  /// Someone ignored a file, or stopped ignoring it. Viewers drop an
+  /// ignored file and hear nothing more about it until it's un-ignored,
+  /// when they load it again.
+  IgnoreChanged { filename: String, ignored: bool },
+  /// The file is being converted for display by `--converter`, which
+  /// can take minutes for CAD files. Sent as the converter starts, then
+  /// whenever it reports more progress.
+  Converting {
+    filename: String,
+    /// Done so far, 0 to 1, if the converter prints percentages
+    progress: Option<f64>,
+  },
+  /// The file's conversion finished; loading it now won't wait. A
+  /// failed one is reported as an `error` instead.
+  Converted { filename: String, duration_ms: u64 },
+  /// Changes made together by `POST /api/batch`, in order. Viewers
+  /// apply them as if sent one by one; none of them is sent on its own.
+  Batch { events: Vec<FileEvent> },
+}
+
+/// An event as broadcast, stamped when the server sent it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StampedEvent {
+  /// Counts up from 1 in each server run (tell runs apart by the
+  /// `server_id` of `/api/state`), so clients can order events and drop
+  /// ones they've seen
+  pub id: u64,
+  /// Milliseconds since the Unix epoch
+  pub time: u64,
+  #[serde(flatten)]
+  pub event: FileEvent,
+}
+
+/// The new version of an added or modified file, so viewers can mark
+/// recent changes and skip reloading contents they already have. Empty
+/// where the server didn't look, e.g. for files it wrote itself.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+  /// Milliseconds since the Unix epoch
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mtime: Option<u64>,
+  /// Bytes gained since the previous version, negative if it shrank;
+  /// the whole size for an added file
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub size_delta: Option<i64>,
+  /// Content hash, as in listings
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hash: Option<String>,
+}
+
+/// What an `error` event is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventErrorCode {
+  /// The file isn't a valid OBJ
+  ParseFailed,
+  /// The file couldn't be converted for an export
+  TranscodeFailed,
+  /// The file couldn't be converted to GLB with `--converter`
+  ConvertFailed,
+  /// The scene manifest was changed on disk into something the server
+  /// can't use; the message names the first problem and where it is.
+  /// Cleared by the next `manifest_changed`.
+  ManifestInvalid,
+  /// A code from a newer server
+  #[serde(other)]
+  Unknown,
+}
+
+/// Commands that drive connected viewers remotely.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+  /// Select a file, or clear the selection
+  Select { file: Option<String> },
+  /// Point the camera at these files (all visible ones if empty),
+  /// keeping the current viewing direction
+  Frame {
+    #[serde(default)]
+    files: Vec<String>,
+  },
+  /// Hide these files, or show them again
+  Hide {
+    files: Vec<String>,
+    #[serde(default = "default_hidden")]
+    hidden: bool,
+  },
+  /// One of the standard views (`front`, `back`, `left`, `right`, `top`,
+  /// `bottom`), or an explicit camera position and look-at target
+  SetView {
+    view: Option<String>,
+    position: Option<[f64; 3]>,
+    target: Option<[f64; 3]>,
+  },
+  /// Capture the canvas and upload it to `/api/screenshots`
+  Screenshot { name: Option<String> },
+  /// Put the view back as it was saved in a snapshot
+  Restore(ViewState),
+  /// Drop every file and load the scene again, e.g. after the watcher
+  /// missed changes
+  ReloadAll,
+  /// Shade the scene with a set from `/api/material-sets`, or with the
+  /// files' own materials if `name` is absent. The server remembers the
+  /// choice and sends viewers the set itself.
+  MaterialSet {
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    set: Option<materials::MaterialSet>,
+  },
+}
+
+/// What a viewer shows, as saved in a snapshot.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ViewState {
+  /// Files that are hidden; all others are shown
+  #[serde(default)]
+  pub hidden: Vec<String>,
+  #[serde(default)]
+  pub selected: Option<String>,
+  #[serde(default)]
+  pub camera: Option<Camera>,
+  #[serde(default)]
+  pub clipping_planes: Vec<ClippingPlane>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Camera {
+  pub position: [f64; 3],
+  /// The point the camera orbits around
+  pub target: [f64; 3],
+}
+
+/// Points with `normal · p + constant < 0` are clipped away.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ClippingPlane {
+  pub normal: [f64; 3],
+  pub constant: f64,
+}
+
+fn default_hidden() -> bool {
+  true
+}
+
+impl FileEvent {
+  /// Every event type, as in the `type` field.
+  pub const TYPES: &'static [&'static str] = &["added", "modified", "removed",
+    "busy", "manifest_changed", "scale_warning", "error", "control",
+    "git_status", "lock_changed", "ignore_changed", "converting",
+    "converted", "batch"];
+
+  /// `added`, without change details.
+  pub fn added(filename: impl Into<String>) -> FileEvent {
+    FileEvent::Added { filename: filename.into(), change: Change::default() }
+  }
+
+  /// `modified`, without change details.
+  pub fn modified(filename: impl Into<String>) -> FileEvent {
+    FileEvent::Modified {
+      filename: filename.into(),
+      change: Change::default(),
+    }
+  }
+
+  /// The event's type, as in its `type` field.
+  pub fn kind(&self) -> &'static str {
+    match self {
+      FileEvent::Added { .. } => "added",
+      FileEvent::Modified { .. } => "modified",
+      FileEvent::Removed { .. } => "removed",
+      FileEvent::Busy { .. } => "busy",
+      FileEvent::ManifestChanged => "manifest_changed",
+      FileEvent::ScaleWarning { .. } => "scale_warning",
+      FileEvent::Error { .. } => "error",
+      FileEvent::Control(_) => "control",
+      FileEvent::GitStatus { .. } => "git_status",
+      FileEvent::LockChanged { .. } => "lock_changed",
+      FileEvent::IgnoreChanged { .. } => "ignore_changed",
+      FileEvent::Converting { .. } => "converting",
+      FileEvent::Converted { .. } => "converted",
+      FileEvent::Batch { .. } => "batch",
+    }
+  }
+
+  pub fn filename(&self) -> Option<&str> {
+    match self {
+      FileEvent::Added { filename, .. }
+      | FileEvent::Modified { filename, .. }
+      | FileEvent::Removed { filename }
+      | FileEvent::Busy { filename }
+      | FileEvent::ScaleWarning { filename, .. }
+      | FileEvent::IgnoreChanged { filename, .. }
+      | FileEvent::Converting { filename, .. }
+      | FileEvent::Converted { filename, .. } => Some(filename),
+      FileEvent::Error { filename, .. } => filename.as_deref(),
+      FileEvent::ManifestChanged
+      | FileEvent::Control(_)
+      | FileEvent::GitStatus { .. }
+      | FileEvent::LockChanged { .. }
+      | FileEvent::Batch { .. } => None,
+    }
+  }
+
+  /// The events of a batch, or this event on its own.
+  pub fn into_events(self) -> Vec<FileEvent> {
+    match self {
+      FileEvent::Batch { events } => events,
+      event => vec![event],
+    }
+  }
+}
@@ -0,0 +1,253 @@
+//! Copies of the scene that need no server process behind them:
+//!
+//! - `export-site`: a folder any static web server can host
+//! - `export-html`: one HTML file, meshes and all, to send to someone
+//!
+//! Each OBJ file is baked to GLB, after its `[pipeline]` steps if it
+//! has any; GLB files without steps are copied as they are. The viewer
+//! page runs from the listing, manifest and config embedded in it.
+
+use crate::viewer_html::{self, THREE_CDN, THREE_FILES};
+use kitbash_viewer::manifest::{self, Manifest, Transform, MANIFEST_FILE};
+use kitbash_viewer::pipeline::Pipelines;
+use kitbash_viewer::source::{DirSource, SceneSource};
+use kitbash_viewer::{cache, glb, http, mesh};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// A scene file ready to export
+struct Baked {
+  name: String,
+  glb: Vec<u8>,
+  /// The file's entry in the embedded listing
+  info: Value,
+}
+
+/// Write the scene in `scene_dir` to `out` as a static site. `config`
+/// is what the viewer would get from `/api/config`; `three` is a
+/// three.js 0.160 package to copy in, so the site doesn't need the CDN.
+/// Returns false if nothing could be exported.
+pub fn site(scene_dir: &Path, out: &Path, config: Value,
+    pipelines: &Pipelines, three: Option<&Path>) -> bool {
+  let Some((baked, manifest)) = bake(scene_dir, pipelines) else {
+    return false
+  };
+  let meshes = out.join("meshes");
+  if let Err(e) = fs::create_dir_all(&meshes) {
+    eprintln!("Can't create {:?}: {}", meshes, e);
+    return false;
+  }
+
+  let mut urls = serde_json::Map::new();
+  let mut bytes = 0;
+  for file in &baked {
+    let glb_name = format!("{}.glb",
+      file.name.strip_suffix(".obj").unwrap_or(&file.name));
+    if let Err(e) = fs::write(meshes.join(&glb_name), &file.glb) {
+      eprintln!("Can't write {}: {}", glb_name, e);
+      return false;
+    }
+    bytes += file.glb.len();
+    urls.insert(file.name.clone(),
+      format!("meshes/{}", http::encode_path(&glb_name)).into());
+  }
+
+  let three_base = match three {
+    Some(package) => match copy_three(package, &out.join("three")) {
+      Ok(()) => Some("./three/"),
+      Err(e) => {
+        eprintln!("Can't copy three.js from {:?}: {}", package, e);
+        return false;
+      }
+    },
+    None => None,
+  };
+  let page = page(&static_scene(&baked, &manifest, config, urls.into()),
+    three_base);
+  let written = fs::write(out.join("index.html"), page)
+    .and_then(|()| fs::write(out.join(MANIFEST_FILE),
+      serde_json::to_vec_pretty(&manifest).unwrap()));
+  if let Err(e) = written {
+    eprintln!("Can't write to {:?}: {}", out, e);
+    return false;
+  }
+
+  println!("Exported {} file(s) to {:?} ({:.1} MB of meshes)",
+    baked.len(), out, bytes as f64 / (1 << 20) as f64);
+  if three_base.is_none() {
+    println!("The page loads three.js from {}; pass --three to copy a \
+      local package instead", THREE_CDN);
+  }
+  true
+}
+
+/// Write the scene in `scene_dir` into the single HTML file `out`, with
+/// the meshes inline. Refuses past `max_bytes`, since the point is a
+/// file small enough to send. Returns false if nothing was written.
+pub fn html(scene_dir: &Path, out: &Path, config: Value,
+    pipelines: &Pipelines, max_bytes: u64) -> bool {
+  let Some((baked, manifest)) = bake(scene_dir, pipelines) else {
+    return false
+  };
+  let mut urls = serde_json::Map::new();
+  for file in &baked {
+    urls.insert(file.name.clone(), format!("data:model/gltf-binary;base64,{}",
+      encode_base64(&file.glb)).into());
+  }
+  let page = page(&static_scene(&baked, &manifest, config, urls.into()),
+    None);
+
+  let size = page.len() as u64;
+  let mb = |bytes: u64| bytes as f64 / (1 << 20) as f64;
+  if size > max_bytes {
+    eprintln!("The page would be {:.1} MB, over the {:.1} MB limit; export \
+      fewer files, or raise --max-mb", mb(size), mb(max_bytes));
+    return false;
+  }
+  if let Err(e) = fs::write(out, &page) {
+    eprintln!("Can't write {:?}: {}", out, e);
+    return false;
+  }
+  println!("Exported {} file(s) to {:?} ({:.1} MB)", baked.len(), out,
+    mb(size));
+  if size > LARGE_HTML {
+    println!("Warning: that's large for an email attachment");
+  }
+  println!("The page loads three.js from {}, so opening it needs a \
+    connection", THREE_CDN);
+  true
+}
+
+// Past this, `export-html` warns that the file may not get through mail
+const LARGE_HTML: u64 = 10 << 20;
+
+// Parse and bake every OBJ file in the scene; files that don't parse
+// are left out with a warning
+fn bake(scene_dir: &Path, pipelines: &Pipelines)
+    -> Option<(Vec<Baked>, Manifest)> {
+  let manifest = match manifest::load(scene_dir) {
+    Ok(manifest) => manifest,
+    Err(e) => {
+      eprintln!("Invalid scene manifest: {}", e);
+      return None;
+    }
+  };
+  let source = DirSource {
+    dir: scene_dir.to_path_buf(),
+    follow_symlinks: true,
+  };
+  let names = match source.list() {
+    Ok(names) => names,
+    Err(e) => {
+      eprintln!("Can't list {:?}: {}", scene_dir, e);
+      return None;
+    }
+  };
+
+  let mut baked = Vec::new();
+  for name in names {
+    let parsed = source.read(&name)
+      .map_err(|e| e.to_string())
+      .and_then(|bytes| {
+        let mesh = mesh::parse_file(&bytes)?;
+        Ok((bytes, mesh))
+      });
+    let (bytes, mesh) = match parsed {
+      Ok(parsed) => parsed,
+      Err(e) => {
+        eprintln!("Skipping {}: {}", name, e);
+        continue;
+      }
+    };
+    let pipeline = pipelines.for_file(&name);
+    let mesh = match pipeline {
+      Some(pipeline) => {
+        let done = pipeline.run(&mesh);
+        println!("Pipeline {:?} on {}: {} -> {} triangles", pipeline.pattern,
+          name, mesh.triangles.len(), done.triangles.len());
+        done
+      }
+      None => mesh,
+    };
+    let glb = if pipeline.is_none() && bytes.starts_with(b"glTF") {
+      // Already a GLB; copied so its materials and hierarchy survive
+      bytes.clone()
+    } else {
+      // Placed by the viewer from the manifest, as it places OBJs
+      glb::encode(&[(name.clone(), &mesh, Transform::default())])
+    };
+    let mtime = fs::metadata(scene_dir.join(&name))
+      .and_then(|meta| meta.modified())
+      .ok()
+      .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+      .map(|d| d.as_millis() as u64);
+    let info = json!({
+      "name": name,
+      "hash": cache::content_hash(&bytes),
+      "bytes": bytes.len(),
+      "mtime": mtime,
+      "triangles": mesh.triangles.len(),
+    });
+    baked.push(Baked { name, glb, info });
+  }
+  if baked.is_empty() {
+    eprintln!("No OBJ files to export in {:?}", scene_dir);
+    return None;
+  }
+  Some((baked, manifest))
+}
+
+// What the viewer page reads in place of the server's API
+fn static_scene(baked: &[Baked], manifest: &Manifest, config: Value,
+    meshes: Value) -> Value {
+  json!({
+    "config": config,
+    "manifest": manifest,
+    "files": baked.iter().map(|file| file.info.clone()).collect::<Vec<_>>(),
+    "meshes": meshes,
+  })
+}
+
+// The viewer page with the scene filled in, loading three.js from
+// `three_base` if given
+fn page(scene: &Value, three_base: Option<&str>) -> String {
+  // `</script>` in a file name mustn't end the script early
+  let json = serde_json::to_string(scene).unwrap().replace("</", "<\\/");
+  let page = viewer_html::HTML.replacen("/* static scene */ null", &json, 1);
+  match three_base {
+    Some(base) => page.replace(THREE_CDN, base),
+    None => page,
+  }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+  const ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let byte = |i: usize| chunk.get(i).copied().unwrap_or(0);
+    let n = u32::from_be_bytes([0, byte(0), byte(1), byte(2)]);
+    for i in 0..4 {
+      if i <= chunk.len() {
+        out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+      } else {
+        out.push('=');
+      }
+    }
+  }
+  out
+}
+
+fn copy_three(package: &Path, to: &Path) -> std::io::Result<()> {
+  for file in THREE_FILES {
+    let target = to.join(file);
+    if let Some(parent) = target.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::copy(package.join(file), &target).map_err(|e|
+      std::io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
+  }
+  Ok(())
+}
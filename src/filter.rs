@@ -0,0 +1,86 @@
+//! File filters shared by the listing endpoint and WebSocket
+//! subscriptions.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FileFilter {
+  /// Glob on the filename or its alias (`*` and `?`)
+  pub filter: Option<String>,
+  /// Only files carrying this manifest tag
+  pub tag: Option<String>,
+  /// Only files with at least this many triangles
+  pub min_tris: Option<usize>,
+  /// Only these event types, e.g. `removed` and `error`; subscriptions
+  /// only, as listings have no events
+  #[serde(default)]
+  pub events: Option<Vec<String>>,
+}
+
+impl FileFilter {
+  /// Whether a file passes. `triangles` is `None` when the count isn't
+  /// known (unparseable or already deleted); such files only fail a
+  /// `min_tris` filter if `strict` is set.
+  pub fn matches(
+      &self,
+      name: &str,
+      alias: Option<&str>,
+      tags: &[String],
+      triangles: Option<usize>,
+      strict: bool) -> bool {
+    if let Some(pattern) = &self.filter {
+      if !glob_match(pattern, name)
+          && !alias.is_some_and(|alias| glob_match(pattern, alias)) {
+        return false;
+      }
+    }
+    if let Some(tag) = &self.tag {
+      if !tags.contains(tag) {
+        return false;
+      }
+    }
+    match (self.min_tris, triangles) {
+      (Some(min), Some(count)) => count >= min,
+      (Some(_), None) => !strict,
+      (None, _) => true,
+    }
+  }
+
+  /// Whether events of type `kind` (as in their `type` field) pass.
+  pub fn wants_event(&self, kind: &str) -> bool {
+    self.events.as_ref().is_none_or(|events| events.iter().any(|e| e == kind))
+  }
+}
+
+/// Shell-style glob match supporting `*` (any run) and `?` (any one
+/// character).
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let name: Vec<char> = name.chars().collect();
+  let (mut p, mut n) = (0, 0);
+  // Where to resume after the most recent `*`, if the match fails
+  let mut backtrack: Option<(usize, usize)> = None;
+
+  while n < name.len() {
+    match pattern.get(p) {
+      Some('*') => {
+        backtrack = Some((p, n));
+        p += 1;
+      }
+      Some(&c) if c == '?' || c == name[n] => {
+        p += 1;
+        n += 1;
+      }
+      _ => match backtrack {
+        // Let the last `*` swallow one more character and retry
+        Some((star, matched)) => {
+          p = star + 1;
+          n = matched + 1;
+          backtrack = Some((star, matched + 1));
+        }
+        None => return false,
+      },
+    }
+  }
+  pattern[p..].iter().all(|&c| c == '*')
+}
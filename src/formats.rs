@@ -0,0 +1,217 @@
+//! The kinds of scene file the server handles, and what it can do with
+//! each. Sources list only files in these formats, and
+//! `/api/capabilities` reports the table, so clients can adapt rather
+//! than guess from file extensions.
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Format {
+  /// Without the dot, as in file names
+  pub extension: &'static str,
+  pub media_type: &'static str,
+  /// Listed and served from `/scene/`
+  pub list: bool,
+  /// Formats it can be converted to, by `/api/export.glb` and the
+  /// export commands; none without the `transcode` feature
+  pub transcode: &'static [&'static str],
+  /// Checked by `/api/files/:name/lint`
+  pub lint: bool,
+  /// Rewritten in place by merges, normalizing and batch edits
+  pub edit: bool,
+}
+
+pub const FORMATS: &[Format] = &[
+  Format {
+    extension: "obj",
+    media_type: "model/obj",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: true,
+  },
+  // Binary or ASCII; parts exported from CAD tools
+  Format {
+    extension: "stl",
+    media_type: "model/stl",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // Loaded by the browser with materials and node hierarchy intact
+  Format {
+    extension: "glb",
+    media_type: "model/gltf-binary",
+    list: true,
+    transcode: &[],
+    lint: true,
+    edit: false,
+  },
+  // Only with embedded buffers, for the server's own parse
+  Format {
+    extension: "gltf",
+    media_type: "model/gltf+json",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // From 3D scanners, usually with per-vertex colours
+  Format {
+    extension: "ply",
+    media_type: "application/ply",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // Zipped XML from 3D-print pipelines
+  Format {
+    extension: "3mf",
+    media_type: "model/3mf",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // XML scenes from DCC tools, node hierarchy and all
+  Format {
+    extension: "dae",
+    media_type: "model/vnd.collada+xml",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // Packaged USD from studio pipelines; only text (usda) layers
+  Format {
+    extension: "usdz",
+    media_type: "model/vnd.usdz+zip",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // Architectural parts from CAD; the viewer shows the server's parse,
+  // so they need the transcode feature
+  Format {
+    extension: "dxf",
+    media_type: "image/vnd.dxf",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // Point clouds from scanners, which the viewer draws from the
+  // server's parse; with no faces, there's nothing to transcode
+  Format {
+    extension: "xyz",
+    media_type: "text/plain",
+    list: true,
+    transcode: &[],
+    lint: true,
+    edit: false,
+  },
+  Format {
+    extension: "pcd",
+    media_type: "application/octet-stream",
+    list: true,
+    transcode: &[],
+    lint: true,
+    edit: false,
+  },
+  // Lidar scans, decimated as they're parsed
+  Format {
+    extension: "las",
+    media_type: "application/vnd.las",
+    list: true,
+    transcode: &[],
+    lint: true,
+    edit: false,
+  },
+  // Listed so they show their error; LAZ isn't decompressed
+  Format {
+    extension: "laz",
+    media_type: "application/vnd.laszip",
+    list: true,
+    transcode: &[],
+    lint: true,
+    edit: false,
+  },
+  // Converted to GLB by `--fbx-converter`, and unreadable without it
+  Format {
+    extension: "fbx",
+    media_type: "application/vnd.autodesk.fbx",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  // CAD parts, tessellated to GLB by a `--converter`, and unreadable
+  // without one
+  Format {
+    extension: "step",
+    media_type: "model/step",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  Format {
+    extension: "stp",
+    media_type: "model/step",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  Format {
+    extension: "iges",
+    media_type: "model/iges",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+  Format {
+    extension: "igs",
+    media_type: "model/iges",
+    list: true,
+    transcode: if cfg!(feature = "transcode") { &["glb"] } else { &[] },
+    lint: true,
+    edit: false,
+  },
+];
+
+/// The format of a file, by its extension.
+pub fn format_of(name: &str) -> Option<&'static Format> {
+  let (_, extension) = name.rsplit_once('.')?;
+  FORMATS.iter()
+    .find(|format| format.extension.eq_ignore_ascii_case(extension))
+}
+
+/// Whether a file with this name belongs in the scene.
+pub fn is_scene_file(name: &str) -> bool {
+  format_of(name).is_some_and(|format| format.list)
+}
+
+/// Image types materials use as textures.
+pub const TEXTURE_EXTENSIONS: &[&str] = &[
+  "png", "jpg", "jpeg", "tga", "bmp", "gif", "webp", "ktx2", "exr", "hdr",
+  "tif", "tiff",
+];
+
+/// Whether a file may be written through WebDAV and served from
+/// `/scene/`: one in a known format, or an MTL library, glTF buffer or
+/// texture that scene files load. Anything else, such as HTML, would be
+/// served on the viewer's own origin.
+pub fn is_scene_asset(name: &str) -> bool {
+  if format_of(name).is_some() {
+    return true;
+  }
+  let Some((_, extension)) = name.rsplit_once('.') else { return false };
+  let extension = extension.to_ascii_lowercase();
+  extension == "mtl" || extension == "bin"
+    || TEXTURE_EXTENSIONS.contains(&extension.as_str())
+}
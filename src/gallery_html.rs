@@ -0,0 +1,141 @@
+pub const HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="UTF-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1.0">
+  <title>Kitbash Viewer - Screenshots</title>
+  <style>
+    body {
+      margin: 0;
+      padding: 16px;
+      background-color: #2a2a2a;
+      color: #ddd;
+      font-family: Arial, sans-serif;
+      font-size: 13px;
+    }
+    h1 {
+      font-size: 18px;
+      font-weight: normal;
+      margin: 0 0 16px;
+    }
+    a {
+      color: #8ab4f8;
+    }
+    #status {
+      color: #999;
+    }
+    #shots {
+      display: grid;
+      grid-template-columns: repeat(auto-fill, minmax(280px, 1fr));
+      gap: 16px;
+    }
+    .shot {
+      background-color: #333;
+      border-radius: 4px;
+      overflow: hidden;
+    }
+    .shot img {
+      display: block;
+      width: 100%;
+      aspect-ratio: 16 / 10;
+      object-fit: contain;
+      background-color: #1e1e1e;
+    }
+    .shot .details {
+      padding: 8px 10px;
+      line-height: 1.5;
+    }
+    .shot .name {
+      font-weight: bold;
+      word-break: break-all;
+    }
+    .shot .meta {
+      color: #aaa;
+    }
+    .shot .files {
+      color: #aaa;
+      word-break: break-all;
+    }
+  </style>
+</head>
+<body>
+  <h1>Screenshots <a href="/">back to the viewer</a></h1>
+  <p id="status">Loading...</p>
+  <div id="shots"></div>
+  <script>
+    const status = document.getElementById('status');
+    const shots = document.getElementById('shots');
+
+    const line = (className, text) => {
+      const div = document.createElement('div');
+      div.className = className;
+      div.textContent = text;
+      return div;
+    };
+    const point = (p) => p.map((x) => x.toFixed(2)).join(', ');
+    const kb = (bytes) => `${(bytes / 1024).toFixed(0)} KB`;
+
+    function card(shot) {
+      const url = `/api/screenshots/${encodeURIComponent(shot.file)}`;
+      const link = document.createElement('a');
+      link.href = url;
+      const img = document.createElement('img');
+      img.src = url;
+      img.alt = shot.file;
+      img.loading = 'lazy';
+      link.appendChild(img);
+
+      const details = document.createElement('div');
+      details.className = 'details';
+      details.appendChild(line('name', shot.file));
+      details.appendChild(line('meta',
+        `${new Date(shot.created).toLocaleString()} - ${kb(shot.bytes)}`));
+      // Viewers that didn't send their view leave just the image
+      if (shot.view) {
+        if (shot.view.selected) {
+          details.appendChild(line('meta', `Selected: ${shot.view.selected}`));
+        }
+        if (shot.view.camera) {
+          details.appendChild(line('meta',
+            `Camera at ${point(shot.view.camera.position)}, ` +
+            `looking at ${point(shot.view.camera.target)}`));
+        }
+        if (shot.view.clipping_planes.length) {
+          details.appendChild(line('meta',
+            `${shot.view.clipping_planes.length} clipping plane(s)`));
+        }
+      }
+      if (shot.files) {
+        details.appendChild(line('files',
+          `Showing ${shot.files.length} file(s): ${shot.files.join(', ')}`));
+      }
+
+      const div = document.createElement('div');
+      div.className = 'shot';
+      div.appendChild(link);
+      div.appendChild(details);
+      return div;
+    }
+
+    async function load() {
+      try {
+        const response = await fetch('/api/screenshots');
+        if (!response.ok) {
+          const body = await response.json().catch(() => ({}));
+          throw new Error(body.message || response.statusText);
+        }
+        const list = await response.json();
+        status.textContent = list.length ?
+          `${list.length} screenshot(s), newest first` :
+          'No screenshots yet. Viewers save them when the control API ' +
+          'sends a screenshot command.';
+        shots.replaceChildren(...list.map(card));
+      } catch (error) {
+        status.textContent = `Couldn't list screenshots: ${error.message}`;
+      }
+    }
+    load();
+  </script>
+</body>
+</html>
+"#;
@@ -0,0 +1,151 @@
+//! Small vector helpers and spatial queries for the mesh analyses.
+
+use std::collections::HashMap;
+
+pub type Vec3 = [f64; 3];
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f64 {
+  a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+  [
+    a[1] * b[2] - a[2] * b[1],
+    a[2] * b[0] - a[0] * b[2],
+    a[0] * b[1] - a[1] * b[0],
+  ]
+}
+
+pub fn length(a: Vec3) -> f64 {
+  dot(a, a).sqrt()
+}
+
+/// Whether two triangles properly intersect, by the separating axis
+/// theorem. Triangles that merely touch (shared edges, flush faces)
+/// count as separated, so adjacent parts aren't reported as clipping.
+pub fn triangles_intersect(a: [Vec3; 3], b: [Vec3; 3]) -> bool {
+  let edges_a = [sub(a[1], a[0]), sub(a[2], a[1]), sub(a[0], a[2])];
+  let edges_b = [sub(b[1], b[0]), sub(b[2], b[1]), sub(b[0], b[2])];
+  let normal_a = cross(edges_a[0], edges_a[1]);
+  let normal_b = cross(edges_b[0], edges_b[1]);
+
+  let mut axes = vec![normal_a, normal_b];
+  for ea in edges_a {
+    for eb in edges_b {
+      axes.push(cross(ea, eb));
+    }
+  }
+  // In-plane axes, needed when the triangles are coplanar
+  for e in edges_a.iter().chain(&edges_b) {
+    axes.push(cross(normal_a, *e));
+  }
+
+  let scale = edges_a.iter().chain(&edges_b)
+    .map(|e| length(*e))
+    .fold(0.0, f64::max);
+  let eps = 1e-9 * scale.max(1.0);
+
+  for axis in axes {
+    let len = length(axis);
+    if len < 1e-12 {
+      continue;
+    }
+    let axis = [axis[0] / len, axis[1] / len, axis[2] / len];
+    let (min_a, max_a) = project(a, axis);
+    let (min_b, max_b) = project(b, axis);
+    if max_a <= min_b + eps || max_b <= min_a + eps {
+      return false;
+    }
+  }
+  true
+}
+
+fn project(triangle: [Vec3; 3], axis: Vec3) -> (f64, f64) {
+  let d = triangle.map(|p| dot(p, axis));
+  (d[0].min(d[1]).min(d[2]), d[0].max(d[1]).max(d[2]))
+}
+
+/// Uniform grid over a point set for nearest-neighbour queries.
+pub struct PointGrid {
+  points: Vec<Vec3>,
+  cell:   f64,
+  cells:  HashMap<[i64; 3], Vec<usize>>,
+  // Largest cell-coordinate distance across the grid, to stop searching
+  span:   i64,
+}
+
+impl PointGrid {
+  pub fn new(points: Vec<Vec3>) -> Self {
+    // Non-finite points from other formats are kept for their index but
+    // never placed, so they can't stretch the grid or be found
+    let finite = |p: &Vec3| p.iter().all(|v| v.is_finite());
+    let (mut lo, mut hi) = ([f64::MAX; 3], [f64::MIN; 3]);
+    for p in points.iter().filter(|p| finite(p)) {
+      for axis in 0..3 {
+        lo[axis] = lo[axis].min(p[axis]);
+        hi[axis] = hi[axis].max(p[axis]);
+      }
+    }
+    // Aim for a handful of points per cell
+    let diagonal = length(sub(hi, lo));
+    let cell = (diagonal / (points.len() as f64).cbrt().max(1.0)).max(1e-9);
+
+    let mut grid = PointGrid {
+      points: Vec::new(),
+      cell,
+      cells: HashMap::new(),
+      span: 0,
+    };
+    for (i, p) in points.iter().enumerate().filter(|(_, p)| finite(p)) {
+      grid.cells.entry(grid.key(*p)).or_default().push(i);
+    }
+    if points.iter().any(finite) {
+      let (klo, khi) = (grid.key(lo), grid.key(hi));
+      grid.span = (0..3).map(|a| khi[a] - klo[a]).max().unwrap_or(0) + 1;
+    }
+    grid.points = points;
+    grid
+  }
+
+  fn key(&self, p: Vec3) -> [i64; 3] {
+    p.map(|v| (v / self.cell).floor() as i64)
+  }
+
+  /// Index of and distance to the closest point, searching outward ring
+  /// by ring of cells.
+  pub fn nearest(&self, p: Vec3) -> Option<(usize, f64)> {
+    let centre = self.key(p);
+    let mut best: Option<(usize, f64)> = None;
+    // Points outside the query's own span of the grid can still be
+    // nearest, so allow rings out to twice the grid size
+    for ring in 0..=(2 * self.span) {
+      for dx in -ring..=ring {
+        for dy in -ring..=ring {
+          for dz in -ring..=ring {
+            if dx.abs().max(dy.abs()).max(dz.abs()) != ring {
+              continue;
+            }
+            let key = [centre[0] + dx, centre[1] + dy, centre[2] + dz];
+            for &i in self.cells.get(&key).into_iter().flatten() {
+              let d = length(sub(self.points[i], p));
+              if best.is_none_or(|(_, bd)| d < bd) {
+                best = Some((i, d));
+              }
+            }
+          }
+        }
+      }
+      // Anything in further rings is at least `ring` cells away
+      if let Some((_, d)) = best {
+        if d <= ring as f64 * self.cell {
+          break;
+        }
+      }
+    }
+    best
+  }
+}
@@ -0,0 +1,103 @@
+//! Git status of the scene files, when the scene directory is inside a
+//! git work tree. Runs the `git` command line tool; without it (or
+//! outside a repository) there's simply no status.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+  /// Same as the last commit
+  Clean,
+  /// Changed since the last commit, staged or not
+  Modified,
+  /// Not tracked by git
+  Untracked,
+  /// Matched by a .gitignore
+  Ignored,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepoStatus {
+  /// Current branch, None when HEAD is detached
+  pub branch: Option<String>,
+  /// Commit hash of HEAD, None before the first commit
+  pub head: Option<String>,
+  /// Files directly in the scene directory that aren't clean
+  pub changed: BTreeMap<String, FileStatus>,
+}
+
+impl RepoStatus {
+  pub fn file(&self, name: &str) -> FileStatus {
+    self.changed.get(name).copied().unwrap_or(FileStatus::Clean)
+  }
+}
+
+/// The status of a scene directory's files, None if it isn't in a git
+/// work tree.
+pub fn status(scene_dir: &Path) -> Option<RepoStatus> {
+  // Porcelain paths are relative to the top of the work tree
+  let prefix = git(scene_dir, &["rev-parse", "--show-prefix"])?;
+  let prefix = prefix.trim_end_matches('\n');
+  let output = git(scene_dir, &[
+    "status", "--porcelain=v1", "-z", "--branch",
+    "--untracked-files=all", "--ignored=matching", "--", ".",
+  ])?;
+
+  let mut status = RepoStatus {
+    head: git(scene_dir, &["rev-parse", "--verify", "--quiet", "HEAD"])
+      .map(|hash| hash.trim().to_string()),
+    ..RepoStatus::default()
+  };
+  let mut entries = output.split('\0');
+  while let Some(entry) = entries.next() {
+    if entry.len() < 3 {
+      continue;
+    }
+    let (code, path) = entry.split_at(3);
+    let code = &code[..2];
+    if code == "##" {
+      status.branch = parse_branch(path);
+      continue;
+    }
+    // Renames and copies are followed by the original path
+    if code.contains(['R', 'C']) {
+      entries.next();
+    }
+    let Some(name) = path.strip_prefix(prefix) else { continue };
+    if name.contains('/') {
+      continue;
+    }
+    let file_status = match code {
+      "??" => FileStatus::Untracked,
+      "!!" => FileStatus::Ignored,
+      _ => FileStatus::Modified,
+    };
+    status.changed.insert(name.to_string(), file_status);
+  }
+  Some(status)
+}
+
+// "## main...origin/main [ahead 1]", "## No commits yet on main" or
+// "## HEAD (no branch)"
+fn parse_branch(line: &str) -> Option<String> {
+  let line = line.strip_prefix("No commits yet on ").unwrap_or(line);
+  let branch = line.split("...").next()?.split(' ').next()?;
+  (branch != "HEAD" && !branch.is_empty()).then(|| branch.to_string())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+  let output = Command::new("git")
+    .arg("-C")
+    .arg(dir)
+    .args(args)
+    .output()
+    .ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  String::from_utf8(output.stdout).ok()
+}
@@ -0,0 +1,435 @@
+//! Binary glTF (GLB) encoding of parsed meshes.
+//!
+//! Each part becomes one node carrying its manifest transform, with one
+//! indexed triangle primitive per material it uses. Normals are left
+//! out so that importers shade flat, which matches how the viewer shows
+//! OBJs without `vn` data. Vertex colours become `COLOR_0`, converted
+//! from the sRGB files store them in to the linear values glTF expects.
+//!
+//! OBJ materials, given as the part's MTL libraries, each become a glTF
+//! material with their diffuse colour and opacity, and their diffuse
+//! texture if it's a PNG or JPEG that was found. A part with a textured
+//! material gets `TEXCOORD_0`, for which its vertices are split
+//! wherever faces give one position different texture coordinates.
+//! Faces with no material, or one the libraries don't define, get the
+//! default grey.
+
+use crate::manifest::Transform;
+use crate::mesh::Mesh;
+use crate::mtl;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const CHUNK_JSON: u32 = 0x4e4f_534a; // "JSON"
+const CHUNK_BIN: u32 = 0x004e_4942; // "BIN\0"
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// Indices of the two materials every file has
+const DEFAULT_MATERIAL: usize = 0;
+const VERTEX_COLORS_MATERIAL: usize = 1;
+
+/// A part's materials, from the MTL libraries its `mtllib` lines name.
+#[derive(Default)]
+pub struct Materials {
+  pub materials: Vec<mtl::Material>,
+  /// Texture file contents, by `diffuse_map` as written
+  pub textures: HashMap<String, Vec<u8>>,
+}
+
+/// Encode named, placed meshes as a single GLB file, without their
+/// materials.
+pub fn encode(parts: &[(String, &Mesh, Transform)]) -> Vec<u8> {
+  let none = Materials::default();
+  let parts: Vec<_> = parts.iter()
+    .map(|(name, mesh, transform)| (name.clone(), *mesh, *transform, &none))
+    .collect();
+  encode_with_materials(&parts)
+}
+
+/// Encode named, placed meshes as a single GLB file, each with its
+/// materials.
+pub fn encode_with_materials(
+    parts: &[(String, &Mesh, Transform, &Materials)]) -> Vec<u8> {
+  let mut out = Builder::default();
+  let mut meshes = Vec::new();
+  let mut nodes = Vec::new();
+  // Same light grey as the viewer's default material
+  let mut materials = vec![json!({
+    "name": "default",
+    "pbrMetallicRoughness": {
+      "baseColorFactor": [0.8, 0.8, 0.8, 1.0],
+      "metallicFactor": 0.0,
+      "roughnessFactor": 0.8,
+    },
+    "doubleSided": true,
+  }), json!({
+    "name": "vertex colors",
+    "pbrMetallicRoughness": {
+      "baseColorFactor": [1.0, 1.0, 1.0, 1.0],
+      "metallicFactor": 0.0,
+      "roughnessFactor": 0.8,
+    },
+    "doubleSided": true,
+  })];
+  let mut images = Vec::new();
+  let mut textures = Vec::new();
+
+  for (name, mesh, transform, library) in parts {
+    let colored =
+      !mesh.colors.is_empty() && mesh.colors.len() == mesh.positions.len();
+    // Colours multiply the base colour, so they get a white one
+    let fallback =
+      if colored { VERTEX_COLORS_MATERIAL } else { DEFAULT_MATERIAL };
+
+    // The glTF material and texture of each of the mesh's own materials,
+    // added only once a triangle uses them
+    let mut used: HashMap<usize, (usize, bool)> = HashMap::new();
+    let mut material_of = |t: usize| -> (usize, bool) {
+      let Some(&Some(index)) = mesh.triangle_materials.get(t)
+      else { return (fallback, false) };
+      if let Some(&found) = used.get(&index) {
+        return found;
+      }
+      let material = library.materials.iter()
+        .find(|m| m.name == mesh.materials[index]);
+      let found = match material {
+        Some(material) => {
+          let texture = material.diffuse_map.as_ref()
+            .and_then(|map| Some((map, library.textures.get(map)?)))
+            .and_then(|(map, bytes)| Some((image_type(map)?, bytes)))
+            .map(|(mime, bytes)| {
+              let view = out.view(bytes, None);
+              images.push(json!({ "bufferView": view, "mimeType": mime }));
+              textures.push(json!({ "source": images.len() - 1 }));
+              textures.len() - 1
+            });
+          materials.push(material_json(material, texture));
+          (materials.len() - 1, texture.is_some())
+        }
+        None => (fallback, false),
+      };
+      used.insert(index, found);
+      found
+    };
+    let triangle_materials: Vec<(usize, bool)> =
+      (0..mesh.triangles.len()).map(&mut material_of).collect();
+
+    // Vertices as (position, texture coordinate) pairs; only split on
+    // texture coordinates when a textured material needs them
+    let textured = !mesh.uv_triangles.is_empty()
+      && triangle_materials.iter().any(|&(_, textured)| textured);
+    let mut vertices: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut triangles: Vec<[u32; 3]> = Vec::with_capacity(mesh.triangles.len());
+    if textured {
+      let mut index: HashMap<(usize, Option<usize>), u32> = HashMap::new();
+      for (t, triangle) in mesh.triangles.iter().enumerate() {
+        let uv = mesh.uv_triangles.get(t).copied().flatten();
+        triangles.push([0, 1, 2].map(|corner| {
+          let vertex = (triangle[corner], uv.map(|uv| uv[corner]));
+          *index.entry(vertex).or_insert_with(|| {
+            vertices.push(vertex);
+            vertices.len() as u32 - 1
+          })
+        }));
+      }
+    } else {
+      vertices = (0..mesh.positions.len()).map(|p| (p, None)).collect();
+      triangles = mesh.triangles.iter()
+        .map(|triangle| triangle.map(|v| v as u32))
+        .collect();
+    }
+
+    // Positions, with their bounds (required on POSITION accessors)
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    let mut bytes = Vec::with_capacity(vertices.len() * 12);
+    for &(p, _) in &vertices {
+      let position = mesh.positions[p];
+      for ((v, lo), hi) in position.iter().zip(&mut min).zip(&mut max) {
+        let v = *v as f32;
+        *lo = lo.min(v);
+        *hi = hi.max(v);
+        bytes.extend_from_slice(&v.to_le_bytes());
+      }
+    }
+    let view = out.view(&bytes, Some(ARRAY_BUFFER));
+    let position_accessor = out.accessor(json!({
+      "bufferView": view,
+      "componentType": FLOAT,
+      "count": vertices.len(),
+      "type": "VEC3",
+      "min": min,
+      "max": max,
+    }));
+    let mut attributes = json!({ "POSITION": position_accessor });
+
+    if colored {
+      let mut bytes = Vec::with_capacity(vertices.len() * 12);
+      for &(p, _) in &vertices {
+        for &c in &mesh.colors[p] {
+          bytes.extend_from_slice(&srgb_to_linear(c).to_le_bytes());
+        }
+      }
+      let view = out.view(&bytes, Some(ARRAY_BUFFER));
+      attributes["COLOR_0"] = json!(out.accessor(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": vertices.len(),
+        "type": "VEC3",
+      })));
+    }
+
+    if textured {
+      // glTF puts v = 0 at the top of the image, OBJ at the bottom
+      let mut bytes = Vec::with_capacity(vertices.len() * 8);
+      for &(_, uv) in &vertices {
+        let [u, v] = uv.and_then(|uv| mesh.uvs.get(uv).copied())
+          .unwrap_or([0.0; 2]);
+        bytes.extend_from_slice(&(u as f32).to_le_bytes());
+        bytes.extend_from_slice(&((1.0 - v) as f32).to_le_bytes());
+      }
+      let view = out.view(&bytes, Some(ARRAY_BUFFER));
+      attributes["TEXCOORD_0"] = json!(out.accessor(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": vertices.len(),
+        "type": "VEC2",
+      })));
+    }
+
+    // One primitive per material, in material order
+    let mut by_material: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+      let indices = by_material.entry(triangle_materials[t].0).or_default();
+      for &vi in triangle {
+        indices.extend_from_slice(&vi.to_le_bytes());
+      }
+    }
+    if by_material.is_empty() {
+      by_material.insert(fallback, Vec::new());
+    }
+    let primitives: Vec<Value> = by_material.into_iter()
+      .map(|(material, indices)| {
+        let view = out.view(&indices, Some(ELEMENT_ARRAY_BUFFER));
+        let indices = out.accessor(json!({
+          "bufferView": view,
+          "componentType": UNSIGNED_INT,
+          "count": indices.len() / 4,
+          "type": "SCALAR",
+        }));
+        json!({
+          "attributes": attributes,
+          "indices": indices,
+          "material": material,
+        })
+      })
+      .collect();
+
+    meshes.push(json!({ "name": name, "primitives": primitives }));
+    let scale = [transform.scale; 3];
+    nodes.push(json!({
+      "name": name,
+      "mesh": meshes.len() - 1,
+      "translation": transform.translation,
+      "scale": scale,
+    }));
+  }
+
+  let mut document = json!({
+    "asset": {
+      "version": "2.0",
+      "generator": concat!("kitbash-viewer ", env!("CARGO_PKG_VERSION")),
+    },
+    "scene": 0,
+    "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+    "nodes": nodes,
+    "meshes": meshes,
+    "materials": materials,
+    "buffers": [{ "byteLength": out.bin.len() }],
+    "bufferViews": out.buffer_views,
+    "accessors": out.accessors,
+  });
+  // glTF forbids empty arrays
+  if !images.is_empty() {
+    document["images"] = json!(images);
+    document["textures"] = json!(textures);
+  }
+
+  assemble(&document, out.bin)
+}
+
+// The binary chunk as it's filled, with the views and accessors on it
+#[derive(Default)]
+struct Builder {
+  bin: Vec<u8>,
+  buffer_views: Vec<Value>,
+  accessors: Vec<Value>,
+}
+
+impl Builder {
+  // Append bytes as a buffer view, returning its index. Views start on
+  // 4-byte boundaries, as accessors need.
+  fn view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+    while !self.bin.len().is_multiple_of(4) {
+      self.bin.push(0);
+    }
+    let mut view = json!({
+      "buffer": 0,
+      "byteOffset": self.bin.len(),
+      "byteLength": bytes.len(),
+    });
+    if let Some(target) = target {
+      view["target"] = json!(target);
+    }
+    self.bin.extend_from_slice(bytes);
+    self.buffer_views.push(view);
+    self.buffer_views.len() - 1
+  }
+
+  fn accessor(&mut self, accessor: Value) -> usize {
+    self.accessors.push(accessor);
+    self.accessors.len() - 1
+  }
+}
+
+fn material_json(material: &mtl::Material, texture: Option<usize>)
+    -> Value {
+  let [r, g, b] = material.diffuse.map(srgb_to_linear);
+  let mut pbr = json!({
+    "baseColorFactor": [r, g, b, material.opacity],
+    "metallicFactor": 0.0,
+    "roughnessFactor": 0.8,
+  });
+  if let Some(texture) = texture {
+    pbr["baseColorTexture"] = json!({ "index": texture });
+  }
+  let mut json = json!({
+    "name": material.name,
+    "pbrMetallicRoughness": pbr,
+    "doubleSided": true,
+  });
+  if material.opacity < 1.0 {
+    json["alphaMode"] = json!("BLEND");
+  }
+  json
+}
+
+// The image types glTF allows, by file name
+fn image_type(name: &str) -> Option<&'static str> {
+  let (_, extension) = name.rsplit_once('.')?;
+  match extension.to_lowercase().as_str() {
+    "png" => Some("image/png"),
+    "jpg" | "jpeg" => Some("image/jpeg"),
+    _ => None,
+  }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+  if c <= 0.04045 {
+    c / 12.92
+  } else {
+    ((c + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+// Lay out the GLB container: header, JSON chunk, BIN chunk. Chunks are
+// padded to 4 bytes, JSON with spaces and BIN with zeros.
+fn assemble(document: &Value, mut bin: Vec<u8>) -> Vec<u8> {
+  let mut json = document.to_string().into_bytes();
+  while !json.len().is_multiple_of(4) {
+    json.push(b' ');
+  }
+  while !bin.len().is_multiple_of(4) {
+    bin.push(0);
+  }
+
+  let bin_chunk = if bin.is_empty() { 0 } else { 8 + bin.len() };
+  let total = 12 + 8 + json.len() + bin_chunk;
+  let mut out = Vec::with_capacity(total);
+  out.extend_from_slice(&GLB_MAGIC.to_le_bytes());
+  out.extend_from_slice(&2u32.to_le_bytes());
+  out.extend_from_slice(&(total as u32).to_le_bytes());
+
+  out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+  out.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+  out.extend_from_slice(&json);
+
+  if !bin.is_empty() {
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+    out.extend_from_slice(&bin);
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh::parse_obj;
+
+  // The JSON chunk of a GLB
+  fn document(glb: &[u8]) -> Value {
+    let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    serde_json::from_slice(&glb[20..20 + length]).unwrap()
+  }
+
+  #[test]
+  fn splits_primitives_by_material() {
+    let mesh = parse_obj("mtllib parts.mtl
+v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0
+vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1
+f 1 2 3
+usemtl painted
+f 1/1 2/2 3/3
+usemtl missing
+f 1 3 4
+usemtl painted
+f 1/4 3/3 4/4
+").unwrap();
+    let library = Materials {
+      materials: mtl::parse_mtl("newmtl painted\nKd 1 0 0\nd 0.5\n\
+        map_Kd paint.png\n"),
+      textures: HashMap::from([("paint.png".to_string(), vec![1, 2, 3])]),
+    };
+    let parts = [("a.obj".to_string(), &mesh, Transform::default(), &library)];
+    let document = document(&encode_with_materials(&parts));
+
+    let materials = document["materials"].as_array().unwrap();
+    assert_eq!(materials.len(), 3);
+    assert_eq!(materials[2]["name"], "painted");
+    assert_eq!(materials[2]["alphaMode"], "BLEND");
+    assert_eq!(
+      materials[2]["pbrMetallicRoughness"]["baseColorTexture"]["index"], 0);
+    assert_eq!(document["images"][0]["mimeType"], "image/png");
+
+    // Untextured and unknown materials share the default
+    let primitives = document["meshes"][0]["primitives"].as_array().unwrap();
+    let materials: Vec<&Value> =
+      primitives.iter().map(|p| &p["material"]).collect();
+    assert_eq!(materials, [0, 2]);
+    let attributes = &primitives[0]["attributes"];
+    assert!(attributes.get("TEXCOORD_0").is_some());
+    // A vertex per position and texture coordinate used: the four
+    // positions without one, and five pairs from the textured faces
+    let accessor = &document["accessors"][attributes["POSITION"].as_u64()
+      .unwrap() as usize];
+    assert_eq!(accessor["count"], 9);
+  }
+
+  #[test]
+  fn encodes_without_materials() {
+    let mesh = parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl red\nf 1 2 3\n")
+      .unwrap();
+    let document = document(
+      &encode(&[("a.obj".to_string(), &mesh, Transform::default())]));
+    let primitives = document["meshes"][0]["primitives"].as_array().unwrap();
+    assert_eq!(primitives.len(), 1);
+    assert_eq!(primitives[0]["material"], 0);
+    assert!(primitives[0]["attributes"].get("TEXCOORD_0").is_none());
+    assert!(document.get("images").is_none());
+  }
+}
@@ -0,0 +1,495 @@
+//! Server-side glTF parsing, for models from DCC tools and asset
+//! stores. The browser loads the file itself with three.js' GLTFLoader,
+//! which keeps its materials and node hierarchy; the server only needs
+//! the geometry, so this reads triangle primitives' positions, indices
+//! and float vertex colours and bakes each node's transform into them.
+//!
+//! Both a binary `.glb` and a `.gltf` with its buffers embedded as
+//! `data:` URIs are read. A `.gltf` whose buffers are separate files
+//! is an error here, though the browser still loads it.
+//!
+//! Nodes can share meshes, so files that place more than
+//! [`mesh::MAX_TRIANGLES`] triangles are refused.
+
+use crate::mesh::{self, Mesh, SubObject};
+use serde_json::Value;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+// Primitive modes and accessor component types, from the spec
+const TRIANGLES: u64 = 4;
+const TRIANGLE_STRIP: u64 = 5;
+const TRIANGLE_FAN: u64 = 6;
+const UNSIGNED_BYTE: u64 = 5121;
+const UNSIGNED_SHORT: u64 = 5123;
+const UNSIGNED_INT: u64 = 5125;
+const FLOAT: u64 = 5126;
+
+type Matrix = [f64; 16];
+
+const IDENTITY: Matrix = [
+  1.0, 0.0, 0.0, 0.0,
+  0.0, 1.0, 0.0, 0.0,
+  0.0, 0.0, 1.0, 0.0,
+  0.0, 0.0, 0.0, 1.0,
+];
+
+/// Whether the contents look like a GLB, or glTF JSON.
+pub fn is_gltf(bytes: &[u8]) -> bool {
+  if bytes.starts_with(GLB_MAGIC) {
+    return true;
+  }
+  let text = bytes.trim_ascii_start();
+  text.starts_with(b"{") && text.windows(7).any(|w| w == b"\"asset\"")
+}
+
+/// Parse a GLB or glTF file. Each node with a mesh becomes an object,
+/// named after the node, or its mesh if the node has no name.
+pub fn parse_gltf(bytes: &[u8]) -> Result<Mesh, String> {
+  let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+    split_glb(bytes)?
+  } else {
+    (bytes, None)
+  };
+  let doc: Value = serde_json::from_slice(json)
+    .map_err(|e| format!("invalid glTF JSON: {}", e))?;
+  let buffers = doc["buffers"].as_array().map(Vec::as_slice).unwrap_or(&[])
+    .iter()
+    .enumerate()
+    .map(|(i, buffer)| load_buffer(i, buffer, bin))
+    .collect::<Result<Vec<_>, String>>()?;
+  let reader = Reader { doc: &doc, buffers };
+
+  let mut mesh = Mesh::default();
+  let nodes = doc["nodes"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+  let mut stack: Vec<(usize, Matrix)> = roots(&doc, nodes.len()).into_iter()
+    .rev()
+    .map(|node| (node, IDENTITY))
+    .collect();
+  let mut visited = vec![false; nodes.len()];
+  while let Some((index, parent)) = stack.pop() {
+    let Some(node) = nodes.get(index) else {
+      return Err(format!("node {} does not exist", index));
+    };
+    if std::mem::replace(&mut visited[index], true) {
+      return Err(format!("node {} appears twice in the hierarchy", index));
+    }
+    let world = multiply(&parent, &local_matrix(node));
+    if let Some(m) = node["mesh"].as_u64() {
+      let start = mesh.triangles.len();
+      reader.add_mesh(m as usize, &world, &mut mesh)?;
+      let name = node["name"].as_str()
+        .or_else(|| doc["meshes"][m as usize]["name"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("node{}", index));
+      let end = mesh.triangles.len();
+      mesh.objects.push(SubObject { name, triangles: start..end });
+    }
+    for child in node["children"].as_array().into_iter().flatten().rev() {
+      if let Some(child) = child.as_u64() {
+        stack.push((child as usize, world));
+      }
+    }
+  }
+  mesh.objects.retain(|o| !o.triangles.is_empty());
+  Ok(mesh)
+}
+
+// The JSON chunk and the binary chunk, if any, of a GLB
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+  let word = |at: usize| -> Result<u32, String> {
+    bytes.get(at..at + 4)
+      .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+      .ok_or_else(|| "GLB is cut short".to_string())
+  };
+  let version = word(4)?;
+  if version != 2 {
+    return Err(format!("GLB version {} is not supported, expected 2",
+      version));
+  }
+  let end = (word(8)? as usize).min(bytes.len());
+  let (mut json, mut bin) = (None, None);
+  let mut at = 12;
+  while at + 8 <= end {
+    let length = word(at)? as usize;
+    let kind = word(at + 4)?;
+    let data = bytes.get(at + 8..at + 8 + length)
+      .ok_or_else(|| "GLB chunk runs past the end of the file".to_string())?;
+    match kind {
+      CHUNK_JSON if json.is_none() => json = Some(data),
+      CHUNK_BIN if bin.is_none() => bin = Some(data),
+      _ => {}
+    }
+    at += 8 + length;
+  }
+  Ok((json.ok_or_else(|| "GLB has no JSON chunk".to_string())?, bin))
+}
+
+fn load_buffer(index: usize, buffer: &Value, bin: Option<&[u8]>)
+    -> Result<Vec<u8>, String> {
+  match buffer["uri"].as_str() {
+    // Only the first buffer of a GLB may leave out its URI
+    None if index == 0 => bin.map(<[u8]>::to_vec)
+      .ok_or_else(|| "buffer 0 has no URI and there's no GLB binary chunk"
+        .to_string()),
+    None => Err(format!("buffer {} has no URI", index)),
+    Some(uri) if uri.starts_with("data:") => {
+      let (_, data) = uri.split_once(";base64,").ok_or_else(|| format!(
+        "buffer {}: only base64 data: URIs are supported", index))?;
+      decode_base64(data)
+        .ok_or_else(|| format!("buffer {}: invalid base64", index))
+    }
+    Some(uri) => Err(format!("buffer {} is the separate file {:?}, which \
+      isn't read; save as .glb or embed the buffers", index, uri)),
+  }
+}
+
+// The nodes the default scene starts from: `scenes[scene].nodes`, or
+// every node that isn't a child if the file has no scenes
+fn roots(doc: &Value, node_count: usize) -> Vec<usize> {
+  let scene = doc["scene"].as_u64().unwrap_or(0) as usize;
+  if let Some(nodes) = doc["scenes"][scene]["nodes"].as_array() {
+    return nodes.iter().filter_map(Value::as_u64)
+      .map(|n| n as usize)
+      .collect();
+  }
+  let mut is_child = vec![false; node_count];
+  for node in doc["nodes"].as_array().into_iter().flatten() {
+    for child in node["children"].as_array().into_iter().flatten() {
+      if let Some(flag) = child.as_u64()
+          .and_then(|c| is_child.get_mut(c as usize)) {
+        *flag = true;
+      }
+    }
+  }
+  (0..node_count).filter(|&n| !is_child[n]).collect()
+}
+
+struct Reader<'a> {
+  doc: &'a Value,
+  buffers: Vec<Vec<u8>>,
+}
+
+impl Reader<'_> {
+  // Add a mesh's triangle primitives, placed by `world`
+  fn add_mesh(&self, index: usize, world: &Matrix, mesh: &mut Mesh)
+      -> Result<(), String> {
+    let primitives = self.doc["meshes"][index]["primitives"].as_array()
+      .ok_or_else(|| format!("mesh {} does not exist", index))?;
+    for primitive in primitives {
+      let mode = primitive["mode"].as_u64().unwrap_or(TRIANGLES);
+      if !matches!(mode, TRIANGLES | TRIANGLE_STRIP | TRIANGLE_FAN) {
+        // Points and lines have no surface to speak of
+        continue;
+      }
+      let Some(position) = primitive["attributes"]["POSITION"].as_u64() else {
+        continue;
+      };
+      let base = mesh.positions.len();
+      let positions = self.floats(position as usize, 3)?;
+      mesh.positions.extend(positions.chunks_exact(3)
+        .map(|p| transform(world, [p[0], p[1], p[2]])));
+      let count = mesh.positions.len() - base;
+      let colors = match primitive["attributes"]["COLOR_0"].as_u64() {
+        Some(accessor) => self.colors(accessor as usize)?,
+        None => None,
+      };
+      match colors {
+        Some(colors) if colors.len() == count => {
+          // Parts without colours are white
+          mesh.colors.resize(base, [1.0; 3]);
+          mesh.colors.extend(colors);
+        }
+        _ if !mesh.colors.is_empty() =>
+          mesh.colors.resize(base + count, [1.0; 3]),
+        _ => {}
+      }
+      let indices = match primitive["indices"].as_u64() {
+        Some(accessor) => self.indices(accessor as usize)?,
+        None => (0..count).collect(),
+      };
+      if let Some(&bad) = indices.iter().find(|&&i| i >= count) {
+        return Err(format!("mesh {}: index {} is past its {} vertices",
+          index, bad, count));
+      }
+      let corners = |i: usize| -> [usize; 3] {
+        match mode {
+          TRIANGLES => [indices[3 * i], indices[3 * i + 1], indices[3 * i + 2]],
+          // Every other strip triangle is flipped to keep the winding
+          TRIANGLE_STRIP if i % 2 == 1 =>
+            [indices[i + 1], indices[i], indices[i + 2]],
+          TRIANGLE_STRIP => [indices[i], indices[i + 1], indices[i + 2]],
+          _ => [indices[0], indices[i + 1], indices[i + 2]],
+        }
+      };
+      let triangles = match mode {
+        TRIANGLES => indices.len() / 3,
+        _ => indices.len().saturating_sub(2),
+      };
+      mesh.triangles.extend((0..triangles).map(corners)
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .map(|t| t.map(|i| base + i)));
+      // Nodes can share a mesh, so a small file can place a lot
+      mesh::check_size(mesh)?;
+    }
+    Ok(())
+  }
+
+  // The bytes an accessor reads from, with the stride between elements
+  fn view(&self, accessor: &Value, element: usize)
+      -> Result<(&[u8], usize), String> {
+    let view = accessor["bufferView"].as_u64()
+      .and_then(|v| self.doc["bufferViews"].get(v as usize))
+      .ok_or_else(|| "accessor has no buffer view".to_string())?;
+    let buffer = view["buffer"].as_u64()
+      .and_then(|b| self.buffers.get(b as usize))
+      .ok_or_else(|| "buffer view has no buffer".to_string())?;
+    let number = |value: &Value| value.as_u64().unwrap_or(0) as usize;
+    let start = number(&view["byteOffset"]);
+    let bytes = start.checked_add(number(&view["byteLength"]))
+      .and_then(|end| buffer.get(start..end))
+      .ok_or_else(|| "buffer view runs past the end of its buffer"
+        .to_string())?;
+    // The spec allows strides of 4 to 252 bytes; one shorter than an
+    // element would read elements over each other
+    let stride = view["byteStride"].as_u64().map_or(element, |s| s as usize);
+    if stride < element || stride > 252 {
+      return Err(format!("byte stride {} is invalid for {}-byte elements",
+        stride, element));
+    }
+    let offset = number(&accessor["byteOffset"]);
+    let count = number(&accessor["count"]);
+    let end = match count {
+      0 => Some(0),
+      _ => stride.checked_mul(count - 1)
+        .and_then(|n| n.checked_add(offset))
+        .and_then(|n| n.checked_add(element)),
+    };
+    if end.is_none_or(|end| end > bytes.len()) {
+      return Err("accessor runs past the end of its buffer view".to_string());
+    }
+    Ok((bytes.get(offset..).unwrap_or(&[]), stride))
+  }
+
+  fn accessor(&self, index: usize) -> Result<&Value, String> {
+    self.doc["accessors"].get(index)
+      .ok_or_else(|| format!("accessor {} does not exist", index))
+  }
+
+  // A float accessor's values, `width` per element
+  fn floats(&self, index: usize, width: usize) -> Result<Vec<f64>, String> {
+    let accessor = self.accessor(index)?;
+    if accessor["componentType"].as_u64() != Some(FLOAT) {
+      return Err(format!("accessor {}: positions must be floats", index));
+    }
+    let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+    let (bytes, stride) = self.view(accessor, 4 * width)
+      .map_err(|e| format!("accessor {}: {}", index, e))?;
+    Ok((0..count).flat_map(|i| (0..width).map(move |c| {
+      let at = stride * i + 4 * c;
+      f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as f64
+    })).collect())
+  }
+
+  // A `COLOR_0` accessor's colours, back in sRGB. Only float colours
+  // are read; normalized integer ones are left to the browser.
+  fn colors(&self, index: usize) -> Result<Option<Vec<[f32; 3]>>, String> {
+    let accessor = self.accessor(index)?;
+    let width = match accessor["type"].as_str() {
+      Some("VEC3") => 3,
+      Some("VEC4") => 4,
+      _ => return Ok(None),
+    };
+    if accessor["componentType"].as_u64() != Some(FLOAT) {
+      return Ok(None);
+    }
+    let values = self.floats(index, width)?;
+    Ok(Some(values.chunks_exact(width)
+      .map(|c| [c[0], c[1], c[2]].map(|v| linear_to_srgb(v as f32)))
+      .collect()))
+  }
+
+  fn indices(&self, index: usize) -> Result<Vec<usize>, String> {
+    let accessor = self.accessor(index)?;
+    let size = match accessor["componentType"].as_u64() {
+      Some(UNSIGNED_BYTE) => 1,
+      Some(UNSIGNED_SHORT) => 2,
+      Some(UNSIGNED_INT) => 4,
+      _ => return Err(format!("accessor {}: indices must be unsigned \
+        integers", index)),
+    };
+    let count = accessor["count"].as_u64().unwrap_or(0) as usize;
+    let (bytes, stride) = self.view(accessor, size)
+      .map_err(|e| format!("accessor {}: {}", index, e))?;
+    Ok((0..count).map(|i| {
+      let at = stride * i;
+      let mut word = [0; 4];
+      word[..size].copy_from_slice(&bytes[at..at + size]);
+      u32::from_le_bytes(word) as usize
+    }).collect())
+  }
+}
+
+// A node's transform: its `matrix`, or translation * rotation * scale
+fn local_matrix(node: &Value) -> Matrix {
+  let numbers = |key: &str| -> Option<Vec<f64>> {
+    node[key].as_array()?.iter().map(Value::as_f64).collect()
+  };
+  if let Some(m) = numbers("matrix").filter(|m| m.len() == 16) {
+    return m.try_into().unwrap();
+  }
+  let [tx, ty, tz] = numbers("translation").filter(|t| t.len() == 3)
+    .map_or([0.0; 3], |t| [t[0], t[1], t[2]]);
+  let [x, y, z, w] = numbers("rotation").filter(|r| r.len() == 4)
+    .map_or([0.0, 0.0, 0.0, 1.0], |r| [r[0], r[1], r[2], r[3]]);
+  let [sx, sy, sz] = numbers("scale").filter(|s| s.len() == 3)
+    .map_or([1.0; 3], |s| [s[0], s[1], s[2]]);
+  // Column-major, as glTF stores matrices
+  [
+    (1.0 - 2.0 * (y * y + z * z)) * sx,
+    (2.0 * (x * y + z * w)) * sx,
+    (2.0 * (x * z - y * w)) * sx,
+    0.0,
+    (2.0 * (x * y - z * w)) * sy,
+    (1.0 - 2.0 * (x * x + z * z)) * sy,
+    (2.0 * (y * z + x * w)) * sy,
+    0.0,
+    (2.0 * (x * z + y * w)) * sz,
+    (2.0 * (y * z - x * w)) * sz,
+    (1.0 - 2.0 * (x * x + y * y)) * sz,
+    0.0,
+    tx, ty, tz, 1.0,
+  ]
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+  if c <= 0.0031308 {
+    c * 12.92
+  } else {
+    1.055 * c.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+  let mut out = [0.0; 16];
+  for col in 0..4 {
+    for row in 0..4 {
+      out[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k])
+        .sum();
+    }
+  }
+  out
+}
+
+fn transform(m: &Matrix, p: [f64; 3]) -> [f64; 3] {
+  [0, 1, 2].map(|row| m[row] * p[0] + m[4 + row] * p[1] + m[8 + row] * p[2]
+    + m[12 + row])
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+  let mut out = Vec::with_capacity(text.len() / 4 * 3);
+  let (mut bits, mut held) = (0u32, 0);
+  for c in text.bytes().take_while(|&c| c != b'=') {
+    let value = match c {
+      b'A'..=b'Z' => c - b'A',
+      b'a'..=b'z' => c - b'a' + 26,
+      b'0'..=b'9' => c - b'0' + 52,
+      b'+' | b'-' => 62,
+      b'/' | b'_' => 63,
+      _ => return None,
+    };
+    bits = bits << 6 | value as u32;
+    held += 6;
+    if held >= 8 {
+      held -= 8;
+      out.push((bits >> held) as u8);
+    }
+  }
+  Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A .gltf with one buffer of three vertices and three indices, and
+  // `views` and `accessors` spliced in
+  fn gltf(views: &str, accessors: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+      buffer.extend_from_slice(&v.to_le_bytes());
+    }
+    for i in [0u16, 1, 2] {
+      buffer.extend_from_slice(&i.to_le_bytes());
+    }
+    format!(r#"{{
+      "asset": {{ "version": "2.0" }},
+      "buffers": [{{ "byteLength": 42,
+        "uri": "data:application/octet-stream;base64,{}" }}],
+      "bufferViews": [{}],
+      "accessors": [{}],
+      "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }},
+        "indices": 1 }}] }}],
+      "nodes": [{{ "name": "part", "mesh": 0, "translation": [0, 0, 5] }}]
+    }}"#, encode_base64(&buffer), views, accessors).into_bytes()
+  }
+
+  fn encode_base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8] =
+      b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    bytes.chunks(3).map(|chunk| {
+      let word = chunk.iter().enumerate()
+        .fold(0u32, |word, (i, &b)| word | (b as u32) << (16 - 8 * i));
+      (0..4).map(|i| match i <= chunk.len() {
+        true => DIGITS[(word >> (18 - 6 * i) & 63) as usize] as char,
+        false => '=',
+      }).collect::<String>()
+    }).collect()
+  }
+
+  const VIEWS: &str = r#"{ "buffer": 0, "byteLength": 36 },
+    { "buffer": 0, "byteOffset": 36, "byteLength": 6 }"#;
+  const INDICES: &str = r#"{ "bufferView": 1, "componentType": 5123,
+    "count": 3, "type": "SCALAR" }"#;
+
+  fn positions(count: u64) -> String {
+    format!(r#"{{ "bufferView": 0, "componentType": 5126, "count": {},
+      "type": "VEC3" }}, {}"#, count, INDICES)
+  }
+
+  #[test]
+  fn parses_placed_triangles() {
+    let bytes = gltf(VIEWS, &positions(3));
+    assert!(is_gltf(&bytes));
+    let mesh = parse_gltf(&bytes).unwrap();
+    assert_eq!(mesh.triangles, [[0, 1, 2]]);
+    assert_eq!(mesh.positions[1], [1.0, 0.0, 5.0]);
+    assert_eq!(mesh.objects[0].name, "part");
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    assert!(parse_gltf(b"glTF\x01\0\0\0").is_err());
+    assert!(parse_gltf(b"{ \"asset\": {} ").is_err());
+    // An accessor past the end of its view
+    assert!(parse_gltf(&gltf(VIEWS, &positions(4))).is_err());
+    // Views past the end of the buffer, or wrapping around
+    for view in [
+      r#"{ "buffer": 0, "byteOffset": 40, "byteLength": 36 }"#,
+      r#"{ "buffer": 0, "byteOffset": 40,
+        "byteLength": 18446744073709551615 }"#,
+    ] {
+      let views = format!("{}, {}", view, VIEWS);
+      assert!(parse_gltf(&gltf(&views, &positions(3))).is_err());
+    }
+    // A stride of zero would read one vertex over and over
+    let views = VIEWS.replacen("36 }", "36, \"byteStride\": 0 }", 1);
+    assert!(parse_gltf(&gltf(&views, &positions(400_000_000))).is_err());
+    // A count whose last element's offset wraps
+    let views = VIEWS.replacen("36 }", "36, \"byteStride\": 16 }", 1);
+    let count = u64::MAX / 16 + 2;
+    assert!(parse_gltf(&gltf(&views, &positions(count))).is_err());
+  }
+}
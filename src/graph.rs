@@ -0,0 +1,430 @@
+//! The scene's asset dependency graph: which files each file needs. An
+//! OBJ needs the MTL libraries its `mtllib` lines name, an MTL the
+//! textures its `map_*`, `bump`, `disp`, `decal`, `refl` and `norm`
+//! lines name, a glTF the buffers and images its URIs name, and the
+//! manifest the files it places, tags or orders. Numbered scene files
+//! (`walk_0001.obj`, `walk_0002.obj`, ...) are grouped into a sequence,
+//! named with `#` for the frame number's digits (`walk_####.obj`), that
+//! needs its frames.
+//!
+//! Names are relative to the scene directory. The watcher keeps the
+//! graph current and cascades changes through it: when an MTL or
+//! texture changes, every scene file needing it, directly or through
+//! other files, is sent as modified so viewers reload it.
+
+use crate::formats;
+use crate::http::decode_path;
+use crate::manifest;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// MTL statements naming a texture besides the `map_*` ones
+const TEXTURE_STATEMENTS: &[&str] = &["bump", "disp", "decal", "refl", "norm"];
+
+// Fewest digits a frame number has, so `part1.obj` and `part2.obj`
+// aren't taken for a sequence
+const FRAME_DIGITS: usize = 3;
+
+/// How one file needs another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+  /// An OBJ's `mtllib`
+  Mtllib,
+  /// A texture map of an MTL
+  Texture,
+  /// A glTF's buffer or image
+  Uri,
+  /// A file the manifest places, tags or orders
+  Manifest,
+  /// A frame of a sequence
+  Frame,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+  /// Listed in the scene
+  Scene,
+  Material,
+  Texture,
+  /// A glTF's binary buffer
+  Buffer,
+  Manifest,
+  Sequence,
+  Other,
+}
+
+#[derive(Serialize)]
+pub struct Node {
+  pub name: String,
+  pub kind: NodeKind,
+  /// Needed but not on disk
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub missing: bool,
+}
+
+/// `from` needs `to`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Edge {
+  pub from: String,
+  pub to: String,
+  pub kind: EdgeKind,
+}
+
+#[derive(Serialize)]
+pub struct GraphReport {
+  pub nodes: Vec<Node>,
+  pub edges: Vec<Edge>,
+}
+
+#[derive(Default)]
+pub struct Graph {
+  /// What each file needs, by the file needing it
+  needs: BTreeMap<String, BTreeSet<(String, EdgeKind)>>,
+  /// Scene files, for finding sequences
+  files: BTreeSet<String>,
+  /// Other files whose contents have been read, such as MTLs
+  read: BTreeSet<String>,
+}
+
+impl Graph {
+  /// The graph of the scene files `files` and the manifest, reading
+  /// contents with `read`, None for a file that can't be.
+  pub fn build(files: Vec<String>,
+      read: impl Fn(&str) -> Option<Vec<u8>>) -> Graph {
+    let mut graph = Graph::default();
+    for file in files {
+      let contents = match Graph::can_need(&file) {
+        true => read(&file).unwrap_or_default(),
+        false => Vec::new(),
+      };
+      graph.changed(&file, &contents);
+    }
+    if let Some(contents) = read(manifest::MANIFEST_FILE) {
+      graph.changed(manifest::MANIFEST_FILE, &contents);
+    }
+    graph.read_needed(read);
+    graph
+  }
+
+  /// Read the needed files that can need others, such as MTLs, that
+  /// haven't been, and what they need in turn.
+  pub fn read_needed(&mut self, read: impl Fn(&str) -> Option<Vec<u8>>) {
+    loop {
+      let unread: BTreeSet<String> = self.needs.values().flatten()
+        .map(|(to, _)| to)
+        .filter(|to| Graph::can_need(to) && !self.files.contains(*to)
+          && !self.read.contains(*to))
+        .cloned()
+        .collect();
+      let mut progress = false;
+      for name in unread {
+        if let Some(contents) = read(&name) {
+          self.changed(&name, &contents);
+          progress = true;
+        }
+      }
+      if !progress {
+        break;
+      }
+    }
+  }
+
+  /// Whether a file of this name can need others, so `changed` needs
+  /// its contents.
+  pub fn can_need(name: &str) -> bool {
+    name == manifest::MANIFEST_FILE
+      || ["obj", "mtl", "gltf"].contains(&extension(name).as_str())
+  }
+
+  /// Note that a file was added or changed, replacing what it needed
+  /// before. `contents` may be empty for files that can't need others.
+  pub fn changed(&mut self, name: &str, contents: &[u8]) {
+    if formats::is_scene_file(name) {
+      self.files.insert(name.to_string());
+    } else {
+      self.read.insert(name.to_string());
+    }
+    let needs = needs_in(name, contents);
+    if needs.is_empty() {
+      self.needs.remove(name);
+    } else {
+      self.needs.insert(name.to_string(), needs);
+    }
+  }
+
+  pub fn removed(&mut self, name: &str) {
+    self.files.remove(name);
+    self.read.remove(name);
+    self.needs.remove(name);
+  }
+
+  /// Every file some file needs.
+  pub fn needed(&self) -> impl Iterator<Item = &str> {
+    self.needs.values().flatten().map(|(to, _)| to.as_str())
+  }
+
+  /// Whether some file needs `name`.
+  pub fn is_needed(&self, name: &str) -> bool {
+    self.needs.values().flatten().any(|(to, _)| to == name)
+  }
+
+  /// Every scene file needing `name`, directly or through other files,
+  /// other than by being in the manifest. These are what change when
+  /// it does.
+  pub fn dependents(&self, name: &str) -> Vec<String> {
+    let edges: Vec<Edge> = self.edges().into_iter()
+      .filter(|edge| edge.kind != EdgeKind::Manifest)
+      .collect();
+    reachable(&edges, name, |edge| (&edge.to, &edge.from)).into_iter()
+      .filter(|dependent| self.files.contains(dependent))
+      .collect()
+  }
+
+  /// The graph, or with `file`, just what it needs and what needs it,
+  /// directly or not. `exists` says whether a file is on disk.
+  pub fn report(&self, file: Option<&str>, exists: impl Fn(&str) -> bool)
+      -> GraphReport {
+    let mut edges = self.edges();
+    if let Some(file) = file {
+      let needed = reachable(&edges, file, |edge| (&edge.from, &edge.to));
+      let needing = reachable(&edges, file, |edge| (&edge.to, &edge.from));
+      let mut related: BTreeSet<String> =
+        needed.into_iter().chain(needing).collect();
+      related.insert(file.to_string());
+      edges.retain(|edge|
+        related.contains(&edge.from) && related.contains(&edge.to));
+    }
+    let mut names: BTreeSet<&str> = edges.iter()
+      .flat_map(|edge| [edge.from.as_str(), edge.to.as_str()])
+      .collect();
+    if file.is_none() {
+      names.extend(self.files.iter().map(String::as_str));
+    }
+    let sequences = self.sequences();
+    let nodes = names.into_iter().map(|name| {
+      let kind = match name {
+        _ if sequences.contains_key(name) => NodeKind::Sequence,
+        manifest::MANIFEST_FILE => NodeKind::Manifest,
+        _ if formats::is_scene_file(name) => NodeKind::Scene,
+        _ => match extension(name).as_str() {
+          "mtl" => NodeKind::Material,
+          "bin" => NodeKind::Buffer,
+          extension if formats::TEXTURE_EXTENSIONS.contains(&extension) =>
+            NodeKind::Texture,
+          _ => NodeKind::Other,
+        },
+      };
+      let missing = kind != NodeKind::Sequence && !exists(name);
+      Node { name: name.to_string(), kind, missing }
+    }).collect();
+    GraphReport { nodes, edges }
+  }
+
+  // Every edge, sequences' included, sorted
+  fn edges(&self) -> Vec<Edge> {
+    let needs = self.needs.iter().flat_map(|(from, needs)|
+      needs.iter().map(move |(to, kind)| Edge {
+        from: from.clone(),
+        to: to.clone(),
+        kind: *kind,
+      }));
+    let frames = self.sequences().into_iter().flat_map(|(name, frames)|
+      frames.into_iter().map(move |frame| Edge {
+        from: name.clone(),
+        to: frame,
+        kind: EdgeKind::Frame,
+      }));
+    let mut edges: Vec<Edge> = needs.chain(frames).collect();
+    edges.sort();
+    edges
+  }
+
+  // Scene files numbered as frames, by the name of their sequence; a
+  // sequence has at least two
+  fn sequences(&self) -> BTreeMap<String, Vec<String>> {
+    let mut sequences: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in &self.files {
+      let Some((stem, extension)) = name.rsplit_once('.') else { continue };
+      let digits = stem.len() - stem.trim_end_matches(|c: char|
+        c.is_ascii_digit()).len();
+      if digits < FRAME_DIGITS {
+        continue;
+      }
+      let sequence = format!("{}{}.{}", &stem[..stem.len() - digits],
+        "#".repeat(digits), extension);
+      sequences.entry(sequence).or_default().push(name.clone());
+    }
+    sequences.retain(|_, frames| frames.len() >= 2);
+    sequences
+  }
+}
+
+// Names reachable from `start` along the edges, each edge leading
+// from the first name `step` gives to the second; `start` is left out
+fn reachable<'a>(edges: &'a [Edge], start: &str,
+    step: impl Fn(&'a Edge) -> (&'a String, &'a String))
+    -> BTreeSet<String> {
+  let mut found = BTreeSet::new();
+  let mut queue = vec![start.to_string()];
+  while let Some(next) = queue.pop() {
+    for (from, to) in edges.iter().map(&step) {
+      if *from == next && to != start && found.insert(to.clone()) {
+        queue.push(to.clone());
+      }
+    }
+  }
+  found
+}
+
+fn extension(name: &str) -> String {
+  name.rsplit_once('.')
+    .map_or(String::new(), |(_, extension)| extension.to_lowercase())
+}
+
+// The files the contents of `name` say it needs
+fn needs_in(name: &str, contents: &[u8]) -> BTreeSet<(String, EdgeKind)> {
+  let mut needs = BTreeSet::new();
+  if name == manifest::MANIFEST_FILE {
+    let text = String::from_utf8_lossy(contents);
+    if let Some(manifest) = manifest::validate(&text).manifest {
+      let files = manifest.transforms.into_keys()
+        .chain(manifest.tags.into_keys())
+        .chain(manifest.order);
+      needs.extend(files.map(|file| (file, EdgeKind::Manifest)));
+    }
+    return needs;
+  }
+  let mut add = |reference: &str, kind: EdgeKind| {
+    if let Some(path) = resolve(name, reference) {
+      needs.insert((path, kind));
+    }
+  };
+  match extension(name).as_str() {
+    "obj" => {
+      // Only `mtllib` lines are looked at, without decoding the rest
+      for line in contents.split(|&b| b == b'\n') {
+        let Some(rest) = line.strip_prefix(b"mtllib") else { continue };
+        for library in String::from_utf8_lossy(rest).split_whitespace() {
+          add(library, EdgeKind::Mtllib);
+        }
+      }
+    }
+    "mtl" => {
+      for line in String::from_utf8_lossy(contents).lines() {
+        let mut words = line.split_whitespace();
+        let Some(statement) = words.next() else { continue };
+        let statement = statement.to_lowercase();
+        if statement.starts_with("map_")
+            || TEXTURE_STATEMENTS.contains(&statement.as_str()) {
+          // Options such as `-s 1 1 1` come before the file
+          if let Some(texture) = words.last() {
+            add(texture, EdgeKind::Texture);
+          }
+        }
+      }
+    }
+    "gltf" => {
+      let Ok(gltf) = serde_json::from_slice::<serde_json::Value>(contents)
+      else { return needs };
+      for key in ["buffers", "images"] {
+        let uris = gltf[key].as_array().into_iter().flatten()
+          .filter_map(|item| item["uri"].as_str());
+        for uri in uris.filter(|uri| !uri.starts_with("data:")) {
+          add(&decode_path(uri), EdgeKind::Uri);
+        }
+      }
+    }
+    _ => {}
+  }
+  needs
+}
+
+/// A reference from the file `from`, as a name relative to the scene
+/// directory; None for absolute paths and ones that leave it.
+pub fn resolve(from: &str, reference: &str) -> Option<String> {
+  let reference = reference.replace('\\', "/");
+  if reference.starts_with('/') || reference.contains(':') {
+    return None;
+  }
+  let mut parts: Vec<&str> = from.split('/').collect();
+  parts.pop();
+  for part in reference.split('/') {
+    match part {
+      "" | "." => {}
+      ".." => {
+        parts.pop()?;
+      }
+      part => parts.push(part),
+    }
+  }
+  Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  #[test]
+  fn resolves_relative_references() {
+    assert_eq!(resolve("parts/a.obj", "a.mtl").as_deref(),
+      Some("parts/a.mtl"));
+    assert_eq!(resolve("parts/a.obj", "./tex\\..\\shared/b.png").as_deref(),
+      Some("parts/shared/b.png"));
+    assert_eq!(resolve("parts/a.obj", "../b.mtl").as_deref(), Some("b.mtl"));
+    // Nothing outside the scene directory, or absolute
+    assert_eq!(resolve("parts/a.obj", "../../b.mtl"), None);
+    assert_eq!(resolve("a.obj", "../b.mtl"), None);
+    assert_eq!(resolve("a.obj", "/etc/b.mtl"), None);
+    assert_eq!(resolve("a.obj", "C:\\b.mtl"), None);
+  }
+
+  #[test]
+  fn cascades_through_materials() {
+    let files: HashMap<&str, &str> = HashMap::from([
+      ("parts/hull.obj", "mtllib ../shared/paint.mtl\nv 0 0 0\n"),
+      ("deck.obj", "mtllib shared/paint.mtl\n"),
+      ("shared/paint.mtl", "newmtl red\nmap_Kd -s 1 1 1 tex/red.png\n\
+        bump tex/bump.png\n"),
+      (manifest::MANIFEST_FILE, r#"{ "tags": { "deck.obj": ["wood"] } }"#),
+    ]);
+    let read =
+      |name: &str| files.get(name).map(|text| text.as_bytes().to_vec());
+    let scene = vec!["parts/hull.obj".to_string(), "deck.obj".to_string(),
+      "walk_0001.obj".to_string(), "walk_0002.obj".to_string()];
+    let graph = Graph::build(scene, read);
+
+    let mut dependents = graph.dependents("shared/tex/red.png");
+    dependents.sort();
+    assert_eq!(dependents, ["deck.obj", "parts/hull.obj"]);
+    // Being in the manifest isn't needing
+    assert!(graph.dependents("deck.obj").is_empty());
+    assert!(graph.is_needed("shared/tex/bump.png"));
+
+    let report = graph.report(None, |name| files.contains_key(name));
+    let kind = |name: &str| report.nodes.iter()
+      .find(|node| node.name == name).map(|node| (node.kind, node.missing));
+    assert_eq!(kind("shared/paint.mtl"), Some((NodeKind::Material, false)));
+    assert_eq!(kind("shared/tex/red.png"), Some((NodeKind::Texture, true)));
+    assert_eq!(kind("walk_####.obj"), Some((NodeKind::Sequence, false)));
+    assert_eq!(kind(manifest::MANIFEST_FILE),
+      Some((NodeKind::Manifest, false)));
+  }
+
+  #[test]
+  fn forgets_what_changed_files_needed() {
+    let mut graph = Graph::default();
+    graph.changed("a.obj", b"mtllib a.mtl\n");
+    assert!(graph.is_needed("a.mtl"));
+    graph.changed("a.obj", b"mtllib b.mtl\n");
+    assert!(!graph.is_needed("a.mtl"));
+    graph.removed("a.obj");
+    assert!(!graph.is_needed("b.mtl"));
+    // Frame numbers need enough digits to be a sequence
+    graph.changed("part1.obj", b"");
+    graph.changed("part2.obj", b"");
+    assert!(graph.report(None, |_| true).edges.is_empty());
+  }
+}
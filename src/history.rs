@@ -0,0 +1,410 @@
+//! Earlier versions of scene files, kept whenever the server itself
+//! rewrites a file so that no server-side edit is destructive.
+//!
+//! Versions are stored as `<dir>/<id>.<ext>`, with the scene file's own
+//! extension, and one JSON line per version appended to
+//! `<dir>/index.jsonl`. When the scene is in a git
+//! repository, each commit that lands ties the versions recorded since
+//! the previous one to it, with a line in `<dir>/commits.jsonl`.
+//!
+//! Every change the server sees to a scene file is also logged to
+//! `<dir>/changes.jsonl`, with the new contents stored once per content
+//! hash as `<dir>/<hash>.<ext>`. Together with the versions above, that
+//! tells which version of each file existed at a given time.
+
+use crate::cache::content_hash;
+use crate::formats;
+use crate::provenance::Provenance;
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+  pub id: String,
+  /// Scene file this is an earlier version of
+  pub file: String,
+  /// Milliseconds since the Unix epoch
+  pub timestamp: u64,
+  /// What replaced this version, e.g. "normalize"
+  pub reason: String,
+  /// Of the stored file, without the dot
+  #[serde(default = "obj")]
+  pub extension: String,
+  /// The first commit made after this version was recorded
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub commit: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+  Added,
+  Modified,
+  Removed,
+}
+
+/// A change to a scene file, as logged to `changes.jsonl`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoggedChange {
+  /// Milliseconds since the Unix epoch
+  pub time: u64,
+  pub file: String,
+  pub change: ChangeKind,
+  /// The version the file changed to, absent for a removal
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  /// Of that version's stored file, without the dot
+  #[serde(default = "obj")]
+  pub extension: String,
+  /// The generator run that produced that version, if it was uploaded
+  /// with one
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provenance: Option<Provenance>,
+}
+
+/// A file as it was at some time.
+#[derive(Clone, Serialize)]
+pub struct FileAt {
+  pub file: String,
+  /// The stored version it had, if it was kept
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  /// Whether it's unchanged since, so the scene file itself is that
+  /// version
+  pub current: bool,
+}
+
+// A line of commits.jsonl
+#[derive(Serialize, Deserialize)]
+struct CommitTag {
+  commit: String,
+  ids: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct History {
+  dir: PathBuf,
+}
+
+impl History {
+  pub fn new(dir: PathBuf) -> Self {
+    History { dir }
+  }
+
+  /// Store the current contents of a file before it gets replaced.
+  pub fn record(&self, file: &str, contents: &[u8], reason: &str)
+      -> io::Result<HistoryEntry> {
+    fs::create_dir_all(&self.dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+      .map(|d| d.as_millis() as u64)
+      .unwrap_or(0);
+
+    // Millisecond timestamps can collide on fast successive edits
+    let taken: HashSet<String> =
+      self.entries()?.into_iter().map(|entry| entry.id).collect();
+    let mut id = format!("{}", timestamp);
+    let mut n = 1;
+    while taken.contains(&id) {
+      id = format!("{}-{}", timestamp, n);
+      n += 1;
+    }
+
+    let extension = extension_of(file);
+    fs::write(self.version_path(&id, &extension), contents)?;
+    let entry = HistoryEntry {
+      id,
+      file: file.to_string(),
+      timestamp,
+      reason: reason.to_string(),
+      extension,
+      commit: None,
+    };
+    let mut index = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(self.dir.join("index.jsonl"))?;
+    writeln!(index, "{}", serde_json::to_string(&entry)?)?;
+    Ok(entry)
+  }
+
+  /// Every recorded version, oldest first, with its commit if it has
+  /// one. Unreadable index lines are skipped.
+  pub fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+    let mut entries: Vec<HistoryEntry> =
+      read_lines(&self.dir.join("index.jsonl"))?;
+    let commits: HashMap<String, String> =
+      read_lines::<CommitTag>(&self.dir.join("commits.jsonl"))?
+        .into_iter()
+        .flat_map(|tag| {
+          let commit = tag.commit;
+          tag.ids.into_iter().map(move |id| (id, commit.clone()))
+        })
+        .collect();
+    for entry in &mut entries {
+      entry.commit = commits.get(&entry.id).cloned();
+    }
+    Ok(entries)
+  }
+
+  /// Log a change to a file, storing `contents` as its new version
+  /// unless that content is already stored.
+  pub fn log_change(&self, file: &str, change: ChangeKind,
+      contents: Option<&[u8]>, provenance: Option<Provenance>, time: u64)
+      -> io::Result<LoggedChange> {
+    fs::create_dir_all(&self.dir)?;
+    let version = contents.map(content_hash);
+    let extension = extension_of(file);
+    if let (Some(version), Some(contents)) = (&version, contents) {
+      let path = self.version_path(version, &extension);
+      if !path.exists() {
+        write_atomic(&path, contents)?;
+      }
+    }
+    let logged = LoggedChange {
+      time,
+      file: file.to_string(),
+      change,
+      version,
+      extension,
+      provenance,
+    };
+    let mut log = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(self.dir.join("changes.jsonl"))?;
+    writeln!(log, "{}", serde_json::to_string(&logged)?)?;
+    Ok(logged)
+  }
+
+  /// Every logged change, oldest first.
+  pub fn changes(&self) -> io::Result<Vec<LoggedChange>> {
+    read_lines(&self.dir.join("changes.jsonl"))
+  }
+
+  /// Each file that existed at `time`, with the version it had then.
+  /// `current` names the files in the scene now. A file with nothing
+  /// logged before `time` had the contents the server replaced after
+  /// it, or failing that, is taken to be unchanged since.
+  pub fn scene_at(&self, time: u64, current: &[String])
+      -> io::Result<Vec<FileAt>> {
+    let changes = self.changes()?;
+    let entries = self.entries()?;
+    let files: BTreeSet<&str> = current.iter().map(String::as_str)
+      .chain(changes.iter().map(|change| change.file.as_str()))
+      .chain(entries.iter().map(|entry| entry.file.as_str()))
+      .collect();
+
+    let mut scene = Vec::new();
+    for file in files {
+      let last = changes.iter().rev()
+        .find(|change| change.file == file && change.time <= time);
+      let next = changes.iter()
+        .find(|change| change.file == file && change.time > time);
+      // The first of the server's own replacements after `time`
+      let replaced = entries.iter()
+        .find(|entry| entry.file == file && entry.timestamp > time);
+      let at = |version: Option<String>, current: bool| FileAt {
+        file: file.to_string(),
+        version,
+        current,
+      };
+      scene.push(match (last, replaced) {
+        (Some(last), _) if last.change == ChangeKind::Removed => continue,
+        (Some(last), _) => at(last.version.clone(),
+          next.is_none() && replaced.is_none()),
+        // Server edits are recorded just before the change is logged
+        (None, Some(entry))
+            if next.is_none_or(|next| entry.timestamp <= next.time) =>
+          at(Some(entry.id.clone()), false),
+        (None, _) => match next {
+          Some(next) if next.change == ChangeKind::Added => continue,
+          // Changed by something else before logging began
+          Some(_) => at(None, false),
+          None if current.iter().any(|name| name == file) => at(None, true),
+          None => continue,
+        },
+      });
+    }
+    Ok(scene)
+  }
+
+  /// Tie every version not yet tied to a commit to `commit`. Returns
+  /// how many versions that was.
+  pub fn tag_commit(&self, commit: &str) -> io::Result<usize> {
+    let ids: Vec<String> = self.entries()?
+      .into_iter()
+      .filter(|entry| entry.commit.is_none())
+      .map(|entry| entry.id)
+      .collect();
+    if ids.is_empty() {
+      return Ok(0);
+    }
+    let count = ids.len();
+    let tag = CommitTag { commit: commit.to_string(), ids };
+    let mut commits = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(self.dir.join("commits.jsonl"))?;
+    writeln!(commits, "{}", serde_json::to_string(&tag)?)?;
+    Ok(count)
+  }
+
+  /// Remove versions, keeping the newest `keep` of each file and any
+  /// recorded at or after `before` (milliseconds since the epoch).
+  /// Logged changes are trimmed the same way, except that each file's
+  /// newest is kept. Returns the removed versions.
+  pub fn prune(&self, keep: usize, before: Option<u64>)
+      -> io::Result<Vec<HistoryEntry>> {
+    self.prune_changes(keep.max(1), before)?;
+    let entries = self.entries()?;
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut removed = HashSet::new();
+    for entry in entries.iter().rev() {
+      let newer = seen.entry(&entry.file).or_default();
+      *newer += 1;
+      if *newer > keep && before.is_none_or(|t| entry.timestamp < t) {
+        removed.insert(entry.id.clone());
+      }
+    }
+    if removed.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    // Rewrite the indexes before deleting, so an interrupted prune
+    // leaves stray files rather than entries without one
+    let (removed_entries, kept): (Vec<_>, Vec<_>) = entries.into_iter()
+      .partition(|entry| removed.contains(&entry.id));
+    let mut index = String::new();
+    for mut entry in kept {
+      entry.commit = None;
+      index += &(serde_json::to_string(&entry)? + "\n");
+    }
+    write_atomic(&self.dir.join("index.jsonl"), index.as_bytes())?;
+    let mut commits = String::new();
+    for mut tag in read_lines::<CommitTag>(&self.dir.join("commits.jsonl"))? {
+      tag.ids.retain(|id| !removed.contains(id));
+      if !tag.ids.is_empty() {
+        commits += &(serde_json::to_string(&tag)? + "\n");
+      }
+    }
+    write_atomic(&self.dir.join("commits.jsonl"), commits.as_bytes())?;
+    for entry in &removed_entries {
+      let _ = fs::remove_file(self.version_path(&entry.id, &entry.extension));
+    }
+    Ok(removed_entries)
+  }
+
+  // Drop logged changes past the newest `keep` of each file and older
+  // than `before`, with the contents only they refer to
+  fn prune_changes(&self, keep: usize, before: Option<u64>)
+      -> io::Result<()> {
+    let changes = self.changes()?;
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut dropped = vec![false; changes.len()];
+    for (i, change) in changes.iter().enumerate().rev() {
+      let newer = seen.entry(&change.file).or_default();
+      *newer += 1;
+      dropped[i] = *newer > keep && before.is_none_or(|t| change.time < t);
+    }
+    if !dropped.contains(&true) {
+      return Ok(());
+    }
+    let mut log = String::new();
+    let mut kept = HashSet::new();
+    for (change, _) in changes.iter().zip(&dropped).filter(|(_, d)| !**d) {
+      if let Some(version) = &change.version {
+        kept.insert(self.version_path(version, &change.extension));
+      }
+      log += &(serde_json::to_string(change)? + "\n");
+    }
+    write_atomic(&self.dir.join("changes.jsonl"), log.as_bytes())?;
+    for (change, _) in changes.iter().zip(&dropped).filter(|(_, d)| **d) {
+      if let Some(path) = change.version.as_ref()
+          .map(|version| self.version_path(version, &change.extension))
+          .filter(|path| !kept.contains(path)) {
+        let _ = fs::remove_file(path);
+      }
+    }
+    Ok(())
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  pub fn version_path(&self, id: &str, extension: &str) -> PathBuf {
+    self.dir.join(format!("{}.{}", id, extension))
+  }
+
+  /// The stored file of a version, by the ID from `entries` or a
+  /// logged change's content hash.
+  pub fn find_version(&self, id: &str) -> io::Result<Option<PathBuf>> {
+    if let Some(entry) = self.entries()?.into_iter()
+        .find(|entry| entry.id == id) {
+      return Ok(Some(self.version_path(id, &entry.extension)));
+    }
+    Ok(self.changes()?.into_iter()
+      .find(|change| change.version.as_deref() == Some(id))
+      .map(|change| self.version_path(id, &change.extension)))
+  }
+}
+
+// Versions of files in no known format are kept as opaque bytes
+fn extension_of(file: &str) -> String {
+  formats::format_of(file)
+    .map_or("bin", |format| format.extension)
+    .to_string()
+}
+
+// Versions recorded before extensions were stored were all OBJ
+fn obj() -> String {
+  "obj".to_string()
+}
+
+// Every parseable JSON line of a file; a missing file has none
+fn read_lines<T: serde::de::DeserializeOwned>(path: &Path)
+    -> io::Result<Vec<T>> {
+  match fs::read_to_string(path) {
+    Ok(text) => Ok(text.lines()
+      .filter_map(|line| serde_json::from_str(line).ok())
+      .collect()),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+    Err(e) => Err(e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keeps_extensions() {
+    let dir = std::env::temp_dir()
+      .join(format!("kitbash-history-{}", std::process::id()));
+    let history = History::new(dir.clone());
+    let entry = history.record("part.stl", b"solid", "upload").unwrap();
+    assert_eq!(entry.extension, "stl");
+    let logged = history.log_change("part.ply", ChangeKind::Added,
+      Some(b"ply"), None, 1).unwrap();
+    let version = logged.version.unwrap();
+
+    let path = history.find_version(&entry.id).unwrap().unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"solid");
+    assert!(path.ends_with(format!("{}.stl", entry.id)));
+    let path = history.find_version(&version).unwrap().unwrap();
+    assert!(path.ends_with(format!("{}.ply", version)));
+    assert!(history.find_version("missing").unwrap().is_none());
+
+    // Index lines from before extensions were stored are OBJ
+    let old: HistoryEntry = serde_json::from_str(
+      r#"{"id":"1","file":"a.obj","timestamp":1,"reason":"batch"}"#)
+      .unwrap();
+    assert_eq!(old.extension, "obj");
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,180 @@
+//! Just enough HTTP/1.1 for talking to other servers: a blocking GET
+//! for the HTTP scene source, and response parsing shared with the
+//! async client. Plain `http://` only; there's no TLS.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Response {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl Response {
+  /// First header of that name, compared case-insensitively.
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.iter()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// Fetch a URL with extra request headers. Any status is returned as a
+/// response; only connection and framing problems are errors.
+pub fn get(url: &str, headers: &[(&str, &str)]) -> io::Result<Response> {
+  let (host, path) = split_url(url)
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+      format!("expected an http:// URL, got {}", url)))?;
+  let addr = if host.contains(':') { host.to_string() }
+    else { format!("{}:80", host) };
+  let mut stream = TcpStream::connect(addr)?;
+  stream.set_read_timeout(Some(TIMEOUT))?;
+  stream.set_write_timeout(Some(TIMEOUT))?;
+
+  let mut head = format!(
+    "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", path, host);
+  for (name, value) in headers {
+    head += &format!("{}: {}\r\n", name, value);
+  }
+  head += "\r\n";
+  stream.write_all(head.as_bytes())?;
+
+  let mut response = Vec::new();
+  stream.read_to_end(&mut response)?;
+  parse_response(&response)
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Host (with any port) and path of an `http://` URL.
+pub fn split_url(url: &str) -> Option<(&str, &str)> {
+  let rest = url.strip_prefix("http://")?;
+  let (host, path) = match rest.find('/') {
+    Some(i) => (&rest[..i], &rest[i..]),
+    None => (rest, "/"),
+  };
+  (!host.is_empty()).then_some((host, path))
+}
+
+/// Parse a complete response, as read from a `Connection: close`
+/// request.
+pub fn parse_response(response: &[u8]) -> Result<Response, String> {
+  let end = response.windows(4).position(|w| w == b"\r\n\r\n")
+    .ok_or("incomplete response headers")?;
+  let head = std::str::from_utf8(&response[..end])
+    .map_err(|_| "response headers aren't UTF-8")?;
+  let mut lines = head.split("\r\n");
+  let status = lines.next()
+    .and_then(|line| line.split(' ').nth(1))
+    .and_then(|code| code.parse().ok())
+    .ok_or("malformed status line")?;
+  let headers: Vec<(String, String)> = lines
+    .filter_map(|line| line.split_once(':'))
+    .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+    .collect();
+
+  let mut response = Response {
+    status,
+    headers,
+    body: response[end + 4..].to_vec(),
+  };
+  if response.header("transfer-encoding")
+      .is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+    response.body = dechunk(&response.body)?;
+  }
+  Ok(response)
+}
+
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, String> {
+  let bad = || "malformed chunked body".to_string();
+  let mut out = Vec::new();
+  loop {
+    let line_end = body.windows(2).position(|w| w == b"\r\n")
+      .ok_or_else(bad)?;
+    let size = std::str::from_utf8(&body[..line_end]).ok()
+      .map(|line| line.split(';').next().unwrap_or("").trim())
+      .and_then(|size| usize::from_str_radix(size, 16).ok())
+      .ok_or_else(bad)?;
+    body = &body[line_end + 2..];
+    if size == 0 {
+      return Ok(out);
+    }
+    // The size is from the peer, so it may be anything
+    let end = size.checked_add(2).filter(|&end| end <= body.len())
+      .ok_or_else(bad)?;
+    out.extend_from_slice(&body[..size]);
+    body = &body[end..];
+  }
+}
+
+/// Percent-encode everything but unreserved characters.
+pub fn encode_path(name: &str) -> String {
+  name.bytes()
+    .map(|b| match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' =>
+        (b as char).to_string(),
+      _ => format!("%{:02X}", b),
+    })
+    .collect()
+}
+
+/// Undo percent-encoding; malformed escapes are kept as they are.
+pub fn decode_path(path: &str) -> String {
+  let bytes = path.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let escaped = (bytes[i] == b'%')
+      .then(|| bytes.get(i + 1..i + 3))
+      .flatten()
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match escaped {
+      Some(b) => {
+        out.push(b);
+        i += 3;
+      }
+      None => {
+        out.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dechunks_bodies() {
+    let body = b"5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n";
+    assert_eq!(dechunk(body).unwrap(), b"hello, world");
+    let response = parse_response(
+      b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+        3\r\nabc\r\n0\r\n\r\n").unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"abc");
+  }
+
+  #[test]
+  fn rejects_malformed_chunks() {
+    assert!(dechunk(b"5\r\nhel").is_err());
+    assert!(dechunk(b"zz\r\nhello\r\n0\r\n\r\n").is_err());
+    assert!(dechunk(b"5\r\nhello").is_err());
+    // A size that overflows when the CRLF is added
+    assert!(dechunk(format!("{:x}\r\nabc", usize::MAX).as_bytes()).is_err());
+    assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+  }
+
+  #[test]
+  fn encodes_paths() {
+    for name in ["a.obj", "dir/part 1.obj", "100%.obj", "ü.obj"] {
+      assert_eq!(decode_path(&encode_path(name)), name);
+    }
+  }
+}
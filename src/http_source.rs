@@ -0,0 +1,243 @@
+//! Scene files served over HTTP, e.g. by a render farm's file server or
+//! a public S3 bucket, so nothing has to be mounted locally.
+//!
+//! The index URL may return a JSON array of file names, an HTML page
+//! linking to the files (a typical directory listing) or an S3
+//! `ListObjects` result. Files are fetched relative to the index URL,
+//! under the S3 key prefix if the URL has a `prefix` query. There's no
+//! push notification over plain HTTP, so changes are found by `poll`,
+//! which uses ETags to keep unchanged files cheap.
+//!
+//! Requests are plain `http://` and unsigned, so only public buckets
+//! served over HTTP can be read: there's no HTTPS and no AWS request
+//! signing.
+
+use crate::cache::content_hash;
+use crate::events::FileEvent;
+use crate::formats;
+use crate::http;
+use crate::source::SceneSource;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::Mutex;
+
+pub struct HttpSource {
+  index_url: String,
+  /// Prefix that file names are appended to
+  base_url: String,
+  /// The S3 key prefix listed, which keys are fetched under
+  key_prefix: String,
+  state: Mutex<PollState>,
+}
+
+#[derive(Default)]
+struct PollState {
+  /// None until the first poll, which only records what's there
+  files: Option<BTreeMap<String, Version>>,
+  index_etag: Option<String>,
+}
+
+// What a file looked like at the last poll
+#[derive(Clone)]
+struct Version {
+  etag: Option<String>,
+  hash: String,
+}
+
+impl HttpSource {
+  pub fn new(index_url: &str) -> Self {
+    HttpSource {
+      index_url: index_url.to_string(),
+      base_url: base_url(index_url),
+      key_prefix: query_param(index_url, "prefix").unwrap_or_default(),
+      state: Mutex::default(),
+    }
+  }
+
+  /// Compare the server's files against the last poll. The first poll
+  /// returns no events. Files whose content didn't change produce no
+  /// event even if the server touched them.
+  pub fn poll(&self) -> io::Result<Vec<FileEvent>> {
+    let (known, index_etag) = {
+      let state = self.state.lock().unwrap();
+      (state.files.clone(), state.index_etag.clone())
+    };
+
+    let mut headers = Vec::new();
+    if let (Some(etag), Some(_)) = (&index_etag, &known) {
+      headers.push(("If-None-Match", etag.as_str()));
+    }
+    let response = http::get(&self.index_url, &headers)?;
+    let (names, index_etag) = match response.status {
+      304 => (known.iter().flatten().map(|(n, _)| n.clone()).collect(),
+        index_etag),
+      200 => (parse_index(&response.body, &self.index_url),
+        response.header("etag").map(str::to_string)),
+      status => return Err(status_error(&self.index_url, status)),
+    };
+
+    let mut events = Vec::new();
+    let mut files = BTreeMap::new();
+    for name in names {
+      let previous = known.as_ref().and_then(|k| k.get(&name));
+      let Some(version) = self.check(&name, previous)? else { continue };
+      match previous {
+        _ if known.is_none() => {}
+        None => events.push(FileEvent::added(name.clone())),
+        Some(p) if p.hash != version.hash =>
+          events.push(FileEvent::modified(name.clone())),
+        Some(_) => {}
+      }
+      files.insert(name, version);
+    }
+    for name in known.iter().flatten().map(|(n, _)| n) {
+      if !files.contains_key(name) {
+        events.push(FileEvent::Removed { filename: name.clone() });
+      }
+    }
+
+    *self.state.lock().unwrap() =
+      PollState { files: Some(files), index_etag };
+    Ok(events)
+  }
+
+  // The file's current version, None if it's gone from the server
+  fn check(&self, name: &str, previous: Option<&Version>)
+      -> io::Result<Option<Version>> {
+    let url = self.file_url(name);
+    let mut headers = Vec::new();
+    if let Some(etag) = previous.and_then(|p| p.etag.as_deref()) {
+      headers.push(("If-None-Match", etag));
+    }
+    let response = http::get(&url, &headers)?;
+    match response.status {
+      304 => Ok(previous.cloned()),
+      200 => Ok(Some(Version {
+        etag: response.header("etag").map(str::to_string),
+        hash: content_hash(&response.body),
+      })),
+      404 | 410 => Ok(None),
+      status => Err(status_error(&url, status)),
+    }
+  }
+
+  fn file_url(&self, name: &str) -> String {
+    // The prefix's slashes are folders in the URL
+    let prefix: Vec<String> =
+      self.key_prefix.split('/').map(http::encode_path).collect();
+    format!("{}{}{}", self.base_url, prefix.join("/"),
+      http::encode_path(name))
+  }
+}
+
+impl SceneSource for HttpSource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    let response = http::get(&self.index_url, &[])?;
+    if response.status != 200 {
+      return Err(status_error(&self.index_url, response.status));
+    }
+    Ok(parse_index(&response.body, &self.index_url))
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    let url = self.file_url(name);
+    let response = http::get(&url, &[])?;
+    match response.status {
+      200 => Ok(response.body),
+      404 | 410 => Err(io::Error::new(io::ErrorKind::NotFound,
+        format!("{} not found", url))),
+      status => Err(status_error(&url, status)),
+    }
+  }
+}
+
+fn status_error(url: &str, status: u16) -> io::Error {
+  io::Error::other(format!("{} answered HTTP {}", url, status))
+}
+
+// The index URL without its query, up to and including the last '/'
+fn base_url(index_url: &str) -> String {
+  let without_query = index_url.split(['?', '#']).next().unwrap_or("");
+  match http::split_url(without_query) {
+    Some((host, path)) => {
+      let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+      format!("http://{}{}", host, if dir.is_empty() { "/" } else { dir })
+    }
+    None => without_query.to_string(),
+  }
+}
+
+// Scene file names in an index page, sorted. Only files directly under
+// the index are included; anything in a subfolder is skipped.
+fn parse_index(body: &[u8], index_url: &str) -> Vec<String> {
+  let text = String::from_utf8_lossy(body);
+  let trimmed = text.trim_start();
+  let candidates: Vec<String> = if trimmed.starts_with('[') {
+    serde_json::from_str(trimmed).unwrap_or_default()
+  } else if text.contains("<ListBucketResult") {
+    // Keys are relative to the bucket; strip the listed prefix
+    let prefix = query_param(index_url, "prefix").unwrap_or_default();
+    tag_values(&text, "Key")
+      .into_iter()
+      .filter_map(|key| key.strip_prefix(prefix.as_str())
+        .map(str::to_string))
+      .collect()
+  } else {
+    // Links may be relative or absolute; keep the part below the
+    // index's own folder
+    let base_path = base_url(index_url);
+    let base_path = http::split_url(&base_path).map_or("/", |(_, p)| p);
+    attribute_values(&text, "href")
+      .into_iter()
+      .filter(|href| !href.contains(['?', '#']))
+      .map(|href| {
+        let path = http::split_url(&href).map_or(href.as_str(), |(_, p)| p);
+        http::decode_path(path.strip_prefix(base_path).unwrap_or(path))
+      })
+      .collect()
+  };
+
+  let names: BTreeSet<String> = candidates.into_iter()
+    .filter(|name| formats::is_scene_file(name)
+      && !name.starts_with('.')
+      && !name.contains(['/', '\\']))
+    .collect();
+  names.into_iter().collect()
+}
+
+// Text inside every <tag>...</tag>, with XML entities decoded
+fn tag_values(text: &str, tag: &str) -> Vec<String> {
+  let open = format!("<{}>", tag);
+  let close = format!("</{}>", tag);
+  text.split(open.as_str()).skip(1)
+    .filter_map(|rest| rest.split_once(close.as_str()))
+    .map(|(value, _)| decode_entities(value))
+    .collect()
+}
+
+// Values of every `name="..."` (or single-quoted) attribute
+fn attribute_values(text: &str, name: &str) -> Vec<String> {
+  let marker = format!("{}=", name);
+  text.split(marker.as_str()).skip(1)
+    .filter_map(|rest| {
+      let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+      rest[1..].split_once(quote).map(|(value, _)| decode_entities(value))
+    })
+    .collect()
+}
+
+fn decode_entities(text: &str) -> String {
+  text.replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&amp;", "&")
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+  let query = url.split_once('?')?.1;
+  query.split('&')
+    .filter_map(|pair| pair.split_once('='))
+    .find(|(key, _)| *key == name)
+    .map(|(_, value)| http::decode_path(value))
+}
@@ -0,0 +1,62 @@
+//! Scene files hidden from viewers without being deleted, such as
+//! reference or junk files in a directory the server can't change.
+//! Ignored files aren't listed and viewers get no events about them; the
+//! files themselves are left alone. The names are kept as a JSON list
+//! in one file, so they stay ignored after a restart.
+
+use crate::rewrite::write_atomic;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+pub struct IgnoredFiles {
+  path: PathBuf,
+  names: RwLock<BTreeSet<String>>,
+}
+
+impl IgnoredFiles {
+  /// The files ignored before, if `path` exists.
+  pub fn load(path: PathBuf) -> io::Result<IgnoredFiles> {
+    let names = match fs::read(&path) {
+      Ok(json) => serde_json::from_slice(&json)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+      Err(e) => return Err(e),
+    };
+    Ok(IgnoredFiles { path, names: RwLock::new(names) })
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.names.read().unwrap().contains(name)
+  }
+
+  /// Every ignored file, sorted.
+  pub fn names(&self) -> Vec<String> {
+    self.names.read().unwrap().iter().cloned().collect()
+  }
+
+  /// Ignore a file, or stop ignoring it. Returns whether that changed
+  /// anything.
+  pub fn set(&self, name: &str, ignored: bool) -> io::Result<bool> {
+    let mut names = self.names.write().unwrap();
+    if names.contains(name) == ignored {
+      return Ok(false);
+    }
+    let mut changed = names.clone();
+    if ignored {
+      changed.insert(name.to_string());
+    } else {
+      changed.remove(name);
+    }
+    if let Some(dir) = self.path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_vec_pretty(&changed)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&self.path, &json)?;
+    *names = changed;
+    Ok(true)
+  }
+}
@@ -0,0 +1,119 @@
+//! The viewer page's keyboard shortcuts. Each action has default keys,
+//! which the config's `[keys]` table can replace. Keys are named as
+//! browsers name them (`KeyboardEvent.key`): `"a"`, `"Tab"`,
+//! `"ArrowLeft"`, and so on. Case matters, so `"A"` is Shift+A.
+//!
+//! ```toml
+//! [keys]
+//! toggle_grid = ["g", "G"]
+//! select_previous = "ArrowLeft"
+//! select_next = "ArrowRight"
+//! ```
+//!
+//! A key taken by a configured action is dropped from any action left
+//! at its defaults, so keys can be swapped by naming both actions.
+
+use crate::config::{Config, Value};
+use std::collections::BTreeMap;
+
+pub struct Action {
+  pub name: &'static str,
+  pub description: &'static str,
+  /// Heading it's listed under in the help
+  pub group: &'static str,
+  pub defaults: &'static [&'static str],
+}
+
+const fn action(
+    name: &'static str,
+    group: &'static str,
+    defaults: &'static [&'static str],
+    description: &'static str) -> Action {
+  Action { name, description, group, defaults }
+}
+
+/// Every action, in the order the help lists them.
+pub const ACTIONS: &[Action] = &[
+  action("reset_camera", "Navigation", &["0"],
+    "Reset camera to initial position"),
+  action("view_front", "Navigation", &["1"], "Front view"),
+  action("view_back", "Navigation", &["2"], "Back view"),
+  action("view_right", "Navigation", &["3"], "Right view"),
+  action("view_left", "Navigation", &["4"], "Left view"),
+  action("view_top", "Navigation", &["5"], "Top view"),
+  action("view_bottom", "Navigation", &["6"], "Bottom view"),
+  action("select_previous", "Selection", &["["], "Select previous object"),
+  action("select_next", "Selection", &["]"], "Select next object"),
+  action("frame_selected", "View", &["f"], "Frame selected object"),
+  action("frame_all", "View", &["F"], "Frame all visible objects"),
+  action("toggle_file_list", "View", &["Tab"], "Toggle file list overlay"),
+  action("toggle_grid", "View", &["g", "G"], "Toggle grid visibility"),
+  action("cycle_wireframe", "View", &["w", "W"],
+    "Cycle wireframe mode (solid/solid+wire/wire)"),
+  action("larger_points", "View", &["+", "="], "Draw point clouds larger"),
+  action("smaller_points", "View", &["-"], "Draw point clouds smaller"),
+  action("toggle_hidden", "Object Management", &["h"],
+    "Hide/show selected object"),
+  action("show_all", "Object Management", &["H"], "Show all hidden objects"),
+  action("reload", "Object Management", &["r", "R"], "Reload all files"),
+  action("save_snapshot", "Object Management", &["s"],
+    "Save the view as a named snapshot"),
+];
+
+/// Keys bound to each action, by action name.
+pub type KeyMap = BTreeMap<String, Vec<String>>;
+
+/// The defaults, with the config's `[keys]` table applied.
+pub fn from_config(config: &Config) -> Result<KeyMap, String> {
+  let mut configured: KeyMap = KeyMap::new();
+  if let Some(table) = config.table(&["keys"]) {
+    for (name, value) in table {
+      if !ACTIONS.iter().any(|action| action.name == name) {
+        return Err(format!("keys: unknown action {}", name));
+      }
+      let keys: Option<Vec<String>> = match value {
+        Value::String(key) => Some(vec![key.clone()]),
+        Value::Array(items) => items.iter()
+          .map(|item| item.as_str().map(str::to_string))
+          .collect(),
+        _ => None,
+      };
+      let keys = keys.filter(|keys| keys.iter().all(|key| !key.is_empty()))
+        .ok_or_else(|| format!(
+          "keys: {} must be a key name or a list of them", name))?;
+      for key in &keys {
+        if let Some((other, _)) = configured.iter()
+            .find(|(_, taken)| taken.contains(key)) {
+          return Err(format!("keys: {:?} is bound to both {} and {}",
+            key, other, name));
+        }
+      }
+      configured.insert(name.clone(), keys);
+    }
+  }
+
+  let taken: Vec<&String> = configured.values().flatten().collect();
+  let mut map = KeyMap::new();
+  for action in ACTIONS {
+    let keys = match configured.get(action.name) {
+      Some(keys) => keys.clone(),
+      None => action.defaults.iter()
+        .filter(|key| !taken.iter().any(|taken| taken == *key))
+        .map(|key| key.to_string())
+        .collect(),
+    };
+    map.insert(action.name.to_string(), keys);
+  }
+  Ok(map)
+}
+
+/// How a key reads in the help, e.g. `Shift+f` for `F`.
+pub fn describe(key: &str) -> String {
+  let mut chars = key.chars();
+  match (chars.next(), chars.next()) {
+    (Some(c), None) if c.is_uppercase() =>
+      format!("{} (Shift+{})", c, c.to_lowercase()),
+    (Some(' '), None) => "Space".to_string(),
+    _ => key.to_string(),
+  }
+}
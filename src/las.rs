@@ -0,0 +1,206 @@
+//! Server-side LAS parsing, for lidar scans. Like XYZ and PCD (see
+//! `points`), a scan parses into a `Mesh` of positions, with vertex
+//! colours if its point format has them, and no triangles; the viewer
+//! draws it from `/api/files/:name/points.bin`.
+//!
+//! Scans often hold hundreds of millions of points, more than is worth
+//! keeping parsed or drawing, so a scan with more than the server's
+//! budget (`--max-points`, [`MAX_POINTS`] unless set) is decimated
+//! evenly as it's read: every nth point is kept.
+//!
+//! Point formats 0 to 10 of LAS 1.0 to 1.4 are read. Colours are 16-bit
+//! in the spec but 8-bit from some writers, so they're scaled by
+//! whichever range they fit; a scan whose colours are all black has
+//! none.
+//!
+//! LAZ, LASzip's compressed LAS, is out of scope: it's recognized and
+//! listed, but refused with an error asking for it to be decompressed
+//! to LAS first (e.g. with `laszip`), as decoding it would mean
+//! implementing LASzip's arithmetic coder.
+
+use crate::mesh::Mesh;
+
+/// Most points a scan is parsed into, unless the server sets its own.
+pub const MAX_POINTS: usize = 4_000_000;
+
+const SIGNATURE: &[u8] = b"LASF";
+
+// Bit set in the point format of LAZ files
+const COMPRESSED: u8 = 0x80;
+
+/// Whether the contents look like a LAS or LAZ file.
+pub fn is_las(bytes: &[u8]) -> bool {
+  bytes.starts_with(SIGNATURE)
+}
+
+/// Parse a LAS scan, decimated to at most `max_points` points.
+pub fn parse_las(bytes: &[u8], max_points: usize) -> Result<Mesh, String> {
+  let truncated = || "LAS header is truncated".to_string();
+  let u8_at = |at: usize| bytes.get(at).copied().ok_or_else(truncated);
+  let u16_at = |at: usize| bytes.get(at..at + 2)
+    .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+    .ok_or_else(truncated);
+  let u32_at = |at: usize| bytes.get(at..at + 4)
+    .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    .ok_or_else(truncated);
+  let u64_at = |at: usize| bytes.get(at..at + 8)
+    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    .ok_or_else(truncated);
+  let f64_at = |at: usize| u64_at(at).map(f64::from_bits);
+
+  let (major, minor) = (u8_at(24)?, u8_at(25)?);
+  if major != 1 || minor > 4 {
+    return Err(format!("LAS {}.{} isn't supported", major, minor));
+  }
+  let header_size = u16_at(94)? as usize;
+  let offset = u32_at(96)? as usize;
+  let format = u8_at(104)?;
+  if format & COMPRESSED != 0 {
+    return Err("LAZ is compressed, which isn't supported; decompress it \
+      to LAS first".to_string());
+  }
+  let record = u16_at(105)? as usize;
+  let Some(&(min_record, color)) = FORMATS.get(format as usize) else {
+    return Err(format!("LAS point format {} isn't supported", format));
+  };
+  if record < min_record {
+    return Err(format!("LAS point format {} needs {} bytes a point, not {}",
+      format, min_record, record));
+  }
+  // LAS 1.4 moved the count to a 64-bit field, leaving the old one 0
+  // when it doesn't fit
+  let mut count = u32_at(107)? as u64;
+  if count == 0 && minor >= 4 && header_size >= 255 {
+    count = u64_at(247)?;
+  }
+  let scale = [f64_at(131)?, f64_at(139)?, f64_at(147)?];
+  let origin = [f64_at(155)?, f64_at(163)?, f64_at(171)?];
+
+  let count = usize::try_from(count).map_err(|_| truncated())?;
+  let data = bytes.get(offset..).unwrap_or_default();
+  if data.len() / record < count {
+    return Err(format!("LAS file is truncated: it should have {} points, \
+      but has room for {}", count, data.len() / record));
+  }
+  let stride = count.div_ceil(max_points.max(1)).max(1);
+  let kept = count.div_ceil(stride);
+  let mut positions = Vec::with_capacity(kept);
+  let mut raw_colors =
+    Vec::with_capacity(if color.is_some() { kept } else { 0 });
+  for point in data.chunks_exact(record).take(count).step_by(stride) {
+    let int = |at: usize| {
+      i32::from_le_bytes(point[at..at + 4].try_into().unwrap()) as f64
+    };
+    positions.push([0, 1, 2].map(|axis|
+      int(4 * axis) * scale[axis] + origin[axis]));
+    if let Some(at) = color {
+      raw_colors.push([0, 1, 2].map(|channel| {
+        let at = at + 2 * channel;
+        u16::from_le_bytes(point[at..at + 2].try_into().unwrap())
+      }));
+    }
+  }
+
+  let brightest = raw_colors.iter().flatten().copied().max().unwrap_or(0);
+  let colors = if brightest == 0 {
+    Vec::new()
+  } else {
+    let range = if brightest <= 255 { 255.0 } else { 65535.0 };
+    raw_colors.iter()
+      .map(|rgb| rgb.map(|c| c as f32 / range))
+      .collect()
+  };
+  Ok(Mesh { positions, colors, ..Mesh::default() })
+}
+
+// Each point format's smallest record size, and where in the record its
+// red, green and blue are, if it has them
+const FORMATS: &[(usize, Option<usize>)] = &[
+  (20, None),
+  (28, None),
+  (26, Some(20)),
+  (34, Some(28)),
+  (57, None),
+  (63, Some(28)),
+  (30, None),
+  (36, Some(30)),
+  (38, Some(30)),
+  (59, None),
+  (67, Some(30)),
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A LAS 1.2 file of point format 2, which has colours
+  fn las(points: &[([i32; 3], [u16; 3])]) -> Vec<u8> {
+    let mut bytes = vec![0; 227];
+    bytes[..4].copy_from_slice(SIGNATURE);
+    bytes[24..26].copy_from_slice(&[1, 2]);
+    bytes[94..96].copy_from_slice(&227u16.to_le_bytes());
+    bytes[96..100].copy_from_slice(&227u32.to_le_bytes());
+    bytes[104] = 2;
+    bytes[105..107].copy_from_slice(&26u16.to_le_bytes());
+    bytes[107..111].copy_from_slice(&(points.len() as u32).to_le_bytes());
+    for axis in 0..3 {
+      let at = 131 + 8 * axis;
+      bytes[at..at + 8].copy_from_slice(&0.01f64.to_le_bytes());
+    }
+    bytes[155..163].copy_from_slice(&100.0f64.to_le_bytes());
+    for (position, color) in points {
+      let mut record = vec![0; 26];
+      for (axis, v) in position.iter().enumerate() {
+        record[4 * axis..4 * axis + 4].copy_from_slice(&v.to_le_bytes());
+      }
+      for (channel, c) in color.iter().enumerate() {
+        record[20 + 2 * channel..22 + 2 * channel]
+          .copy_from_slice(&c.to_le_bytes());
+      }
+      bytes.extend_from_slice(&record);
+    }
+    bytes
+  }
+
+  #[test]
+  fn parses_points_and_colours() {
+    let bytes = las(&[([100, 200, -300], [65535, 0, 0]),
+      ([0, 0, 0], [0, 0, 32768])]);
+    assert!(is_las(&bytes));
+    let mesh = parse_las(&bytes, MAX_POINTS).unwrap();
+    assert_eq!(mesh.positions, [[101.0, 2.0, -3.0], [100.0, 0.0, 0.0]]);
+    assert_eq!(mesh.colors[0], [1.0, 0.0, 0.0]);
+    assert!((mesh.colors[1][2] - 0.5).abs() < 0.01);
+
+    // Colours written 8-bit are scaled by 255
+    let bytes = las(&[([0, 0, 0], [255, 128, 0])]);
+    assert_eq!(parse_las(&bytes, MAX_POINTS).unwrap().colors[0][0], 1.0);
+  }
+
+  #[test]
+  fn decimates_to_the_budget() {
+    let points: Vec<_> = (0..10).map(|i| ([i, 0, 0], [0; 3])).collect();
+    let mesh = parse_las(&las(&points), 4).unwrap();
+    // Every third point
+    assert_eq!(mesh.positions.len(), 4);
+    assert_eq!(mesh.positions[1], [100.03, 0.0, 0.0]);
+    assert_eq!(parse_las(&las(&points), 10).unwrap().positions.len(), 10);
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    let bytes = las(&[([0, 0, 0], [0, 0, 0]); 2]);
+    assert!(parse_las(&bytes[..bytes.len() - 1], MAX_POINTS).is_err());
+    assert!(parse_las(&bytes[..100], MAX_POINTS).is_err());
+
+    let mut laz = bytes.clone();
+    laz[104] |= COMPRESSED;
+    assert!(parse_las(&laz, MAX_POINTS).is_err());
+    let mut short_records = bytes.clone();
+    short_records[105..107].copy_from_slice(&20u16.to_le_bytes());
+    assert!(parse_las(&short_records, MAX_POINTS).is_err());
+    let mut huge = bytes;
+    huge[107..111].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(parse_las(&huge, MAX_POINTS).is_err());
+  }
+}
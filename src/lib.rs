@@ -0,0 +1,62 @@
+//! Scene handling behind the kitbash-viewer server: mesh parsing and
+//! analysis, the scene manifest, and the sources scene files are read
+//! from. The `testing` module simulates a scene in memory.
+
+pub mod aliases;
+pub mod api;
+pub mod auth;
+pub mod busy;
+pub mod cache;
+pub mod checks;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod collada;
+pub mod config;
+pub mod deflate;
+pub mod dirs;
+pub mod dxf;
+pub mod events;
+pub mod filter;
+pub mod formats;
+pub mod geom;
+pub mod git;
+#[cfg(feature = "transcode")]
+pub mod glb;
+pub mod gltf;
+pub mod graph;
+pub mod history;
+pub mod http;
+pub mod http_source;
+pub mod ignore;
+pub mod keys;
+pub mod las;
+pub mod links;
+pub mod lock;
+pub mod manifest;
+pub mod materials;
+pub mod mesh;
+pub mod msgpack;
+pub mod mtl;
+pub mod order;
+pub mod palette;
+pub mod pipeline;
+pub mod ply;
+pub mod points;
+pub mod prefs;
+pub mod provenance;
+pub mod rewrite;
+pub mod saves;
+pub mod scene;
+pub mod screenshots;
+pub mod sessions;
+pub mod snapshots;
+pub mod source;
+pub mod stats;
+pub mod stl;
+pub mod testing;
+pub mod threemf;
+pub mod tree;
+pub mod usdz;
+pub mod uv;
+pub mod xml;
+pub mod zip;
@@ -0,0 +1,66 @@
+//! Symlink resolution rules, shared by the listing, WebDAV and the
+//! watcher.
+//!
+//! Without `--follow-symlinks`, symlinks in the scene directory are
+//! ignored everywhere: they aren't listed, walked or reported. With it,
+//! a link to a regular file is listed under the link's own name, and
+//! changes to the target are reported as changes to the link, even if
+//! the target lives outside the scene directory. Links to folders are
+//! walked by WebDAV listings. Dangling links are never listed.
+
+use crate::formats;
+use std::collections::HashMap;
+use std::fs::{self, DirEntry};
+use std::path::{Path, PathBuf};
+
+/// Whether a directory entry counts as a regular file.
+pub fn is_file(entry: &DirEntry, follow_symlinks: bool) -> bool {
+  match entry.file_type() {
+    Ok(t) if t.is_symlink() => follow_symlinks
+      && fs::metadata(entry.path()).is_ok_and(|m| m.is_file()),
+    Ok(t) => t.is_file(),
+    Err(_) => false,
+  }
+}
+
+/// Whether a directory entry counts as a folder to descend into.
+pub fn is_dir(entry: &DirEntry, follow_symlinks: bool) -> bool {
+  match entry.file_type() {
+    Ok(t) if t.is_symlink() => follow_symlinks
+      && fs::metadata(entry.path()).is_ok_and(|m| m.is_dir()),
+    Ok(t) => t.is_dir(),
+    Err(_) => false,
+  }
+}
+
+/// Whether a path is itself a symlink (without following it).
+pub fn is_symlink(path: &Path) -> bool {
+  fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Whether any part of `relative`, a `/`-separated path below `root`,
+/// is a symlink, which could lead out of `root`.
+pub fn through_symlink(root: &Path, relative: &str) -> bool {
+  let mut path = root.to_path_buf();
+  relative.split('/').filter(|s| !s.is_empty()).any(|segment| {
+    path.push(segment);
+    is_symlink(&path)
+  })
+}
+
+/// The symlinked OBJ files in a directory, keyed by the canonical path
+/// of their target. Several links may share one target.
+pub fn targets(dir: &Path) -> HashMap<PathBuf, Vec<String>> {
+  let mut targets: HashMap<PathBuf, Vec<String>> = HashMap::new();
+  for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+    let Some(name) = entry.file_name().to_str().map(str::to_string)
+    else { continue };
+    if !formats::is_scene_file(&name) || !is_symlink(&entry.path()) {
+      continue;
+    }
+    if let Ok(target) = fs::canonicalize(entry.path()) {
+      targets.entry(target).or_default().push(name);
+    }
+  }
+  targets
+}
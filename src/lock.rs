@@ -0,0 +1,158 @@
+//! Exclusive write access to the scene, for edit sessions on a shared
+//! server. Whoever holds the lock (a user, or a pipeline job with its
+//! key) may change scene files; everyone else gets 423 Locked until it's
+//! released or runs out.
+//!
+//! On a server with users, the lock belongs to the user who claimed it.
+//! On an open server there's no one to tell apart, so it belongs to
+//! whoever sends its key back in an `X-Scene-Lock` header.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// The lock as shown to everyone.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+  /// Who holds it: the user's name, or the name the claimer gave
+  pub holder: String,
+  /// What they're doing, e.g. "re-exporting the hull"
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub note: Option<String>,
+  /// Milliseconds since the Unix epoch
+  pub since: u64,
+  pub expires: u64,
+  /// The authenticated user who claimed it, if the server has users
+  #[serde(skip)]
+  user: Option<String>,
+  #[serde(skip)]
+  key: String,
+}
+
+/// Who is asking to change the scene.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Requester<'a> {
+  /// Authenticated user name
+  pub user: Option<&'a str>,
+  /// The `X-Scene-Lock` header
+  pub key: Option<&'a str>,
+}
+
+impl Lock {
+  /// Whether the lock is the requester's own.
+  pub fn held_by(&self, requester: Requester) -> bool {
+    let by_key = requester.key.is_some_and(|key|
+      crate::auth::constant_time_eq(key.as_bytes(), self.key.as_bytes()));
+    let by_user =
+      self.user.is_some() && self.user.as_deref() == requester.user;
+    by_key || by_user
+  }
+}
+
+/// The lock, if anyone holds it. Held in memory only, so restarting
+/// the server releases it.
+#[derive(Debug, Default)]
+pub struct SceneLock {
+  current: Mutex<Option<Lock>>,
+}
+
+impl SceneLock {
+  /// The lock, unless it has run out by `now`.
+  pub fn current(&self, now: u64) -> Option<Lock> {
+    let mut current = self.current.lock().unwrap();
+    if current.as_ref().is_some_and(|lock| lock.expires <= now) {
+      *current = None;
+    }
+    current.clone()
+  }
+
+  /// Claim the lock for `lifetime_ms` from `now`, or extend it if the
+  /// requester already holds it. Returns the lock and its key, or the
+  /// lock someone else holds.
+  pub fn claim(&self, requester: Requester, holder: &str,
+      note: Option<String>, now: u64, lifetime_ms: u64)
+      -> Result<(Lock, String), Lock> {
+    let mut current = self.current.lock().unwrap();
+    let key = match current.as_ref() {
+      Some(lock) if lock.expires > now && !lock.held_by(requester) =>
+        return Err(lock.clone()),
+      Some(lock) if lock.expires > now => lock.key.clone(),
+      _ => crate::auth::random_token(),
+    };
+    let lock = Lock {
+      holder: holder.to_string(),
+      note,
+      since: current.as_ref().filter(|lock| lock.expires > now)
+        .map_or(now, |lock| lock.since),
+      expires: now.saturating_add(lifetime_ms),
+      user: requester.user.map(str::to_string),
+      key: key.clone(),
+    };
+    *current = Some(lock.clone());
+    Ok((lock, key))
+  }
+
+  /// Release the lock if the requester holds it, or regardless with
+  /// `force`. Returns the lock someone else holds if it stays.
+  pub fn release(&self, requester: Requester, force: bool, now: u64)
+      -> Result<Option<Lock>, Lock> {
+    let mut current = self.current.lock().unwrap();
+    match current.take() {
+      Some(lock) if lock.expires <= now => Ok(None),
+      Some(lock) if force || lock.held_by(requester) => Ok(Some(lock)),
+      Some(lock) => {
+        *current = Some(lock.clone());
+        Err(lock)
+      }
+      None => Ok(None),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALICE: Requester = Requester { user: Some("alice"), key: None };
+  const BOB: Requester = Requester { user: Some("bob"), key: None };
+
+  #[test]
+  fn holders_keep_others_out_until_it_runs_out() {
+    let lock = SceneLock::default();
+    let (claimed, _) = lock.claim(ALICE, "alice", None, 1000, 500).unwrap();
+    assert_eq!(claimed.expires, 1500);
+    assert!(claimed.held_by(ALICE));
+    assert!(!claimed.held_by(BOB));
+
+    // Someone else is refused; the holder extends it from its start
+    assert_eq!(lock.claim(BOB, "bob", None, 1200, 500).unwrap_err().holder,
+      "alice");
+    let (extended, _) = lock.claim(ALICE, "alice", Some("hull".to_string()),
+      1400, 500).unwrap();
+    assert_eq!((extended.since, extended.expires), (1000, 1900));
+
+    // Run out, it's gone, and anyone may claim it
+    assert!(lock.current(1899).is_some());
+    assert!(lock.current(1900).is_none());
+    assert_eq!(lock.claim(BOB, "bob", None, 2000, 500).unwrap().0.since,
+      2000);
+  }
+
+  #[test]
+  fn keys_stand_in_for_users() {
+    let lock = SceneLock::default();
+    let anyone = Requester::default();
+    let (_, key) = lock.claim(anyone, "pipeline", None, 0, 1000).unwrap();
+    // Without users, no one else is the holder
+    assert!(!lock.current(1).unwrap().held_by(anyone));
+    let holder = Requester { user: None, key: Some(&key) };
+    assert!(lock.current(1).unwrap().held_by(holder));
+    assert_eq!(lock.claim(holder, "pipeline", None, 1, 1000).unwrap().1, key);
+
+    assert!(lock.release(anyone, false, 2).is_err());
+    assert!(lock.release(holder, false, 2).unwrap().is_some());
+    assert!(lock.current(2).is_none());
+
+    lock.claim(holder, "pipeline", None, 3, 1000).unwrap();
+    assert!(lock.release(anyone, true, 4).unwrap().is_some());
+  }
+}
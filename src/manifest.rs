@@ -0,0 +1,379 @@
+//! The scene manifest: per-file placement that lives alongside the OBJ
+//! files in the scene directory as `kitbash-scene.json`.
+//!
+//! Manifests carry a schema `version`. Older ones are migrated when
+//! loaded and written back at the current version the next time the
+//! server saves the manifest; manifests without one are version 0.
+//! Loading checks the whole file, reporting each problem with where it
+//! is: a line and column for malformed JSON, a JSON pointer (e.g.
+//! `/transforms/a.obj/scale`) otherwise.
+
+use crate::mesh::Bounds;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "kitbash-scene.json";
+
+/// The schema version this server writes.
+pub const MANIFEST_VERSION: u64 = 1;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+  /// Schema version; see [`MANIFEST_VERSION`]
+  #[serde(default)]
+  pub version: u64,
+  /// Placement of each file in the scene, keyed by filename
+  #[serde(default)]
+  pub transforms: BTreeMap<String, Transform>,
+  /// Free-form labels per file, used for filtering
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub tags: BTreeMap<String, Vec<String>>,
+  /// Files in the order the `manifest` listing order shows them; others
+  /// follow by name
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub order: Vec<String>,
+}
+
+/// Uniform scale followed by a translation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+  #[serde(default)]
+  pub translation: [f64; 3],
+  #[serde(default = "unit_scale")]
+  pub scale: f64,
+}
+
+fn unit_scale() -> f64 {
+  1.0
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Transform { translation: [0.0; 3], scale: 1.0 }
+  }
+}
+
+impl Transform {
+  pub fn apply(&self, p: [f64; 3]) -> [f64; 3] {
+    [
+      p[0] * self.scale + self.translation[0],
+      p[1] * self.scale + self.translation[1],
+      p[2] * self.scale + self.translation[2],
+    ]
+  }
+
+  pub fn apply_bounds(&self, bounds: Bounds) -> Bounds {
+    // A negative scale swaps the corners, so re-sort them
+    let mut out = Bounds {
+      min: self.apply(bounds.min),
+      max: self.apply(bounds.min),
+    };
+    out.extend(self.apply(bounds.max));
+    out
+  }
+}
+
+impl Manifest {
+  pub fn transform(&self, filename: &str) -> Transform {
+    self.transforms.get(filename).copied().unwrap_or_default()
+  }
+
+  pub fn tags(&self, filename: &str) -> &[String] {
+    self.tags.get(filename).map_or(&[], Vec::as_slice)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+  /// The manifest can't be used
+  Error,
+  /// Ignored, but probably not what was meant
+  Warning,
+}
+
+/// Something wrong in a manifest, and where.
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+  pub severity: Severity,
+  /// JSON pointer to the offending value; empty for the whole file
+  pub path: String,
+  /// Set for malformed JSON
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub column: Option<usize>,
+  pub message: String,
+}
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match (self.line, self.column) {
+      (Some(line), Some(column)) =>
+        write!(f, "line {} column {}: {}", line, column, self.message),
+      _ if self.path.is_empty() => f.write_str(&self.message),
+      _ => write!(f, "{}: {}", self.path, self.message),
+    }
+  }
+}
+
+/// What checking a manifest found.
+pub struct Validation {
+  /// The manifest, migrated to the current version; None if it has
+  /// errors
+  pub manifest: Option<Manifest>,
+  /// The version the text was written at, if it could be read
+  pub version: Option<u64>,
+  pub problems: Vec<Problem>,
+}
+
+/// Check manifest text and migrate it to the current version.
+pub fn validate(text: &str) -> Validation {
+  let mut problems = Vec::new();
+  let mut value: Value = match serde_json::from_str(text) {
+    Ok(value) => value,
+    Err(e) => return Validation {
+      manifest: None,
+      version: None,
+      problems: vec![Problem {
+        severity: Severity::Error,
+        path: String::new(),
+        line: Some(e.line()),
+        column: Some(e.column()),
+        // The location is in the fields already
+        message: e.to_string()
+          .trim_end_matches(&format!(" at line {} column {}", e.line(),
+            e.column()))
+          .to_string(),
+      }],
+    },
+  };
+  let mut error = |path: String, message: String| problems.push(Problem {
+    severity: Severity::Error, path, line: None, column: None, message,
+  });
+  let version = match value.get("version") {
+    None => Some(0),
+    Some(v) => match v.as_u64() {
+      Some(n) if n <= MANIFEST_VERSION => Some(n),
+      Some(n) => {
+        error("/version".into(), format!("version {} is newer than this \
+          server understands (up to {})", n, MANIFEST_VERSION));
+        None
+      }
+      None => {
+        error("/version".into(), "must be a whole number".into());
+        None
+      }
+    },
+  };
+  if !value.is_object() {
+    error(String::new(), "the manifest must be a JSON object".into());
+  }
+  if let (Some(version), Some(object)) = (version, value.as_object_mut()) {
+    migrate(object, version);
+    check(object, &mut problems);
+  }
+
+  let failed = problems.iter().any(|p| p.severity == Severity::Error);
+  let manifest = if failed {
+    None
+  } else {
+    match serde_json::from_value(value) {
+      Ok(manifest) => Some(manifest),
+      Err(e) => {
+        problems.push(Problem {
+          severity: Severity::Error,
+          path: String::new(),
+          line: None,
+          column: None,
+          message: e.to_string(),
+        });
+        None
+      }
+    }
+  };
+  Validation { manifest, version, problems }
+}
+
+// Bring a manifest written at `from` up to the current version, one
+// version at a time. Manifests from before versioning (0) already have
+// the version 1 layout, so for now that's just the version number.
+fn migrate(object: &mut Map<String, Value>, from: u64) {
+  debug_assert!(from <= MANIFEST_VERSION);
+  object.insert("version".into(), MANIFEST_VERSION.into());
+}
+
+// Check a current-version manifest's structure, which serde would
+// otherwise reject with no location, or skip over silently
+fn check(object: &Map<String, Value>, problems: &mut Vec<Problem>) {
+  let mut report = |severity, path: String, message: &str| {
+    problems.push(Problem {
+      severity, path, line: None, column: None, message: message.into(),
+    });
+  };
+  let error = Severity::Error;
+  for (key, value) in object {
+    let path = format!("/{}", pointer_escape(key));
+    match key.as_str() {
+      "version" => {}
+      "transforms" => {
+        let Some(transforms) = value.as_object() else {
+          report(error, path, "must be an object of file names to \
+            transforms");
+          continue;
+        };
+        for (file, transform) in transforms {
+          let path = format!("{}/{}", path, pointer_escape(file));
+          let Some(transform) = transform.as_object() else {
+            report(error, path, "must be an object with translation and \
+              scale");
+            continue;
+          };
+          for (field, value) in transform {
+            let path = format!("{}/{}", path, pointer_escape(field));
+            match field.as_str() {
+              "translation" => if !value.as_array().is_some_and(|xyz|
+                  xyz.len() == 3 && xyz.iter().all(Value::is_number)) {
+                report(error, path, "must be three numbers, [x, y, z]");
+              },
+              "scale" => match value.as_f64() {
+                None => report(error, path, "must be a number"),
+                Some(0.0) => report(error, path, "can't be 0"),
+                Some(_) => {}
+              },
+              _ => report(Severity::Warning, path, "unknown field, ignored"),
+            }
+          }
+        }
+      }
+      "tags" => {
+        let Some(tags) = value.as_object() else {
+          report(error, path, "must be an object of file names to tag \
+            lists");
+          continue;
+        };
+        for (file, list) in tags {
+          if !list.as_array()
+              .is_some_and(|list| list.iter().all(Value::is_string)) {
+            report(error, format!("{}/{}", path, pointer_escape(file)),
+              "must be a list of strings");
+          }
+        }
+      }
+      "order" => {
+        if !value.as_array()
+            .is_some_and(|names| names.iter().all(Value::is_string)) {
+          report(error, path, "must be a list of file names");
+        }
+      }
+      _ => report(Severity::Warning, path, "unknown key, ignored"),
+    }
+  }
+}
+
+// A key as a JSON pointer segment
+fn pointer_escape(key: &str) -> String {
+  key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Load the manifest from a scene directory, migrated to the current
+/// version; a missing file is an empty manifest, an unreadable or
+/// invalid one is an error naming the first problem.
+pub fn load(scene_dir: &Path) -> io::Result<Manifest> {
+  match fs::read_to_string(scene_dir.join(MANIFEST_FILE)) {
+    Ok(text) => {
+      let validation = validate(&text);
+      validation.manifest.ok_or_else(|| {
+        let first = validation.problems.iter()
+          .find(|p| p.severity == Severity::Error);
+        io::Error::new(io::ErrorKind::InvalidData,
+          first.map_or("invalid manifest".to_string(), Problem::to_string))
+      })
+    }
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+    Err(e) => Err(e),
+  }
+}
+
+/// Write the manifest at the current version, via a temporary file so
+/// readers never see a half-written manifest.
+pub fn save(scene_dir: &Path, manifest: &Manifest) -> io::Result<()> {
+  let manifest = Manifest { version: MANIFEST_VERSION, ..manifest.clone() };
+  let json = serde_json::to_string_pretty(&manifest)
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+  let tmp = scene_dir.join(format!(".{}.tmp", MANIFEST_FILE));
+  fs::write(&tmp, json)?;
+  fs::rename(&tmp, scene_dir.join(MANIFEST_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn migrates_unversioned_manifests() {
+    let validation = validate(r#"{
+      "transforms": { "a.obj": { "translation": [1, 2, 3] } },
+      "tags": { "a.obj": ["hull"] }
+    }"#);
+    assert_eq!(validation.version, Some(0));
+    assert!(validation.problems.is_empty());
+    let manifest = validation.manifest.unwrap();
+    assert_eq!(manifest.version, MANIFEST_VERSION);
+    assert_eq!(manifest.transform("a.obj"),
+      Transform { translation: [1.0, 2.0, 3.0], scale: 1.0 });
+    assert_eq!(manifest.tags("a.obj"), ["hull"]);
+    assert!(manifest.tags("b.obj").is_empty());
+  }
+
+  #[test]
+  fn locates_problems() {
+    // Malformed JSON by line and column
+    let validation = validate("{\n  \"transforms\": {,\n}");
+    assert!(validation.manifest.is_none());
+    let problem = &validation.problems[0];
+    assert_eq!((problem.line, problem.column), (Some(2), Some(18)));
+    assert!(problem.to_string().starts_with("line 2 column 18: "));
+
+    // Everything else by JSON pointer, escaping `/` and `~` in names
+    let validation = validate(r#"{
+      "version": 1,
+      "transforms": {
+        "parts/a~1.obj": { "scale": 0, "rotation": [0, 0, 0] },
+        "b.obj": { "translation": [1, 2] }
+      },
+      "tags": { "b.obj": "hull" },
+      "colour": "red"
+    }"#);
+    assert!(validation.manifest.is_none());
+    let problems: Vec<(Severity, &str)> = validation.problems.iter()
+      .map(|p| (p.severity, p.path.as_str()))
+      .collect();
+    assert_eq!(problems, [
+      (Severity::Warning, "/colour"),
+      (Severity::Error, "/tags/b.obj"),
+      (Severity::Error, "/transforms/b.obj/translation"),
+      (Severity::Warning, "/transforms/parts~1a~01.obj/rotation"),
+      (Severity::Error, "/transforms/parts~1a~01.obj/scale"),
+    ]);
+
+    // Warnings alone don't stop it loading
+    let validation = validate(r#"{ "colour": "red" }"#);
+    assert!(validation.manifest.is_some());
+    assert_eq!(validation.problems.len(), 1);
+  }
+
+  #[test]
+  fn refuses_newer_versions() {
+    let validation = validate(r#"{ "version": 99 }"#);
+    assert!(validation.manifest.is_none());
+    assert_eq!(validation.version, None);
+    assert_eq!(validation.problems[0].path, "/version");
+    assert!(validate(r#"[]"#).manifest.is_none());
+  }
+}
@@ -0,0 +1,138 @@
+//! Named material override sets: how every viewer shades the scene
+//! during a review (clay, MatCap, a UV checker or the files' own
+//! materials), with colours for particular files on top. Each set is
+//! stored as `<dir>/<name>.json`; the one in use is named in
+//! `<dir>/.active`, so it survives restarts and new viewers pick it up.
+
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const ACTIVE_FILE: &str = ".active";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shading {
+  /// The files' own materials, or their palette colours
+  #[default]
+  Original,
+  /// Matte grey, for judging form
+  Clay,
+  /// A lit-sphere material that ignores the scene's lights
+  Matcap,
+  /// A checkerboard over the files' UVs, for spotting stretching
+  UvChecker,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MaterialSet {
+  /// Taken from where it's stored
+  #[serde(default)]
+  pub name: String,
+  #[serde(default)]
+  pub shading: Shading,
+  /// `#rrggbb` by filename, tinting or replacing the shading's colour
+  #[serde(default)]
+  pub colors: BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+pub struct MaterialSets {
+  dir: PathBuf,
+}
+
+/// Whether a colour is `#rrggbb`.
+pub fn is_color(color: &str) -> bool {
+  color.len() == 7 && color.strip_prefix('#')
+    .is_some_and(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl MaterialSets {
+  pub fn new(dir: PathBuf) -> Self {
+    MaterialSets { dir }
+  }
+
+  /// Store a set under `name`, replacing any with that name. The name
+  /// must be safe to use as a file name.
+  pub fn save(&self, name: &str, mut set: MaterialSet)
+      -> io::Result<MaterialSet> {
+    set.name = name.to_string();
+    fs::create_dir_all(&self.dir)?;
+    let json = serde_json::to_vec_pretty(&set)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&self.path(name), &json)?;
+    Ok(set)
+  }
+
+  /// The set called `name`; NotFound if there's none.
+  pub fn load(&self, name: &str) -> io::Result<MaterialSet> {
+    let json = fs::read(self.path(name))?;
+    let mut set: MaterialSet = serde_json::from_slice(&json)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    set.name = name.to_string();
+    Ok(set)
+  }
+
+  /// Delete the set called `name`, no longer using it if it was in
+  /// use. NotFound if there's none.
+  pub fn remove(&self, name: &str) -> io::Result<()> {
+    fs::remove_file(self.path(name))?;
+    if self.active()?.as_deref() == Some(name) {
+      self.set_active(None)?;
+    }
+    Ok(())
+  }
+
+  /// Every set, sorted by name.
+  pub fn list(&self) -> io::Result<Vec<MaterialSet>> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e),
+    };
+    let mut names: Vec<String> = entries.flatten()
+      .filter_map(|entry| entry.file_name().to_str()
+        .and_then(|name| name.strip_suffix(".json"))
+        .filter(|name| !name.starts_with('.'))
+        .map(str::to_string))
+      .collect();
+    names.sort();
+    names.iter().map(|name| self.load(name)).collect()
+  }
+
+  /// The name of the set in use, if any.
+  pub fn active(&self) -> io::Result<Option<String>> {
+    match fs::read_to_string(self.dir.join(ACTIVE_FILE)) {
+      Ok(name) => Ok(Some(name.trim().to_string())
+        .filter(|name| !name.is_empty())),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Use the set called `name`, or the files' own materials.
+  pub fn set_active(&self, name: Option<&str>) -> io::Result<()> {
+    let path = self.dir.join(ACTIVE_FILE);
+    match name {
+      Some(name) => {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&path, name.as_bytes())
+      }
+      None => match fs::remove_file(&path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+      },
+    }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  fn path(&self, name: &str) -> PathBuf {
+    self.dir.join(format!("{}.json", name))
+  }
+}
@@ -0,0 +1,615 @@
+//! Server-side OBJ parsing; STL, glTF, PLY, 3MF, USDZ, COLLADA and DXF
+//! files go through `stl`, `gltf`, `ply`, `threemf`, `usdz`, `collada`
+//! and `dxf`, XYZ and PCD point clouds through `points` and LAS lidar
+//! scans through `las`.
+//!
+//! The browser does the real loading with three.js' OBJLoader; this is a
+//! deliberately small parser that gives the server enough geometry
+//! (positions and triangles) to answer questions about the scene.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Range;
+
+/// Most triangles, or vertices, a file may place. Formats that instance
+/// parts (COLLADA library nodes, 3MF components) can nest them so that
+/// a small file places billions of copies.
+pub const MAX_TRIANGLES: usize = 10_000_000;
+
+/// Fail once a mesh being placed holds more than [`MAX_TRIANGLES`]
+/// triangles or vertices.
+pub fn check_size(mesh: &Mesh) -> Result<(), String> {
+  if mesh.triangles.len().max(mesh.positions.len()) > MAX_TRIANGLES {
+    return Err(format!("file places over {} triangles or vertices",
+      MAX_TRIANGLES));
+  }
+  Ok(())
+}
+
+/// A parsed mesh: vertex positions plus fan-triangulated faces, split
+/// into the named objects/groups that appear in the file.
+#[derive(Default)]
+pub struct Mesh {
+  pub positions: Vec<[f64; 3]>,
+  pub triangles: Vec<[usize; 3]>,
+  pub objects:   Vec<SubObject>,
+  /// Texture coordinates, from OBJ `vt` statements
+  pub uvs:       Vec<[f64; 2]>,
+  /// Indices into `uvs` for each triangle's corners, in step with
+  /// `triangles`; None for faces without texture coordinates, and empty
+  /// if no face has any
+  pub uv_triangles: Vec<Option<[usize; 3]>>,
+  /// Vertex colours from 0 to 1, in step with `positions`; empty if the
+  /// file has none
+  pub colors:    Vec<[f32; 3]>,
+  /// Material names from OBJ `usemtl` statements, in order of first use
+  pub materials: Vec<String>,
+  /// Index into `materials` of each triangle's, in step with
+  /// `triangles`; None for faces before any `usemtl`, and empty if no
+  /// face has one
+  pub triangle_materials: Vec<Option<usize>>,
+  /// Material libraries from OBJ `mtllib` statements, as written
+  pub mtllibs:   Vec<String>,
+}
+
+/// A named `o`/`g` section of a mesh, as a range into `Mesh::triangles`.
+pub struct SubObject {
+  pub name:      String,
+  pub triangles: Range<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Bounds {
+  pub min: [f64; 3],
+  pub max: [f64; 3],
+}
+
+impl Bounds {
+  /// Grow to include a point.
+  pub fn extend(&mut self, p: [f64; 3]) {
+    for ((lo, hi), v) in self.min.iter_mut().zip(&mut self.max).zip(p) {
+      *lo = lo.min(v);
+      *hi = hi.max(v);
+    }
+  }
+
+  /// The region shared with another box, if they overlap with positive
+  /// volume (boxes that only touch don't count).
+  pub fn intersection(&self, other: &Bounds) -> Option<Bounds> {
+    let mut shared = *self;
+    for axis in 0..3 {
+      shared.min[axis] = self.min[axis].max(other.min[axis]);
+      shared.max[axis] = self.max[axis].min(other.max[axis]);
+      if shared.max[axis] <= shared.min[axis] {
+        return None;
+      }
+    }
+    Some(shared)
+  }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+  pub line:    usize,
+  pub message: String,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a scene file in any supported format. Formats are told apart
+/// by content rather than file name, as parses are cached by content.
+pub fn parse_file(bytes: &[u8]) -> Result<Mesh, String> {
+  parse_file_with(bytes, ParseOptions::default())
+}
+
+/// `parse_file`, with the server's own limits.
+pub fn parse_file_with(bytes: &[u8], options: ParseOptions)
+    -> Result<Mesh, String> {
+  parse_faces_with(bytes, options).map(Parsed::triangulate)
+}
+
+/// Limits a server can set on what parsing keeps.
+#[derive(Clone, Copy, Debug)]
+pub struct ParseOptions {
+  /// Most points a LAS scan is decimated to
+  pub max_points: usize,
+}
+
+impl Default for ParseOptions {
+  fn default() -> Self {
+    ParseOptions { max_points: crate::las::MAX_POINTS }
+  }
+}
+
+/// A parsed scene file. OBJ faces are left as polygons until
+/// `triangulate`, so `bench` can time the two steps apart; every other
+/// format comes out of its parser as triangles.
+pub enum Parsed {
+  Mesh(Mesh),
+  Polygons(Polygons),
+}
+
+impl Parsed {
+  pub fn triangulate(self) -> Mesh {
+    match self {
+      Parsed::Mesh(mesh) => mesh,
+      Parsed::Polygons(polygons) => polygons.triangulate(),
+    }
+  }
+}
+
+/// `parse_file`, without triangulating OBJ faces.
+pub fn parse_faces(bytes: &[u8]) -> Result<Parsed, String> {
+  parse_faces_with(bytes, ParseOptions::default())
+}
+
+fn parse_faces_with(bytes: &[u8], options: ParseOptions)
+    -> Result<Parsed, String> {
+  // First, as its signature is certain and a scan could have the size
+  // a binary STL's header gives
+  if crate::las::is_las(bytes) {
+    return crate::las::parse_las(bytes, options.max_points).map(Parsed::Mesh);
+  }
+  if crate::stl::is_stl(bytes) {
+    return crate::stl::parse_stl(bytes).map(Parsed::Mesh);
+  }
+  if crate::gltf::is_gltf(bytes) {
+    return crate::gltf::parse_gltf(bytes).map(Parsed::Mesh);
+  }
+  if crate::ply::is_ply(bytes) {
+    return crate::ply::parse_ply(bytes).map(Parsed::Mesh);
+  }
+  // Before 3MF, which takes any zip
+  if crate::usdz::is_usdz(bytes) {
+    return crate::usdz::parse_usdz(bytes).map(Parsed::Mesh);
+  }
+  if crate::threemf::is_3mf(bytes) {
+    return crate::threemf::parse_3mf(bytes).map(Parsed::Mesh);
+  }
+  if crate::collada::is_dae(bytes) {
+    return crate::collada::parse_dae(bytes).map(Parsed::Mesh);
+  }
+  if crate::dxf::is_dxf(bytes) {
+    return crate::dxf::parse_dxf(bytes).map(Parsed::Mesh);
+  }
+  if crate::points::is_pcd(bytes) {
+    return crate::points::parse_pcd(bytes).map(Parsed::Mesh);
+  }
+  // Last, as OBJ statements never start with a number
+  if crate::points::is_xyz(bytes) {
+    return crate::points::parse_xyz(bytes).map(Parsed::Mesh);
+  }
+  let text = std::str::from_utf8(bytes)
+    .map_err(|_| "file is not valid UTF-8".to_string())?;
+  parse_polygons(text).map(Parsed::Polygons).map_err(|e| e.to_string())
+}
+
+/// OBJ geometry with its faces as written, before triangulation.
+pub struct Polygons {
+  positions: Vec<[f64; 3]>,
+  uvs:       Vec<[f64; 2]>,
+  colors:    Vec<[f32; 3]>,
+  /// Every face's position indices, one face after another
+  corners:    Vec<usize>,
+  /// Texture coordinate index of each corner, in step with `corners`
+  uv_corners: Vec<Option<usize>>,
+  /// Where each face starts in `corners`
+  faces:      Vec<usize>,
+  /// As in `Mesh`, but with each face's material
+  materials:      Vec<String>,
+  face_materials: Vec<Option<usize>>,
+  mtllibs:        Vec<String>,
+  /// As in `Mesh`, but with ranges of faces rather than triangles
+  objects:    Vec<SubObject>,
+}
+
+/// Parse OBJ text. Only `v`, `vt`, `f`, `o`, `g`, `usemtl` and `mtllib`
+/// statements are interpreted; everything else (normals, smoothing
+/// groups) is skipped.
+pub fn parse_obj(text: &str) -> Result<Mesh, ParseError> {
+  parse_polygons(text).map(Polygons::triangulate)
+}
+
+/// Parse a number, refusing `inf` and `nan`, which Rust's float parser
+/// accepts but would poison bounds and the viewer's camera.
+fn parse_finite<T>(token: &str) -> Option<T>
+where
+  T: std::str::FromStr + Copy + Into<f64>,
+{
+  token.parse::<T>().ok().filter(|&v| v.into().is_finite())
+}
+
+/// Parse OBJ text without triangulating its faces.
+pub fn parse_polygons(text: &str) -> Result<Polygons, ParseError> {
+  let mut positions = Vec::new();
+  let mut objects: Vec<SubObject> = Vec::new();
+  let mut uvs = Vec::new();
+  let mut colors = Vec::new();
+  let mut corners = Vec::new();
+  let mut uv_corners = Vec::new();
+  let mut faces = Vec::new();
+  let mut materials: Vec<String> = Vec::new();
+  let mut face_materials = Vec::new();
+  let mut material = None;
+  let mut mtllibs = Vec::new();
+
+  for (index, raw_line) in text.lines().enumerate() {
+    let line_no = index + 1;
+    let line = raw_line.split('#').next().unwrap_or("").trim();
+    let mut parts = line.split_whitespace();
+    let Some(keyword) = parts.next() else { continue };
+
+    match keyword {
+      "v" => {
+        let mut coords = [0.0; 3];
+        for coord in coords.iter_mut() {
+          let token = parts.next().ok_or_else(|| ParseError {
+            line:    line_no,
+            message: "vertex has fewer than 3 coordinates".to_string(),
+          })?;
+          *coord = parse_finite(token).ok_or_else(|| ParseError {
+            line:    line_no,
+            message: format!("invalid vertex coordinate '{}'", token),
+          })?;
+        }
+        // Colours follow the position, as in `v x y z r g b`; vertices
+        // without one are white
+        let color: Vec<&str> = parts.take(3).collect();
+        if color.len() == 3 {
+          let mut rgb = [0.0; 3];
+          for (channel, token) in rgb.iter_mut().zip(color) {
+            *channel = parse_finite(token).ok_or_else(|| ParseError {
+              line:    line_no,
+              message: format!("invalid vertex colour '{}'", token),
+            })?;
+          }
+          colors.resize(positions.len(), [1.0; 3]);
+          colors.push(rgb);
+        } else if !colors.is_empty() {
+          colors.push([1.0; 3]);
+        }
+        positions.push(coords);
+      }
+      "vt" => {
+        // `v` and `w` may be left out
+        let mut coords = [0.0; 2];
+        for (i, coord) in coords.iter_mut().enumerate() {
+          let Some(token) = parts.next() else {
+            if i == 0 {
+              return Err(ParseError {
+                line:    line_no,
+                message: "texture coordinate has no u".to_string(),
+              });
+            }
+            break;
+          };
+          *coord = parse_finite(token).ok_or_else(|| ParseError {
+            line:    line_no,
+            message: format!("invalid texture coordinate '{}'", token),
+          })?;
+        }
+        uvs.push(coords);
+      }
+      "f" => {
+        let start = corners.len();
+        for token in parts {
+          corners.push(resolve_index(token, positions.len(), line_no)?);
+          uv_corners.push(resolve_uv_index(token, uvs.len(), line_no)?);
+        }
+        if corners.len() - start < 3 {
+          return Err(ParseError {
+            line:    line_no,
+            message: "face has fewer than 3 vertices".to_string(),
+          });
+        }
+        faces.push(start);
+        face_materials.push(material);
+      }
+      "o" | "g" => {
+        let name = parts.collect::<Vec<_>>().join(" ");
+        start_object(&mut objects, name, faces.len());
+      }
+      "usemtl" => {
+        let name = parts.collect::<Vec<_>>().join(" ");
+        material = Some(match materials.iter().position(|m| *m == name) {
+          Some(index) => index,
+          None => {
+            materials.push(name);
+            materials.len() - 1
+          }
+        });
+      }
+      "mtllib" => mtllibs.extend(parts.map(str::to_string)),
+      _ => {}
+    }
+  }
+
+  // Close the last object; faces before any o/g belong to an unnamed one
+  if let Some(last) = objects.last_mut() {
+    last.triangles.end = faces.len();
+  }
+  if objects.first().map_or(!faces.is_empty(),
+                            |first| first.triangles.start > 0) {
+    objects.insert(0, SubObject {
+      name:      String::new(),
+      triangles: 0..objects.first().map_or(faces.len(),
+                                           |o| o.triangles.start),
+    });
+  }
+  objects.retain(|o| !o.triangles.is_empty());
+
+  Ok(Polygons {
+    positions, uvs, colors, corners, uv_corners, faces, objects, materials,
+    face_materials, mtllibs,
+  })
+}
+
+impl Polygons {
+  /// Fan-triangulate every face, same as OBJLoader.
+  pub fn triangulate(self) -> Mesh {
+    let mut triangles = Vec::new();
+    let mut uv_triangles = Vec::new();
+    let mut triangle_materials = Vec::new();
+    // The first triangle of each face, plus one past the last triangle
+    let mut firsts = Vec::with_capacity(self.faces.len() + 1);
+    for (face, &start) in self.faces.iter().enumerate() {
+      firsts.push(triangles.len());
+      let end = self.faces.get(face + 1).copied()
+        .unwrap_or(self.corners.len());
+      let corners = &self.corners[start..end];
+      let uv = &self.uv_corners[start..end];
+      // Faces only get texture coordinates if every corner has one
+      let uv: Option<Vec<usize>> = uv.iter().copied().collect();
+      for i in 1..corners.len() - 1 {
+        triangles.push([corners[0], corners[i], corners[i + 1]]);
+        uv_triangles.push(uv.as_ref().map(|uv| [uv[0], uv[i], uv[i + 1]]));
+        triangle_materials.push(self.face_materials[face]);
+      }
+    }
+    firsts.push(triangles.len());
+    if uv_triangles.iter().all(Option::is_none) {
+      uv_triangles.clear();
+    }
+    if triangle_materials.iter().all(Option::is_none) {
+      triangle_materials.clear();
+    }
+    let objects = self.objects.into_iter()
+      .map(|o| SubObject {
+        name:      o.name,
+        triangles: firsts[o.triangles.start]..firsts[o.triangles.end],
+      })
+      .collect();
+
+    Mesh {
+      positions: self.positions,
+      triangles,
+      objects,
+      uvs: self.uvs,
+      uv_triangles,
+      colors: self.colors,
+      materials: self.materials,
+      triangle_materials,
+      mtllibs: self.mtllibs,
+    }
+  }
+}
+
+fn start_object(objects: &mut Vec<SubObject>, name: String, at: usize) {
+  if let Some(last) = objects.last_mut() {
+    last.triangles.end = at;
+  }
+  objects.push(SubObject { name, triangles: at..at });
+}
+
+// Turn a face corner ("7", "7/1", "7//3", "-1/...") into a 0-based
+// position index
+fn resolve_index(
+    token: &str,
+    vertex_count: usize,
+    line_no: usize) -> Result<usize, ParseError> {
+  let first = token.split('/').next().unwrap_or("");
+  resolve(first, token, vertex_count, "vertices", line_no)
+}
+
+// The 0-based texture coordinate index of a face corner ("7/1",
+// "7/1/3"), None if it has none ("7", "7//3")
+fn resolve_uv_index(
+    token: &str,
+    uv_count: usize,
+    line_no: usize) -> Result<Option<usize>, ParseError> {
+  match token.split('/').nth(1) {
+    Some(index) if !index.is_empty() =>
+      resolve(index, token, uv_count, "texture coordinates", line_no)
+        .map(Some),
+    _ => Ok(None),
+  }
+}
+
+// An OBJ index, 1-based or negative for counting back from the end
+fn resolve(
+    index: &str,
+    token: &str,
+    count: usize,
+    what: &str,
+    line_no: usize) -> Result<usize, ParseError> {
+  let index: i64 = index.parse().map_err(|_| ParseError {
+    line:    line_no,
+    message: format!("invalid face index '{}'", token),
+  })?;
+  let resolved = if index < 0 {
+    count as i64 + index
+  } else {
+    index - 1
+  };
+  if resolved < 0 || resolved >= count as i64 {
+    return Err(ParseError {
+      line:    line_no,
+      message: format!(
+        "face index {} out of range ({} {})", index, count, what),
+    });
+  }
+  Ok(resolved as usize)
+}
+
+impl Mesh {
+  /// Points without faces, as XYZ, PCD and LAS scans are.
+  pub fn is_point_cloud(&self) -> bool {
+    self.triangles.is_empty() && !self.positions.is_empty()
+  }
+
+  /// Bounds of all faces, or of all points for a point cloud; `None`
+  /// for an empty mesh.
+  pub fn bounds(&self) -> Option<Bounds> {
+    if self.triangles.is_empty() {
+      let (&first, rest) = self.positions.split_first()?;
+      let mut bounds = Bounds { min: first, max: first };
+      rest.iter().for_each(|&p| bounds.extend(p));
+      return Some(bounds);
+    }
+    self.triangle_bounds(0..self.triangles.len())
+  }
+
+  /// Bounds of the vertices referenced by a range of triangles.
+  pub fn triangle_bounds(&self, range: Range<usize>) -> Option<Bounds> {
+    let mut bounds: Option<Bounds> = None;
+    for triangle in &self.triangles[range] {
+      for &vi in triangle {
+        let p = self.positions[vi];
+        let b = bounds.get_or_insert(Bounds { min: p, max: p });
+        b.extend(p);
+      }
+    }
+    bounds
+  }
+
+  /// Hash of the geometry in a range of triangles, taken relative to
+  /// the minimum corner of its bounds so that translated copies of the
+  /// same part hash identically. Returns the hash and that corner (the
+  /// copy's offset).
+  pub fn geometry_hash(&self, range: Range<usize>)
+      -> Option<(String, [f64; 3])> {
+    let origin = self.triangle_bounds(range.clone())?.min;
+    let mut hasher = Fnv1a::new();
+    for triangle in &self.triangles[range] {
+      for &vi in triangle {
+        for (v, o) in self.positions[vi].iter().zip(origin) {
+          // Quantize so float noise from exporters doesn't break matches
+          let q = ((v - o) * 1e5).round() as i64;
+          hasher.write(&q.to_le_bytes());
+        }
+      }
+    }
+    Some((hasher.hex(), origin))
+  }
+}
+
+/// 64-bit FNV-1a; stable across runs and platforms, unlike `DefaultHasher`.
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+  fn default() -> Self {
+    Fnv1a(0xcbf29ce484222325)
+  }
+}
+
+impl Fnv1a {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn write(&mut self, bytes: &[u8]) {
+    for &b in bytes {
+      self.0 ^= b as u64;
+      self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+  }
+
+  pub fn hex(&self) -> String {
+    format!("{:016x}", self.0)
+  }
+
+  pub fn finish(&self) -> u64 {
+    self.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const OBJ: &str = "# two parts
+mtllib parts.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0 1 0 0
+vt 0 0
+vt 1 0
+vt 1 1
+f 1 2 3
+o quad
+usemtl red
+f 1/1 2/2 3/3 4
+g empty
+o tri
+usemtl blue
+f -4/-3 -3/-2 -2/-1
+";
+
+  #[test]
+  fn parses_obj() {
+    let mesh = parse_obj(OBJ).unwrap();
+    assert_eq!(mesh.positions.len(), 4);
+    // Faces are fan-triangulated and relative indices resolved
+    assert_eq!(mesh.triangles,
+      [[0, 1, 2], [0, 1, 2], [0, 2, 3], [0, 1, 2]]);
+    // Faces before any o/g go in an unnamed object; empty ones are
+    // dropped
+    let objects: Vec<(&str, Range<usize>)> = mesh.objects.iter()
+      .map(|o| (o.name.as_str(), o.triangles.clone()))
+      .collect();
+    assert_eq!(objects, [("", 0..1), ("quad", 1..3), ("tri", 3..4)]);
+    // Only faces with a texture coordinate at every corner get any
+    assert_eq!(mesh.uv_triangles,
+      [None, None, None, Some([0, 1, 2])]);
+    // Vertices without a colour are white
+    assert_eq!(mesh.colors[0], [1.0; 3]);
+    assert_eq!(mesh.colors[3], [1.0, 0.0, 0.0]);
+    // Faces before any `usemtl` have no material
+    assert_eq!(mesh.materials, ["red", "blue"]);
+    assert_eq!(mesh.triangle_materials, [None, Some(0), Some(0), Some(1)]);
+    assert_eq!(mesh.mtllibs, ["parts.mtl"]);
+  }
+
+  #[test]
+  fn parses_through_polygons() {
+    let mesh = parse_faces(OBJ.as_bytes()).unwrap();
+    assert!(matches!(mesh, Parsed::Polygons(_)));
+    assert_eq!(mesh.triangulate().triangles,
+      parse_obj(OBJ).unwrap().triangles);
+  }
+
+  #[test]
+  fn rejects_malformed_obj() {
+    let line = |text: &str| parse_obj(text).err().map(|e| e.line);
+    assert_eq!(line("v 0 0 0\nv 1 0 0\nf 1 2\n"), Some(3));
+    assert_eq!(line("v 0 0 0\nf 1 1 4\n"), Some(2));
+    assert_eq!(line("v 0 0 0\nf 1 1 -2\n"), Some(2));
+    assert_eq!(line("v 0 0 0\nf 1 1 0\n"), Some(2));
+    assert_eq!(line("v 0 zero 0\n"), Some(1));
+    assert_eq!(line("v 0 0\n"), Some(1));
+    assert_eq!(line("v 0 inf 0\n"), Some(1));
+    assert_eq!(line("v 0 0 NaN\n"), Some(1));
+    assert_eq!(line("v 0 0 0 1 -inf 1\n"), Some(1));
+    assert_eq!(line("vt nan\n"), Some(1));
+    assert_eq!(line("vt\n"), Some(1));
+    assert_eq!(line("v 0 0 0\nvt 0 0\nf 1/2 1/1 1/1\n"), Some(3));
+    assert!(parse_file(&[0xff, 0xfe, b'v']).is_err());
+  }
+}
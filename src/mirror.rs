@@ -0,0 +1,155 @@
+//! `--mirror <URL>`: keep the scene directory a copy of another
+//! instance's. Changed files are pulled as the remote reports them, and
+//! local viewers pick them up through the watcher like any other edit.
+//!
+//! Mirroring only pulls: local edits to mirrored files are overwritten
+//! by the next remote change. Files the mirror replaces or removes are
+//! kept in history first.
+
+use kitbash_viewer::cache::content_hash;
+use kitbash_viewer::client::{self, Client};
+use kitbash_viewer::events::FileEvent;
+use kitbash_viewer::history::History;
+use kitbash_viewer::{formats, links, manifest, rewrite};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Follow the remote instance forever, reconnecting whenever the
+/// connection drops.
+pub async fn run(remote: String, scene_dir: PathBuf, history: History) {
+  let mirror = Mirror { client: Client::new(&remote), scene_dir, history };
+  loop {
+    match mirror.follow().await {
+      Ok(()) => eprintln!("Mirror: {} closed the connection", remote),
+      Err(e) => eprintln!("Mirror: can't follow {}: {}", remote, e),
+    }
+    tokio::time::sleep(RETRY_DELAY).await;
+  }
+}
+
+struct Mirror {
+  client: Client,
+  scene_dir: PathBuf,
+  history: History,
+}
+
+impl Mirror {
+  async fn follow(&self) -> client::Result<()> {
+    let mut events = self.client.events().await?;
+    println!("Mirroring {} file(s)", events.snapshot().len());
+
+    // Catch up with everything that changed while disconnected
+    let remote: HashSet<String> = events.snapshot().iter()
+      .map(|f| f.name.clone())
+      .collect();
+    for file in events.snapshot() {
+      let local = fs::read(self.scene_dir.join(&file.name)).ok();
+      let unchanged = file.hash.is_some()
+        && local.map(|bytes| content_hash(&bytes)) == file.hash;
+      if !unchanged {
+        self.pull(&file.name).await;
+      }
+    }
+    for name in local_files(&self.scene_dir) {
+      if !remote.contains(&name) {
+        self.remove(&name).await;
+      }
+    }
+    self.pull(manifest::MANIFEST_FILE).await;
+
+    while let Some(event) = events.next().await {
+      // Changes made together by POST /api/batch arrive as one event
+      for event in event?.into_events() {
+        match event {
+          FileEvent::Added { filename, .. }
+          | FileEvent::Modified { filename, .. } =>
+            self.pull(&filename).await,
+          FileEvent::Removed { filename } => self.remove(&filename).await,
+          FileEvent::ManifestChanged =>
+            self.pull(manifest::MANIFEST_FILE).await,
+          _ => {}
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // Copy one file from the remote. Failures are logged; the next change
+  // to the file tries again.
+  async fn pull(&self, name: &str) {
+    if !is_local_name(name) {
+      eprintln!("Mirror: ignoring remote file {:?}", name);
+      return;
+    }
+    let contents = match self.client.download(name).await {
+      Ok(contents) => contents,
+      // No manifest on the remote is normal
+      Err(client::Error::Http { status: 404, .. })
+        if name == manifest::MANIFEST_FILE => return,
+      Err(e) => {
+        eprintln!("Mirror: failed to fetch {}: {}", name, e);
+        return;
+      }
+    };
+    let path = self.scene_dir.join(name);
+    let history = self.history.clone();
+    let file = name.to_string();
+    let written = tokio::task::spawn_blocking(move || {
+      let existing = fs::read(&path).ok();
+      if existing.as_ref() == Some(&contents) {
+        return Ok(false);
+      }
+      if let Some(existing) = existing {
+        history.record(&file, &existing, "mirror")?;
+      }
+      rewrite::write_atomic(&path, &contents)?;
+      Ok(true)
+    }).await.unwrap_or_else(|e| Err(io::Error::other(e)));
+    match written {
+      Ok(true) => println!("Mirrored {}", name),
+      Ok(false) => {}
+      Err(e) => eprintln!("Mirror: failed to write {}: {}", name, e),
+    }
+  }
+
+  async fn remove(&self, name: &str) {
+    if !is_local_name(name) {
+      return;
+    }
+    let path = self.scene_dir.join(name);
+    let history = self.history.clone();
+    let file = name.to_string();
+    let removed = tokio::task::spawn_blocking(move || {
+      let Ok(existing) = fs::read(&path) else { return Ok(false) };
+      history.record(&file, &existing, "mirror")?;
+      fs::remove_file(&path)?;
+      Ok(true)
+    }).await.unwrap_or_else(|e| Err(io::Error::other(e)));
+    match removed {
+      Ok(true) => println!("Mirror removed {}", name),
+      Ok(false) => {}
+      Err(e) => eprintln!("Mirror: failed to remove {}: {}", name, e),
+    }
+  }
+}
+
+// OBJ files directly in the scene directory. Symlinks aren't the
+// mirror's to remove, so they're left out.
+fn local_files(scene_dir: &Path) -> Vec<String> {
+  fs::read_dir(scene_dir).into_iter().flatten().flatten()
+    .filter(|entry| links::is_file(entry, false))
+    .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+    .filter(|name| formats::is_scene_file(name) && !name.starts_with('.'))
+    .collect()
+}
+
+// Remote names are only trusted to refer to files directly in the scene
+// directory
+fn is_local_name(name: &str) -> bool {
+  !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
@@ -0,0 +1,238 @@
+//! MessagePack encoding of JSON values, for WebSocket clients that
+//! negotiate the binary event encoding. Going through `serde_json::Value`
+//! keeps the field names and shapes identical to the JSON messages.
+
+use serde_json::{Map, Number, Value};
+
+pub fn encode(value: &Value) -> Vec<u8> {
+  let mut out = Vec::new();
+  write_value(&mut out, value);
+  out
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+  match value {
+    Value::Null => out.push(0xc0),
+    Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+    Value::Number(n) => write_number(out, n),
+    Value::String(s) => {
+      write_length(out, s.len(), 0xa0, 32, [0xd9, 0xda, 0xdb]);
+      out.extend_from_slice(s.as_bytes());
+    }
+    Value::Array(items) => {
+      write_length(out, items.len(), 0x90, 16, [0, 0xdc, 0xdd]);
+      for item in items {
+        write_value(out, item);
+      }
+    }
+    Value::Object(map) => {
+      write_length(out, map.len(), 0x80, 16, [0, 0xde, 0xdf]);
+      for (key, item) in map {
+        write_value(out, &Value::String(key.clone()));
+        write_value(out, item);
+      }
+    }
+  }
+}
+
+// A length prefix: packed into the tag below `fix_limit`, otherwise an
+// 8-, 16- or 32-bit length after one of `tags` (0 where there's none)
+fn write_length(
+    out: &mut Vec<u8>,
+    len: usize,
+    fix_tag: u8,
+    fix_limit: usize,
+    tags: [u8; 3]) {
+  if len < fix_limit {
+    out.push(fix_tag | len as u8);
+  } else if len <= u8::MAX as usize && tags[0] != 0 {
+    out.extend_from_slice(&[tags[0], len as u8]);
+  } else if len <= u16::MAX as usize {
+    out.push(tags[1]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+  } else {
+    out.push(tags[2]);
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+  }
+}
+
+fn write_number(out: &mut Vec<u8>, n: &Number) {
+  if let Some(u) = n.as_u64() {
+    match u {
+      0..=0x7f => out.push(u as u8),
+      0x80..=0xff => out.extend_from_slice(&[0xcc, u as u8]),
+      0x100..=0xffff => {
+        out.push(0xcd);
+        out.extend_from_slice(&(u as u16).to_be_bytes());
+      }
+      0x1_0000..=0xffff_ffff => {
+        out.push(0xce);
+        out.extend_from_slice(&(u as u32).to_be_bytes());
+      }
+      _ => {
+        out.push(0xcf);
+        out.extend_from_slice(&u.to_be_bytes());
+      }
+    }
+  } else if let Some(i) = n.as_i64() {
+    // Only negative values get here
+    if i >= -32 {
+      out.push(i as u8);
+    } else if i >= i8::MIN as i64 {
+      out.extend_from_slice(&[0xd0, i as u8]);
+    } else if i >= i16::MIN as i64 {
+      out.push(0xd1);
+      out.extend_from_slice(&(i as i16).to_be_bytes());
+    } else if i >= i32::MIN as i64 {
+      out.push(0xd2);
+      out.extend_from_slice(&(i as i32).to_be_bytes());
+    } else {
+      out.push(0xd3);
+      out.extend_from_slice(&i.to_be_bytes());
+    }
+  } else {
+    out.push(0xcb);
+    out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+  }
+}
+
+/// Decode one MessagePack value. Binary data becomes an array of byte
+/// values; extension types aren't supported.
+pub fn decode(bytes: &[u8]) -> Result<Value, String> {
+  let mut reader = Reader { bytes, pos: 0 };
+  let value = reader.value()?;
+  if reader.pos != bytes.len() {
+    return Err("trailing bytes after value".to_string());
+  }
+  Ok(value)
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl Reader<'_> {
+  fn take(&mut self, n: usize) -> Result<&[u8], String> {
+    let end = self.pos.checked_add(n)
+      .filter(|&end| end <= self.bytes.len())
+      .ok_or("unexpected end of data")?;
+    let slice = &self.bytes[self.pos..end];
+    self.pos = end;
+    Ok(slice)
+  }
+
+  // Big-endian unsigned integer of `n` bytes
+  fn uint(&mut self, n: usize) -> Result<u64, String> {
+    Ok(self.take(n)?.iter().fold(0, |acc, &b| acc << 8 | b as u64))
+  }
+
+  fn value(&mut self) -> Result<Value, String> {
+    let tag = self.take(1)?[0];
+    let value = match tag {
+      0x00..=0x7f => Value::from(tag),
+      0x80..=0x8f => self.map((tag & 0x0f) as usize)?,
+      0x90..=0x9f => self.array((tag & 0x0f) as usize)?,
+      0xa0..=0xbf => self.string((tag & 0x1f) as usize)?,
+      0xc0 => Value::Null,
+      0xc2 => Value::Bool(false),
+      0xc3 => Value::Bool(true),
+      0xc4..=0xc6 => {
+        let len = self.uint(1 << (tag - 0xc4))? as usize;
+        Value::from(self.take(len)?.to_vec())
+      }
+      0xca => Value::from(f32::from_bits(self.uint(4)? as u32) as f64),
+      0xcb => Value::from(f64::from_bits(self.uint(8)?)),
+      0xcc..=0xcf => Value::from(self.uint(1 << (tag - 0xcc))?),
+      0xd0 => Value::from(self.uint(1)? as u8 as i8),
+      0xd1 => Value::from(self.uint(2)? as u16 as i16),
+      0xd2 => Value::from(self.uint(4)? as u32 as i32),
+      0xd3 => Value::from(self.uint(8)? as i64),
+      0xd9..=0xdb => {
+        let len = self.uint(1 << (tag - 0xd9))? as usize;
+        self.string(len)?
+      }
+      0xdc | 0xdd => {
+        let len = self.uint(if tag == 0xdc { 2 } else { 4 })? as usize;
+        self.array(len)?
+      }
+      0xde | 0xdf => {
+        let len = self.uint(if tag == 0xde { 2 } else { 4 })? as usize;
+        self.map(len)?
+      }
+      0xe0..=0xff => Value::from(tag as i8),
+      _ => return Err(format!("unsupported MessagePack type 0x{:02x}", tag)),
+    };
+    Ok(value)
+  }
+
+  fn string(&mut self, len: usize) -> Result<Value, String> {
+    let bytes = self.take(len)?;
+    std::str::from_utf8(bytes)
+      .map(Value::from)
+      .map_err(|_| "string is not valid UTF-8".to_string())
+  }
+
+  fn array(&mut self, len: usize) -> Result<Value, String> {
+    // Don't trust the length for preallocation; it may be bogus
+    let mut items = Vec::new();
+    for _ in 0..len {
+      items.push(self.value()?);
+    }
+    Ok(Value::Array(items))
+  }
+
+  fn map(&mut self, len: usize) -> Result<Value, String> {
+    let mut map = Map::new();
+    for _ in 0..len {
+      let Value::String(key) = self.value()? else {
+        return Err("map keys must be strings".to_string());
+      };
+      map.insert(key, self.value()?);
+    }
+    Ok(Value::Object(map))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn round_trips() {
+    let long = "x".repeat(70_000);
+    let values = [
+      json!(null),
+      json!(true),
+      json!(-1),
+      json!(-200),
+      json!(300),
+      json!(u64::MAX),
+      json!(i64::MIN),
+      json!(0.5),
+      json!("a.obj"),
+      json!(long),
+      json!([1, "two", [3.5], {}]),
+      json!({"type": "added", "filename": "a.obj", "size": 1234}),
+      json!((0..20).collect::<Vec<_>>()),
+    ];
+    for value in values {
+      assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+  }
+
+  #[test]
+  fn rejects_malformed_input() {
+    let encoded = encode(&json!({"filename": "a.obj"}));
+    assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+    assert!(decode(&[encoded.as_slice(), &[0]].concat()).is_err());
+    assert!(decode(&[]).is_err());
+    // Extension types
+    assert!(decode(&[0xd4, 1, 0]).is_err());
+    // A string that isn't UTF-8
+    assert!(decode(&[0xa2, 0xff, 0xfe]).is_err());
+    // An array claiming far more items than there are bytes
+    assert!(decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+  }
+}
@@ -0,0 +1,92 @@
+//! MTL material libraries, as named by OBJ `mtllib` lines. Only what a
+//! GLB export can carry is read: the diffuse colour (`Kd`), opacity
+//! (`d`, or `Tr` as its inverse) and diffuse texture (`map_Kd`).
+
+/// One `newmtl` block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+  pub name: String,
+  /// sRGB, from 0 to 1
+  pub diffuse: [f32; 3],
+  pub opacity: f32,
+  /// The texture file, as written, relative to the library
+  pub diffuse_map: Option<String>,
+}
+
+impl Material {
+  fn new(name: String) -> Self {
+    // Same light grey as the viewer's default material
+    Material { name, diffuse: [0.8; 3], opacity: 1.0, diffuse_map: None }
+  }
+}
+
+/// Parse a material library. Statements outside a `newmtl` block and
+/// values that don't parse are skipped, as three.js' MTLLoader does.
+pub fn parse_mtl(text: &str) -> Vec<Material> {
+  let mut materials: Vec<Material> = Vec::new();
+  for line in text.lines() {
+    let line = line.split('#').next().unwrap_or("").trim();
+    let mut words = line.split_whitespace();
+    let Some(statement) = words.next() else { continue };
+    if statement == "newmtl" {
+      let name = words.collect::<Vec<_>>().join(" ");
+      materials.push(Material::new(name));
+      continue;
+    }
+    let Some(material) = materials.last_mut() else { continue };
+    let number = |word: Option<&str>| word
+      .and_then(|w| w.parse::<f32>().ok())
+      .filter(|v| v.is_finite());
+    match statement.to_lowercase().as_str() {
+      "kd" => {
+        let rgb: Vec<f32> = words.map_while(|w| number(Some(w))).collect();
+        if let [r, g, b, ..] = rgb[..] {
+          material.diffuse = [r, g, b];
+        }
+      }
+      "d" => if let Some(d) = number(words.next()) {
+        material.opacity = d.clamp(0.0, 1.0);
+      },
+      "tr" => if let Some(tr) = number(words.next()) {
+        material.opacity = (1.0 - tr).clamp(0.0, 1.0);
+      },
+      // Options such as `-s 1 1 1` come before the file
+      "map_kd" => material.diffuse_map = words.last().map(str::to_string),
+      _ => {}
+    }
+  }
+  materials
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_materials() {
+    let materials = parse_mtl("\
+      # exported\n\
+      Kd 0 0 0\n\
+      newmtl red paint\n\
+      Kd 1 0 0\n\
+      d 0.5\n\
+      map_Kd -s 2 2 1 textures/red.png\n\
+      newmtl plain\n\
+      Kd nan 1 1\n\
+      Tr 0.25\n");
+    assert_eq!(materials, [
+      Material {
+        name: "red paint".to_string(),
+        diffuse: [1.0, 0.0, 0.0],
+        opacity: 0.5,
+        diffuse_map: Some("textures/red.png".to_string()),
+      },
+      Material {
+        name: "plain".to_string(),
+        diffuse: [0.8; 3],
+        opacity: 0.75,
+        diffuse_map: None,
+      },
+    ]);
+  }
+}
@@ -0,0 +1,132 @@
+//! Orders for the file listing. `/api/files` and `/api/files.ndjson`
+//! take one as `?sort=`, and the config's `[viewer]` table picks the
+//! default, which the viewer also follows for its file list and `[`/`]`
+//! cycling:
+//!
+//! ```toml
+//! [viewer]
+//! order = "mtime"
+//! ```
+//!
+//! - `name`: by file name
+//! - `manifest`: as listed in the manifest's `order`, then by name
+//! - `mtime`: most recently changed first
+//! - `size`: by largest dimension
+//! - `triangles`: by triangle count
+//! - `bytes`: by file size
+//!
+//! A leading `-` reverses any of them, e.g. `-size` for largest first.
+
+use crate::api::FileInfo;
+use crate::config::Config;
+use crate::manifest::Manifest;
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+  #[default]
+  Name,
+  Manifest,
+  Mtime,
+  Size,
+  Triangles,
+  Bytes,
+}
+
+impl SortKey {
+  pub const ALL: [SortKey; 6] = [SortKey::Name, SortKey::Manifest,
+    SortKey::Mtime, SortKey::Size, SortKey::Triangles, SortKey::Bytes];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      SortKey::Name => "name",
+      SortKey::Manifest => "manifest",
+      SortKey::Mtime => "mtime",
+      SortKey::Size => "size",
+      SortKey::Triangles => "triangles",
+      SortKey::Bytes => "bytes",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Order {
+  pub key: SortKey,
+  pub descending: bool,
+}
+
+impl FromStr for Order {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Order, String> {
+    let (descending, name) = match s.strip_prefix('-') {
+      Some(name) => (true, name),
+      None => (false, s),
+    };
+    let key = SortKey::ALL.into_iter()
+      .find(|key| key.name() == name)
+      .ok_or_else(|| format!("unknown sort key {}, expected one of {}",
+        name, names().join(", ")))?;
+    Ok(Order { key, descending })
+  }
+}
+
+impl fmt::Display for Order {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.descending {
+      f.write_str("-")?;
+    }
+    f.write_str(self.key.name())
+  }
+}
+
+// As the string it's parsed from, e.g. "-size"
+impl Serialize for Order {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl Order {
+  /// The `[viewer]` table's `order`, by name if there is none.
+  pub fn from_config(config: &Config) -> Result<Order, String> {
+    let Some(value) = config.table(&["viewer"])
+      .and_then(|table| table.get("order"))
+    else { return Ok(Order::default()) };
+    value.as_str()
+      .ok_or("viewer: order must be a string")?
+      .parse()
+      .map_err(|e| format!("viewer: order: {}", e))
+  }
+
+  /// Sort a listing. Ties, and files missing what the key needs, fall
+  /// back to name order.
+  pub fn sort(&self, files: &mut [FileInfo], manifest: &Manifest) {
+    let largest_dimension = |f: &FileInfo| f.bounds.map_or(0.0, |b| {
+      (0..3).map(|axis| b.max[axis] - b.min[axis]).fold(0.0, f64::max)
+    });
+    let position = |f: &FileInfo| manifest.order.iter()
+      .position(|name| *name == f.name)
+      .unwrap_or(usize::MAX);
+    let compare = |a: &FileInfo, b: &FileInfo| match self.key {
+      SortKey::Name => Ordering::Equal,
+      SortKey::Manifest => position(a).cmp(&position(b)),
+      SortKey::Mtime => b.mtime.cmp(&a.mtime),
+      SortKey::Size => largest_dimension(a).total_cmp(&largest_dimension(b)),
+      SortKey::Triangles => a.triangles.unwrap_or(0)
+        .cmp(&b.triangles.unwrap_or(0)),
+      SortKey::Bytes => a.bytes.cmp(&b.bytes),
+    };
+    files.sort_by(|a, b| compare(a, b).then_with(|| a.name.cmp(&b.name)));
+    if self.descending {
+      files.reverse();
+    }
+  }
+}
+
+pub fn names() -> Vec<&'static str> {
+  SortKey::ALL.into_iter().map(SortKey::name).collect()
+}
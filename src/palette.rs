@@ -0,0 +1,84 @@
+//! Colour palettes for the viewer: a colour per scene file, picked from
+//! a hash of its name so it stays the same across reloads and for every
+//! viewer, and the glow a selected object gets. Chosen with the config's
+//! `[viewer]` table:
+//!
+//! ```toml
+//! [viewer]
+//! palette = "okabe-ito"
+//! ```
+//!
+//! `okabe-ito` and `tol-bright` stay distinguishable with deuteranopia
+//! and protanopia; their selection glow brightens rather than tints, so
+//! it doesn't depend on telling hues apart either.
+
+use crate::config::Config;
+use crate::mesh::Fnv1a;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Palette {
+  pub name: &'static str,
+  /// `#rrggbb`, assigned to files by name
+  pub colors: &'static [&'static str],
+  /// Emissive colour of the selected object
+  pub selection: &'static str,
+  /// Files from `--ref-dir`, muted so they read as context
+  pub reference: &'static str,
+}
+
+pub const PALETTES: &[Palette] = &[
+  // Everything grey, as before palettes
+  Palette { name: "neutral", colors: &["#cccccc"], selection: "#224488",
+    reference: "#7f8fa6" },
+  Palette {
+    name: "tableau",
+    colors: &["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f",
+      "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac"],
+    selection: "#224488",
+    reference: "#5a6270",
+  },
+  Palette {
+    name: "okabe-ito",
+    colors: &["#e69f00", "#56b4e9", "#009e73", "#f0e442", "#0072b2",
+      "#d55e00", "#cc79a7", "#999999"],
+    selection: "#666666",
+    reference: "#444444",
+  },
+  Palette {
+    name: "tol-bright",
+    colors: &["#4477aa", "#ee6677", "#228833", "#ccbb44", "#66ccee",
+      "#aa3377", "#bbbbbb"],
+    selection: "#666666",
+    reference: "#555555",
+  },
+];
+
+impl Palette {
+  /// The palette named in the config, `neutral` if none is.
+  pub fn from_config(config: &Config) -> Result<Palette, String> {
+    let Some(value) = config.table(&["viewer"])
+      .and_then(|table| table.get("palette"))
+    else { return Ok(PALETTES[0]) };
+    let name = value.as_str()
+      .ok_or("viewer: palette must be a string")?;
+    PALETTES.iter().find(|palette| palette.name == name).copied()
+      .ok_or_else(|| format!("viewer: unknown palette {}, expected one of {}",
+        name, names().join(", ")))
+  }
+
+  /// A scene file's colour. The viewer computes the same in JavaScript
+  /// for files it hears about from events.
+  pub fn color_for(&self, filename: &str) -> &'static str {
+    if crate::source::is_ref(filename) {
+      return self.reference;
+    }
+    let mut hasher = Fnv1a::new();
+    hasher.write(filename.as_bytes());
+    self.colors[(hasher.finish() % self.colors.len() as u64) as usize]
+  }
+}
+
+pub fn names() -> Vec<&'static str> {
+  PALETTES.iter().map(|palette| palette.name).collect()
+}
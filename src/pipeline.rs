@@ -0,0 +1,278 @@
+//! Automatic cleanup of scene files on their way to GLB, set per file
+//! pattern in the config's `[pipeline."<glob>"]` tables:
+//!
+//! ```toml
+//! [pipeline."*.scan.obj"]
+//! steps = ["weld", "decimate:0.5", "recenter"]
+//! ```
+//!
+//! - `weld[:tolerance]`: merge vertices closer than `tolerance` (default
+//!   0.000001) and drop the triangles that collapse
+//! - `decimate:<ratio>`: merge nearby vertices until at most `ratio` of
+//!   the triangles are left
+//! - `recenter`: move the bounds centre to the origin
+//! - `scale:<factor>`: scale uniformly
+//!
+//! A file matching several patterns goes through the longest one's
+//! steps. The scene files themselves are left alone; results are cached
+//! per file until its content hash changes.
+
+use crate::config::Config;
+use crate::filter::glob_match;
+use crate::mesh::{Mesh, SubObject};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+const WELD_TOLERANCE: f64 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+  Weld(f64),
+  Decimate(f64),
+  Recenter,
+  Scale(f64),
+}
+
+impl FromStr for Step {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Step, String> {
+    let (name, arg) = match s.split_once(':') {
+      Some((name, arg)) => (name, Some(arg)),
+      None => (s, None),
+    };
+    let number = |what: &str| -> Result<f64, String> {
+      let arg = arg.ok_or_else(|| format!("{} needs {}, e.g. {}:0.5",
+        name, what, name))?;
+      arg.parse().ok().filter(|x: &f64| x.is_finite() && *x > 0.0)
+        .ok_or_else(|| format!("{}: {} must be a positive number", name, what))
+    };
+    match (name, arg) {
+      ("weld", None) => Ok(Step::Weld(WELD_TOLERANCE)),
+      ("weld", Some(_)) => Ok(Step::Weld(number("a tolerance")?)),
+      ("decimate", _) => match number("a ratio")? {
+        ratio if ratio <= 1.0 => Ok(Step::Decimate(ratio)),
+        _ => Err("decimate: the ratio can be at most 1".to_string()),
+      },
+      ("recenter", None) => Ok(Step::Recenter),
+      ("scale", _) => Ok(Step::Scale(number("a factor")?)),
+      _ => Err(format!("unknown step {:?}, expected weld, decimate, \
+        recenter or scale", s)),
+    }
+  }
+}
+
+impl fmt::Display for Step {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Step::Weld(tolerance) => write!(f, "weld:{}", tolerance),
+      Step::Decimate(ratio) => write!(f, "decimate:{}", ratio),
+      Step::Recenter => f.write_str("recenter"),
+      Step::Scale(factor) => write!(f, "scale:{}", factor),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+  /// Glob on the file name, as in `/api/files?filter=`
+  pub pattern: String,
+  pub steps: Vec<Step>,
+}
+
+impl Pipeline {
+  /// The mesh after every step.
+  pub fn run(&self, mesh: &Mesh) -> Mesh {
+    let mut out = copy(mesh);
+    for step in &self.steps {
+      match *step {
+        Step::Weld(tolerance) => {
+          let (positions, map) = cluster(&out.positions, tolerance);
+          remap(&mut out, positions, &map);
+        }
+        Step::Decimate(ratio) => decimate(&mut out, ratio),
+        Step::Recenter => if let Some(bounds) = out.bounds() {
+          let centre: Vec<f64> =
+            (0..3).map(|axis| (bounds.min[axis] + bounds.max[axis]) / 2.0)
+              .collect();
+          for p in &mut out.positions {
+            for (v, c) in p.iter_mut().zip(&centre) {
+              *v -= c;
+            }
+          }
+        },
+        Step::Scale(factor) => for p in &mut out.positions {
+          for v in p.iter_mut() {
+            *v *= factor;
+          }
+        },
+      }
+    }
+    out
+  }
+}
+
+/// The configured pipelines, with the meshes they've produced.
+#[derive(Default)]
+pub struct Pipelines {
+  pipelines: Vec<Pipeline>,
+  /// File name -> content hash it was run on, and the result
+  results: Mutex<HashMap<String, (String, Arc<Mesh>)>>,
+}
+
+impl Pipelines {
+  pub fn from_config(config: &Config) -> Result<Pipelines, String> {
+    let mut pipelines = Vec::new();
+    for (pattern, table) in config.children("pipeline") {
+      let steps = table.get("steps").and_then(|v| v.as_array())
+        .ok_or_else(|| format!("pipeline {}: steps must be an array",
+          pattern))?;
+      let steps = steps.iter()
+        .map(|step| step.as_str()
+          .ok_or_else(|| "steps must be strings".to_string())
+          .and_then(str::parse))
+        .collect::<Result<Vec<Step>, String>>()
+        .map_err(|e| format!("pipeline {}: {}", pattern, e))?;
+      pipelines.push(Pipeline { pattern: pattern.to_string(), steps });
+    }
+    Ok(Pipelines { pipelines, results: Mutex::default() })
+  }
+
+  /// The pipeline a file goes through, if any.
+  pub fn for_file(&self, name: &str) -> Option<&Pipeline> {
+    self.pipelines.iter()
+      .filter(|pipeline| glob_match(&pipeline.pattern, name))
+      .max_by_key(|pipeline| pipeline.pattern.len())
+  }
+
+  /// What the file's pipeline made of the contents with this hash, if
+  /// it has been run on them.
+  pub fn cached(&self, name: &str, hash: &str) -> Option<Arc<Mesh>> {
+    let results = self.results.lock().unwrap();
+    results.get(name).filter(|(run_on, _)| run_on == hash)
+      .map(|(_, mesh)| mesh.clone())
+  }
+
+  pub fn store(&self, name: &str, hash: &str, mesh: Arc<Mesh>) {
+    self.results.lock().unwrap()
+      .insert(name.to_string(), (hash.to_string(), mesh));
+  }
+}
+
+// Merge positions that fall in the same `cell`-sized grid cell into
+// their average. Returns the new positions and where each old one went.
+fn cluster(positions: &[[f64; 3]], cell: f64) -> (Vec<[f64; 3]>, Vec<usize>) {
+  let mut cells: HashMap<[i64; 3], usize> = HashMap::new();
+  let mut sums: Vec<([f64; 3], f64)> = Vec::new();
+  let map = positions.iter().map(|p| {
+    let key = p.map(|v| (v / cell).round() as i64);
+    let index = *cells.entry(key).or_insert_with(|| {
+      sums.push(([0.0; 3], 0.0));
+      sums.len() - 1
+    });
+    let (sum, count) = &mut sums[index];
+    for (s, v) in sum.iter_mut().zip(p) {
+      *s += v;
+    }
+    *count += 1.0;
+    index
+  }).collect();
+  let merged = sums.into_iter()
+    .map(|(sum, count)| sum.map(|s| s / count))
+    .collect();
+  (merged, map)
+}
+
+// Point triangles at merged vertices, dropping those that collapse and
+// keeping each object's range over what's left
+fn remap(mesh: &mut Mesh, positions: Vec<[f64; 3]>, map: &[usize]) {
+  let mut triangles = Vec::with_capacity(mesh.triangles.len());
+  let mut uv_triangles = Vec::with_capacity(mesh.uv_triangles.len());
+  let mut triangle_materials =
+    Vec::with_capacity(mesh.triangle_materials.len());
+  for object in &mut mesh.objects {
+    let start = triangles.len();
+    for t in object.triangles.clone() {
+      let [a, b, c] = mesh.triangles[t].map(|vi| map[vi]);
+      if a != b && b != c && a != c {
+        triangles.push([a, b, c]);
+        if let Some(uv) = mesh.uv_triangles.get(t) {
+          uv_triangles.push(*uv);
+        }
+        if let Some(material) = mesh.triangle_materials.get(t) {
+          triangle_materials.push(*material);
+        }
+      }
+    }
+    object.triangles = start..triangles.len();
+  }
+  if !mesh.colors.is_empty() {
+    // Merged vertices take the average of their colours
+    let mut sums = vec![([0.0f32; 3], 0.0f32); positions.len()];
+    for (color, &to) in mesh.colors.iter().zip(map) {
+      let (sum, count) = &mut sums[to];
+      for (s, c) in sum.iter_mut().zip(color) {
+        *s += c;
+      }
+      *count += 1.0;
+    }
+    mesh.colors = sums.into_iter()
+      .map(|(sum, count)| sum.map(|s| s / count.max(1.0)))
+      .collect();
+  }
+  mesh.positions = positions;
+  mesh.triangles = triangles;
+  mesh.uv_triangles = uv_triangles;
+  mesh.triangle_materials = triangle_materials;
+}
+
+fn copy(mesh: &Mesh) -> Mesh {
+  Mesh {
+    positions: mesh.positions.clone(),
+    triangles: mesh.triangles.clone(),
+    objects: mesh.objects.iter().map(|object| SubObject {
+      name: object.name.clone(),
+      triangles: object.triangles.clone(),
+    }).collect(),
+    uvs: mesh.uvs.clone(),
+    uv_triangles: mesh.uv_triangles.clone(),
+    colors: mesh.colors.clone(),
+    materials: mesh.materials.clone(),
+    triangle_materials: mesh.triangle_materials.clone(),
+    mtllibs: mesh.mtllibs.clone(),
+  }
+}
+
+// Vertex clustering on the finest grid that keeps at most `ratio` of
+// the triangles, found by bisecting the number of cells per axis
+fn decimate(mesh: &mut Mesh, ratio: f64) {
+  let Some(bounds) = mesh.bounds() else { return };
+  let target = (mesh.triangles.len() as f64 * ratio).floor() as usize;
+  if target >= mesh.triangles.len() {
+    return;
+  }
+  let size = (0..3).map(|axis| bounds.max[axis] - bounds.min[axis])
+    .fold(0.0, f64::max);
+  if size <= 0.0 {
+    return;
+  }
+  let (mut lo, mut hi) = (1u32, 4096u32);
+  let mut best = None;
+  while lo <= hi {
+    let cells = lo + (hi - lo) / 2;
+    let mut trial = copy(mesh);
+    let (positions, map) = cluster(&mesh.positions, size / cells as f64);
+    remap(&mut trial, positions, &map);
+    if trial.triangles.len() <= target {
+      best = Some(trial);
+      lo = cells + 1;
+    } else {
+      hi = cells - 1;
+    }
+  }
+  if let Some(best) = best {
+    *mesh = best;
+  }
+}
@@ -0,0 +1,354 @@
+//! Server-side PLY parsing, for meshes from 3D scanners. Like the OBJ
+//! parser it keeps positions, faces and the per-vertex colours scans
+//! usually carry; the browser loads the file itself with three.js'
+//! PLYLoader.
+//!
+//! ASCII and both binary encodings are read. Every element's data is
+//! walked, since binary rows can only be skipped by reading them, but
+//! only `vertex` x/y/z and red/green/blue and `face` index lists are
+//! kept. Integer colours are scaled to 0 to 1 by their type's range.
+//! Polygons are fan-triangulated, as OBJ faces are. Element counts the
+//! body can't hold, or over [`mesh::MAX_TRIANGLES`], are refused up
+//! front rather than read until the file runs out.
+
+use crate::mesh::{self, Mesh, SubObject};
+
+const HEADER_END: &[u8] = b"end_header";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+  Ascii,
+  LittleEndian,
+  BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+  I8,
+  U8,
+  I16,
+  U16,
+  I32,
+  U32,
+  F32,
+  F64,
+}
+
+impl Scalar {
+  fn parse(name: &str) -> Option<Scalar> {
+    Some(match name {
+      "char" | "int8" => Scalar::I8,
+      "uchar" | "uint8" => Scalar::U8,
+      "short" | "int16" => Scalar::I16,
+      "ushort" | "uint16" => Scalar::U16,
+      "int" | "int32" => Scalar::I32,
+      "uint" | "uint32" => Scalar::U32,
+      "float" | "float32" => Scalar::F32,
+      "double" | "float64" => Scalar::F64,
+      _ => return None,
+    })
+  }
+
+  fn size(self) -> usize {
+    match self {
+      Scalar::I8 | Scalar::U8 => 1,
+      Scalar::I16 | Scalar::U16 => 2,
+      Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+      Scalar::F64 => 8,
+    }
+  }
+
+  // What a colour channel of this type is divided by to fall in 0 to 1
+  fn full_scale(self) -> f64 {
+    match self {
+      Scalar::I8 | Scalar::U8 => 255.0,
+      Scalar::I16 | Scalar::U16 => 65535.0,
+      Scalar::I32 | Scalar::U32 => 4294967295.0,
+      Scalar::F32 | Scalar::F64 => 1.0,
+    }
+  }
+}
+
+enum Property {
+  Scalar(Scalar),
+  /// The type of the length, then of the items
+  List(Scalar, Scalar),
+}
+
+struct Element {
+  name: String,
+  count: usize,
+  properties: Vec<(String, Property)>,
+}
+
+/// Whether the contents look like a PLY file.
+pub fn is_ply(bytes: &[u8]) -> bool {
+  bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+}
+
+/// Parse ASCII or binary PLY into a single unnamed object.
+pub fn parse_ply(bytes: &[u8]) -> Result<Mesh, String> {
+  let (encoding, elements, body) = parse_header(bytes)?;
+  let mut values = Values { encoding, body, at: 0 };
+  let mut positions = Vec::new();
+  let mut triangles = Vec::new();
+  let mut colors = Vec::new();
+  for element in &elements {
+    let xyz = ["x", "y", "z"].map(|axis| element.properties.iter()
+      .position(|(name, _)| name == axis));
+    let rgb = ["red", "green", "blue"].map(|channel| element.properties
+      .iter()
+      .position(|(name, _)| name == channel
+        || name.strip_prefix("diffuse_") == Some(channel)));
+    let has_color = rgb.iter().all(Option::is_some);
+    let indices = element.properties.iter().position(|(name, _)|
+      name == "vertex_indices" || name == "vertex_index");
+    // Rows are read one value at a time, so a count the body can't hold
+    // would only fail once the file ran out, and never for an element
+    // with no properties
+    let least_row: usize = element.properties.iter()
+      .map(|(_, property)| match (encoding, property) {
+        (Encoding::Ascii, _) => 1,
+        (_, Property::Scalar(kind)) | (_, Property::List(kind, _)) =>
+          kind.size(),
+      })
+      .sum();
+    if element.count > mesh::MAX_TRIANGLES
+        || element.count.saturating_mul(least_row) > body.len() - values.at {
+      return Err(format!("{} {} rows are more than the file holds",
+        element.count, element.name));
+    }
+    for row in 0..element.count {
+      let mut position = [0.0; 3];
+      let mut color = [0.0; 3];
+      let mut corners = Vec::new();
+      for (i, (name, property)) in element.properties.iter().enumerate() {
+        let at = |e: String| format!("{} {}, {}: {}", element.name, row,
+          name, e);
+        match *property {
+          Property::Scalar(kind) => {
+            let value = values.read(kind).map_err(at)?;
+            if element.name == "vertex" {
+              if let Some(axis) = xyz.iter().position(|&p| p == Some(i)) {
+                position[axis] = value;
+              }
+              if let Some(channel) = rgb.iter().position(|&p| p == Some(i)) {
+                color[channel] = (value / kind.full_scale()) as f32;
+              }
+            }
+          }
+          Property::List(length, item) => {
+            let length = values.read(length).map_err(at)? as usize;
+            for _ in 0..length {
+              let value = values.read(item).map_err(at)?;
+              if element.name == "face" && indices == Some(i) {
+                corners.push(value as usize);
+              }
+            }
+          }
+        }
+      }
+      match element.name.as_str() {
+        "vertex" => {
+          positions.push(position);
+          if has_color {
+            colors.push(color);
+          }
+        }
+        "face" => {
+          if let Some(&bad) = corners.iter().find(|&&c| c >= positions.len()) {
+            return Err(format!("face {}: vertex index {} out of range ({} \
+              vertices)", row, bad, positions.len()));
+          }
+          for i in 1..corners.len().saturating_sub(1) {
+            triangles.push([corners[0], corners[i], corners[i + 1]]);
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+  if triangles.len() > mesh::MAX_TRIANGLES {
+    return Err(format!("file has over {} triangles", mesh::MAX_TRIANGLES));
+  }
+  let objects = if triangles.is_empty() {
+    Vec::new()
+  } else {
+    vec![SubObject { name: String::new(), triangles: 0..triangles.len() }]
+  };
+  Ok(Mesh { positions, triangles, objects, colors, ..Mesh::default() })
+}
+
+fn parse_header(bytes: &[u8])
+    -> Result<(Encoding, Vec<Element>, &[u8]), String> {
+  let end = bytes.windows(HEADER_END.len())
+    .position(|w| w == HEADER_END)
+    .ok_or_else(|| "PLY header has no end_header".to_string())?;
+  // The body starts after the end_header line's newline
+  let body_at = bytes[end..].iter().position(|&b| b == b'\n')
+    .map_or(bytes.len(), |n| end + n + 1);
+  let header = std::str::from_utf8(&bytes[..end])
+    .map_err(|_| "PLY header is not valid UTF-8".to_string())?;
+
+  let mut encoding = None;
+  let mut elements: Vec<Element> = Vec::new();
+  for (index, line) in header.lines().enumerate().skip(1) {
+    let line_no = index + 1;
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let scalar = |name: &str| Scalar::parse(name).ok_or_else(|| format!(
+      "line {}: unknown property type '{}'", line_no, name));
+    match parts[..] {
+      ["format", kind, _] => encoding = Some(match kind {
+        "ascii" => Encoding::Ascii,
+        "binary_little_endian" => Encoding::LittleEndian,
+        "binary_big_endian" => Encoding::BigEndian,
+        _ => return Err(format!("line {}: unknown format '{}'", line_no,
+          kind)),
+      }),
+      ["element", name, count] => elements.push(Element {
+        name: name.to_string(),
+        count: count.parse().map_err(|_| format!(
+          "line {}: invalid element count '{}'", line_no, count))?,
+        properties: Vec::new(),
+      }),
+      ["property", "list", length, item, name] => {
+        let property = Property::List(scalar(length)?, scalar(item)?);
+        elements.last_mut()
+          .ok_or_else(|| format!("line {}: property before any element",
+            line_no))?
+          .properties.push((name.to_string(), property));
+      }
+      ["property", kind, name] => {
+        let property = Property::Scalar(scalar(kind)?);
+        elements.last_mut()
+          .ok_or_else(|| format!("line {}: property before any element",
+            line_no))?
+          .properties.push((name.to_string(), property));
+      }
+      ["comment", ..] | ["obj_info", ..] | [] => {}
+      _ => return Err(format!("line {}: unexpected '{}'", line_no,
+        line.trim())),
+    }
+  }
+  let encoding = encoding
+    .ok_or_else(|| "PLY header has no format line".to_string())?;
+  Ok((encoding, elements, &bytes[body_at..]))
+}
+
+// Reads the body's values one at a time, in either encoding
+struct Values<'a> {
+  encoding: Encoding,
+  body: &'a [u8],
+  at: usize,
+}
+
+impl Values<'_> {
+  fn read(&mut self, kind: Scalar) -> Result<f64, String> {
+    if self.encoding == Encoding::Ascii {
+      return self.read_ascii();
+    }
+    let size = kind.size();
+    let raw = self.body.get(self.at..self.at + size)
+      .ok_or_else(|| "file ends early".to_string())?;
+    self.at += size;
+    let mut word = [0; 8];
+    word[..size].copy_from_slice(raw);
+    if self.encoding == Encoding::BigEndian {
+      word[..size].reverse();
+    }
+    Ok(match kind {
+      Scalar::I8 => word[0] as i8 as f64,
+      Scalar::U8 => word[0] as f64,
+      Scalar::I16 => i16::from_le_bytes([word[0], word[1]]) as f64,
+      Scalar::U16 => u16::from_le_bytes([word[0], word[1]]) as f64,
+      Scalar::I32 => i32::from_le_bytes(word[..4].try_into().unwrap()) as f64,
+      Scalar::U32 => u32::from_le_bytes(word[..4].try_into().unwrap()) as f64,
+      Scalar::F32 => f32::from_le_bytes(word[..4].try_into().unwrap()) as f64,
+      Scalar::F64 => f64::from_le_bytes(word),
+    })
+  }
+
+  fn read_ascii(&mut self) -> Result<f64, String> {
+    let rest = &self.body[self.at..];
+    let start = rest.iter().position(|b| !b.is_ascii_whitespace())
+      .ok_or_else(|| "file ends early".to_string())?;
+    let len = rest[start..].iter().position(|b| b.is_ascii_whitespace())
+      .unwrap_or(rest.len() - start);
+    let token = &rest[start..start + len];
+    self.at += start + len;
+    std::str::from_utf8(token).ok()
+      .and_then(|token| token.parse().ok())
+      .ok_or_else(|| format!("invalid number '{}'",
+        String::from_utf8_lossy(token)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HEADER: &str = "element vertex 4\nproperty float x\n\
+    property float y\nproperty float z\nproperty uchar red\n\
+    property uchar green\nproperty uchar blue\nelement face 1\n\
+    property list uchar int vertex_indices\nend_header\n";
+
+  const POSITIONS: [[f32; 3]; 4] =
+    [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+  fn binary(format: &str, big_endian: bool) -> Vec<u8> {
+    let mut bytes = format!("ply\nformat {} 1.0\n{}", format, HEADER)
+      .into_bytes();
+    for position in POSITIONS {
+      for v in position {
+        let v = if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        bytes.extend_from_slice(&v);
+      }
+      bytes.extend_from_slice(&[255, 0, 0]);
+    }
+    bytes.push(4);
+    for i in 0..4i32 {
+      let i = if big_endian { i.to_be_bytes() } else { i.to_le_bytes() };
+      bytes.extend_from_slice(&i);
+    }
+    bytes
+  }
+
+  #[test]
+  fn parses_every_encoding() {
+    let ascii = format!("ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n\
+      1 0 0 255 0 0\n1 1 0 255 0 0\n0 1 0 255 0 0\n4 0 1 2 3\n", HEADER);
+    let files = [ascii.into_bytes(),
+      binary("binary_little_endian", false),
+      binary("binary_big_endian", true)];
+    for bytes in files {
+      assert!(is_ply(&bytes));
+      let mesh = parse_ply(&bytes).unwrap();
+      let expected = POSITIONS.map(|p| p.map(f64::from));
+      assert_eq!(mesh.positions, expected);
+      // The quad is fan-triangulated
+      assert_eq!(mesh.triangles, [[0, 1, 2], [0, 2, 3]]);
+      assert_eq!(mesh.colors, [[1.0, 0.0, 0.0]; 4]);
+    }
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    let mut cut = binary("binary_little_endian", false);
+    cut.truncate(cut.len() - 3);
+    assert!(parse_ply(&cut).is_err());
+    assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\n").is_err());
+    assert!(parse_ply(b"ply\nformat utf16 1.0\nend_header\n").is_err());
+
+    // More rows than the body holds, with or without properties
+    assert!(parse_ply(b"ply\nformat binary_little_endian 1.0\n\
+      element vertex 100000000000\nend_header\n").is_err());
+    assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1000000\n\
+      property float x\nend_header\n1 2 3\n").is_err());
+
+    // A face using a vertex that doesn't exist
+    let mut bad = binary("binary_little_endian", false);
+    let at = bad.len() - 4;
+    bad[at..].copy_from_slice(&9i32.to_le_bytes());
+    assert!(parse_ply(&bad).is_err());
+  }
+}
@@ -0,0 +1,552 @@
+//! Point clouds from `.xyz` and `.pcd` scans, which have no faces. Both
+//! parse into a `Mesh` of positions, with vertex colours if the scan
+//! has them, and no triangles. three.js has no loader for either, so
+//! the viewer draws the points from `/api/files/:name/points.bin`, laid
+//! out by [`encode`].
+//!
+//! XYZ is text, a point to a line: x, y and z separated by spaces or
+//! commas, then red, green and blue if there are six columns, or after
+//! an intensity if there are seven. Colours are out of 255 unless none
+//! is above 1; columns with values outside 0 to 255 (normals, say)
+//! aren't colours and are dropped.
+//!
+//! PCD is the Point Cloud Library's format, with `ascii`, `binary` or
+//! `binary_compressed` data. Colours come from its packed `rgb` or
+//! `rgba` field, and points with NaN coordinates, which organized
+//! clouds use for gaps, are skipped.
+
+use crate::mesh::Mesh;
+
+// Flags in `encode`'s header
+const HAS_COLORS: u32 = 1;
+
+/// Whether the contents look like a PCD file: its header's first line,
+/// after any comments, is `VERSION` or `FIELDS`.
+pub fn is_pcd(bytes: &[u8]) -> bool {
+  let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+  head.lines().map(str::trim)
+    .find(|line| !line.is_empty() && !line.starts_with('#'))
+    .and_then(|line| line.split_whitespace().next())
+    .is_some_and(|word| word == "VERSION" || word == "FIELDS")
+}
+
+/// Whether the contents look like an XYZ file: its first line, after
+/// blanks and comments, is three or more numbers.
+pub fn is_xyz(bytes: &[u8]) -> bool {
+  let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+  head.lines().map(str::trim)
+    .find(|line| !line.is_empty() && !line.starts_with('#'))
+    .and_then(numbers)
+    .is_some_and(|row| row.len() >= 3)
+}
+
+// The numbers on an XYZ line, or None if something else is there
+fn numbers(line: &str) -> Option<Vec<f64>> {
+  line.split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|word| !word.is_empty())
+    .map(|word| word.parse().ok())
+    .collect()
+}
+
+/// Parse an XYZ point cloud.
+pub fn parse_xyz(bytes: &[u8]) -> Result<Mesh, String> {
+  let text = std::str::from_utf8(bytes)
+    .map_err(|_| "file is not valid UTF-8".to_string())?;
+  let mut positions = Vec::new();
+  let mut colors = Vec::new();
+  let mut colored = true;
+  for (index, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let row = numbers(line).filter(|row| row.len() >= 3)
+      .ok_or_else(|| format!("line {}: expected x y z", index + 1))?;
+    positions.push([row[0], row[1], row[2]]);
+    let rgb = match row.len() {
+      6 => &row[3..6],
+      7 => &row[4..7],
+      _ => &[][..],
+    };
+    colored &= rgb.len() == 3
+      && rgb.iter().all(|c| (0.0..=255.0).contains(c));
+    if colored {
+      colors.push([rgb[0] as f32, rgb[1] as f32, rgb[2] as f32]);
+    }
+  }
+  if !colored {
+    colors.clear();
+  }
+  if colors.iter().flatten().any(|&c| c > 1.0) {
+    for c in colors.iter_mut().flatten() {
+      *c /= 255.0;
+    }
+  }
+  Ok(Mesh { positions, colors, ..Mesh::default() })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Data {
+  Ascii,
+  Binary,
+  Compressed,
+}
+
+struct Field {
+  name: String,
+  /// Bytes per value
+  size: usize,
+  /// `F`, `I` or `U`
+  kind: char,
+  /// Values per point
+  count: usize,
+}
+
+impl Field {
+  fn bytes(&self) -> usize {
+    self.size * self.count
+  }
+
+  // A value's number from its little-endian bytes
+  fn read(&self, bytes: &[u8]) -> Result<f64, String> {
+    let bad = || format!("{}: unsupported type {}{}", self.name, self.kind,
+      self.size);
+    macro_rules! le {
+      ($t:ty) => {
+        <$t>::from_le_bytes(bytes.try_into().map_err(|_| bad())?) as f64
+      };
+    }
+    Ok(match (self.kind, self.size) {
+      ('F', 4) => le!(f32),
+      ('F', 8) => le!(f64),
+      ('U', 1) => le!(u8),
+      ('U', 2) => le!(u16),
+      ('U', 4) => le!(u32),
+      ('U', 8) => le!(u64),
+      ('I', 1) => le!(i8),
+      ('I', 2) => le!(i16),
+      ('I', 4) => le!(i32),
+      ('I', 8) => le!(i64),
+      _ => return Err(bad()),
+    })
+  }
+}
+
+struct Header {
+  fields: Vec<Field>,
+  points: usize,
+  data: Data,
+}
+
+/// Parse a PCD point cloud.
+pub fn parse_pcd(bytes: &[u8]) -> Result<Mesh, String> {
+  let (Header { fields, points, data }, body) = parse_header(bytes)?;
+  let find = |names: &[&str]| fields.iter()
+    .position(|field| names.contains(&field.name.as_str()));
+  let xyz = [find(&["x"]), find(&["y"]), find(&["z"])];
+  let [Some(x), Some(y), Some(z)] = xyz else {
+    return Err("PCD has no x, y and z fields".to_string());
+  };
+  let rgb = find(&["rgb", "rgba"]);
+  if let Some(rgb) = rgb {
+    if fields[rgb].size != 4 {
+      return Err(format!("{} field must be 4 bytes", fields[rgb].name));
+    }
+  }
+  // Each point's first value of every field, in field order
+  let rows = match data {
+    Data::Ascii => ascii_rows(&fields, points, body)?,
+    Data::Binary => binary_rows(&fields, points, body)?,
+    Data::Compressed => compressed_rows(&fields, points, body)?,
+  };
+
+  let mut positions = Vec::with_capacity(rows.len());
+  let mut colors = Vec::new();
+  for row in rows {
+    let position = [row[x], row[y], row[z]];
+    if position.iter().any(|v| !v.is_finite()) {
+      continue;
+    }
+    positions.push(position);
+    if let Some(rgb) = rgb {
+      // A u32 of 0x00RRGGBB, though often typed as a float
+      let packed = match fields[rgb].kind {
+        'F' => (row[rgb] as f32).to_bits(),
+        _ => row[rgb] as u32,
+      };
+      let channel = |shift: u32| ((packed >> shift) & 0xff) as f32 / 255.0;
+      colors.push([channel(16), channel(8), channel(0)]);
+    }
+  }
+  Ok(Mesh { positions, colors, ..Mesh::default() })
+}
+
+fn parse_header(bytes: &[u8])
+    -> Result<(Header, &[u8]), String> {
+  let mut names: Vec<&str> = Vec::new();
+  let mut sizes: Vec<&str> = Vec::new();
+  let mut kinds: Vec<&str> = Vec::new();
+  let mut counts: Vec<&str> = Vec::new();
+  let mut width = None;
+  let mut height = 1;
+  let mut points = None;
+  let mut at = 0;
+  let mut line_no = 0;
+  while at < bytes.len() {
+    let end = bytes[at..].iter().position(|&b| b == b'\n')
+      .map_or(bytes.len(), |n| at + n + 1);
+    let line = std::str::from_utf8(&bytes[at..end])
+      .map_err(|_| "PCD header is not valid UTF-8".to_string())?;
+    at = end;
+    line_no += 1;
+    let mut words = line.split_whitespace();
+    let Some(key) = words.next() else { continue };
+    let values: Vec<&str> = words.collect();
+    let number = |value: Option<&&str>| value
+      .and_then(|value| value.parse::<usize>().ok())
+      .ok_or_else(|| format!("line {}: invalid {}", line_no, key));
+    match key {
+      "FIELDS" => names = values,
+      "SIZE" => sizes = values,
+      "TYPE" => kinds = values,
+      "COUNT" => counts = values,
+      "WIDTH" => width = Some(number(values.first())?),
+      "HEIGHT" => height = number(values.first())?,
+      "POINTS" => points = Some(number(values.first())?),
+      "DATA" => {
+        let data = match values.first().copied() {
+          Some("ascii") => Data::Ascii,
+          Some("binary") => Data::Binary,
+          Some("binary_compressed") => Data::Compressed,
+          other => return Err(format!("line {}: unknown DATA '{}'", line_no,
+            other.unwrap_or(""))),
+        };
+        let fields = fields(&names, &sizes, &kinds, &counts)?;
+        // Older files leave out POINTS, which is then WIDTH by HEIGHT
+        let points = match (points, width) {
+          (Some(points), _) => points,
+          (None, Some(width)) => width.checked_mul(height)
+            .ok_or_else(|| "PCD WIDTH by HEIGHT is too large".to_string())?,
+          (None, None) => return Err("PCD header has no POINTS".to_string()),
+        };
+        return Ok((Header { fields, points, data }, &bytes[at..]));
+      }
+      _ => {}
+    }
+  }
+  Err("PCD header has no DATA line".to_string())
+}
+
+fn fields(names: &[&str], sizes: &[&str], kinds: &[&str], counts: &[&str])
+    -> Result<Vec<Field>, String> {
+  if names.is_empty() {
+    return Err("PCD header has no FIELDS".to_string());
+  }
+  if sizes.len() != names.len() || kinds.len() != names.len()
+      || !(counts.is_empty() || counts.len() == names.len()) {
+    return Err("PCD header's SIZE, TYPE and COUNT don't match its FIELDS"
+      .to_string());
+  }
+  names.iter().enumerate().map(|(i, name)| {
+    let invalid = |what: &str, value: &str| format!("{}: invalid {} '{}'",
+      name, what, value);
+    Ok(Field {
+      name: name.to_string(),
+      size: sizes[i].parse().map_err(|_| invalid("SIZE", sizes[i]))?,
+      kind: match kinds[i] {
+        "F" => 'F',
+        "I" => 'I',
+        "U" => 'U',
+        kind => return Err(invalid("TYPE", kind)),
+      },
+      // Every field has at least one value, which is what's read
+      count: match counts.get(i) {
+        Some(count) => count.parse().ok().filter(|&count| count > 0)
+          .ok_or_else(|| invalid("COUNT", count))?,
+        None => 1,
+      },
+    })
+  }).collect()
+}
+
+// Bytes per point, each field's size times its count; an error if that
+// doesn't fit a usize, as with a header's huge COUNT
+fn stride(fields: &[Field]) -> Result<usize, String> {
+  fields.iter()
+    .try_fold(0usize, |sum, field| field.size.checked_mul(field.count)
+      .and_then(|bytes| sum.checked_add(bytes)))
+    .filter(|&stride| stride > 0)
+    .ok_or_else(|| "PCD fields' SIZE and COUNT are invalid".to_string())
+}
+
+// Whether `points` points of `stride` bytes fit in `len` bytes; header
+// values are checked this way before anything is allocated for them
+fn fits(points: usize, stride: usize, len: usize) -> bool {
+  points.checked_mul(stride).is_some_and(|bytes| bytes <= len)
+}
+
+fn ascii_rows(fields: &[Field], points: usize, body: &[u8])
+    -> Result<Vec<Vec<f64>>, String> {
+  // Each point takes a line, so at least a byte
+  if points > body.len() {
+    return Err(format!("PCD data is {} bytes, too short for {} points",
+      body.len(), points));
+  }
+  let text = std::str::from_utf8(body)
+    .map_err(|_| "PCD data is not valid UTF-8".to_string())?;
+  let mut rows = Vec::new();
+  for line in text.lines() {
+    if rows.len() == points {
+      break;
+    }
+    let values: Vec<&str> = line.split_whitespace().collect();
+    if values.is_empty() {
+      continue;
+    }
+    let point = rows.len();
+    let mut row = Vec::with_capacity(fields.len());
+    let mut at = 0;
+    for field in fields {
+      let value = values.get(at)
+        .ok_or_else(|| format!("point {}: missing {}", point, field.name))?;
+      // Packed colours are written as a float or as an integer
+      let value = if matches!(field.name.as_str(), "rgb" | "rgba") {
+        value.parse::<u32>().map(|n| match field.kind {
+          'F' => f32::from_bits(n) as f64,
+          _ => n as f64,
+        }).or_else(|_| value.parse::<f32>().map(|v| v as f64))
+      } else {
+        value.parse::<f64>()
+      }.map_err(|_| format!("point {}: invalid {} '{}'", point, field.name,
+        value))?;
+      row.push(value);
+      at += field.count;
+    }
+    rows.push(row);
+  }
+  if rows.len() < points {
+    return Err(format!("PCD has {} of its {} points", rows.len(), points));
+  }
+  Ok(rows)
+}
+
+// Points one after another, each with all its fields
+fn binary_rows(fields: &[Field], points: usize, body: &[u8])
+    -> Result<Vec<Vec<f64>>, String> {
+  let stride = stride(fields)?;
+  if !fits(points, stride, body.len()) {
+    return Err(format!("PCD data is {} bytes, short of {} points of {}",
+      body.len(), points, stride));
+  }
+  body.chunks_exact(stride).take(points).map(|point| {
+    let mut at = 0;
+    fields.iter().map(|field| {
+      let value = field.read(&point[at..at + field.size]);
+      at += field.bytes();
+      value
+    }).collect()
+  }).collect()
+}
+
+// LZF-compressed, and a field at a time: every point's first field,
+// then every point's second, and so on
+fn compressed_rows(fields: &[Field], points: usize, body: &[u8])
+    -> Result<Vec<Vec<f64>>, String> {
+  let size = |at: usize| body.get(at..at + 4)
+    .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    .ok_or_else(|| "PCD compressed data has no sizes".to_string());
+  let (compressed, uncompressed) = (size(0)?, size(4)?);
+  let input = body.get(8..8 + compressed)
+    .ok_or_else(|| "PCD compressed data is cut short".to_string())?;
+  let stride = stride(fields)?;
+  if !fits(points, stride, uncompressed) {
+    return Err(format!("PCD data is {} bytes, short of {} points of {}",
+      uncompressed, points, stride));
+  }
+  let columns = lzf_decompress(input, uncompressed)?;
+  if !fits(points, stride, columns.len()) {
+    return Err(format!("PCD data is {} bytes, short of {} points of {}",
+      columns.len(), points, stride));
+  }
+  let mut rows = vec![Vec::with_capacity(fields.len()); points];
+  let mut start = 0;
+  for field in fields {
+    for (point, row) in rows.iter_mut().enumerate() {
+      let at = start + point * field.bytes();
+      row.push(field.read(&columns[at..at + field.size])?);
+    }
+    start += points * field.bytes();
+  }
+  Ok(rows)
+}
+
+// Most bytes one byte of LZF input can stand for: a three-byte back
+// reference copies up to 264
+const LZF_MAX_RATIO: usize = 88;
+
+// Decompress LZF data that should come to `size` bytes, which is from
+// the file and so only trusted as far as the input could reach it
+fn lzf_decompress(input: &[u8], size: usize) -> Result<Vec<u8>, String> {
+  let corrupt = || "PCD compressed data is corrupt".to_string();
+  if size > input.len().saturating_mul(LZF_MAX_RATIO) {
+    return Err(corrupt());
+  }
+  let mut out = Vec::new();
+  let mut at = 0;
+  while at < input.len() {
+    let control = input[at] as usize;
+    at += 1;
+    if control < 32 {
+      // A run of control + 1 literal bytes
+      let run = input.get(at..at + control + 1).ok_or_else(corrupt)?;
+      if out.len() + run.len() > size {
+        return Err(corrupt());
+      }
+      out.extend_from_slice(run);
+      at += control + 1;
+      continue;
+    }
+    // A back reference: a length in the top three bits, extended by the
+    // next byte if they're all set, then a 13-bit distance
+    let mut length = control >> 5;
+    if length == 7 {
+      length += *input.get(at).ok_or_else(corrupt)? as usize;
+      at += 1;
+    }
+    length += 2;
+    let low = *input.get(at).ok_or_else(corrupt)? as usize;
+    at += 1;
+    let distance = ((control & 0x1f) << 8) + low + 1;
+    let from = out.len().checked_sub(distance).ok_or_else(corrupt)?;
+    if out.len() + length > size {
+      return Err(corrupt());
+    }
+    // Byte by byte, as the reference may overlap what it writes
+    for i in from..from + length {
+      out.push(out[i]);
+    }
+  }
+  if out.len() != size {
+    return Err(corrupt());
+  }
+  Ok(out)
+}
+
+/// Lay a point cloud out for the viewer, keeping every nth point so
+/// there are no more than `budget`: the number of points and a flags
+/// word as little-endian u32s, bit 0 of the flags set if there are
+/// colours; the origin's x, y and z as little-endian f64s; then each
+/// point's x, y and z from the origin as little-endian f32s; then, if
+/// there are colours, each point's red, green and blue as sRGB bytes.
+///
+/// The origin is the middle of the points' bounds, so that scans far
+/// from zero, as georeferenced lidar is, keep their precision as f32s.
+pub fn encode(mesh: &Mesh, budget: usize) -> Vec<u8> {
+  let colored =
+    !mesh.colors.is_empty() && mesh.colors.len() == mesh.positions.len();
+  let stride = mesh.positions.len().div_ceil(budget.max(1)).max(1);
+  let count = mesh.positions.len().div_ceil(stride);
+  let origin = mesh.bounds()
+    .map_or([0.0; 3], |b| [0, 1, 2].map(|i| (b.min[i] + b.max[i]) / 2.0));
+  let mut out =
+    Vec::with_capacity(32 + count * if colored { 15 } else { 12 });
+  out.extend_from_slice(&(count as u32).to_le_bytes());
+  let flags = if colored { HAS_COLORS } else { 0 };
+  out.extend_from_slice(&flags.to_le_bytes());
+  for v in origin {
+    out.extend_from_slice(&v.to_le_bytes());
+  }
+  for p in mesh.positions.iter().step_by(stride) {
+    for (v, o) in p.iter().zip(origin) {
+      out.extend_from_slice(&((v - o) as f32).to_le_bytes());
+    }
+  }
+  if colored {
+    for color in mesh.colors.iter().step_by(stride) {
+      for c in color {
+        out.push((c.clamp(0.0, 1.0) * 255.0).round() as u8);
+      }
+    }
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HEADER: &str = "# .PCD v0.7\nVERSION 0.7\nFIELDS x y z\n\
+    SIZE 4 4 4\nTYPE F F F\nCOUNT 1 1 1\nWIDTH 2\nHEIGHT 1\nPOINTS 2\n";
+
+  fn pcd(data: &str, body: &[u8]) -> Vec<u8> {
+    [HEADER.as_bytes(), b"DATA ", data.as_bytes(), b"\n", body].concat()
+  }
+
+  // Little-endian f32s, as binary PCD data holds them
+  fn floats(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+  }
+
+  #[test]
+  fn parses_xyz() {
+    let mesh = parse_xyz(b"0 0 0 255 0 0\n1,2,3,0,255,0\n").unwrap();
+    assert_eq!(mesh.positions, [[0.0, 0.0, 0.0], [1.0, 2.0, 3.0]]);
+    assert_eq!(mesh.colors, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+    assert!(mesh.triangles.is_empty());
+    assert!(parse_xyz(b"0 0\n").is_err());
+    assert!(parse_xyz(b"0 0 zero\n").is_err());
+  }
+
+  #[test]
+  fn parses_every_pcd_encoding() {
+    let expected = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+    let ascii = pcd("ascii", b"1 2 3\n4 5 6\nnan nan nan\n");
+    assert!(is_pcd(&ascii));
+    assert_eq!(parse_pcd(&ascii).unwrap().positions, expected);
+
+    let binary = pcd("binary", &floats(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+    assert_eq!(parse_pcd(&binary).unwrap().positions, expected);
+
+    // Field by field, as 24 literal bytes in one LZF run
+    let columns = floats(&[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    let mut body = 25u32.to_le_bytes().to_vec();
+    body.extend_from_slice(&24u32.to_le_bytes());
+    body.push(23);
+    body.extend_from_slice(&columns);
+    let compressed = pcd("binary_compressed", &body);
+    assert_eq!(parse_pcd(&compressed).unwrap().positions, expected);
+  }
+
+  #[test]
+  fn reads_packed_colours() {
+    let text = HEADER.replace("x y z", "x y z rgb")
+      .replace("4 4 4", "4 4 4 4")
+      .replace("F F F", "F F F U")
+      .replace("1 1 1", "1 1 1 1");
+    let bytes = format!("{}DATA ascii\n0 0 0 16711680\n1 1 1 255\n", text);
+    let mesh = parse_pcd(bytes.as_bytes()).unwrap();
+    assert_eq!(mesh.colors, [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+  }
+
+  #[test]
+  fn rejects_malformed_pcd() {
+    assert!(parse_pcd(&pcd("ascii", b"1 2 3\n")).is_err());
+    assert!(parse_pcd(&pcd("binary", &floats(&[1.0, 2.0]))).is_err());
+    assert!(parse_pcd(&pcd("gzip", b"")).is_err());
+    assert!(parse_pcd(HEADER.as_bytes()).is_err());
+    // A field with no values
+    let empty = HEADER.replace("COUNT 1 1 1", "COUNT 1 1 0");
+    let bytes = [empty.as_bytes(), b"DATA binary\n", &[0; 16]].concat();
+    assert!(parse_pcd(&bytes).is_err());
+
+    // Sizes claiming more than the data could decompress to
+    let mut body = 2u32.to_le_bytes().to_vec();
+    body.extend_from_slice(&u32::MAX.to_le_bytes());
+    body.extend_from_slice(&[0, 0]);
+    assert!(parse_pcd(&pcd("binary_compressed", &body)).is_err());
+    // A back reference before the start of the output
+    assert!(lzf_decompress(&[0, 1, 0x20, 5], 4).is_err());
+    // A literal run past the end of the input
+    assert!(lzf_decompress(&[5, 1], 6).is_err());
+  }
+}
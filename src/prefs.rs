@@ -0,0 +1,48 @@
+//! Viewer preferences such as the panel layout, kept on the server so
+//! they survive reloads and follow a user from browser to browser. Each
+//! key's preferences are one JSON object in `<dir>/<key>.json`; what's
+//! in it is up to the viewer.
+
+use crate::rewrite::write_atomic;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone)]
+pub struct Prefs {
+  dir: PathBuf,
+}
+
+impl Prefs {
+  pub fn new(dir: PathBuf) -> Self {
+    Prefs { dir }
+  }
+
+  /// The preferences stored under `key`, empty if there are none. The
+  /// key must be safe to use as a file name.
+  pub fn load(&self, key: &str) -> io::Result<Map<String, Value>> {
+    match fs::read(self.path(key)) {
+      Ok(bytes) => serde_json::from_slice(&bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Map::new()),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Replace the preferences stored under `key`.
+  pub fn save(&self, key: &str, prefs: &Map<String, Value>) -> io::Result<()> {
+    fs::create_dir_all(&self.dir)?;
+    let json = serde_json::to_vec(prefs)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&self.path(key), &json)
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  fn path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{}.json", key))
+  }
+}
@@ -0,0 +1,125 @@
+//! Where generated scene files came from: the generator run that
+//! produced each, as reported with its upload. Each file's provenance is
+//! a sidecar, `<dir>/<name>.json`, kept outside the scene directory so
+//! the scene (and its git history) holds only the files.
+//!
+//! A sidecar carries the content hash of the upload it came with, and
+//! only describes the file while its contents still hash the same; a
+//! file rewritten some other way has no known provenance.
+
+use crate::http::encode_path;
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+  /// Name of the generator
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub generator: Option<String>,
+  /// The generator's version
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub version: Option<String>,
+  /// Random seed of the run
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub seed: Option<String>,
+  /// Commit of the generator's source
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub commit: Option<String>,
+  /// Content hash of the upload it came with; set by the server
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hash: Option<String>,
+  /// When it was uploaded, in milliseconds since the Unix epoch; set by
+  /// the server
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub uploaded: Option<u64>,
+}
+
+impl Provenance {
+  /// Whether the uploader reported nothing.
+  pub fn is_empty(&self) -> bool {
+    self.generator.is_none() && self.version.is_none()
+      && self.seed.is_none() && self.commit.is_none()
+  }
+
+  /// The reported fields as URL query parameters, as uploads take them.
+  pub fn query(&self) -> String {
+    [("generator", &self.generator), ("version", &self.version),
+      ("seed", &self.seed), ("commit", &self.commit)]
+      .into_iter()
+      .filter_map(|(key, value)| value.as_ref()
+        .map(|value| format!("{}={}", key, encode_path(value))))
+      .collect::<Vec<_>>()
+      .join("&")
+  }
+}
+
+impl fmt::Display for Provenance {
+  // "gen 1.2 (seed 42, commit abc123)", leaving out what's unknown
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let generator = self.generator.as_deref().unwrap_or("unknown generator");
+    write!(f, "{}", generator)?;
+    if let Some(version) = &self.version {
+      write!(f, " {}", version)?;
+    }
+    let details: Vec<String> =
+      [("seed", &self.seed), ("commit", &self.commit)].into_iter()
+      .filter_map(|(key, value)| value.as_ref()
+        .map(|value| format!("{} {}", key, value)))
+      .collect();
+    if !details.is_empty() {
+      write!(f, " ({})", details.join(", "))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone)]
+pub struct Provenances {
+  dir: PathBuf,
+}
+
+impl Provenances {
+  pub fn new(dir: PathBuf) -> Self {
+    Provenances { dir }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Store the provenance of a file's new contents, or forget the old
+  /// one if the new contents came without any.
+  pub fn set(&self, file: &str, provenance: Option<&Provenance>)
+      -> io::Result<()> {
+    let path = self.path(file);
+    match provenance {
+      Some(provenance) => {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_vec_pretty(provenance)
+          .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(&path, &json)
+      }
+      None => match fs::remove_file(&path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+      },
+    }
+  }
+
+  /// The provenance of a file whose contents hash to `hash`, if its
+  /// sidecar is for those contents. A missing or unreadable sidecar just
+  /// means it's unknown.
+  pub fn get(&self, file: &str, hash: &str) -> Option<Provenance> {
+    let text = fs::read(self.path(file)).ok()?;
+    serde_json::from_slice::<Provenance>(&text).ok()
+      .filter(|provenance| provenance.hash.as_deref() == Some(hash))
+  }
+
+  fn path(&self, file: &str) -> PathBuf {
+    self.dir.join(format!("{}.json", file))
+  }
+}
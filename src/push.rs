@@ -0,0 +1,174 @@
+//! `--push <URL>`: upload local changes to a downstream instance as they
+//! happen, the other direction of `--mirror`.
+//!
+//! The downstream's listing is fetched once to learn its content hashes,
+//! so files it already has aren't sent again. Removals are pushed as
+//! deletes; the manifest isn't pushed. If the downstream is unreachable,
+//! the whole scene is compared again once it's back. Files uploaded here
+//! with a provenance are pushed with it.
+
+use kitbash_viewer::cache::content_hash;
+use kitbash_viewer::client::{self, Client};
+use kitbash_viewer::events::{FileEvent, StampedEvent};
+use kitbash_viewer::provenance::Provenances;
+use kitbash_viewer::source::{IndexedSource, SceneSource};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+struct Push {
+  client: Client,
+  source: Arc<IndexedSource>,
+  provenances: Provenances,
+  /// Content hash of each file on the downstream, None until its
+  /// listing has been fetched (or after a failure)
+  remote: Option<HashMap<String, Option<String>>>,
+  /// Whether the downstream being unreachable has been logged
+  reported_down: bool,
+}
+
+/// Push every local change until the event channel closes.
+pub async fn run(
+    downstream: String,
+    source: Arc<IndexedSource>,
+    provenances: Provenances,
+    mut rx: broadcast::Receiver<StampedEvent>) {
+  let client = Client::new(&downstream);
+  let mut push = Push {
+    client,
+    source,
+    provenances,
+    remote: None,
+    reported_down: false,
+  };
+  push.sync_all(&downstream).await;
+  loop {
+    let event = if push.remote.is_some() {
+      rx.recv().await
+    } else {
+      match tokio::time::timeout(RETRY_DELAY, rx.recv()).await {
+        Ok(event) => event,
+        Err(_) => {
+          push.sync_all(&downstream).await;
+          continue;
+        }
+      }
+    };
+    let event = match event {
+      Ok(stamped) => stamped.event,
+      Err(broadcast::error::RecvError::Lagged(_)) => {
+        push.sync_all(&downstream).await;
+        continue;
+      }
+      Err(broadcast::error::RecvError::Closed) => break,
+    };
+    for event in event.into_events() {
+      if push.remote.is_none() {
+        // Anything missed while out of sync is covered by a full pass
+        if event.filename().is_some() {
+          push.sync_all(&downstream).await;
+        }
+        continue;
+      }
+      match event {
+        FileEvent::Added { filename, .. }
+          | FileEvent::Modified { filename, .. } =>
+          push.upload(&filename).await,
+        FileEvent::Removed { filename } => push.delete(&filename).await,
+        _ => {}
+      }
+    }
+  }
+}
+
+impl Push {
+  // Compare every local file against the downstream and send what
+  // differs
+  async fn sync_all(&mut self, downstream: &str) {
+    let files = match self.client.files().await {
+      Ok(files) => files,
+      Err(e) => {
+        self.remote = None;
+        if !self.reported_down {
+          eprintln!("Push: can't reach {}: {}", downstream, e);
+          self.reported_down = true;
+        }
+        return;
+      }
+    };
+    self.reported_down = false;
+    self.remote = Some(files.into_iter().map(|f| (f.name, f.hash)).collect());
+    let local = self.source.list().unwrap_or_default();
+    println!("Pushing to {} ({} local file(s))", downstream, local.len());
+    for name in &local {
+      self.upload(name).await;
+    }
+    let gone: Vec<String> = self.remote.iter().flatten()
+      .map(|(name, _)| name.clone())
+      .filter(|name| !local.contains(name))
+      .collect();
+    for name in gone {
+      self.delete(&name).await;
+    }
+  }
+
+  async fn upload(&mut self, name: &str) {
+    if self.remote.is_none() {
+      return;
+    }
+    let source = self.source.clone();
+    let file = name.to_string();
+    let read = tokio::task::spawn_blocking(move || source.read(&file));
+    let contents = match read.await {
+      Ok(Ok(contents)) => contents,
+      // Gone again already; its removal follows
+      _ => return,
+    };
+    let hash = content_hash(&contents);
+    let known = self.remote.as_ref().and_then(|r| r.get(name));
+    if known == Some(&Some(hash.clone())) {
+      return;
+    }
+    let uploaded = match self.provenances.get(name, &hash) {
+      Some(provenance) =>
+        self.client.upload_generated(name, &contents, &provenance).await,
+      None => self.client.upload(name, &contents).await,
+    };
+    match uploaded {
+      Ok(()) => {
+        println!("Pushed {}", name);
+        if let Some(remote) = &mut self.remote {
+          remote.insert(name.to_string(), Some(hash));
+        }
+      }
+      Err(e) => self.failed("push", name, e),
+    }
+  }
+
+  async fn delete(&mut self, name: &str) {
+    if !self.remote.as_ref().is_some_and(|r| r.contains_key(name)) {
+      return;
+    }
+    match self.client.delete(name).await {
+      Ok(()) => {
+        println!("Pushed removal of {}", name);
+        if let Some(remote) = &mut self.remote {
+          remote.remove(name);
+        }
+      }
+      Err(e) => self.failed("remove", name, e),
+    }
+  }
+
+  fn failed(&mut self, action: &str, name: &str, e: client::Error) {
+    eprintln!("Push: failed to {} {}: {}", action, name, e);
+    // A rejected file doesn't mean the downstream is gone; anything
+    // else does, so compare everything again once it's back
+    if !matches!(e, client::Error::Http { status: 400..=499, .. }) {
+      self.remote = None;
+    }
+  }
+}
@@ -0,0 +1,101 @@
+//! What makes the viewer an installable web app that opens offline: the
+//! web app manifest, its icon, and a service worker generated from the
+//! scene listing. The worker answers from the network while the server
+//! is reachable, caching as it goes, and from the cache when it isn't.
+
+use crate::viewer_html::{THREE_CDN, THREE_FILES};
+use kitbash_viewer::{cache, http};
+
+pub const MANIFEST: &str = r##"{
+  "name": "Kitbash Viewer",
+  "short_name": "Kitbash",
+  "start_url": "/",
+  "scope": "/",
+  "display": "standalone",
+  "background_color": "#2a2a2a",
+  "theme_color": "#2a2a2a",
+  "icons": [
+    { "src": "/icon.svg", "sizes": "any", "type": "image/svg+xml" }
+  ]
+}
+"##;
+
+pub const ICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg"
+  viewBox="0 0 64 64">
+  <rect width="64" height="64" rx="12" fill="#2a2a2a"/>
+  <path d="M32 10 52 21v22L32 54 12 43V21z" fill="#4a90d9"/>
+  <path d="M32 10 52 21 32 32 12 21z" fill="#8ab4f8"/>
+  <path d="M32 32v22L12 43V21z" fill="#2f6db0"/>
+</svg>
+"##;
+
+/// The service worker for a scene of `files` (name and content hash).
+/// Its cache name, and so its text, changes whenever a file does, which
+/// is what makes browsers install it again and fetch the new files.
+pub fn service_worker(files: &[(String, String)], asset_origin: &str)
+    -> String {
+  let mut urls = vec![
+    "/".to_string(),
+    "/api/config".to_string(),
+    "/api/files".to_string(),
+    "/api/scene/manifest".to_string(),
+  ];
+  urls.extend(files.iter()
+    .map(|(name, _)| format!("/scene/{}", http::encode_path(name))));
+  urls.extend(THREE_FILES.iter().map(|file| format!("{}{}", THREE_CDN, file)));
+  let hashes: serde_json::Map<String, serde_json::Value> = files.iter()
+    .map(|(name, hash)| (name.clone(), hash.clone().into()))
+    .collect();
+  let listing = serde_json::to_string(&hashes).unwrap();
+  format!(r#"// Generated from the scene listing; see pwa.rs
+const CACHE = 'kitbash-{version}';
+const ASSET_ORIGIN = {origin};
+const PRECACHE = {urls};
+
+// A file that can't be fetched now is cached once it's next requested,
+// so one failure doesn't abandon the rest
+self.addEventListener('install', (event) => {{
+  event.waitUntil(caches.open(CACHE)
+    .then((cache) => Promise.allSettled(PRECACHE.map((url) => cache.add(url))))
+    .then(() => self.skipWaiting()));
+}});
+
+self.addEventListener('activate', (event) => {{
+  event.waitUntil(caches.keys()
+    .then((keys) => Promise.all(keys
+      .filter((key) => key.startsWith('kitbash-') && key !== CACHE)
+      .map((key) => caches.delete(key))))
+    .then(() => self.clients.claim()));
+}});
+
+// The page, what it reads at startup, the scene files and three.js
+function cacheable(url) {{
+  if (url.origin === ASSET_ORIGIN) {{
+    return true;
+  }}
+  return url.origin === self.location.origin && (
+    PRECACHE.includes(url.pathname) || url.pathname.startsWith('/scene/'));
+}}
+
+self.addEventListener('fetch', (event) => {{
+  const request = event.request;
+  const url = new URL(request.url);
+  if (request.method !== 'GET' || !cacheable(url)) {{
+    return;
+  }}
+  event.respondWith(fetch(request)
+    .then((response) => {{
+      if (response.ok) {{
+        const copy = response.clone();
+        caches.open(CACHE).then((cache) => cache.put(request, copy));
+      }}
+      return response;
+    }})
+    .catch(() => caches.match(request, {{ ignoreSearch: true }})
+      .then((cached) => cached || Response.error())));
+}});
+"#,
+    version = cache::content_hash(listing.as_bytes()),
+    origin = serde_json::to_string(asset_origin).unwrap(),
+    urls = serde_json::to_string(&urls).unwrap())
+}
@@ -0,0 +1,155 @@
+//! Text-level OBJ rewriting. Edits touch only what they must, so
+//! comments, normals, groups and materials survive unchanged.
+
+use crate::manifest::Transform;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Rewrite every `v` statement's position with `f`, keeping any extra
+/// values on the line (w, vertex colours) as they were.
+pub fn map_positions(text: &str, f: impl Fn([f64; 3]) -> [f64; 3]) -> String {
+  let mut out = String::with_capacity(text.len());
+  for line in text.lines() {
+    let mut parts = line.split_whitespace();
+    if parts.next() == Some("v") {
+      let coords: Vec<&str> = parts.collect();
+      let parsed: Option<Vec<f64>> = coords.iter().take(3)
+        .map(|c| c.parse().ok())
+        .collect();
+      if let Some([x, y, z]) = parsed.as_deref() {
+        let [x, y, z] = f([*x, *y, *z]);
+        out.push_str(&format!("v {} {} {}", x, y, z));
+        for rest in &coords[3..] {
+          out.push(' ');
+          out.push_str(rest);
+        }
+        out.push('\n');
+        continue;
+      }
+    }
+    out.push_str(line);
+    out.push('\n');
+  }
+  out
+}
+
+/// Replace a file via a temporary sibling and a rename, so the watcher
+/// and browsers never see it half-written. The temporary name doesn't
+/// end in `.obj`, so the watcher ignores it.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+  let file_name = path.file_name()
+    .and_then(|n| n.to_str())
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad path"))?;
+  let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+  fs::write(&tmp, contents)?;
+  fs::rename(&tmp, path)
+}
+
+/// Concatenate OBJ files into one, baking each part's transform into its
+/// positions and renumbering face/line/point references. Each part
+/// starts a new object named after its file.
+pub fn merge(parts: &[(String, String, Transform)]) -> String {
+  let mut out = String::new();
+  // Running totals of v, vt, vn written so far
+  let mut offsets = [0i64; 3];
+
+  for (name, text, transform) in parts {
+    let stem = name.strip_suffix(".obj").unwrap_or(name);
+    out.push_str(&format!("o {}\n", stem));
+
+    // Counts within this part, for resolving relative (negative) indices
+    let mut local = [0i64; 3];
+    let transformed = map_positions(text, |p| transform.apply(p));
+    for line in transformed.lines() {
+      let mut parts = line.split_whitespace();
+      let keyword = parts.next().unwrap_or("");
+      match keyword {
+        "v" | "vt" | "vn" => {
+          let slot = ["v", "vt", "vn"].iter().position(|k| *k == keyword);
+          local[slot.unwrap()] += 1;
+          if keyword == "vn" && transform.scale < 0.0 {
+            // A mirrored part needs its normals flipped to match
+            let flipped: Vec<String> = parts
+              .map(|c| c.parse::<f64>()
+                .map_or(c.to_string(), |v| (-v).to_string()))
+              .collect();
+            out.push_str(&format!("vn {}\n", flipped.join(" ")));
+            continue;
+          }
+          out.push_str(line);
+        }
+        "f" | "l" | "p" => {
+          let mut corners: Vec<&str> = parts.collect();
+          if keyword == "f" && transform.scale < 0.0 {
+            // ...and its faces wound the other way, or they'd face in
+            corners.reverse();
+          }
+          out.push_str(keyword);
+          for corner in corners {
+            out.push(' ');
+            out.push_str(&renumber_corner(corner, &local, &offsets));
+          }
+        }
+        _ => out.push_str(line),
+      }
+      out.push('\n');
+    }
+
+    for (offset, count) in offsets.iter_mut().zip(local) {
+      *offset += count;
+    }
+  }
+  out
+}
+
+// Shift each index of a "v/vt/vn" corner into the merged numbering
+fn renumber_corner(
+    corner: &str,
+    local: &[i64; 3],
+    offsets: &[i64; 3]) -> String {
+  corner.split('/')
+    .enumerate()
+    .map(|(slot, index)| match index.parse::<i64>() {
+      Ok(i) if slot < 3 => {
+        let absolute = if i < 0 { local[slot] + i + 1 } else { i };
+        (absolute + offsets[slot]).to_string()
+      }
+      _ => index.to_string(),
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PART: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\n\
+    f 1//1 2//1 -1//1\n";
+
+  #[test]
+  fn merges_parts() {
+    let moved = Transform { translation: [10.0, 0.0, 0.0], scale: 1.0 };
+    let merged = merge(&[("a.obj".to_string(), PART.to_string(),
+      Transform::default()), ("b.obj".to_string(), PART.to_string(), moved)]);
+    let lines: Vec<&str> = merged.lines().collect();
+    assert_eq!(lines[0], "o a");
+    assert_eq!(lines[5], "f 1//1 2//1 3//1");
+    assert_eq!(lines[6], "o b");
+    assert_eq!(lines[7], "v 10 0 0");
+    // Indices continue from the parts before
+    assert_eq!(lines[11], "f 4//2 5//2 6//2");
+  }
+
+  #[test]
+  fn mirrors_parts_inside_out() {
+    let mirrored = Transform { translation: [0.0; 3], scale: -1.0 };
+    let merged = merge(&[("a.obj".to_string(), PART.to_string(), mirrored)]);
+    let lines: Vec<&str> = merged.lines().collect();
+    assert_eq!(lines[2], "v -1 0 0");
+    assert_eq!(lines[4], "vn -0 -0 -1");
+    // Wound the other way, so the face still faces its normal
+    assert_eq!(lines[5], "f 3//1 2//1 1//1");
+  }
+}
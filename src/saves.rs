@@ -0,0 +1,30 @@
+//! How editors save files, so the watcher can see through it. Few write
+//! a file in place:
+//!
+//! - vim moves the file aside (to `foo.obj~`), writes a new one and
+//!   deletes the old, after probing the directory with a file called
+//!   `4913`; its swap file is `.foo.obj.swp`
+//! - emacs keeps a `.#foo.obj` lock link and `#foo.obj#` auto-saves
+//! - Blender and many exporters write a temporary file and rename it
+//!   over the target
+//!
+//! The watcher ignores the scratch files below, and a scene file that
+//! vanishes and comes back within `REPLACE_WINDOW` counts as modified.
+
+use std::time::Duration;
+
+/// How long a removed scene file may take to reappear and count as
+/// replaced rather than removed.
+pub const REPLACE_WINDOW: Duration = Duration::from_millis(300);
+
+/// Whether a file name is an editor's lock, swap, backup or temporary
+/// file rather than a scene file.
+pub fn is_scratch(name: &str) -> bool {
+  name == "4913"
+    || name.starts_with(".#")
+    || name.starts_with('~')
+    || name.ends_with('~')
+    || name.ends_with('@')
+    || (name.starts_with('#') && name.ends_with('#'))
+    || [".swp", ".swo", ".swx", ".tmp"].iter().any(|ext| name.ends_with(ext))
+}
@@ -0,0 +1,274 @@
+//! Scene-level analysis over the parsed meshes of all scene files.
+
+use crate::geom::{self, Vec3};
+use crate::manifest::{Manifest, Transform};
+use crate::mesh::{Bounds, Mesh};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub struct InstanceMember {
+  file:   String,
+  /// Name of the o/g section, absent for whole-file matches
+  #[serde(skip_serializing_if = "Option::is_none")]
+  object: Option<String>,
+  /// Translation of this copy relative to the shared geometry
+  offset: [f64; 3],
+}
+
+#[derive(Serialize)]
+pub struct InstanceGroup {
+  hash:      String,
+  triangles: usize,
+  members:   Vec<InstanceMember>,
+}
+
+#[derive(Serialize)]
+pub struct InstanceReport {
+  /// Files whose whole geometry is identical (up to translation)
+  files:   Vec<InstanceGroup>,
+  /// Named sub-objects with identical geometry, across all files
+  objects: Vec<InstanceGroup>,
+}
+
+/// Group files and sub-objects by geometry hash, keeping only groups
+/// with more than one member.
+pub fn find_instances(meshes: &[(String, Arc<Mesh>)]) -> InstanceReport {
+  let mut files:   BTreeMap<String, InstanceGroup> = BTreeMap::new();
+  let mut objects: BTreeMap<String, InstanceGroup> = BTreeMap::new();
+
+  for (filename, mesh) in meshes {
+    let all = 0..mesh.triangles.len();
+    if let Some((hash, offset)) = mesh.geometry_hash(all.clone()) {
+      add_member(&mut files, hash, all.len(), InstanceMember {
+        file: filename.clone(),
+        object: None,
+        offset,
+      });
+    }
+
+    // A file with a single object is already covered by the file groups
+    if mesh.objects.len() < 2 {
+      continue;
+    }
+    for object in &mesh.objects {
+      let range = object.triangles.clone();
+      if let Some((hash, offset)) = mesh.geometry_hash(range.clone()) {
+        add_member(&mut objects, hash, range.len(), InstanceMember {
+          file:   filename.clone(),
+          object: Some(object.name.clone()),
+          offset,
+        });
+      }
+    }
+  }
+
+  let keep_repeats = |groups: BTreeMap<String, InstanceGroup>| {
+    groups.into_values().filter(|g| g.members.len() > 1).collect()
+  };
+
+  InstanceReport {
+    files:   keep_repeats(files),
+    objects: keep_repeats(objects),
+  }
+}
+
+fn add_member(
+    groups: &mut BTreeMap<String, InstanceGroup>,
+    hash: String,
+    triangles: usize,
+    member: InstanceMember) {
+  groups.entry(hash.clone())
+    .or_insert_with(|| InstanceGroup { hash, triangles, members: Vec::new() })
+    .members.push(member);
+}
+
+#[derive(Default, Deserialize)]
+pub struct LayoutOptions {
+  /// Gap between parts; defaults to a tenth of the largest part
+  spacing: Option<f64>,
+  /// Parts per row; defaults to whatever keeps the layout roughly square
+  columns: Option<usize>,
+}
+
+/// Lay parts out in rows on the XZ plane so that no two bounding boxes
+/// overlap, centred on the origin. Existing scales and heights are kept;
+/// returns the new transform for every part.
+pub fn auto_layout(
+    parts: &[(String, Bounds)],
+    manifest: &Manifest,
+    options: &LayoutOptions) -> BTreeMap<String, Transform> {
+  // Footprints after each part's own scale, before any translation
+  let scaled: Vec<(&String, Transform, Bounds)> = parts.iter()
+    .map(|(name, bounds)| {
+      let current = manifest.transform(name);
+      let scale_only = Transform { scale: current.scale, ..Default::default() };
+      (name, current, scale_only.apply_bounds(*bounds))
+    })
+    .collect();
+
+  let size = |b: &Bounds| (b.max[0] - b.min[0], b.max[2] - b.min[2]);
+  let largest = scaled.iter()
+    .map(|(_, _, b)| { let (w, d) = size(b); w.max(d) })
+    .fold(0.0, f64::max);
+  let spacing = options.spacing.unwrap_or(largest * 0.1);
+  let target_width = scaled.iter()
+    .map(|(_, _, b)| { let (w, d) = size(b); (w + spacing) * (d + spacing) })
+    .sum::<f64>()
+    .sqrt();
+
+  let mut placed = Vec::new();
+  let (mut x, mut z, mut row_depth, mut in_row) = (0.0, 0.0, 0.0f64, 0);
+  let mut extent_x = 0.0f64;
+  for (name, current, bounds) in &scaled {
+    let (w, d) = size(bounds);
+    let row_full = match options.columns {
+      Some(columns) => in_row >= columns.max(1),
+      None          => x + w > target_width,
+    };
+    if in_row > 0 && row_full {
+      x = 0.0;
+      z += row_depth + spacing;
+      row_depth = 0.0;
+      in_row = 0;
+    }
+    let translation = [
+      x - bounds.min[0],
+      current.translation[1],
+      z - bounds.min[2],
+    ];
+    placed.push(((*name).clone(), Transform { translation, ..*current }));
+    extent_x = extent_x.max(x + w);
+    x += w + spacing;
+    row_depth = row_depth.max(d);
+    in_row += 1;
+  }
+
+  // Centre the whole arrangement on the origin
+  let (shift_x, shift_z) = (extent_x / 2.0, (z + row_depth) / 2.0);
+  placed.into_iter()
+    .map(|(name, mut transform)| {
+      transform.translation[0] -= shift_x;
+      transform.translation[2] -= shift_z;
+      (name, transform)
+    })
+    .collect()
+}
+
+#[derive(Serialize)]
+pub struct Overlap {
+  a: String,
+  b: String,
+  /// Region where the two world-space bounding boxes overlap
+  region: Bounds,
+  /// Smallest distance either part would have to move along one axis to
+  /// separate the boxes
+  depth: f64,
+  /// Axis ("x", "y" or "z") along which that separation is shortest
+  axis: &'static str,
+  /// Number of intersecting triangle pairs, when a triangle-level check
+  /// was requested
+  #[serde(skip_serializing_if = "Option::is_none")]
+  intersecting_triangles: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct OverlapReport {
+  overlaps: Vec<Overlap>,
+}
+
+/// Pairwise bounding-box overlaps between parts, in world space (with
+/// manifest transforms applied). With `triangles` set, each overlapping
+/// pair is also checked triangle against triangle; pairs whose boxes
+/// overlap but whose surfaces don't cross are then dropped.
+pub fn find_overlaps(
+    meshes: &[(String, Arc<Mesh>)],
+    manifest: &Manifest,
+    triangles: bool) -> OverlapReport {
+  let placed: Vec<(&String, &Mesh, Transform, Bounds)> = meshes.iter()
+    .filter_map(|(name, mesh)| {
+      let transform = manifest.transform(name);
+      mesh.bounds().map(|b| (name, &**mesh, transform, transform.apply_bounds(b)))
+    })
+    .collect();
+
+  let mut overlaps = Vec::new();
+  for (i, (name_a, mesh_a, transform_a, bounds_a)) in placed.iter().enumerate() {
+    for (name_b, mesh_b, transform_b, bounds_b) in &placed[i + 1..] {
+      let Some(region) = bounds_a.intersection(bounds_b) else { continue };
+
+      let intersecting_triangles = if triangles {
+        let count = count_intersecting_triangles(
+          (mesh_a, *transform_a), (mesh_b, *transform_b), &region);
+        if count == 0 {
+          continue;
+        }
+        Some(count)
+      } else {
+        None
+      };
+
+      // Penetration along each axis: how far the boxes would have to
+      // move apart along it
+      let depths = [0, 1, 2].map(|axis| {
+        (bounds_a.max[axis] - bounds_b.min[axis])
+          .min(bounds_b.max[axis] - bounds_a.min[axis])
+      });
+      let (axis, depth) = depths.iter().enumerate()
+        .min_by(|x, y| x.1.total_cmp(y.1))
+        .map(|(axis, depth)| (["x", "y", "z"][axis], *depth))
+        .unwrap();
+
+      overlaps.push(Overlap {
+        a: (*name_a).clone(),
+        b: (*name_b).clone(),
+        region,
+        depth,
+        axis,
+        intersecting_triangles,
+      });
+    }
+  }
+
+  OverlapReport { overlaps }
+}
+
+// World-space triangles of a mesh whose bounds touch a region, each
+// with its own bounds for cheap rejection
+fn triangles_near(
+    mesh: &Mesh,
+    transform: Transform,
+    region: &Bounds) -> Vec<([Vec3; 3], Bounds)> {
+  mesh.triangles.iter()
+    .filter_map(|triangle| {
+      let corners = triangle.map(|vi| transform.apply(mesh.positions[vi]));
+      let mut bounds = Bounds { min: corners[0], max: corners[0] };
+      bounds.extend(corners[1]);
+      bounds.extend(corners[2]);
+      touches(&bounds, region).then_some((corners, bounds))
+    })
+    .collect()
+}
+
+fn touches(a: &Bounds, b: &Bounds) -> bool {
+  (0..3).all(|axis| a.min[axis] <= b.max[axis] && b.min[axis] <= a.max[axis])
+}
+
+fn count_intersecting_triangles(
+    (mesh_a, transform_a): (&Mesh, Transform),
+    (mesh_b, transform_b): (&Mesh, Transform),
+    region: &Bounds) -> usize {
+  let near_a = triangles_near(mesh_a, transform_a, region);
+  let near_b = triangles_near(mesh_b, transform_b, region);
+
+  let mut count = 0;
+  for (tri_a, bounds_a) in &near_a {
+    for (tri_b, bounds_b) in &near_b {
+      if touches(bounds_a, bounds_b) && geom::triangles_intersect(*tri_a, *tri_b) {
+        count += 1;
+      }
+    }
+  }
+  count
+}
@@ -0,0 +1,130 @@
+//! Screenshots uploaded by viewers, browsed through the gallery. Each is
+//! stored as `<dir>/<name>.png`, next to a `<name>.json` recording what
+//! the viewer showed when it was taken, if it sent that along.
+
+use crate::events::ViewState;
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a screenshot shows, kept next to it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Shown {
+  /// Milliseconds since the Unix epoch
+  pub created: u64,
+  pub view: ViewState,
+  /// Scene files that were shown
+  pub files: Vec<String>,
+}
+
+/// A screenshot in a listing.
+#[derive(Clone, Serialize)]
+pub struct ScreenshotInfo {
+  /// File name, e.g. `front.png`
+  pub file: String,
+  pub bytes: u64,
+  /// Milliseconds since the Unix epoch: when it was taken, or when the
+  /// file was last written if that wasn't recorded
+  pub created: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub view: Option<ViewState>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub files: Option<Vec<String>>,
+}
+
+#[derive(Clone)]
+pub struct Screenshots {
+  dir: PathBuf,
+}
+
+impl Screenshots {
+  pub fn new(dir: PathBuf) -> Self {
+    Screenshots { dir }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Store a PNG as `<name>.png`, replacing any with that name, along
+  /// with what it shows if known. The name must be safe to use as a
+  /// file name. Returns the file name.
+  pub fn save(&self, name: &str, png: &[u8], view: Option<ViewState>,
+      files: Vec<String>) -> io::Result<String> {
+    let file = format!("{}.png", name);
+    fs::create_dir_all(&self.dir)?;
+    write_atomic(&self.dir.join(&file), png)?;
+    let sidecar = self.dir.join(format!("{}.json", name));
+    match view {
+      Some(view) => {
+        let shown = Shown { created: unix_millis(SystemTime::now()), view,
+          files };
+        let json = serde_json::to_vec_pretty(&shown)
+          .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(&sidecar, &json)?;
+      }
+      // Don't leave an older screenshot's view next to this one
+      None => match fs::remove_file(&sidecar) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+      },
+    }
+    Ok(file)
+  }
+
+  /// Path of the screenshot with this file name, None if the name
+  /// couldn't be one.
+  pub fn path(&self, file: &str) -> Option<PathBuf> {
+    let plain = !file.starts_with('.') && !file.contains(['/', '\\']);
+    (plain && file.ends_with(".png")).then(|| self.dir.join(file))
+  }
+
+  /// Every screenshot, newest first.
+  pub fn list(&self) -> io::Result<Vec<ScreenshotInfo>> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e),
+    };
+    let mut shots = Vec::new();
+    for entry in entries.flatten() {
+      let Some(file) = entry.file_name().to_str().map(str::to_string)
+      else { continue };
+      let Some(name) = file.strip_suffix(".png")
+        .filter(|name| !name.starts_with('.'))
+      else { continue };
+      let Ok(meta) = entry.metadata() else { continue };
+      // A missing or unreadable sidecar just means less to show
+      let shown: Option<Shown> =
+        fs::read(self.dir.join(format!("{}.json", name))).ok()
+          .and_then(|json| serde_json::from_slice(&json).ok());
+      let written = meta.modified().map_or(0, unix_millis);
+      shots.push(match shown {
+        Some(shown) => ScreenshotInfo {
+          file,
+          bytes: meta.len(),
+          created: shown.created,
+          view: Some(shown.view),
+          files: Some(shown.files),
+        },
+        None => ScreenshotInfo {
+          file,
+          bytes: meta.len(),
+          created: written,
+          view: None,
+          files: None,
+        },
+      });
+    }
+    shots.sort_by(|a, b| b.created.cmp(&a.created)
+      .then_with(|| a.file.cmp(&b.file)));
+    Ok(shots)
+  }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+  time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
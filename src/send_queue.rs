@@ -0,0 +1,180 @@
+//! A WebSocket client's own queue of events to send. Each connection
+//! drains the shared broadcast channel into its queue as events arrive,
+//! so a client on a slow link falls behind on its own instead of
+//! lagging the channel for everyone.
+//!
+//! While events wait, a `modified` or `removed` for a file drops the
+//! `modified` queued before it, which is out of date by then. A client
+//! that still falls [`SendQueue::new`]'s capacity behind gets a fresh
+//! snapshot in place of the backlog.
+
+use kitbash_viewer::events::{FileEvent, StampedEvent};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// What to send the client next.
+pub enum Next {
+  Event(StampedEvent),
+  /// The backlog was dropped; send a snapshot
+  Resync,
+  /// No more events will come
+  Closed,
+}
+
+pub struct SendQueue {
+  capacity: usize,
+  inner: Mutex<Inner>,
+  ready: Notify,
+}
+
+#[derive(Default)]
+struct Inner {
+  events: VecDeque<StampedEvent>,
+  resync: bool,
+  closed: bool,
+}
+
+impl SendQueue {
+  pub fn new(capacity: usize) -> SendQueue {
+    SendQueue {
+      capacity,
+      inner: Mutex::default(),
+      ready: Notify::new(),
+    }
+  }
+
+  /// Queue an event, dropping any it makes out of date. Returns false
+  /// if the queue was full, so the client will be resynced instead.
+  pub fn push(&self, event: StampedEvent) -> bool {
+    let mut inner = self.inner.lock().unwrap();
+    if inner.resync {
+      // The snapshot will cover it
+      return true;
+    }
+    if let Some(name) = superseding(&event.event) {
+      inner.events.retain(|queued| !matches!(&queued.event,
+        FileEvent::Modified { filename, .. } if filename == name));
+    }
+    let fits = inner.events.len() < self.capacity;
+    if fits {
+      inner.events.push_back(event);
+    } else {
+      inner.events.clear();
+      inner.resync = true;
+    }
+    drop(inner);
+    self.ready.notify_one();
+    fits
+  }
+
+  /// Drop the backlog and resync the client, e.g. after missing events.
+  pub fn overflow(&self) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.events.clear();
+    inner.resync = true;
+    drop(inner);
+    self.ready.notify_one();
+  }
+
+  /// No more events; `pop` returns `Closed` once the queue is empty.
+  pub fn close(&self) {
+    self.inner.lock().unwrap().closed = true;
+    self.ready.notify_one();
+  }
+
+  pub async fn pop(&self) -> Next {
+    loop {
+      {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.resync {
+          inner.resync = false;
+          return Next::Resync;
+        }
+        if let Some(event) = inner.events.pop_front() {
+          return Next::Event(event);
+        }
+        if inner.closed {
+          return Next::Closed;
+        }
+      }
+      self.ready.notified().await;
+    }
+  }
+}
+
+// The file whose queued `modified` an event makes out of date
+fn superseding(event: &FileEvent) -> Option<&str> {
+  match event {
+    FileEvent::Modified { filename, .. }
+    | FileEvent::Removed { filename } => Some(filename),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use kitbash_viewer::testing::{EventInjector, MemorySource};
+  use std::sync::Arc;
+  use tokio::sync::broadcast;
+
+  // Queue everything the injector has sent so far, stamped in order
+  fn drain(rx: &mut broadcast::Receiver<FileEvent>, queue: &SendQueue) {
+    let mut id = 0;
+    while let Ok(event) = rx.try_recv() {
+      id += 1;
+      queue.push(StampedEvent { id, time: 0, event });
+    }
+  }
+
+  async fn next_event(queue: &SendQueue) -> Option<(&'static str, String)> {
+    match queue.pop().await {
+      Next::Event(stamped) => Some((stamped.event.kind(),
+        stamped.event.filename().unwrap_or("").to_string())),
+      _ => None,
+    }
+  }
+
+  #[tokio::test]
+  async fn later_changes_drop_queued_modifications() {
+    let (tx, mut rx) = broadcast::channel(16);
+    let injector = EventInjector::new(Arc::new(MemorySource::new()), tx);
+    injector.write("a.obj", "v 0 0 0");
+    injector.write("a.obj", "v 1 0 0");
+    injector.write("b.obj", "v 0 0 0");
+    injector.write("b.obj", "v 2 0 0");
+    injector.write("a.obj", "v 3 0 0");
+    injector.remove("b.obj");
+    let queue = SendQueue::new(16);
+    drain(&mut rx, &queue);
+    queue.close();
+
+    // Adds are kept; each file's modifications collapse into its last
+    // change
+    let expected = [("added", "a.obj"), ("added", "b.obj"),
+      ("modified", "a.obj"), ("removed", "b.obj")];
+    for (kind, name) in expected {
+      assert_eq!(next_event(&queue).await, Some((kind, name.to_string())));
+    }
+    assert!(matches!(queue.pop().await, Next::Closed));
+  }
+
+  #[tokio::test]
+  async fn overflowing_resyncs() {
+    let (tx, mut rx) = broadcast::channel(16);
+    let injector = EventInjector::new(Arc::new(MemorySource::new()), tx);
+    for name in ["a.obj", "b.obj", "c.obj"] {
+      injector.write(name, "v 0 0 0");
+    }
+    let queue = SendQueue::new(2);
+    drain(&mut rx, &queue);
+    assert!(matches!(queue.pop().await, Next::Resync));
+
+    // Events after the resync queue again
+    injector.write("d.obj", "v 0 0 0");
+    drain(&mut rx, &queue);
+    assert_eq!(next_event(&queue).await,
+      Some(("added", "d.obj".to_string())));
+  }
+}
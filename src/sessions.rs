@@ -0,0 +1,162 @@
+//! Recorded reviews: the control commands viewers were sent while a
+//! recording ran (camera moves, selections, hidden files, restored
+//! snapshots, material sets), each with how far into the review it
+//! came, so the review can be played back into the viewers later at
+//! its original pace. Each session is stored as `<dir>/<name>.json`.
+//!
+//! What reviewers do in their own viewers is recorded too: viewers
+//! report their camera, selection, hidden files and clipping planes
+//! after their user changes them, and each report is recorded as a
+//! `restore` command. Only viewers whose user may use the control API
+//! are recorded. There are no annotations to record in this tree.
+
+use crate::events::ControlCommand;
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+  pub name: String,
+  /// When recording started, in milliseconds since the Unix epoch
+  pub started: u64,
+  /// From starting the recording to stopping it
+  pub duration_ms: u64,
+  pub commands: Vec<RecordedCommand>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedCommand {
+  /// Since the recording started
+  pub at_ms: u64,
+  #[serde(flatten)]
+  pub command: ControlCommand,
+}
+
+/// A session without its commands, for listings.
+#[derive(Serialize)]
+pub struct SessionSummary {
+  pub name: String,
+  /// Milliseconds since the Unix epoch
+  pub started: u64,
+  pub duration_ms: u64,
+  pub commands: usize,
+}
+
+impl Session {
+  pub fn summary(&self) -> SessionSummary {
+    SessionSummary {
+      name: self.name.clone(),
+      started: self.started,
+      duration_ms: self.duration_ms,
+      commands: self.commands.len(),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct Sessions {
+  dir: PathBuf,
+  /// The session being recorded, if one is
+  recording: Arc<Mutex<Option<Session>>>,
+}
+
+impl Sessions {
+  pub fn new(dir: PathBuf) -> Self {
+    Sessions { dir, recording: Arc::new(Mutex::new(None)) }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Start recording a session called `name`, at `now` in milliseconds
+  /// since the Unix epoch; None if one is already being recorded. The
+  /// name must be safe to use as a file name.
+  pub fn start(&self, name: &str, now: u64) -> Option<SessionSummary> {
+    let mut recording = self.recording.lock().unwrap();
+    if recording.is_some() {
+      return None;
+    }
+    let session = Session {
+      name: name.to_string(),
+      started: now,
+      duration_ms: 0,
+      commands: Vec::new(),
+    };
+    let summary = session.summary();
+    *recording = Some(session);
+    Some(summary)
+  }
+
+  /// Add a command sent at `time` to the session being recorded, if
+  /// there is one.
+  pub fn record(&self, command: &ControlCommand, time: u64) {
+    if let Some(session) = self.recording.lock().unwrap().as_mut() {
+      session.commands.push(RecordedCommand {
+        at_ms: time.saturating_sub(session.started),
+        command: command.clone(),
+      });
+    }
+  }
+
+  /// The session being recorded, as far as it's got.
+  pub fn recording(&self, now: u64) -> Option<SessionSummary> {
+    self.recording.lock().unwrap().as_ref().map(|session| SessionSummary {
+      duration_ms: now.saturating_sub(session.started),
+      ..session.summary()
+    })
+  }
+
+  /// Stop recording at `now` and store the session, replacing any with
+  /// its name; None if nothing was being recorded.
+  pub fn stop(&self, now: u64) -> io::Result<Option<Session>> {
+    let Some(mut session) = self.recording.lock().unwrap().take() else {
+      return Ok(None);
+    };
+    session.duration_ms = now.saturating_sub(session.started);
+    fs::create_dir_all(&self.dir)?;
+    let json = serde_json::to_vec_pretty(&session)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&self.path(&session.name), &json)?;
+    Ok(Some(session))
+  }
+
+  /// The session called `name`; NotFound if there's none.
+  pub fn load(&self, name: &str) -> io::Result<Session> {
+    let text = fs::read(self.path(name))?;
+    serde_json::from_slice(&text)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+  }
+
+  /// NotFound if there's no session called `name`.
+  pub fn delete(&self, name: &str) -> io::Result<()> {
+    fs::remove_file(self.path(name))
+  }
+
+  /// Every stored session, sorted by name. Unreadable ones are left
+  /// out.
+  pub fn list(&self) -> io::Result<Vec<SessionSummary>> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e),
+    };
+    let mut sessions: Vec<SessionSummary> = entries.flatten()
+      .filter_map(|entry| entry.file_name().to_str()
+        .and_then(|name| name.strip_suffix(".json"))
+        .filter(|name| !name.starts_with('.'))
+        .and_then(|name| self.load(name).ok())
+        .map(|session| session.summary()))
+      .collect();
+    sessions.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(sessions)
+  }
+
+  fn path(&self, name: &str) -> PathBuf {
+    self.dir.join(format!("{}.json", name))
+  }
+}
@@ -0,0 +1,82 @@
+//! Named review states: what the viewers showed (visibility, selection,
+//! camera, clipping planes) plus the manifest's placements at the time,
+//! so a review can be set up again exactly. Each snapshot is stored as
+//! `<dir>/<name>.json`.
+
+use crate::events::ViewState;
+use crate::manifest::Transform;
+use crate::rewrite::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+  pub name: String,
+  /// Milliseconds since the Unix epoch
+  pub created: u64,
+  pub view: ViewState,
+  /// The manifest's placements, keyed by filename
+  pub transforms: BTreeMap<String, Transform>,
+}
+
+#[derive(Clone)]
+pub struct Snapshots {
+  dir: PathBuf,
+}
+
+impl Snapshots {
+  pub fn new(dir: PathBuf) -> Self {
+    Snapshots { dir }
+  }
+
+  /// Store a snapshot under `name`, replacing any with that name. The
+  /// name must be safe to use as a file name.
+  pub fn save(&self, name: &str, view: ViewState,
+      transforms: BTreeMap<String, Transform>) -> io::Result<Snapshot> {
+    let snapshot = Snapshot {
+      name: name.to_string(),
+      created: SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0),
+      view,
+      transforms,
+    };
+    fs::create_dir_all(&self.dir)?;
+    let json = serde_json::to_vec_pretty(&snapshot)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(&self.dir.join(format!("{}.json", name)), &json)?;
+    Ok(snapshot)
+  }
+
+  /// The snapshot called `name`; NotFound if there's none.
+  pub fn load(&self, name: &str) -> io::Result<Snapshot> {
+    let text = fs::read(self.dir.join(format!("{}.json", name)))?;
+    serde_json::from_slice(&text)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Names of all snapshots, sorted.
+  pub fn names(&self) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e),
+    };
+    let mut names: Vec<String> = entries.flatten()
+      .filter_map(|entry| entry.file_name().to_str()
+        .and_then(|name| name.strip_suffix(".json"))
+        .filter(|name| !name.starts_with('.'))
+        .map(str::to_string))
+      .collect();
+    names.sort();
+    Ok(names)
+  }
+}
@@ -0,0 +1,250 @@
+//! Where scene files come from. The server lists and reads scene files
+//! through a `SceneSource`; writes (normalize, merge, the manifest)
+//! still go straight to the scene directory.
+
+use crate::events::FileEvent;
+use crate::formats;
+use crate::links;
+use crate::saves;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+pub trait SceneSource: Send + Sync {
+  /// Names of the scene files, in any format `formats` lists, sorted
+  /// by name
+  fn list(&self) -> io::Result<Vec<String>>;
+
+  /// Contents of one scene file
+  fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+}
+
+impl<S: SceneSource + ?Sized> SceneSource for std::sync::Arc<S> {
+  fn list(&self) -> io::Result<Vec<String>> {
+    (**self).list()
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    (**self).read(name)
+  }
+}
+
+/// A scene directory on the local filesystem.
+pub struct DirSource {
+  pub dir: PathBuf,
+  /// See `links` for how symlinks are treated
+  pub follow_symlinks: bool,
+}
+
+impl SceneSource for DirSource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&self.dir)?.flatten() {
+      let Some(name) = entry.file_name().to_str().map(str::to_string)
+      else { continue };
+      if formats::is_scene_file(&name) && !saves::is_scratch(&name)
+          && links::is_file(&entry, self.follow_symlinks) {
+        files.push(name);
+      }
+    }
+    files.sort();
+    Ok(files)
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    fs::read(self.dir.join(name))
+  }
+}
+
+/// A scene directory with a second directory laid over it, such as a
+/// generator's output folder. Files from both are listed by name, and
+/// where both have a file of the same name the overlay's is used.
+pub struct OverlaySource {
+  pub scene: DirSource,
+  pub overlay: DirSource,
+}
+
+impl OverlaySource {
+  /// Where a scene file is on disk: in the overlay if it has one by
+  /// that name, otherwise in the scene directory.
+  pub fn path(&self, name: &str) -> PathBuf {
+    overlay_path(&self.scene.dir, Some(&self.overlay.dir), name)
+  }
+}
+
+impl SceneSource for OverlaySource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    let mut files: BTreeSet<String> = self.scene.list()?.into_iter().collect();
+    files.extend(self.overlay.list()?);
+    Ok(files.into_iter().collect())
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    fs::read(self.path(name))
+  }
+}
+
+/// The file `name` of a scene directory with an optional overlay, as
+/// `OverlaySource` resolves it.
+pub fn overlay_path(scene_dir: &Path, overlay_dir: Option<&Path>, name: &str)
+    -> PathBuf {
+  overlay_dir.map(|dir| dir.join(name))
+    .filter(|path| path.exists())
+    .unwrap_or_else(|| scene_dir.join(name))
+}
+
+/// Names of files from reference directories start with this. They're
+/// shown and watched like any other, but never changed by the server.
+pub const REF_PREFIX: &str = "ref/";
+
+pub fn is_ref(name: &str) -> bool {
+  name.starts_with(REF_PREFIX)
+}
+
+/// Another source with read-only reference directories, such as scans
+/// to model against, whose files are listed as `ref/<name>`. Where two
+/// reference directories have a file of the same name, the first one's
+/// is used.
+pub struct RefSource {
+  pub inner: Box<dyn SceneSource>,
+  pub refs: Vec<DirSource>,
+}
+
+impl SceneSource for RefSource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    let mut files: BTreeSet<String> = self.inner.list()?.into_iter().collect();
+    for dir in &self.refs {
+      files.extend(dir.list()?.into_iter()
+        .map(|name| format!("{}{}", REF_PREFIX, name)));
+    }
+    Ok(files.into_iter().collect())
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    let dirs: Vec<PathBuf> = self.refs.iter().map(|r| r.dir.clone()).collect();
+    match ref_path(&dirs, name) {
+      Some(path) => fs::read(path),
+      None => self.inner.read(name),
+    }
+  }
+}
+
+/// Where a `ref/` file is on disk, None for other names.
+pub fn ref_path(ref_dirs: &[PathBuf], name: &str) -> Option<PathBuf> {
+  let file = name.strip_prefix(REF_PREFIX)?;
+  ref_dirs.iter().map(|dir| dir.join(file))
+    .find(|path| path.exists())
+    .or_else(|| ref_dirs.first().map(|dir| dir.join(file)))
+}
+
+/// Wraps another source, answering `list` from a set of names that is
+/// kept up to date by applying the watcher's events, so listings don't
+/// touch the (possibly slow, networked) filesystem.
+///
+/// Each name carries a version that changes whenever the file may have,
+/// so what's worked out from a file's contents can be kept until then.
+pub struct IndexedSource {
+  inner: Box<dyn SceneSource>,
+  names: RwLock<BTreeMap<String, u64>>,
+  next_version: AtomicU64,
+}
+
+impl IndexedSource {
+  /// Index the source's current files.
+  pub fn new(inner: Box<dyn SceneSource>) -> io::Result<Self> {
+    let index = IndexedSource {
+      inner,
+      names: RwLock::new(BTreeMap::new()),
+      next_version: AtomicU64::new(0),
+    };
+    index.refresh()?;
+    Ok(index)
+  }
+
+  /// Update the index for an event. Call this before broadcasting the
+  /// event, so clients reacting to it see the new listing.
+  pub fn apply(&self, event: &FileEvent) {
+    let mut names = self.names.write().unwrap();
+    match event {
+      FileEvent::Added { filename, .. }
+      | FileEvent::Modified { filename, .. } => {
+        names.insert(filename.clone(), self.bump());
+      }
+      FileEvent::Removed { filename } => {
+        names.remove(filename);
+      }
+      _ => {}
+    }
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.names.read().unwrap().contains_key(name)
+  }
+
+  /// How many files are indexed.
+  pub fn count(&self) -> usize {
+    self.names.read().unwrap().len()
+  }
+
+  /// The file's current version, None if it isn't indexed.
+  pub fn version(&self, name: &str) -> Option<u64> {
+    self.names.read().unwrap().get(name).copied()
+  }
+
+  /// Re-list the inner source, e.g. after the watcher dropped events.
+  /// Every file gets a new version, as any of them may have changed
+  /// unseen.
+  pub fn refresh(&self) -> io::Result<()> {
+    let fresh = self.inner.list()?.into_iter()
+      .map(|name| (name, self.bump()))
+      .collect();
+    *self.names.write().unwrap() = fresh;
+    Ok(())
+  }
+
+  fn bump(&self) -> u64 {
+    self.next_version.fetch_add(1, Ordering::Relaxed)
+  }
+}
+
+impl SceneSource for IndexedSource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    Ok(self.names.read().unwrap().keys().cloned().collect())
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    self.inner.read(name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::MemorySource;
+
+  #[test]
+  fn versions_change_with_events() {
+    let memory = MemorySource::new();
+    memory.insert("a.obj", "v 0 0 0\n");
+    memory.insert("b.obj", "v 0 0 0\n");
+    let index = IndexedSource::new(Box::new(memory)).unwrap();
+    let (a, b) = (index.version("a.obj"), index.version("b.obj"));
+    assert!(a.is_some() && b.is_some() && a != b);
+
+    index.apply(&FileEvent::modified("a.obj"));
+    assert_ne!(index.version("a.obj"), a);
+    assert_eq!(index.version("b.obj"), b);
+    index.apply(&FileEvent::Removed { filename: "b.obj".to_string() });
+    assert_eq!(index.version("b.obj"), None);
+    assert_eq!(index.count(), 1);
+
+    // Anything may have changed unseen
+    let a = index.version("a.obj");
+    index.refresh().unwrap();
+    assert_ne!(index.version("a.obj"), a);
+    assert!(index.contains("b.obj"));
+  }
+}
@@ -0,0 +1,113 @@
+//! Timing of the mesh pipeline stages (read, parse, pipeline,
+//! transcode), kept per stage and per file for the logs and
+//! `/api/stats`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Default, Serialize)]
+pub struct StageStats {
+  pub count: u64,
+  pub total_ms: f64,
+  pub max_ms: f64,
+  /// Runs that took longer than the slow threshold
+  pub slow: u64,
+}
+
+#[derive(Serialize)]
+pub struct StatsReport {
+  pub slow_threshold_ms: f64,
+  pub cache_hits: u64,
+  pub cache_misses: u64,
+  /// Transcodes that reused a config pipeline's earlier result
+  pub pipeline_hits: u64,
+  pub stages: BTreeMap<&'static str, StageStats>,
+  /// Most recent time of each stage, per file
+  pub files: BTreeMap<String, BTreeMap<&'static str, f64>>,
+}
+
+pub struct PipelineStats {
+  slow_threshold: Duration,
+  inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+  cache_hits: u64,
+  cache_misses: u64,
+  pipeline_hits: u64,
+  stages: BTreeMap<&'static str, StageStats>,
+  files: BTreeMap<String, BTreeMap<&'static str, f64>>,
+}
+
+impl PipelineStats {
+  /// Stages slower than `slow_threshold` are logged as warnings.
+  pub fn new(slow_threshold: Duration) -> Self {
+    PipelineStats { slow_threshold, inner: Mutex::default() }
+  }
+
+  /// Run `f` as one stage, recording how long it took.
+  pub fn time<T>(&self, stage: &'static str, file: Option<&str>,
+      f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let out = f();
+    self.record(stage, file, start.elapsed());
+    out
+  }
+
+  pub fn record(&self, stage: &'static str, file: Option<&str>,
+      elapsed: Duration) {
+    // Microsecond resolution is plenty and keeps the JSON readable
+    let ms = (elapsed.as_secs_f64() * 1e6).round() / 1e3;
+    let slow = elapsed > self.slow_threshold;
+    if slow {
+      eprintln!("Slow {}{}: {:.1} ms (threshold {:.0} ms)",
+        stage,
+        file.map(|f| format!(" of {}", f)).unwrap_or_default(),
+        ms,
+        self.slow_threshold.as_secs_f64() * 1000.0);
+    }
+
+    let mut inner = self.inner.lock().unwrap();
+    let stats = inner.stages.entry(stage).or_default();
+    stats.count += 1;
+    stats.total_ms += ms;
+    stats.max_ms = stats.max_ms.max(ms);
+    stats.slow += slow as u64;
+    if let Some(file) = file {
+      inner.files.entry(file.to_string()).or_default().insert(stage, ms);
+    }
+  }
+
+  pub fn record_cache(&self, hit: bool) {
+    let mut inner = self.inner.lock().unwrap();
+    if hit {
+      inner.cache_hits += 1;
+    } else {
+      inner.cache_misses += 1;
+    }
+  }
+
+  pub fn record_pipeline_hit(&self) {
+    self.inner.lock().unwrap().pipeline_hits += 1;
+  }
+
+  /// Drop per-file timings, e.g. when the file is deleted.
+  pub fn forget(&self, file: &str) {
+    self.inner.lock().unwrap().files.remove(file);
+  }
+
+  pub fn report(&self) -> StatsReport {
+    let inner = self.inner.lock().unwrap();
+    StatsReport {
+      slow_threshold_ms: self.slow_threshold.as_secs_f64() * 1000.0,
+      cache_hits: inner.cache_hits,
+      cache_misses: inner.cache_misses,
+      pipeline_hits: inner.pipeline_hits,
+      stages: inner.stages.clone(),
+      files: inner.files.clone(),
+    }
+  }
+}
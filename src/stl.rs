@@ -0,0 +1,204 @@
+//! Server-side STL parsing, for parts exported from CAD tools. Like
+//! the OBJ parser it only keeps positions and triangles; the browser
+//! loads the file itself with three.js' STLLoader.
+//!
+//! Both kinds of STL are read. Binary files are an 80-byte header, a
+//! triangle count and 50 bytes per triangle, so they're told apart by
+//! size, since their header may start with `solid` too. STL repeats
+//! each corner for every triangle using it; identical corners are
+//! merged, so the mesh has shared vertices as an OBJ would.
+
+use crate::mesh::{Mesh, SubObject};
+use std::collections::HashMap;
+
+/// Whether the contents look like an STL file of either kind.
+pub fn is_stl(bytes: &[u8]) -> bool {
+  binary_count(bytes).is_some() || ascii_start(bytes)
+}
+
+/// Parse binary or ASCII STL. Each ASCII `solid` becomes an object.
+pub fn parse_stl(bytes: &[u8]) -> Result<Mesh, String> {
+  match binary_count(bytes) {
+    Some(count) => Ok(parse_binary(bytes, count)),
+    None if ascii_start(bytes) => {
+      let text = std::str::from_utf8(bytes)
+        .map_err(|_| "ASCII STL is not valid UTF-8".to_string())?;
+      parse_ascii(text)
+    }
+    None => Err("not an STL file".to_string()),
+  }
+}
+
+fn binary_count(bytes: &[u8]) -> Option<usize> {
+  let count = u32::from_le_bytes(bytes.get(80..84)?.try_into().ok()?);
+  (bytes.len() as u64 == 84 + 50 * count as u64).then_some(count as usize)
+}
+
+fn ascii_start(bytes: &[u8]) -> bool {
+  bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+// Collects triangles, merging corners with the same coordinates
+#[derive(Default)]
+struct Builder {
+  positions: Vec<[f64; 3]>,
+  triangles: Vec<[usize; 3]>,
+  objects: Vec<SubObject>,
+  seen: HashMap<[u64; 3], usize>,
+}
+
+impl Builder {
+  fn vertex(&mut self, p: [f64; 3]) -> usize {
+    let positions = &mut self.positions;
+    // Adding 0 turns -0 into 0, which would otherwise differ in bits
+    let key = p.map(|v| (v + 0.0).to_bits());
+    *self.seen.entry(key).or_insert_with(|| {
+      positions.push(p);
+      positions.len() - 1
+    })
+  }
+
+  fn triangle(&mut self, corners: [[f64; 3]; 3]) {
+    let [a, b, c] = corners.map(|p| self.vertex(p));
+    self.triangles.push([a, b, c]);
+  }
+
+  fn finish(mut self) -> Mesh {
+    self.objects.retain(|o| !o.triangles.is_empty());
+    Mesh {
+      positions: self.positions,
+      triangles: self.triangles,
+      objects: self.objects,
+      ..Mesh::default()
+    }
+  }
+}
+
+fn parse_binary(bytes: &[u8], count: usize) -> Mesh {
+  let mut builder = Builder::default();
+  let float = |at: usize| {
+    f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as f64
+  };
+  for i in 0..count {
+    // Skip the normal (12 bytes); the attribute count trails the corners
+    let at = 84 + 50 * i + 12;
+    let corner = |c: usize| {
+      let at = at + 12 * c;
+      [float(at), float(at + 4), float(at + 8)]
+    };
+    builder.triangle([corner(0), corner(1), corner(2)]);
+  }
+  let name = String::from_utf8_lossy(&bytes[..80])
+    .trim_end_matches(['\0', ' '])
+    .to_string();
+  builder.objects.push(SubObject { name, triangles: 0..count });
+  builder.finish()
+}
+
+fn parse_ascii(text: &str) -> Result<Mesh, String> {
+  let mut builder = Builder::default();
+  let mut corners: Vec<[f64; 3]> = Vec::new();
+  for (index, line) in text.lines().enumerate() {
+    let line_no = index + 1;
+    let mut parts = line.split_whitespace();
+    let Some(keyword) = parts.next() else { continue };
+    match keyword {
+      "solid" => {
+        let name = parts.collect::<Vec<_>>().join(" ");
+        let at = builder.triangles.len();
+        builder.objects.push(SubObject { name, triangles: at..at });
+      }
+      "vertex" => {
+        let mut p = [0.0; 3];
+        for coord in p.iter_mut() {
+          let token = parts.next().ok_or_else(|| format!(
+            "line {}: vertex has fewer than 3 coordinates", line_no))?;
+          *coord = token.parse().map_err(|_| format!(
+            "line {}: invalid vertex coordinate '{}'", line_no, token))?;
+        }
+        corners.push(p);
+      }
+      "endloop" => {
+        let [a, b, c] = corners[..] else {
+          return Err(format!("line {}: facet has {} vertices, expected 3",
+            line_no, corners.len()));
+        };
+        builder.triangle([a, b, c]);
+        corners.clear();
+      }
+      "endsolid" => {
+        let end = builder.triangles.len();
+        if let Some(object) = builder.objects.last_mut() {
+          object.triangles.end = end;
+        }
+      }
+      _ => {}
+    }
+  }
+  // A file cut short still shows what it has
+  let end = builder.triangles.len();
+  if let Some(object) = builder.objects.last_mut() {
+    object.triangles.end = end;
+  }
+  Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TRIANGLES: [[[f32; 3]; 3]; 2] = [
+    [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+  ];
+
+  fn binary(header: &[u8]) -> Vec<u8> {
+    let mut bytes = header.to_vec();
+    bytes.resize(80, 0);
+    bytes.extend_from_slice(&(TRIANGLES.len() as u32).to_le_bytes());
+    for triangle in TRIANGLES {
+      // Normal, corners, attribute byte count
+      bytes.extend_from_slice(&[0; 12]);
+      for corner in triangle.iter().flatten() {
+        bytes.extend_from_slice(&corner.to_le_bytes());
+      }
+      bytes.extend_from_slice(&[0; 2]);
+    }
+    bytes
+  }
+
+  #[test]
+  fn parses_both_kinds() {
+    let mut ascii = String::from("solid part\n");
+    for triangle in TRIANGLES {
+      ascii.push_str(" facet normal 0 0 0\n  outer loop\n");
+      for [x, y, z] in triangle {
+        ascii.push_str(&format!("   vertex {} {} {}\n", x, y, z));
+      }
+      ascii.push_str("  endloop\n endfacet\n");
+    }
+    ascii.push_str("endsolid part\n");
+
+    // Binary headers may start with "solid" too
+    for bytes in [ascii.into_bytes(), binary(b""), binary(b"solid part")] {
+      assert!(is_stl(&bytes));
+      let mesh = parse_stl(&bytes).unwrap();
+      // Shared corners are merged
+      assert_eq!(mesh.positions.len(), 4);
+      assert_eq!(mesh.triangles.len(), 2);
+      assert_eq!(mesh.triangles[0][0], mesh.triangles[1][0]);
+    }
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    let mut cut = binary(b"");
+    cut.pop();
+    assert!(!is_stl(&cut));
+    assert!(parse_stl(&cut).is_err());
+    assert!(parse_stl(b"solid part\n facet normal 0 0 0\n  outer loop\n   \
+      vertex 0 0\n").is_err());
+    assert!(parse_stl(b"solid part\n facet normal 0 0 0\n  outer loop\n   \
+      vertex 0 0 zero\n").is_err());
+  }
+}
@@ -0,0 +1,137 @@
+//! Keeps the background watchers running. Each one (the scene directory
+//! watcher or remote poller, the git status poller) is its own task; one
+//! that fails or panics is started again after a delay that doubles up
+//! to a minute, and starts over once it has stayed up that long. Their
+//! health is shown by `/readyz` and `/api/stats`.
+
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatcherState {
+  /// Setting up, e.g. adding its watches
+  Starting,
+  Running,
+  /// Failed, and waiting to be started again
+  Restarting,
+  /// Finished without an error, and won't be started again
+  Stopped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WatcherStatus {
+  pub name: &'static str,
+  pub state: WatcherState,
+  /// Times it has been started again
+  pub restarts: u32,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub last_error: Option<String>,
+}
+
+/// One watcher's status, for the task to update.
+#[derive(Clone)]
+pub struct Health {
+  status: Arc<Mutex<WatcherStatus>>,
+}
+
+impl Health {
+  /// The watcher is up, e.g. once its watches are in place.
+  pub fn running(&self) {
+    self.status.lock().unwrap().state = WatcherState::Running;
+  }
+
+  /// Whether this run is a restart, which may have missed changes.
+  pub fn restarted(&self) -> bool {
+    self.status.lock().unwrap().restarts > 0
+  }
+
+  fn set(&self, state: WatcherState) {
+    self.status.lock().unwrap().state = state;
+  }
+
+  fn failed(&self, error: String) {
+    let mut status = self.status.lock().unwrap();
+    status.state = WatcherState::Restarting;
+    status.restarts += 1;
+    status.last_error = Some(error);
+  }
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+  watchers: Mutex<Vec<Health>>,
+}
+
+impl Supervisor {
+  /// Run a watcher, started by `start` now and again after each
+  /// failure. It should call [`Health::running`] once it's up.
+  pub fn spawn<F, Fut>(&self, name: &'static str, mut start: F)
+  where
+    F: FnMut(Health) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+  {
+    let health = Health {
+      status: Arc::new(Mutex::new(WatcherStatus {
+        name,
+        state: WatcherState::Starting,
+        restarts: 0,
+        last_error: None,
+      })),
+    };
+    self.watchers.lock().unwrap().push(health.clone());
+    tokio::spawn(async move {
+      let mut backoff = FIRST_BACKOFF;
+      loop {
+        let started = Instant::now();
+        // A task of its own, so a panic ends only this run
+        let error = match tokio::spawn(start(health.clone())).await {
+          Ok(Ok(())) => {
+            health.set(WatcherState::Stopped);
+            return;
+          }
+          Ok(Err(e)) => e,
+          Err(e) if e.is_panic() => panic_message(e.into_panic()),
+          Err(e) => e.to_string(),
+        };
+        if started.elapsed() >= MAX_BACKOFF {
+          backoff = FIRST_BACKOFF;
+        }
+        eprintln!("The {} watcher failed: {}; restarting in {}s", name, error,
+          backoff.as_secs());
+        health.failed(error);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        health.set(WatcherState::Starting);
+      }
+    });
+  }
+
+  pub fn statuses(&self) -> Vec<WatcherStatus> {
+    self.watchers.lock().unwrap().iter()
+      .map(|health| health.status.lock().unwrap().clone())
+      .collect()
+  }
+
+  /// Names of the watchers that aren't running.
+  pub fn not_running(&self) -> Vec<&'static str> {
+    self.statuses().into_iter()
+      .filter(|status| status.state != WatcherState::Running)
+      .map(|status| status.name)
+      .collect()
+  }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+  let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned());
+  match message {
+    Some(message) => format!("panicked: {}", message),
+    None => "panicked".to_string(),
+  }
+}
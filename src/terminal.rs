@@ -0,0 +1,68 @@
+//! Hotkeys in the terminal the server runs in. On Unix, stdin leaves
+//! line mode while serving so a key acts as soon as it's pressed, and
+//! gets its settings back on the way out; elsewhere each key is
+//! followed by Enter.
+
+use std::io::{IsTerminal, Read};
+#[cfg(unix)]
+use std::sync::Mutex;
+
+// Settings to put back, while stdin is out of line mode
+#[cfg(unix)]
+static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Switch stdin to single keys. False if it isn't a terminal, in which
+/// case there's nothing to read keys from.
+pub fn enter_key_mode() -> bool {
+  if !std::io::stdin().is_terminal() {
+    return false;
+  }
+  #[cfg(unix)]
+  unsafe {
+    let mut saved: libc::termios = std::mem::zeroed();
+    if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+      return false;
+    }
+    // No echo or line editing; Ctrl-C and output work as before
+    let mut keys = saved;
+    keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+    keys.c_cc[libc::VMIN] = 1;
+    keys.c_cc[libc::VTIME] = 0;
+    if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) != 0 {
+      return false;
+    }
+    *SAVED.lock().unwrap() = Some(saved);
+  }
+  true
+}
+
+/// Put stdin back the way it was. Safe to call more than once.
+pub fn restore() {
+  #[cfg(unix)]
+  if let Some(saved) = SAVED.lock().unwrap().take() {
+    unsafe {
+      libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved);
+    }
+  }
+}
+
+/// Block for the next key, None once stdin is closed.
+pub fn read_key() -> Option<char> {
+  let mut byte = [0u8; 1];
+  loop {
+    match std::io::stdin().lock().read(&mut byte) {
+      Ok(0) => return None,
+      Ok(_) => return Some(byte[0] as char),
+      Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+      Err(_) => return None,
+    }
+  }
+}
+
+/// Clear the screen and move the cursor to the top.
+pub fn clear() {
+  use std::io::Write;
+  let mut stdout = std::io::stdout();
+  let _ = stdout.write_all(b"\x1b[2J\x1b[H");
+  let _ = stdout.flush();
+}
@@ -0,0 +1,133 @@
+//! Helpers for simulating a scene without touching the filesystem or
+//! depending on notify's timing: an in-memory `SceneSource`, and an
+//! injector that changes it and broadcasts the matching `FileEvent`s.
+//! `zip` builds the archives 3MF and USDZ files are, for parser tests.
+
+use crate::deflate;
+use crate::events::FileEvent;
+use crate::formats;
+use crate::source::SceneSource;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Scene files held in memory, keyed by name.
+#[derive(Default)]
+pub struct MemorySource {
+  files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemorySource {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add or replace a file, returning whether it already existed.
+  pub fn insert(&self, name: &str, contents: impl Into<Vec<u8>>) -> bool {
+    self.files.lock().unwrap()
+      .insert(name.to_string(), contents.into())
+      .is_some()
+  }
+
+  /// Remove a file, returning whether it existed.
+  pub fn remove(&self, name: &str) -> bool {
+    self.files.lock().unwrap().remove(name).is_some()
+  }
+}
+
+impl SceneSource for MemorySource {
+  fn list(&self) -> io::Result<Vec<String>> {
+    Ok(self.files.lock().unwrap().keys()
+      .filter(|name| formats::is_scene_file(name))
+      .cloned()
+      .collect())
+  }
+
+  fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+    self.files.lock().unwrap().get(name).cloned()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, name.to_string()))
+  }
+}
+
+/// Drives a `MemorySource` the way the watcher drives a real scene:
+/// each change updates the source first, then broadcasts its event.
+pub struct EventInjector {
+  source: Arc<MemorySource>,
+  tx: broadcast::Sender<FileEvent>,
+}
+
+impl EventInjector {
+  pub fn new(source: Arc<MemorySource>, tx: broadcast::Sender<FileEvent>)
+      -> Self {
+    EventInjector { source, tx }
+  }
+
+  /// Write a file, sending `added` or `modified` as appropriate.
+  pub fn write(&self, name: &str, contents: impl Into<Vec<u8>>) {
+    let event = if self.source.insert(name, contents) {
+      FileEvent::modified(name)
+    } else {
+      FileEvent::added(name)
+    };
+    self.send(event);
+  }
+
+  /// Delete a file, sending `removed` if it existed.
+  pub fn remove(&self, name: &str) {
+    if self.source.remove(name) {
+      self.send(FileEvent::Removed { filename: name.to_string() });
+    }
+  }
+
+  /// Send an arbitrary event without touching the source, e.g. to
+  /// simulate a spurious or duplicated notification.
+  pub fn send(&self, event: FileEvent) {
+    // No receivers just means nobody is listening yet
+    let _ = self.tx.send(event);
+  }
+}
+
+/// A zip archive of `(name, contents, deflated)` entries, in order.
+/// CRCs are left at 0, as `zip` doesn't check them.
+pub fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+  let mut out = Vec::new();
+  let mut directory = Vec::new();
+  for &(name, contents, deflated) in files {
+    let data = if deflated {
+      deflate::compress(contents)
+    } else {
+      contents.to_vec()
+    };
+    // Version, flags, method, time, date, CRC, sizes and name length
+    let mut header = vec![20, 0, 0, 0, if deflated { 8 } else { 0 }, 0];
+    header.extend_from_slice(&[0; 8]);
+    header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+    directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    directory.extend_from_slice(&[20, 0]);
+    directory.extend_from_slice(&header);
+    // No extra field, comment, disk number or attributes
+    directory.extend_from_slice(&[0; 12]);
+    directory.extend_from_slice(&(out.len() as u32).to_le_bytes());
+    directory.extend_from_slice(name.as_bytes());
+
+    out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&data);
+  }
+  let offset = out.len() as u32;
+  out.extend_from_slice(&directory);
+  out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+  out.extend_from_slice(&[0; 4]);
+  out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+  out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+  out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+  out.extend_from_slice(&offset.to_le_bytes());
+  out.extend_from_slice(&[0, 0]);
+  out
+}
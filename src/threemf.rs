@@ -0,0 +1,309 @@
+//! Server-side 3MF parsing, for parts from 3D-print pipelines. A 3MF
+//! file is a zip holding an XML model; like the other parsers this only
+//! keeps positions and triangles, and the browser loads the file itself
+//! with three.js' 3MFLoader.
+//!
+//! Objects are placed where the model's build items put them, with
+//! component objects flattened into their parents, and each build item
+//! becomes a named object. Zip entries may be stored or deflated. ZIP64
+//! archives and models split over several parts (the production
+//! extension) aren't read.
+//!
+//! Components can multiply a small file many times over, so files that
+//! place more than [`mesh::MAX_TRIANGLES`] triangles, or a million
+//! objects, are refused.
+
+use crate::mesh::{self, Mesh, SubObject};
+use crate::xml::{unescape, Tag, Tags};
+use crate::zip;
+use std::collections::HashMap;
+
+// Where the model is unless the package's relationships say otherwise
+const DEFAULT_MODEL: &str = "3D/3dmodel.model";
+const MODEL_RELATIONSHIP: &str = "/3dmodel";
+// Components nested deeper than this are taken to be a cycle
+const MAX_DEPTH: usize = 32;
+// Most objects placed, counting each component. Objects that hold
+// another several times multiply at every level, mesh or not.
+const MAX_PLACED: usize = 1_000_000;
+
+/// A 3x4 matrix applied to row vectors, as 3MF writes it: the rotation
+/// and scale rows, then the translation.
+type Transform = [f64; 12];
+
+const IDENTITY: Transform =
+  [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+/// An object, by id, and where it goes.
+type Placement = (String, Transform);
+
+#[derive(Default)]
+struct Object {
+  name: Option<String>,
+  positions: Vec<[f64; 3]>,
+  triangles: Vec<[usize; 3]>,
+  /// Other objects, placed in this one
+  components: Vec<Placement>,
+}
+
+/// Whether the contents look like a 3MF file, which is any zip here.
+pub fn is_3mf(bytes: &[u8]) -> bool {
+  zip::is_zip(bytes)
+}
+
+/// Parse a 3MF package into an object per build item.
+pub fn parse_3mf(bytes: &[u8]) -> Result<Mesh, String> {
+  let entries = zip::entries(bytes)?;
+  let model_path = match entries.get("_rels/.rels") {
+    Some(rels) => model_part(&String::from_utf8_lossy(&rels.read(bytes)?)),
+    None => None,
+  }.unwrap_or_else(|| DEFAULT_MODEL.to_string());
+  let model = entries.get(model_path.as_str())
+    .ok_or_else(|| format!("3MF file has no {}", model_path))?
+    .read(bytes)
+    .map_err(|e| format!("{}: {}", model_path, e))?;
+  let model = std::str::from_utf8(&model)
+    .map_err(|_| format!("{} is not valid UTF-8", model_path))?;
+  let (objects, items) = parse_model(model)?;
+
+  let mut mesh = Mesh::default();
+  let mut placed = 0;
+  for (id, transform) in &items {
+    let start = mesh.triangles.len();
+    place(&objects, id, *transform, 0, &mut placed, &mut mesh)?;
+    let name = objects[id].name.clone()
+      .unwrap_or_else(|| format!("object {}", id));
+    let triangles = start..mesh.triangles.len();
+    mesh.objects.push(SubObject { name, triangles });
+  }
+  Ok(mesh)
+}
+
+// The model part named in the package relationships
+fn model_part(rels: &str) -> Option<String> {
+  Tags::new(rels).flatten()
+    .filter(|tag| tag.name == "Relationship" && !tag.closing)
+    .find(|tag| tag.get("Type")
+      .is_some_and(|kind| kind.ends_with(MODEL_RELATIONSHIP)))
+    .and_then(|tag| tag.get("Target"))
+    .map(|target| unescape(target.trim_start_matches('/')))
+}
+
+// The model's objects by id, and its build items
+fn parse_model(xml: &str)
+    -> Result<(HashMap<String, Object>, Vec<Placement>), String> {
+  let mut objects = HashMap::new();
+  let mut items = Vec::new();
+  let mut current: Option<(String, Object)> = None;
+  for tag in Tags::new(xml) {
+    let tag = tag?;
+    if tag.closing {
+      if tag.name == "object" {
+        if let Some((id, object)) = current.take() {
+          objects.insert(id, object);
+        }
+      }
+      continue;
+    }
+    match tag.name {
+      "object" => {
+        let id = tag.require("id")?.to_string();
+        let object = Object {
+          name: tag.get("name").map(unescape),
+          ..Object::default()
+        };
+        if tag.empty {
+          objects.insert(id, object);
+        } else {
+          current = Some((id, object));
+        }
+      }
+      "vertex" => {
+        let position = [tag.parse("x")?, tag.parse("y")?, tag.parse("z")?];
+        in_object(&mut current, &tag)?.positions.push(position);
+      }
+      "triangle" => {
+        let corners = [tag.parse("v1")?, tag.parse("v2")?, tag.parse("v3")?];
+        in_object(&mut current, &tag)?.triangles.push(corners);
+      }
+      "component" => {
+        let component = (tag.require("objectid")?.to_string(),
+          transform(&tag)?);
+        in_object(&mut current, &tag)?.components.push(component);
+      }
+      "item" => items.push((tag.require("objectid")?.to_string(),
+        transform(&tag)?)),
+      _ => {}
+    }
+  }
+  if let Some((id, _)) = current {
+    return Err(format!("object {} is never closed", id));
+  }
+  if let Some((id, _)) = items.iter().find(|(id, _)| !objects.contains_key(id))
+  {
+    return Err(format!("build item refers to missing object {}", id));
+  }
+  Ok((objects, items))
+}
+
+// The object being read, which a mesh or component tag must be in
+fn in_object<'c>(current: &'c mut Option<(String, Object)>, tag: &Tag)
+    -> Result<&'c mut Object, String> {
+  current.as_mut()
+    .map(|(_, object)| object)
+    .ok_or_else(|| format!("<{}> outside an object", tag.name))
+}
+
+// Add an object, and its components, to the mesh
+fn place(objects: &HashMap<String, Object>, id: &str, transform: Transform,
+    depth: usize, placed: &mut usize, mesh: &mut Mesh)
+    -> Result<(), String> {
+  if depth > MAX_DEPTH {
+    return Err(format!("components nest over {} deep", MAX_DEPTH));
+  }
+  *placed += 1;
+  if *placed > MAX_PLACED {
+    return Err(format!("components place over {} objects", MAX_PLACED));
+  }
+  let object = objects.get(id)
+    .ok_or_else(|| format!("component refers to missing object {}", id))?;
+  let base = mesh.positions.len();
+  mesh.positions.extend(object.positions.iter()
+    .map(|p| apply(&transform, p)));
+  for triangle in &object.triangles {
+    if let Some(&bad) = triangle.iter().find(|&&v| v >= object.positions.len())
+    {
+      return Err(format!("object {}: vertex index {} out of range ({} \
+        vertices)", id, bad, object.positions.len()));
+    }
+    mesh.triangles.push(triangle.map(|v| base + v));
+  }
+  mesh::check_size(mesh)?;
+  for (child, placement) in &object.components {
+    place(objects, child, compose(placement, &transform), depth + 1, placed,
+      mesh)?;
+  }
+  Ok(())
+}
+
+fn apply(t: &Transform, p: &[f64; 3]) -> [f64; 3] {
+  [0, 1, 2].map(|c| p[0] * t[c] + p[1] * t[3 + c] + p[2] * t[6 + c]
+    + t[9 + c])
+}
+
+// `first`, then `then`
+fn compose(first: &Transform, then: &Transform) -> Transform {
+  let mut result = [0.0; 12];
+  for row in 0..4 {
+    for c in 0..3 {
+      result[row * 3 + c] = (0..3)
+        .map(|k| first[row * 3 + k] * then[k * 3 + c])
+        .sum::<f64>()
+        + if row == 3 { then[9 + c] } else { 0.0 };
+    }
+  }
+  result
+}
+
+// A component's or build item's `transform` attribute
+fn transform(tag: &Tag) -> Result<Transform, String> {
+  let Some(value) = tag.get("transform") else { return Ok(IDENTITY) };
+  let numbers: Vec<f64> = value.split_whitespace()
+    .map(|n| n.parse())
+    .collect::<Result<_, _>>()
+    .map_err(|_| format!("<{}> has invalid transform '{}'", tag.name,
+      value))?;
+  numbers.try_into()
+    .map_err(|_| format!("<{}> transform needs 12 numbers", tag.name))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+
+  const MODEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter">
+  <resources>
+    <object id="1" name="tri" type="model">
+      <mesh>
+        <vertices>
+          <vertex x="0" y="0" z="0"/>
+          <vertex x="1" y="0" z="0"/>
+          <vertex x="0" y="1" z="0"/>
+        </vertices>
+        <triangles><triangle v1="0" v2="1" v3="2"/></triangles>
+      </mesh>
+    </object>
+    <object id="2" name="pair" type="model">
+      <components>
+        <component objectid="1"/>
+        <component objectid="1" transform="1 0 0 0 1 0 0 0 1 5 0 0"/>
+      </components>
+    </object>
+  </resources>
+  <build>
+    <item objectid="1"/>
+    <item objectid="2" transform="1 0 0 0 1 0 0 0 1 0 0 10"/>
+  </build>
+</model>
+"#;
+
+  #[test]
+  fn places_build_items() {
+    let bytes = testing::zip(&[("3D/3dmodel.model", MODEL.as_bytes(), true)]);
+    assert!(is_3mf(&bytes));
+    let mesh = parse_3mf(&bytes).unwrap();
+    let objects: Vec<(&str, usize)> = mesh.objects.iter()
+      .map(|o| (o.name.as_str(), o.triangles.len()))
+      .collect();
+    assert_eq!(objects, [("tri", 1), ("pair", 2)]);
+    assert!(mesh.positions.contains(&[6.0, 0.0, 10.0]));
+  }
+
+  #[test]
+  fn follows_package_relationships() {
+    let rels = format!("<Relationships><Relationship Id=\"rel0\" \
+      Target=\"/3D/part.model\" Type=\"{}{}\"/></Relationships>",
+      "http://schemas.microsoft.com/3dmanufacturing/2013/01",
+      MODEL_RELATIONSHIP);
+    let bytes = testing::zip(&[("_rels/.rels", rels.as_bytes(), false),
+      ("3D/part.model", MODEL.as_bytes(), true)]);
+    assert_eq!(parse_3mf(&bytes).unwrap().objects.len(), 2);
+  }
+
+  #[test]
+  fn rejects_malformed_files() {
+    let zip = |model: &str|
+      testing::zip(&[("3D/3dmodel.model", model.as_bytes(), true)]);
+    assert!(parse_3mf(&testing::zip(&[("a.txt", b"", false)])).is_err());
+    assert!(parse_3mf(&zip(&MODEL.replace("v3=\"2\"", "v3=\"two\"")))
+      .is_err());
+    assert!(parse_3mf(&zip(&MODEL.replace("v3=\"2\"", "v3=\"9\"")))
+      .is_err());
+    assert!(parse_3mf(&zip(&MODEL.replace("objectid=\"2\"",
+      "objectid=\"7\""))).is_err());
+    // Components that contain each other
+    assert!(parse_3mf(&zip(&MODEL.replace("<component objectid=\"1\"/>",
+      "<component objectid=\"2\"/>"))).is_err());
+    assert!(parse_3mf(&zip(&MODEL[..MODEL.len() / 2])).is_err());
+  }
+
+  #[test]
+  fn limits_nested_components() {
+    // Each object holds the one before it twice, so the last is 2^30
+    // objects, even with no mesh in them
+    let mut objects = String::from(r#"<object id="c0"/>"#);
+    for i in 1..=30 {
+      objects.push_str(&format!(r#"<object id="c{}"><components>
+        <component objectid="c{}"/><component objectid="c{}"/>
+        </components></object>"#, i, i - 1, i - 1));
+    }
+    let model = MODEL
+      .replace("</resources>", &format!("{}</resources>", objects))
+      .replace(r#"<item objectid="2""#, r#"<item objectid="c30""#);
+    let bytes = testing::zip(&[("3D/3dmodel.model", model.as_bytes(), true)]);
+    let error = parse_3mf(&bytes).err().unwrap();
+    assert!(error.contains("over"), "{}", error);
+  }
+}
@@ -0,0 +1,111 @@
+//! Nested view of the scene files for `/api/tree`.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct TreeFile {
+  name: String,
+  size: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  triangles: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct TreeFolder {
+  name: String,
+  /// Path relative to the scene directory ("" for the root)
+  path: String,
+  files:   Vec<TreeFile>,
+  folders: Vec<TreeFolder>,
+  /// Mesh files in this folder and all folders below it
+  total_files: usize,
+  total_size:  u64,
+  total_triangles: usize,
+}
+
+impl TreeFolder {
+  fn new(name: String, path: String) -> TreeFolder {
+    TreeFolder {
+      name,
+      path,
+      files: Vec::new(),
+      folders: Vec::new(),
+      total_files: 0,
+      total_size: 0,
+      total_triangles: 0,
+    }
+  }
+
+  // The folder `name` below this one, added if it isn't there yet
+  fn folder(&mut self, name: &str) -> &mut TreeFolder {
+    let at = match self.folders.iter().position(|f| f.name == name) {
+      Some(at) => at,
+      None => {
+        let path = match self.path.is_empty() {
+          true => name.to_string(),
+          false => format!("{}/{}", self.path, name),
+        };
+        self.folders.push(TreeFolder::new(name.to_string(), path));
+        self.folders.len() - 1
+      }
+    };
+    &mut self.folders[at]
+  }
+
+  // Fill in the totals and sort, below this folder first
+  fn finish(&mut self) {
+    self.total_files = self.files.len();
+    self.total_size = self.files.iter().map(|f| f.size).sum();
+    self.total_triangles = self.files.iter()
+      .map(|f| f.triangles.unwrap_or(0)).sum();
+    for child in &mut self.folders {
+      child.finish();
+      self.total_files += child.total_files;
+      self.total_size += child.total_size;
+      self.total_triangles += child.total_triangles;
+    }
+    self.files.sort_by(|a, b| a.name.cmp(&b.name));
+    self.folders.sort_by(|a, b| a.name.cmp(&b.name));
+  }
+}
+
+/// Nest scene files by the folders in their '/'-separated names, from
+/// each file's name, size and triangle count. Folders without files
+/// aren't listed.
+pub fn build(files: impl IntoIterator<Item = (String, u64, Option<usize>)>)
+    -> TreeFolder {
+  let mut root = TreeFolder::new(String::new(), String::new());
+  for (name, size, triangles) in files {
+    let mut parts: Vec<&str> = name.split('/').collect();
+    let file = parts.pop().unwrap_or_default().to_string();
+    let folder = parts.iter()
+      .fold(&mut root, |folder, part| folder.folder(part));
+    folder.files.push(TreeFile { name: file, size, triangles });
+  }
+  root.finish();
+  root
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nests_files_by_folder() {
+    let tree = build([
+      ("b.obj".to_string(), 10, Some(2)),
+      ("parts/wheels/w.stl".to_string(), 5, Some(4)),
+      ("parts/a.obj".to_string(), 1, None),
+    ]);
+    assert_eq!(tree.total_files, 3);
+    assert_eq!(tree.total_size, 16);
+    assert_eq!(tree.total_triangles, 6);
+    assert_eq!(tree.files[0].name, "b.obj");
+    let parts = &tree.folders[0];
+    assert_eq!((parts.name.as_str(), parts.path.as_str()), ("parts", "parts"));
+    assert_eq!(parts.files[0].name, "a.obj");
+    assert_eq!(parts.total_files, 2);
+    assert_eq!(parts.folders[0].path, "parts/wheels");
+    assert_eq!(parts.folders[0].total_triangles, 4);
+  }
+}
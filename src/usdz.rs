@@ -0,0 +1,770 @@
+//! Server-side USDZ parsing, for studio assets. A USDZ file is a zip
+//! whose first file is the root USD layer; like the other parsers this
+//! only keeps positions, triangles and texture coordinates, and the
+//! browser loads the package itself with three.js' USDZLoader.
+//!
+//! Only text (usda) layers are read, as USDZLoader only reads those;
+//! binary crate (usdc) layers are reported as such. Mesh prims are
+//! placed by their own and their ancestors' xformOps, and each becomes
+//! a named object. `class` prims, time samples, variants, instancers
+//! and references to other layers are skipped.
+
+use crate::mesh::{Mesh, SubObject};
+use crate::zip;
+use std::collections::HashMap;
+
+// Prims or values nested deeper than this are taken to be garbage
+const MAX_DEPTH: usize = 64;
+
+/// A 4x4 matrix in row-major order, applied to column vectors.
+type Matrix = [f64; 16];
+
+const IDENTITY: Matrix = [
+  1.0, 0.0, 0.0, 0.0,
+  0.0, 1.0, 0.0, 0.0,
+  0.0, 0.0, 1.0, 0.0,
+  0.0, 0.0, 0.0, 1.0,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  /// Keywords, type and property names, and numbers
+  Word(String),
+  Text(String),
+  /// A `<path>` to a prim or property
+  Path,
+  /// An `@asset@` reference
+  Asset,
+  Punct(char),
+}
+
+enum Value {
+  Number(f64),
+  Text(String),
+  /// A tuple or an array
+  List(Vec<Value>),
+  /// Paths, assets, dictionaries, `None` and the like
+  Other,
+}
+
+struct Attribute {
+  /// As declared, e.g. `texCoord2f[]`
+  type_name: String,
+  value: Value,
+  metadata: HashMap<String, Value>,
+}
+
+#[derive(Default)]
+struct Prim {
+  name: String,
+  type_name: String,
+  /// Declared with `class`: a template that isn't drawn
+  class: bool,
+  attributes: HashMap<String, Attribute>,
+  children: Vec<Prim>,
+}
+
+/// Whether the contents look like a USDZ package: a zip starting with
+/// a USD layer.
+pub fn is_usdz(bytes: &[u8]) -> bool {
+  zip::first_name(bytes).is_some_and(|name| {
+    let name = name.to_ascii_lowercase();
+    [".usda", ".usdc", ".usd"].iter().any(|ext| name.ends_with(ext))
+  })
+}
+
+/// Parse a USDZ package's root layer into an object per mesh prim.
+pub fn parse_usdz(bytes: &[u8]) -> Result<Mesh, String> {
+  let root = zip::first_name(bytes)
+    .ok_or_else(|| "USDZ file has no root layer".to_string())?;
+  let layer = zip::entries(bytes)?.get(&root)
+    .ok_or_else(|| format!("USDZ file has no {}", root))?
+    .read(bytes)
+    .map_err(|e| format!("{}: {}", root, e))?;
+  if layer.starts_with(b"PXR-USDC") {
+    return Err(format!("{} is a binary (usdc) layer; only text (usda) \
+      layers are read", root));
+  }
+  let text = std::str::from_utf8(&layer)
+    .map_err(|_| format!("{} is not valid UTF-8", root))?;
+  if !text.starts_with("#usda") {
+    return Err(format!("{} is not a USD layer", root));
+  }
+  let prims = Parser { tokens: tokenize(text)?, at: 0 }.layer()
+    .map_err(|e| format!("{}: {}", root, e))?;
+
+  let mut mesh = Mesh::default();
+  for prim in &prims {
+    place(prim, &IDENTITY, &mut mesh)?;
+  }
+  if mesh.uv_triangles.iter().all(Option::is_none) {
+    mesh.uv_triangles.clear();
+  }
+  Ok(mesh)
+}
+
+// Split a layer into tokens, each with its line number
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+  let mut tokens = Vec::new();
+  let mut chars = text.chars().peekable();
+  let mut line = 1;
+  while let Some(c) = chars.next() {
+    let start = line;
+    let token = match c {
+      '\n' => {
+        line += 1;
+        continue;
+      }
+      c if c.is_whitespace() => continue,
+      '#' => {
+        while chars.next_if(|&c| c != '\n').is_some() {}
+        continue;
+      }
+      '"' | '\'' => {
+        let mut quotes = 1;
+        while quotes < 3 && chars.next_if_eq(&c).is_some() {
+          quotes += 1;
+        }
+        // `""` is empty; `"""` opens a string that may span lines
+        let delimiter = if quotes == 3 { 3 } else { 1 };
+        let mut text = String::new();
+        if quotes != 2 {
+          let mut closing = 0;
+          loop {
+            let Some(next) = chars.next() else {
+              return Err(format!("line {}: string is never closed", start));
+            };
+            if next == '\n' {
+              line += 1;
+            }
+            if next == c {
+              closing += 1;
+              if closing == delimiter {
+                break;
+              }
+              continue;
+            }
+            text.extend(std::iter::repeat_n(c, closing));
+            closing = 0;
+            if next == '\\' {
+              text.extend(chars.next());
+            } else {
+              text.push(next);
+            }
+          }
+        }
+        Token::Text(text)
+      }
+      '<' => {
+        chars.by_ref().take_while(|&c| c != '>').for_each(drop);
+        Token::Path
+      }
+      '@' => {
+        chars.by_ref().take_while(|&c| c != '@').for_each(drop);
+        Token::Asset
+      }
+      '(' | ')' | '[' | ']' | '{' | '}' | '=' | ',' | ';' => {
+        Token::Punct(c)
+      }
+      c => {
+        let mut word = c.to_string();
+        while let Some(next) = chars.next_if(|&c| is_word_char(c)) {
+          word.push(next);
+        }
+        // Array types, as in `point3f[]`
+        if chars.peek() == Some(&'[') {
+          let mut ahead = chars.clone();
+          ahead.next();
+          if ahead.next() == Some(']') {
+            chars.next();
+            chars.next();
+            word.push_str("[]");
+          }
+        }
+        Token::Word(word)
+      }
+    };
+    tokens.push((token, start));
+  }
+  Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+  c.is_alphanumeric() || matches!(c, '_' | ':' | '.' | '-' | '+' | '!')
+}
+
+struct Parser {
+  tokens: Vec<(Token, usize)>,
+  at: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.at).map(|(token, _)| token)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.at).map(|(token, _)| token.clone());
+    self.at += 1;
+    token
+  }
+
+  fn line(&self) -> usize {
+    self.tokens.get(self.at.min(self.tokens.len().saturating_sub(1)))
+      .map_or(0, |(_, line)| *line)
+  }
+
+  fn error(&self, message: &str) -> String {
+    format!("line {}: {}", self.line(), message)
+  }
+
+  fn eat(&mut self, punct: char) -> bool {
+    let found = self.peek() == Some(&Token::Punct(punct));
+    if found {
+      self.at += 1;
+    }
+    found
+  }
+
+  fn expect(&mut self, punct: char) -> Result<(), String> {
+    if self.eat(punct) {
+      Ok(())
+    } else {
+      Err(self.error(&format!("expected '{}'", punct)))
+    }
+  }
+
+  // The layer's metadata, then its root prims
+  fn layer(mut self) -> Result<Vec<Prim>, String> {
+    if self.peek() == Some(&Token::Punct('(')) {
+      self.metadata(0)?;
+    }
+    let mut prims = Vec::new();
+    while self.peek().is_some() {
+      match self.next() {
+        Some(Token::Word(word)) if is_specifier(&word) => {
+          prims.push(self.prim(&word, 0)?);
+        }
+        _ => {
+          self.at -= 1;
+          return Err(self.error("expected a prim"));
+        }
+      }
+    }
+    Ok(prims)
+  }
+
+  // A prim after its `def`, `over` or `class`
+  fn prim(&mut self, specifier: &str, depth: usize) -> Result<Prim, String> {
+    if depth > MAX_DEPTH {
+      return Err(self.error(&format!("prims nest over {} deep", MAX_DEPTH)));
+    }
+    let mut prim = Prim { class: specifier == "class", ..Prim::default() };
+    if let Some(Token::Word(_)) = self.peek() {
+      let Some(Token::Word(type_name)) = self.next() else { unreachable!() };
+      prim.type_name = type_name;
+    }
+    match self.next() {
+      Some(Token::Text(name)) => prim.name = name,
+      _ => {
+        self.at -= 1;
+        return Err(self.error("expected a prim name"));
+      }
+    }
+    if self.peek() == Some(&Token::Punct('(')) {
+      self.metadata(depth)?;
+    }
+    self.expect('{')?;
+    loop {
+      let line = self.line();
+      match self.next() {
+        Some(Token::Punct('}')) => break,
+        Some(Token::Punct(';')) => {}
+        Some(Token::Word(word)) if is_specifier(&word) => {
+          prim.children.push(self.prim(&word, depth + 1)?);
+        }
+        Some(Token::Word(word)) if word == "variantSet" => {
+          // The variants' contents aren't composed
+          self.next();
+          self.expect('=')?;
+          self.skip_group(depth)?;
+        }
+        Some(Token::Word(word)) => {
+          // Qualifiers, the type and the name, on one line
+          let mut words = vec![word];
+          while matches!(self.peek(), Some(Token::Word(_)))
+              && self.line() == line {
+            let Some(Token::Word(word)) = self.next() else { unreachable!() };
+            words.push(word);
+          }
+          let name = words.pop().unwrap();
+          let value = if self.eat('=') {
+            self.value(depth)?
+          } else {
+            Value::Other
+          };
+          let metadata = if self.peek() == Some(&Token::Punct('(')) {
+            self.metadata(depth)?
+          } else {
+            HashMap::new()
+          };
+          let type_name = words.pop().unwrap_or_default();
+          prim.attributes.insert(name,
+            Attribute { type_name, value, metadata });
+        }
+        Some(_) => {
+          self.at -= 1;
+          return Err(self.error(&format!("unexpected token in prim {}",
+            prim.name)));
+        }
+        None => return Err(format!("prim {} is never closed", prim.name)),
+      }
+    }
+    Ok(prim)
+  }
+
+  // `( key = value ... )` after a layer header, prim or property
+  fn metadata(&mut self, depth: usize)
+      -> Result<HashMap<String, Value>, String> {
+    self.expect('(')?;
+    let mut metadata = HashMap::new();
+    loop {
+      match self.next() {
+        Some(Token::Punct(')')) => break,
+        Some(Token::Punct(';')) | Some(Token::Text(_)) => {}
+        Some(Token::Word(mut key)) => {
+          // List edits, as in `prepend apiSchemas`
+          while let Some(Token::Word(_)) = self.peek() {
+            let Some(Token::Word(word)) = self.next() else { unreachable!() };
+            key = word;
+          }
+          self.expect('=')?;
+          let value = self.value(depth)?;
+          metadata.insert(key, value);
+        }
+        Some(_) => {
+          self.at -= 1;
+          return Err(self.error("unexpected token in metadata"));
+        }
+        None => return Err("metadata is never closed".to_string()),
+      }
+    }
+    Ok(metadata)
+  }
+
+  fn value(&mut self, depth: usize) -> Result<Value, String> {
+    if depth > MAX_DEPTH {
+      return Err(self.error(&format!("values nest over {} deep",
+        MAX_DEPTH)));
+    }
+    Ok(match self.next() {
+      Some(Token::Punct(open @ ('(' | '['))) => {
+        let close = if open == '(' { ')' } else { ']' };
+        let mut items = Vec::new();
+        while !self.eat(close) {
+          items.push(self.value(depth + 1)?);
+          if !self.eat(',') && self.peek() != Some(&Token::Punct(close)) {
+            return Err(self.error(&format!("expected ',' or '{}'", close)));
+          }
+        }
+        Value::List(items)
+      }
+      Some(Token::Punct('{')) => {
+        self.at -= 1;
+        self.skip_group(depth)?;
+        Value::Other
+      }
+      Some(Token::Asset) => {
+        // A reference's prim path and layer offset
+        if self.peek() == Some(&Token::Path) {
+          self.at += 1;
+        }
+        if self.peek() == Some(&Token::Punct('(')) {
+          self.skip_group(depth)?;
+        }
+        Value::Other
+      }
+      Some(Token::Text(text)) => Value::Text(text),
+      Some(Token::Word(word)) => word.parse().map_or(Value::Other,
+        Value::Number),
+      Some(Token::Path) => Value::Other,
+      _ => {
+        self.at -= 1;
+        return Err(self.error("expected a value"));
+      }
+    })
+  }
+
+  // Past a bracketed group and everything in it
+  fn skip_group(&mut self, depth: usize) -> Result<(), String> {
+    let line = self.line();
+    let mut open = Vec::new();
+    loop {
+      match self.next() {
+        Some(Token::Punct(c @ ('(' | '[' | '{'))) => {
+          open.push(c);
+          if open.len() + depth > MAX_DEPTH {
+            return Err(self.error(&format!("values nest over {} deep",
+              MAX_DEPTH)));
+          }
+        }
+        Some(Token::Punct(')' | ']' | '}')) => {
+          open.pop();
+        }
+        Some(_) => {}
+        None => return Err(format!("line {}: group is never closed", line)),
+      }
+      if open.is_empty() {
+        return Ok(());
+      }
+    }
+  }
+}
+
+fn is_specifier(word: &str) -> bool {
+  matches!(word, "def" | "over" | "class")
+}
+
+// Add a prim's mesh, and its children's, to the mesh
+fn place(prim: &Prim, parent: &Matrix, mesh: &mut Mesh)
+    -> Result<(), String> {
+  if prim.class {
+    return Ok(());
+  }
+  let transform = local_transform(prim, parent)?;
+  if prim.type_name == "Mesh" {
+    let start = mesh.triangles.len();
+    add_geometry(prim, &transform, mesh)?;
+    let triangles = start..mesh.triangles.len();
+    mesh.objects.push(SubObject { name: prim.name.clone(), triangles });
+  }
+  for child in &prim.children {
+    place(child, &transform, mesh)?;
+  }
+  Ok(())
+}
+
+fn add_geometry(prim: &Prim, transform: &Matrix, mesh: &mut Mesh)
+    -> Result<(), String> {
+  let name = &prim.name;
+  let attribute = |name: &str| prim.attributes.get(name)
+    .map_or(Vec::new(), |a| numbers(&a.value));
+  let points = attribute("points");
+  let counts = indices(&attribute("faceVertexCounts"))
+    .ok_or_else(|| format!("mesh {}: invalid faceVertexCounts", name))?;
+  let corners = indices(&attribute("faceVertexIndices"))
+    .ok_or_else(|| format!("mesh {}: invalid faceVertexIndices", name))?;
+  let count = points.len() / 3;
+  if let Some(&bad) = corners.iter().find(|&&v| v >= count) {
+    return Err(format!("mesh {}: point index {} out of range ({} points)",
+      name, bad, count));
+  }
+  let left_handed = matches!(prim.attributes.get("orientation"),
+    Some(Attribute { value: Value::Text(text), .. }) if text == "leftHanded");
+
+  let base = mesh.positions.len();
+  mesh.positions.extend(points.chunks_exact(3)
+    .map(|p| apply(transform, [p[0], p[1], p[2]])));
+
+  // UV index for each face corner, if the mesh has UVs per point or
+  // per corner
+  let uv_base = mesh.uvs.len();
+  let uv_corners: Option<Vec<usize>> = texture_coordinates(prim)
+    .map(|TextureCoordinates { uvs, per_point, indices: uv_indices }| {
+      let per_corner = if per_point {
+        corners.clone()
+      } else {
+        (0..corners.len()).collect()
+      };
+      let per_corner: Vec<usize> = match uv_indices {
+        Some(uv_indices) => per_corner.iter()
+          .map(|&i| uv_indices.get(i).copied().unwrap_or(usize::MAX))
+          .collect(),
+        None => per_corner,
+      };
+      if let Some(&bad) = per_corner.iter().find(|&&i| i >= uvs.len()) {
+        return Err(format!("mesh {}: texture coordinate index {} out of \
+          range ({} coordinates)", name, bad, uvs.len()));
+      }
+      mesh.uvs.extend(uvs);
+      Ok(per_corner)
+    })
+    .transpose()?;
+
+  let mut at = 0;
+  for &size in &counts {
+    let Some(polygon) = corners.get(at..at + size) else {
+      return Err(format!("mesh {}: faceVertexCounts run past \
+        faceVertexIndices", name));
+    };
+    // A fan, as USDZLoader triangulates
+    for i in 1..size.saturating_sub(1) {
+      let mut corner = [0, i, i + 1];
+      if left_handed {
+        corner.swap(1, 2);
+      }
+      mesh.triangles.push(corner.map(|c| base + polygon[c]));
+      mesh.uv_triangles.push(uv_corners.as_ref()
+        .map(|uv| corner.map(|c| uv_base + uv[at + c])));
+    }
+    at += size;
+  }
+  Ok(())
+}
+
+struct TextureCoordinates {
+  uvs: Vec<[f64; 2]>,
+  /// One per point rather than one per face corner
+  per_point: bool,
+  /// Into `uvs`, if they're indexed
+  indices: Option<Vec<usize>>,
+}
+
+// The mesh's `primvars:st`, or other 2D texture coordinates. Ones
+// constant over the mesh or a face are left out.
+fn texture_coordinates(prim: &Prim) -> Option<TextureCoordinates> {
+  let (name, attribute) = prim.attributes.get_key_value("primvars:st")
+    .or_else(|| prim.attributes.iter().find(|(name, a)| {
+      name.starts_with("primvars:") && a.type_name.starts_with("texCoord2")
+    }))?;
+  let uvs: Vec<[f64; 2]> = numbers(&attribute.value).chunks_exact(2)
+    .map(|uv| [uv[0], uv[1]])
+    .collect();
+  let per_point = match attribute.metadata.get("interpolation") {
+    Some(Value::Text(text)) if text == "faceVarying" => false,
+    Some(Value::Text(text)) if text == "vertex" || text == "varying" => true,
+    _ => return None,
+  };
+  let indices = prim.attributes.get(&format!("{}:indices", name))
+    .and_then(|a| indices(&numbers(&a.value)));
+  Some(TextureCoordinates { uvs, per_point, indices })
+}
+
+// The prim's xformOps applied to its parent's transform, in the order
+// `xformOpOrder` gives; ops it doesn't list don't apply
+fn local_transform(prim: &Prim, parent: &Matrix) -> Result<Matrix, String> {
+  let Some(Attribute { value: Value::List(order), .. }) =
+    prim.attributes.get("xformOpOrder") else { return Ok(*parent) };
+  let mut transform = *parent;
+  for op in order {
+    let Value::Text(op) = op else { continue };
+    if op == "!resetXformStack!" {
+      transform = IDENTITY;
+      continue;
+    }
+    let (inverse, op) = match op.strip_prefix("!invert!") {
+      Some(op) => (true, op),
+      None => (false, op.as_str()),
+    };
+    // Animated ops without a default value are left out
+    let Some(attribute) = prim.attributes.get(op) else { continue };
+    let kind = op.split(':').nth(1).unwrap_or("");
+    let matrix = xform_op(kind, &numbers(&attribute.value))
+      .ok_or_else(|| format!("prim {}: invalid {}", prim.name, op))?;
+    let matrix = if inverse {
+      invert(&matrix)
+        .ok_or_else(|| format!("prim {}: {} can't be inverted", prim.name,
+          op))?
+    } else {
+      matrix
+    };
+    transform = multiply(&transform, &matrix);
+  }
+  Ok(transform)
+}
+
+fn xform_op(kind: &str, v: &[f64]) -> Option<Matrix> {
+  let expected = match kind {
+    "transform" => 16,
+    "orient" => 4,
+    "rotateX" | "rotateY" | "rotateZ" => 1,
+    _ => 3,
+  };
+  if v.len() != expected {
+    return None;
+  }
+  Some(match kind {
+    // Written for row vectors, so transposed
+    "transform" => std::array::from_fn(|i| v[(i % 4) * 4 + i / 4]),
+    "translate" => [
+      1.0, 0.0, 0.0, v[0],
+      0.0, 1.0, 0.0, v[1],
+      0.0, 0.0, 1.0, v[2],
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    "scale" => [
+      v[0], 0.0, 0.0, 0.0,
+      0.0, v[1], 0.0, 0.0,
+      0.0, 0.0, v[2], 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    "orient" => {
+      // Real part first
+      let length = v.iter().map(|c| c * c).sum::<f64>().sqrt();
+      if length == 0.0 {
+        return None;
+      }
+      let [w, x, y, z] = [v[0], v[1], v[2], v[3]].map(|c| c / length);
+      [
+        1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w),
+        2.0 * (x * z + y * w), 0.0,
+        2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z),
+        2.0 * (y * z - x * w), 0.0,
+        2.0 * (x * z - y * w), 2.0 * (y * z + x * w),
+        1.0 - 2.0 * (x * x + y * y), 0.0,
+        0.0, 0.0, 0.0, 1.0,
+      ]
+    }
+    "rotateX" | "rotateY" | "rotateZ" => {
+      rotation(kind.as_bytes()[6], v[0])
+    }
+    // rotateXYZ and the like: the axes in the order they apply
+    _ => {
+      let axes = kind.strip_prefix("rotate")
+        .filter(|axes| axes.len() == 3)?
+        .as_bytes();
+      (0..3).fold(IDENTITY, |m, i| multiply(&rotation(axes[i], v[i]), &m))
+    }
+  })
+}
+
+// About the X, Y or Z axis, in degrees by the right-hand rule
+fn rotation(axis: u8, degrees: f64) -> Matrix {
+  let (s, c) = degrees.to_radians().sin_cos();
+  match axis {
+    b'X' => [
+      1.0, 0.0, 0.0, 0.0,
+      0.0, c, -s, 0.0,
+      0.0, s, c, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    b'Y' => [
+      c, 0.0, s, 0.0,
+      0.0, 1.0, 0.0, 0.0,
+      -s, 0.0, c, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    b'Z' => [
+      c, -s, 0.0, 0.0,
+      s, c, 0.0, 0.0,
+      0.0, 0.0, 1.0, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ],
+    _ => IDENTITY,
+  }
+}
+
+// `a` applied after `b`
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+  let mut result = [0.0; 16];
+  for row in 0..4 {
+    for column in 0..4 {
+      result[row * 4 + column] = (0..4)
+        .map(|k| a[row * 4 + k] * b[k * 4 + column])
+        .sum();
+    }
+  }
+  result
+}
+
+// Of an affine matrix, None if it flattens space
+fn invert(m: &Matrix) -> Option<Matrix> {
+  let at = |row: usize, column: usize| m[row * 4 + column];
+  let cofactor = |row: usize, column: usize| {
+    let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+    let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+    at(r0, c0) * at(r1, c1) - at(r0, c1) * at(r1, c0)
+  };
+  let determinant: f64 = (0..3).map(|c| at(0, c) * cofactor(0, c)).sum();
+  if determinant.abs() < 1e-300 {
+    return None;
+  }
+  let mut result = IDENTITY;
+  for row in 0..3 {
+    for column in 0..3 {
+      result[row * 4 + column] = cofactor(column, row) / determinant;
+    }
+  }
+  for row in 0..3 {
+    result[row * 4 + 3] = -(0..3)
+      .map(|k| result[row * 4 + k] * at(k, 3))
+      .sum::<f64>();
+  }
+  Some(result)
+}
+
+fn apply(m: &Matrix, p: [f64; 3]) -> [f64; 3] {
+  [0, 1, 2].map(|row| m[row * 4] * p[0] + m[row * 4 + 1] * p[1]
+    + m[row * 4 + 2] * p[2] + m[row * 4 + 3])
+}
+
+// Every number in a value, tuples flattened
+fn numbers(value: &Value) -> Vec<f64> {
+  match value {
+    Value::Number(n) => vec![*n],
+    Value::List(items) => items.iter().flat_map(numbers).collect(),
+    _ => Vec::new(),
+  }
+}
+
+fn indices(numbers: &[f64]) -> Option<Vec<usize>> {
+  numbers.iter()
+    .map(|&n| (n >= 0.0 && n.fract() == 0.0).then_some(n as usize))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+
+  fn usdz(layer: &str) -> Vec<u8> {
+    testing::zip(&[("model.usda", layer.as_bytes(), false),
+      ("textures/a.png", b"png", false)])
+  }
+
+  // A layer with one mesh, given its face indices
+  fn layer(indices: &str) -> String {
+    format!(r#"#usda 1.0
+(
+    defaultPrim = "Root"
+)
+
+def Xform "Root"
+{{
+    double3 xformOp:translate = (10, 0, 0)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+
+    def Mesh "Quad"
+    {{
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+        int[] faceVertexCounts = [4]
+        int[] faceVertexIndices = [{}]
+    }}
+}}
+"#, indices)
+  }
+
+  #[test]
+  fn parses_text_layers() {
+    let bytes = usdz(&layer("0, 1, 2, 3"));
+    assert!(is_usdz(&bytes));
+    let mesh = parse_usdz(&bytes).unwrap();
+    assert_eq!(mesh.objects.len(), 1);
+    assert_eq!(mesh.objects[0].name, "Quad");
+    assert_eq!(mesh.triangles.len(), 2);
+    assert_eq!(mesh.positions[2], [11.0, 1.0, 0.0]);
+  }
+
+  #[test]
+  fn rejects_malformed_packages() {
+    assert!(parse_usdz(&usdz(&layer("0, 1, 2, 9"))).is_err());
+    assert!(parse_usdz(&usdz(&layer("0, 1, 2"))).is_err());
+    let text = layer("0, 1, 2, 3");
+    assert!(parse_usdz(&usdz(&text[..text.len() / 2])).is_err());
+    assert!(parse_usdz(&usdz("PXR-USDC")).is_err());
+    assert!(parse_usdz(&usdz("not a layer")).is_err());
+    // Only a zip whose first file is a layer is a USDZ package
+    assert!(!is_usdz(&testing::zip(&[("a.png", b"png", false)])));
+  }
+}
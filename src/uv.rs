@@ -0,0 +1,194 @@
+//! UV layout inspection: how much of the texture a mesh's texture
+//! coordinates use and where they overlap, and a drawing of the layout.
+//!
+//! Both work on the unit UV square, where textures are usually laid
+//! out; coordinates outside it (for tiling) are counted but not drawn.
+//! Coverage and overlap come from rasterizing the triangles onto a
+//! `GRID` x `GRID` grid, so they're accurate to about a cell.
+
+use crate::mesh::Mesh;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Cells per side of the grid the triangles are rasterized on.
+pub const GRID: usize = 256;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UvStats {
+  /// Triangles with texture coordinates
+  pub triangles: usize,
+  /// Share of the unit UV square some triangle covers, 0 to 1
+  pub coverage: f64,
+  /// Share of the unit UV square covered more than once, as by
+  /// overlapping islands
+  pub overlap: f64,
+  /// Triangles reaching outside the unit square
+  pub outside: usize,
+}
+
+/// How many triangles cover each grid cell, row by row from v = 0.
+pub struct Coverage {
+  pub counts: Vec<u32>,
+}
+
+impl Coverage {
+  pub fn get(&self, column: usize, row: usize) -> u32 {
+    self.counts[row * GRID + column]
+  }
+}
+
+// A triangle's corners in UV space
+fn uv_triangles(mesh: &Mesh) -> impl Iterator<Item = [[f64; 2]; 3]> + '_ {
+  mesh.uv_triangles.iter().flatten()
+    .map(|corners| corners.map(|i| mesh.uvs[i]))
+}
+
+/// Coverage and overlap of a mesh's UVs, None if it has none.
+pub fn stats(mesh: &Mesh) -> Option<UvStats> {
+  if mesh.uv_triangles.is_empty() {
+    return None;
+  }
+  let coverage = rasterize(mesh);
+  let cells = (GRID * GRID) as f64;
+  let covered = coverage.counts.iter().filter(|&&n| n > 0).count();
+  let overlapping = coverage.counts.iter().filter(|&&n| n > 1).count();
+  Some(UvStats {
+    triangles: uv_triangles(mesh).count(),
+    coverage: covered as f64 / cells,
+    overlap: overlapping as f64 / cells,
+    outside: uv_triangles(mesh)
+      .filter(|t| t.iter().flatten().any(|&c| !(0.0..=1.0).contains(&c)))
+      .count(),
+  })
+}
+
+/// Count the triangles over each cell centre.
+pub fn rasterize(mesh: &Mesh) -> Coverage {
+  let mut counts = vec![0; GRID * GRID];
+  for triangle in uv_triangles(mesh) {
+    cover(triangle, |cell| counts[cell] += 1);
+  }
+  Coverage { counts }
+}
+
+/// Call `visit` with the index (`row * GRID + column`) of each cell whose
+/// centre is inside the triangle. Edges follow the top-left rule, so
+/// neighbouring triangles don't both claim the cells on the edge they
+/// share.
+pub fn cover(triangle: [[f64; 2]; 3], mut visit: impl FnMut(usize)) {
+  let size = GRID as f64;
+  let [a, b, c] = triangle.map(|[u, v]| [u * size, v * size]);
+  // Counter-clockwise, so inside is to the left of every edge
+  let (b, c) = if cross(a, b, c) < 0.0 { (c, b) } else { (b, c) };
+  if cross(a, b, c) == 0.0 {
+    return;
+  }
+  let range = |axis: usize| {
+    let lo = a[axis].min(b[axis]).min(c[axis]).floor().max(0.0) as usize;
+    let hi = (a[axis].max(b[axis]).max(c[axis]).ceil().max(0.0) as usize)
+      .min(GRID);
+    lo..hi
+  };
+  for row in range(1) {
+    for column in range(0) {
+      let p = [column as f64 + 0.5, row as f64 + 0.5];
+      if [(a, b), (b, c), (c, a)].iter().all(|&(from, to)| {
+        let side = cross(from, to, p);
+        side > 0.0 || (side == 0.0 && is_top_left(from, to))
+      }) {
+        visit(row * GRID + column);
+      }
+    }
+  }
+}
+
+/// Twice the signed area of `a b c`, positive if counter-clockwise.
+pub fn cross(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+  (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// Of a counter-clockwise triangle's edges, the left ones (going down)
+// and the top ones (flat, going left) own the points on them
+fn is_top_left(from: [f64; 2], to: [f64; 2]) -> bool {
+  let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+  dy < 0.0 || (dy == 0.0 && dx < 0.0)
+}
+
+/// Draw the layout as an SVG `size` pixels square: the unit square with
+/// v pointing up, each object's triangle edges in its own colour, and
+/// the cells where triangles overlap in red. None if the mesh has no
+/// UVs.
+pub fn svg(mesh: &Mesh, size: u32) -> Option<String> {
+  if mesh.uv_triangles.is_empty() {
+    return None;
+  }
+  let s = size as f64;
+  let point = |[u, v]: [f64; 2]| format!("{:.2},{:.2}", u * s, (1.0 - v) * s);
+  let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+    width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n\
+    <rect width=\"{0}\" height=\"{0}\" fill=\"#fff\"/>\n", size);
+  // Quarter lines, as a scale
+  for i in 1..4 {
+    let at = s * i as f64 / 4.0;
+    out.push_str(&format!("<path d=\"M{0:.2},0V{1:.2}M0,{0:.2}H{1:.2}\" \
+      stroke=\"#ddd\" stroke-width=\"1\"/>\n", at, s));
+  }
+
+  let coverage = rasterize(mesh);
+  let cell = s / GRID as f64;
+  let mut overlaps = String::new();
+  for row in 0..GRID {
+    // Runs of overlapping cells as one rectangle each
+    let mut column = 0;
+    while column < GRID {
+      if coverage.get(column, row) < 2 {
+        column += 1;
+        continue;
+      }
+      let start = column;
+      while column < GRID && coverage.get(column, row) > 1 {
+        column += 1;
+      }
+      let width = (column - start) as f64 * cell;
+      overlaps.push_str(&format!("M{:.2},{:.2}h{:.2}v{:.2}h{:.2}z",
+        start as f64 * cell, (GRID - row - 1) as f64 * cell, width, cell,
+        -width));
+    }
+  }
+  if !overlaps.is_empty() {
+    out.push_str(&format!("<path d=\"{}\" fill=\"#e33\" \
+      fill-opacity=\"0.5\"><title>overlap</title></path>\n", overlaps));
+  }
+
+  // Faces before any named object are in one without a name
+  let objects: Vec<(&str, Range<usize>)> = if mesh.objects.is_empty() {
+    vec![("", 0..mesh.triangles.len())]
+  } else {
+    mesh.objects.iter().map(|o| (o.name.as_str(), o.triangles.clone()))
+      .collect()
+  };
+  for (i, (name, triangles)) in objects.into_iter().enumerate() {
+    let mut path = String::new();
+    for t in triangles {
+      let Some(Some(corners)) = mesh.uv_triangles.get(t) else { continue };
+      let [a, b, c] = corners.map(|i| point(mesh.uvs[i]));
+      path.push_str(&format!("M{}L{}L{}z", a, b, c));
+    }
+    if path.is_empty() {
+      continue;
+    }
+    // Golden-angle hues keep neighbouring objects apart
+    let hue = (i as f64 * 137.508) % 360.0;
+    out.push_str(&format!("<path d=\"{}\" fill=\"none\" \
+      stroke=\"hsl({:.0},70%,40%)\" stroke-width=\"0.5\" \
+      stroke-linejoin=\"round\"><title>{}</title></path>\n",
+      path, hue, escape(name)));
+  }
+  out.push_str(&format!("<rect width=\"{0}\" height=\"{0}\" \
+    fill=\"none\" stroke=\"#888\" stroke-width=\"1\"/>\n</svg>\n", size));
+  Some(out)
+}
+
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -1,9 +1,31 @@
+// Where the page loads three.js from, unless an export copies it along
+pub const THREE_CDN: &str = "https://cdn.jsdelivr.net/npm/three@0.160.0/";
+
+// The parts of a three.js package the page imports
+pub const THREE_FILES: &[&str] = &[
+  "build/three.module.js",
+  "examples/jsm/controls/OrbitControls.js",
+  "examples/jsm/loaders/OBJLoader.js",
+  "examples/jsm/loaders/STLLoader.js",
+  "examples/jsm/loaders/PLYLoader.js",
+  "examples/jsm/loaders/3MFLoader.js",
+  "examples/jsm/libs/fflate.module.js",
+  "examples/jsm/loaders/ColladaLoader.js",
+  "examples/jsm/loaders/TGALoader.js",
+  "examples/jsm/loaders/USDZLoader.js",
+  "examples/jsm/loaders/GLTFLoader.js",
+  "examples/jsm/utils/BufferGeometryUtils.js",
+];
+
 pub const HTML: &str = r#"<!DOCTYPE html>
 <html lang="en">
 <head>
   <meta charset="UTF-8">
   <meta name="viewport" content="width=device-width, initial-scale=1.0">
   <title>Kitbash Viewer</title>
+  <meta name="theme-color" content="rgb(42, 42, 42)">
+  <link rel="manifest" href="/manifest.webmanifest">
+  <link rel="icon" href="/icon.svg" type="image/svg+xml">
   <style>
     body {
       margin: 0;
@@ -26,14 +48,19 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       padding: 15px;
       border-radius: 8px;
       min-width: 200px;
-      max-width: 300px;
+      max-width: 80vw;
+      width: 250px;
       font-size: 14px;
       opacity: 0.9;
+      resize: horizontal;
+      overflow: auto;
     }
     #file-list-overlay.hidden {
       display: none;
     }
     #file-list-header {
+      cursor: move;
+      user-select: none;
       font-weight: bold;
       margin-bottom: 10px;
       padding-bottom: 8px;
@@ -66,12 +93,61 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       opacity: 0.4;
       font-style: italic;
     }
+    .file-list-item.recent {
+      box-shadow: inset 3px 0 0 #ffcc44;
+    }
     .file-list-item.failed {
       color: #ff6666;
     }
     .file-list-item.failed .visibility-icon {
       color: #ff4444;
     }
+    .file-list-item .scale-warning {
+      color: #ffcc44;
+    }
+    .file-list-item .vertex-colors {
+      color: #c48bd8;
+    }
+    .file-list-item .server-error {
+      color: #ff6666;
+    }
+    .file-list-item .git-status {
+      float: right;
+      margin-left: 8px;
+      font-size: 11px;
+      font-weight: bold;
+    }
+    .git-status.modified { color: #e2c08d; }
+    .git-status.untracked { color: #73c991; }
+    .git-status.ignored { color: #888; }
+    .file-list-item .busy {
+      color: #888;
+      font-style: italic;
+    }
+    .file-list-item .ignore-toggle {
+      float: right;
+      margin-left: 8px;
+      color: #888;
+      visibility: hidden;
+    }
+    .file-list-item:hover .ignore-toggle,
+    .file-list-item.ignored .ignore-toggle {
+      visibility: visible;
+    }
+    .file-list-item.ignored {
+      color: #777;
+      font-style: italic;
+    }
+    #lock-banner {
+      margin-bottom: 8px;
+      padding: 4px 8px;
+      border-radius: 4px;
+      background-color: rgba(226, 192, 141, 0.2);
+      color: #e2c08d;
+    }
+    #lock-banner.hidden {
+      display: none;
+    }
     .file-list-item .visibility-icon {
       display: inline-block;
       width: 16px;
@@ -85,6 +161,7 @@ pub const HTML: &str = r#"<!DOCTYPE html>
 
   <div id="file-list-overlay">
     <div id="file-list-header">Files (Tab to toggle)</div>
+    <div id="lock-banner" class="hidden"></div>
     <div id="file-list-content"></div>
   </div>
 
@@ -103,6 +180,19 @@ pub const HTML: &str = r#"<!DOCTYPE html>
     import * as THREE from 'three';
     import { OrbitControls } from 'three/addons/controls/OrbitControls.js';
     import { OBJLoader } from 'three/addons/loaders/OBJLoader.js';
+    import { STLLoader } from 'three/addons/loaders/STLLoader.js';
+    import { PLYLoader } from 'three/addons/loaders/PLYLoader.js';
+    import { ThreeMFLoader } from 'three/addons/loaders/3MFLoader.js';
+    import { ColladaLoader } from 'three/addons/loaders/ColladaLoader.js';
+    import { USDZLoader } from 'three/addons/loaders/USDZLoader.js';
+    import { GLTFLoader } from 'three/addons/loaders/GLTFLoader.js';
+
+    // Filled in by `export-site` and `export-html`: the scene's config,
+    // manifest, listing and baked meshes, for a copy with no server
+    const staticScene = /* static scene */ null;
+    // Set when served at `/xr`: review in a headset, at real size with
+    // scene units taken as meters
+    const xrMode = /* xr */ false;
 
     // Scene setup
     const scene = new THREE.Scene();
@@ -144,8 +234,74 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       gridSize, gridDivisions, 0xaaaaaa, 0x666666);
     scene.add(gridHelper);
 
+    // Keep the grid under the meshes rather than through them, at the
+    // scene's lowest point as the server works it out. Files arrive one
+    // by one, so wait for a pause before asking.
+    let groundTimer = null;
+    function placeGround() {
+      if (staticScene) return;
+      clearTimeout(groundTimer);
+      groundTimer = setTimeout(async () => {
+        try {
+          const response = await fetch('/api/scene/bounds');
+          if (response.ok) {
+            gridHelper.position.y = (await response.json()).ground;
+          }
+        } catch (error) {
+          console.log('Scene bounds unavailable:', error.message);
+        }
+      }, 300);
+    }
+
     // Camera controls
     const controls = new OrbitControls(camera, renderer.domElement);
+    controls.addEventListener('end', reportView);
+
+    // WebXR review. In the headset, the viewer stands on the grid below
+    // where the camera was, facing what it looked at; the control API
+    // moves them the same way it moves the camera.
+    let xrBaseSpace = null;
+    let desktopView = null;
+
+    function standInXr() {
+      if (!renderer.xr.isPresenting || !xrBaseSpace) return;
+      const facing = controls.target.clone().sub(camera.position);
+      const standing = new THREE.Vector3(
+        camera.position.x, gridHelper.position.y, camera.position.z);
+      const turn = new THREE.Quaternion().setFromAxisAngle(
+        new THREE.Vector3(0, 1, 0), Math.atan2(-facing.x, -facing.z));
+      // The reference space moves the opposite way to the viewer
+      const offset = new THREE.Matrix4()
+        .compose(standing, turn, new THREE.Vector3(1, 1, 1))
+        .invert();
+      const position = new THREE.Vector3();
+      const rotation = new THREE.Quaternion();
+      offset.decompose(position, rotation, new THREE.Vector3());
+      renderer.xr.setReferenceSpace(xrBaseSpace.getOffsetReferenceSpace(
+        new XRRigidTransform(position, rotation)));
+    }
+
+    if (xrMode) {
+      renderer.xr.enabled = true;
+      renderer.xr.addEventListener('sessionstart', () => {
+        desktopView = {
+          position: camera.position.clone(),
+          target: controls.target.clone(),
+        };
+        xrBaseSpace = renderer.xr.getReferenceSpace();
+        standInXr();
+      });
+      renderer.xr.addEventListener('sessionend', () => {
+        renderer.xr.setReferenceSpace(null);
+        xrBaseSpace = null;
+        camera.position.copy(desktopView.position);
+        controls.target.copy(desktopView.target);
+        controls.update();
+      });
+      import('three/addons/webxr/VRButton.js').then(({ VRButton }) => {
+        document.body.appendChild(VRButton.createButton(renderer));
+      });
+    }
     // controls.enableDamping = true;
     // controls.dampingFactor = 0.05;
 
@@ -159,14 +315,361 @@ pub const HTML: &str = r#"<!DOCTYPE html>
     // Map from object to wireframe overlay
     const wireframeOverlays = new Map(); 
 
-    // OBJ Loader
+    // OBJ, STL, PLY, 3MF, COLLADA, USDZ and glTF Loaders, and one for
+    // the server's point cloud buffers
     const objLoader    = new OBJLoader();
+    const stlLoader    = new STLLoader();
+    const plyLoader    = new PLYLoader();
+    const threeMFLoader = new ThreeMFLoader();
+    const colladaLoader = new ColladaLoader();
+    const usdzLoader   = new USDZLoader();
+    const gltfLoader   = new GLTFLoader();
+    const pointsLoader = new THREE.FileLoader().setResponseType('arraybuffer');
+    // Names this page to the server, which times its mesh downloads for
+    // the WebSocket connection of the same id (see /api/clients)
+    const clientId = Array.from(crypto.getRandomValues(new Uint8Array(8)),
+      (b) => b.toString(16).padStart(2, '0')).join('');
+    for (const loader of [objLoader, stlLoader, plyLoader, threeMFLoader,
+        colladaLoader, usdzLoader, gltfLoader, pointsLoader]) {
+      loader.setRequestHeader({ 'X-Kitbash-Client': clientId });
+    }
     const loadedMeshes = new Map();
     const loadingFiles = new Set(); // Track files currently being loaded
     const failedFiles  = new Map(); // Track files that failed to load 
                                     // (filename -> error)
+    const scaleWarnings = new Map(); // Server scale warnings
+    const serverErrors = new Map(); // Server parse/transcode errors
+                                     // (filename -> warning message)
+    const fileHashes   = new Map(); // Content hashes, from snapshots and events
+    const gitStatus    = new Map(); // Files that aren't clean in git
+                                    // (filename -> status)
+    let gitBranch = null;
+    const busyFiles    = new Set(); // Files still being written elsewhere
+    const convertingFiles = new Map(); // Files the server is converting
+                                       // (filename -> progress or null)
+    const vertexColorFiles = new Set(); // Files with per-vertex colours
+    const ignoredFiles = new Set(); // Hidden from every viewer, not loaded
+    let sharedView = null; // View of the snapshot a shared link opens
+    // Who we are and which route groups (read, mutate, control, admin)
+    // the server lets us use, from /api/config. Actions outside
+    // `access.allowed` shouldn't be offered.
+    let access = {
+      user: null,
+      allowed: ['read', 'mutate', 'control', 'admin'],
+      csrf_token: '',
+      keys: {}, // action -> keys
+      palette: {
+        name: 'neutral', colors: ['#cccccc'], selection: '#224488',
+        reference: '#7f8fa6',
+      },
+      aliases: {}, // filename -> logical name
+      order: 'name', // a sort key of /api/files
+      point_size: 2, // pixels across a point cloud's points
+    };
+    // Point size picked with the keys, replacing the server's
+    let pointSize = null;
+
+    // What the file list calls a file: its alias, if it has one
+    function displayName(filename) {
+      return access.aliases[filename] || filename;
+    }
+
+    // A file's colour from the palette, by a 64-bit FNV-1a hash of its
+    // name as the server computes it
+    function fileColor(filename) {
+      if (filename.startsWith('ref/')) {
+        return access.palette.reference;
+      }
+      const mask = (1n << 64n) - 1n;
+      let hash = 0xcbf29ce484222325n;
+      for (const byte of new TextEncoder().encode(filename)) {
+        hash ^= BigInt(byte);
+        hash = (hash * 0x100000001b3n) & mask;
+      }
+      const colors = access.palette.colors;
+      return colors[Number(hash % BigInt(colors.length))];
+    }
+
+    // For ordering by mtime, size and bytes: when each file last changed
+    // (milliseconds), the largest dimension of its mesh, and its length
+    const fileTimes = new Map();
+    const fileSizes = new Map();
+    const fileBytes = new Map();
+    // Files changed while we were watching, marked in the file list for
+    // a while (filename -> when we heard, in our clock)
+    const recentChanges = new Map();
+    const RECENT_MS = 10000;
+
+    // Note the details an added or modified event carries
+    function noteChange(msg) {
+      fileTimes.set(msg.filename, msg.mtime || Date.now());
+      if (msg.size_delta !== undefined) {
+        fileBytes.set(msg.filename,
+          (fileBytes.get(msg.filename) || 0) + msg.size_delta);
+      }
+      recentChanges.set(msg.filename, Date.now());
+      setTimeout(updateFileList, RECENT_MS);
+    }
+
+    // Compare filenames in the server's configured order, as the file
+    // list shows them and `[`/`]` steps through them
+    function compareFiles(a, b) {
+      const descending = access.order.startsWith('-');
+      const key = access.order.replace(/^-/, '');
+      const rank = (filename) => {
+        const position = (manifest.order || []).indexOf(filename);
+        return position < 0 ? Infinity : position;
+      };
+      const triangles = (filename) => {
+        let count = 0;
+        const object = loadedMeshes.get(filename);
+        if (object) {
+          object.traverse((child) => {
+            if (child.isMesh) {
+              count += child.geometry.attributes.position.count / 3;
+            }
+          });
+        }
+        return count;
+      };
+      let result = 0;
+      if (key === 'manifest') {
+        const [ra, rb] = [rank(a), rank(b)];
+        result = ra === rb ? 0 : ra < rb ? -1 : 1;
+      } else if (key === 'mtime') {
+        result = (fileTimes.get(b) || 0) - (fileTimes.get(a) || 0);
+      } else if (key === 'size') {
+        result = (fileSizes.get(a) || 0) - (fileSizes.get(b) || 0);
+      } else if (key === 'triangles') {
+        result = triangles(a) - triangles(b);
+      } else if (key === 'bytes') {
+        result = (fileBytes.get(a) || 0) - (fileBytes.get(b) || 0);
+      }
+      if (result === 0) {
+        const [na, nb] = [displayName(a), displayName(b)];
+        result = na < nb ? -1 : na > nb ? 1 : 0;
+      }
+      return descending ? -result : result;
+    }
+
+    // Scene files wait here to be fetched a few at a time, those in view
+    // and nearest the camera first, so a big scene fills in where you're
+    // looking. The order is worked out afresh as each load finishes, so
+    // moving the camera redirects what comes next.
+    const MAX_LOADS = 4;
+    const queuedLoads = new Map(); // filename -> function starting it
+    let activeLoads = 0;
+    // Bounds of each file before its manifest transform, from listings
+    const fileExtents = new Map();
+
+    function loadMesh(filename, onLoad, onProgress, onError) {
+      const finish = (callback) => (result) => {
+        activeLoads--;
+        try {
+          callback(result);
+        } finally {
+          nextLoad();
+        }
+      };
+      queuedLoads.set(filename, () => fetchMesh(
+        filename, finish(onLoad), onProgress, finish(onError)));
+      nextLoad();
+    }
+
+    function nextLoad() {
+      if (activeLoads >= MAX_LOADS || queuedLoads.size === 0) return;
+      camera.updateMatrixWorld();
+      const frustum = new THREE.Frustum().setFromProjectionMatrix(
+        new THREE.Matrix4().multiplyMatrices(
+          camera.projectionMatrix, camera.matrixWorldInverse));
+      // In view, then not yet known (listed without bounds), then out
+      // of view; nearest first within each
+      const priority = (filename) => {
+        const extent = fileExtents.get(filename);
+        if (!extent) return [1, 0];
+        const transform = manifest.transforms[filename] ||
+          { translation: [0, 0, 0], scale: 1 };
+        const box = new THREE.Box3(
+          new THREE.Vector3(...extent.min), new THREE.Vector3(...extent.max))
+          .applyMatrix4(new THREE.Matrix4()
+            .makeTranslation(...transform.translation)
+            .multiply(new THREE.Matrix4().makeScale(
+              transform.scale, transform.scale, transform.scale)));
+        return [frustum.intersectsBox(box) ? 0 : 2,
+          box.distanceToPoint(camera.position)];
+      };
+      while (activeLoads < MAX_LOADS && queuedLoads.size > 0) {
+        let best = null;
+        let bestPriority = null;
+        for (const filename of queuedLoads.keys()) {
+          const p = priority(filename);
+          if (!best || p[0] < bestPriority[0] ||
+              (p[0] === bestPriority[0] && p[1] < bestPriority[1])) {
+            best = filename;
+            bestPriority = p;
+          }
+        }
+        const start = queuedLoads.get(best);
+        queuedLoads.delete(best);
+        activeLoads++;
+        start();
+      }
+    }
+
+    // glTF, COLLADA and USDZ files, and FBX and CAD files converted to
+    // glTF, bring their own materials, which are kept rather than
+    // replaced with the file's colour
+    function hasOwnMaterials(filename) {
+      return /\.(glb|gltf|dae|usdz)$/i.test(filename || '')
+        || isConverted(filename);
+    }
+
+    // Files the server converts to GLB with `--converter`
+    function isConverted(filename) {
+      return /\.(fbx|step|stp|iges|igs)$/i.test(filename || '');
+    }
+
+    // Fetch a scene file as a three.js object: the OBJ, STL, PLY, 3MF,
+    // COLLADA, USDZ or glTF from the server, the GLB the server converted an
+    // FBX or CAD file to or parsed a DXF into, the points it parsed an XYZ,
+    // PCD or LAS file into, or the GLB an export baked
+    function fetchMesh(filename, onLoad, onProgress, onError) {
+      if (!staticScene && filename.toLowerCase().endsWith('.dae')) {
+        // The visual scene's node hierarchy, turned Y-up and scaled to
+        // the asset's unit by the loader
+        colladaLoader.load(`/scene/${filename}`,
+          (collada) => onLoad(collada.scene), onProgress, onError);
+        return;
+      }
+      if (!staticScene && filename.toLowerCase().endsWith('.usdz')) {
+        // A group of the root layer's prims, with their materials
+        usdzLoader.load(`/scene/${filename}`, onLoad, onProgress, onError);
+        return;
+      }
+      if (!staticScene && isConverted(filename)) {
+        gltfLoader.load(`/scene-converted/${filename}`,
+          (gltf) => onLoad(gltf.scene), onProgress, onError);
+        return;
+      }
+      if (!staticScene && hasOwnMaterials(filename)) {
+        // The whole node hierarchy, with its materials
+        gltfLoader.load(`/scene/${filename}`, (gltf) => onLoad(gltf.scene),
+          onProgress, onError);
+        return;
+      }
+      if (!staticScene && filename.toLowerCase().endsWith('.stl')) {
+        // STLLoader gives a bare geometry, ASCII or binary; wrapped so
+        // it's handled like an OBJ's group (the material is replaced)
+        stlLoader.load(`/scene/${filename}`, (geometry) => {
+          const group = new THREE.Group();
+          group.add(new THREE.Mesh(geometry, new THREE.MeshPhongMaterial()));
+          onLoad(group);
+        }, onProgress, onError);
+        return;
+      }
+      if (!staticScene && filename.toLowerCase().endsWith('.ply')) {
+        plyLoader.load(`/scene/${filename}`, (geometry) => {
+          if (!geometry.index) {
+            onLoad(pointCloud(geometry));
+            return;
+          }
+          if (!geometry.attributes.normal) geometry.computeVertexNormals();
+          // Vertex colours, if any, are kept when the material is replaced
+          const group = new THREE.Group();
+          group.add(new THREE.Mesh(geometry, new THREE.MeshPhongMaterial()));
+          onLoad(group);
+        }, onProgress, onError);
+        return;
+      }
+      if (!staticScene && filename.toLowerCase().endsWith('.3mf')) {
+        // A group of the build's objects; their materials are replaced
+        // like an OBJ's, unless the file colours its triangles
+        threeMFLoader.load(`/scene/${filename}`, onLoad, onProgress, onError);
+        return;
+      }
+      if (!staticScene && filename.toLowerCase().endsWith('.dxf')) {
+        // three.js has no DXF loader, so this is the server's parse
+        gltfLoader.load(
+          `/api/files/${encodeURIComponent(filename)}/mesh.glb`,
+          (gltf) => onLoad(bakedScene(gltf)), onProgress, onError);
+        return;
+      }
+      if (!staticScene && /\.(xyz|pcd|las|laz)$/i.test(filename)) {
+        // The server's parse, as a compact buffer of points
+        pointsLoader.load(
+          `/api/files/${encodeURIComponent(filename)}/points.bin`,
+          (buffer) => onLoad(pointCloud(decodePoints(buffer))), onProgress,
+          onError);
+        return;
+      }
+      if (!staticScene) {
+        objLoader.load(`/scene/${filename}`, onLoad, onProgress, onError);
+        return;
+      }
+      gltfLoader.load(staticScene.meshes[filename],
+        (gltf) => onLoad(bakedScene(gltf)), onProgress, onError);
+    }
+
+    // A GLB the server baked, which has no normals, like OBJs without
+    // `vn` lines
+    function bakedScene(gltf) {
+      gltf.scene.traverse((child) => {
+        if (child.isMesh && !child.geometry.attributes.normal) {
+          child.geometry.computeVertexNormals();
+        }
+      });
+      return gltf.scene;
+    }
+
+    // The server's point cloud buffer: the number of points and flags,
+    // the origin as doubles, then the points' positions from it as floats
+    // and, with flag 1, their sRGB colours as bytes
+    function decodePoints(buffer) {
+      const header = new DataView(buffer, 0, 32);
+      const count = header.getUint32(0, true);
+      const geometry = new THREE.BufferGeometry();
+      geometry.setAttribute('position', new THREE.BufferAttribute(
+        new Float32Array(buffer, 32, count * 3), 3));
+      geometry.userData.origin = [8, 16, 24].map((at) =>
+        header.getFloat64(at, true));
+      if (header.getUint32(4, true) & 1) {
+        const srgb = new Uint8Array(buffer, 32 + count * 12, count * 3);
+        const color = new THREE.Color();
+        const linear = Array.from({ length: 256 }, (_, i) =>
+          color.setRGB(i / 255, 0, 0, THREE.SRGBColorSpace).r);
+        geometry.setAttribute('color', new THREE.BufferAttribute(
+          Float32Array.from(srgb, (c) => linear[c]), 3));
+      }
+      return geometry;
+    }
+
+    // Points without faces, in a group like an STL's mesh, moved to the
+    // origin the server gave them; the material is replaced
+    function pointCloud(geometry) {
+      const group = new THREE.Group();
+      const points = new THREE.Points(geometry, new THREE.PointsMaterial());
+      if (geometry.userData.origin) {
+        points.position.fromArray(geometry.userData.origin);
+      }
+      group.add(points);
+      return group;
+    }
+
+    function currentPointSize() {
+      return pointSize || access.point_size;
+    }
+
+    // Grow or shrink every point cloud's points
+    function scalePoints(factor) {
+      pointSize = Math.min(Math.max(currentPointSize() * factor, 0.5), 64);
+      loadedMeshes.forEach((object) => {
+        object.traverse((child) => {
+          if (child.isPoints) child.material.size = pointSize;
+        });
+      });
+      console.log(`Point size: ${pointSize.toFixed(1)} px`);
+    }
 
-    // Function to load and display an OBJ file
     function loadOBJ(filename) {
       // Prevent duplicate loads (race condition protection)
       if (loadingFiles.has(filename) || loadedMeshes.has(filename)) {
@@ -180,13 +683,13 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       loadingFiles.add(filename);
       console.log(`Starting load: ${filename}`);
 
-      objLoader.load(
-        `/scene/${filename}`,
+      loadMesh(
+        filename,
         (object) => {
           // Check if the object contains any actual geometry
           let hasMeshes = false;
           object.traverse((child) => {
-            if (child.isMesh && child.geometry && 
+            if ((child.isMesh || child.isPoints) && child.geometry &&
                 child.geometry.attributes.position) {
               hasMeshes = true;
             }
@@ -208,9 +711,32 @@ pub const HTML: &str = r#"<!DOCTYPE html>
 
           // Apply material to all meshes in the loaded object
           object.traverse((child) => {
-            if (child.isMesh) {
+            if (child.isPoints) {
+              // Drawn the same size however far away, like a scan's
+              // viewer would
+              const colored = !!child.geometry.attributes.color;
+              child.material = new THREE.PointsMaterial({
+                size: currentPointSize(),
+                sizeAttenuation: false,
+                vertexColors: colored,
+                color: colored ? 0xffffff : fileColor(filename)
+              });
+              if (colored) {
+                child.userData.originalColor = child.material.color.clone();
+              }
+            } else if (child.isMesh && hasOwnMaterials(filename)) {
+              // Kept for the wireframe modes to restore
+              child.userData.originalColor = child.material.color?.clone();
+            } else if (child.isMesh && child.geometry.attributes.color) {
+              // A scan's own colours, shown as they are
+              child.material = new THREE.MeshPhongMaterial({
+                vertexColors: true,
+                side: THREE.DoubleSide
+              });
+              child.userData.originalColor = child.material.color.clone();
+            } else if (child.isMesh) {
               child.material = new THREE.MeshPhongMaterial({
-                color: 0xcccccc,
+                color: fileColor(filename),
                 flatShading: false,
                 side: THREE.DoubleSide
                 // TODO: May remove this and require correct winding
@@ -219,12 +745,21 @@ pub const HTML: &str = r#"<!DOCTYPE html>
             }
           });
 
+          const size = new THREE.Box3().setFromObject(object)
+            .getSize(new THREE.Vector3());
+          fileSizes.set(filename, Math.max(size.x, size.y, size.z));
+          applyManifestTransform(filename, object);
           scene.add(object);
           loadedMeshes.set(filename, object);
           loadingFiles.delete(filename);
-          applyWireframeToObject(object); // Apply current wireframe mode
+          if (sharedView) {
+            object.visible = !sharedView.hidden.includes(filename);
+          }
+          // Apply the material set and current wireframe mode
+          applyMaterialSetToObject(object);
           console.log(`Loaded: ${filename}`);
           updateFileList();
+          placeGround();
         },
         (xhr) => {
           console.log(
@@ -261,21 +796,106 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       });
       loadedMeshes.clear();
       loadingFiles.clear();
+      queuedLoads.clear();
       failedFiles.clear();
       selectedObject = null;
       console.log('Cleared all meshes');
       updateFileList();
     }
 
+    // Scene manifest (per-file placement), kept in sync with the server
+    let manifest = { transforms: {} };
+
+    // Position an object according to its manifest transform
+    function applyManifestTransform(filename, object) {
+      const transform = manifest.transforms[filename];
+      if (transform) {
+        object.position.set(...transform.translation);
+        object.scale.setScalar(transform.scale);
+      } else {
+        object.position.set(0, 0, 0);
+        object.scale.setScalar(1);
+      }
+    }
+
+    // An Error from a failed API response's {code, message} envelope
+    async function apiError(response) {
+      const text = await response.text();
+      try {
+        const body = JSON.parse(text);
+        const error = new Error(`${body.message} (${body.code})`);
+        error.code = body.code;
+        error.details = body.details;
+        return error;
+      } catch {
+        return new Error(text || `HTTP ${response.status}`);
+      }
+    }
+
+    // Fetch the manifest and re-place all loaded objects
+    async function loadManifest() {
+      if (staticScene) {
+        manifest = staticScene.manifest;
+        return;
+      }
+      try {
+        const response = await fetch('/api/scene/manifest');
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        manifest = await response.json();
+        loadedMeshes.forEach((object, filename) => {
+          applyManifestTransform(filename, object);
+        });
+      } catch (error) {
+        console.error('Error loading scene manifest:', error);
+      }
+    }
+
+    // Optional file filter from the page URL (e.g. ?filter=wing*&tag=hull),
+    // applied to both the file listing and live updates
+    const fileFilter = {};
+    const pageParams = new URLSearchParams(window.location.search);
+    ['filter', 'tag', 'min_tris'].forEach((key) => {
+      if (pageParams.has(key)) {
+        fileFilter[key] = key === 'min_tris' ?
+          Number(pageParams.get(key)) : pageParams.get(key);
+      }
+    });
+
+    // Links handed out with a token can open a snapshot (?snapshot=name);
+    // its view applies now and to files as they finish loading
+    async function loadSharedView(name) {
+      try {
+        const response =
+          await fetch(`/api/snapshots/${encodeURIComponent(name)}`);
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        sharedView = (await response.json()).view;
+        restoreView(sharedView);
+      } catch (error) {
+        console.error('Error loading shared snapshot:', error);
+      }
+    }
+    if (pageParams.has('snapshot')) {
+      loadSharedView(pageParams.get('snapshot'));
+    }
+
     // Function to load all OBJ files from the scene directory
     async function loadAllFiles() {
+      await loadManifest();
       try {
-        const response = await fetch('/api/files');
-        const data = await response.json();
+        const data = staticScene ? { files: staticScene.files } :
+          await (await fetch(`/api/files?${new URLSearchParams(fileFilter)}`))
+            .json();
 
         console.log(`Found ${data.files.length} OBJ file(s)`);
 
         for (const fileInfo of data.files) {
+          if (fileInfo.vertex_colors) {
+            vertexColorFiles.add(fileInfo.name);
+          }
           loadOBJ(fileInfo.name);
         }
       } catch (error) {
@@ -304,7 +924,7 @@ pub const HTML: &str = r#"<!DOCTYPE html>
     function selectAdjacentObject(direction) {
       if (loadedMeshes.size === 0) return;
 
-      const filenames = Array.from(loadedMeshes.keys()).sort();
+      const filenames = Array.from(loadedMeshes.keys()).sort(compareFiles);
       const currentFilename = selectedObject ?
         getObjectFilename(selectedObject) : null;
       let currentIndex;
@@ -331,36 +951,39 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       updateFileList();
     }
 
-    // Keyboard controls
+    // Keyboard controls, bound to keys by the server's config
+    function actionForKey(key) {
+      return Object.keys(access.keys)
+        .find((action) => access.keys[action].includes(key));
+    }
+
     window.addEventListener('keydown', (event) => {
-      switch(event.key) {
-        case '0':
+      switch(actionForKey(event.key)) {
+        case 'reset_camera':
           // Reset camera to initial position
           camera.position.copy(initialCameraPosition);
           controls.target.copy(initialCameraTarget);
           controls.update();
           console.log('Camera reset to initial position');
           break;
-        case 'r':
-        case 'R':
+        case 'reload':
           reloadAllFiles();
           break;
-        case 'Tab':
+        case 'toggle_file_list':
           event.preventDefault();
           const overlay = document.getElementById('file-list-overlay');
           overlay.classList.toggle('hidden');
+          saveLayout();
           break;
-        case 'h':
-        case 'H':
-          if (event.shiftKey) {
-            // Shift+H: Show all objects
-            loadedMeshes.forEach((object) => {
-              object.visible = true;
-            });
-            console.log('Showing all objects');
-            updateFileList();
-          } else if (selectedObject) {
-            // H: Toggle visibility of selected object
+        case 'show_all':
+          loadedMeshes.forEach((object) => {
+            object.visible = true;
+          });
+          console.log('Showing all objects');
+          updateFileList();
+          break;
+        case 'toggle_hidden':
+          if (selectedObject) {
             selectedObject.visible = !selectedObject.visible;
             const filename = getObjectFilename(selectedObject);
             console.log(
@@ -368,64 +991,76 @@ pub const HTML: &str = r#"<!DOCTYPE html>
             updateFileList();
           }
           break;
-        case '[':
-          selectAdjacentObject(-1); // Previous
+        case 'select_previous':
+          selectAdjacentObject(-1);
           break;
-        case ']':
-          selectAdjacentObject(+1); // Next
+        case 'select_next':
+          selectAdjacentObject(+1);
           break;
-        case 'f':
-        case 'F':
-          if (event.shiftKey) {
-            // Shift+F: Frame all visible objects
-            const visibleObjects =
-              Array.from(loadedMeshes.values()).filter(obj => obj.visible);
-            if (visibleObjects.length > 0) {
-              frameObjects(visibleObjects,
-                camera.position.clone().sub(controls.target).normalize());
-              console.log('Framed all visible objects');
-            }
-          } else if (selectedObject) {
-            // F: Frame selected object
+        case 'frame_all': {
+          const visibleObjects =
+            Array.from(loadedMeshes.values()).filter(obj => obj.visible);
+          if (visibleObjects.length > 0) {
+            frameObjects(visibleObjects,
+              camera.position.clone().sub(controls.target).normalize());
+            console.log('Framed all visible objects');
+          }
+          break;
+        }
+        case 'frame_selected':
+          if (selectedObject) {
             frameObjects([selectedObject],
               camera.position.clone().sub(controls.target).normalize());
             const filename = getObjectFilename(selectedObject);
             console.log(`Framed: ${filename}`);
           }
           break;
-        case '1':
+        case 'view_front':
           setStandardView(new THREE.Vector3(0, 0, 1), 'Front view');
           break;
-        case '2':
+        case 'view_back':
           setStandardView(new THREE.Vector3(0, 0, -1), 'Back view');
           break;
-        case '3':
+        case 'view_right':
           setStandardView(new THREE.Vector3(1, 0, 0), 'Right view');
           break;
-        case '4':
+        case 'view_left':
           setStandardView(new THREE.Vector3(-1, 0, 0), 'Left view');
           break;
-        case '5':
+        case 'view_top':
           setStandardView(new THREE.Vector3(0, 1, 0), 'Top view');
           break;
-        case '6':
+        case 'view_bottom':
           setStandardView(new THREE.Vector3(0, -1, 0), 'Bottom view');
           break;
-        case 'w':
-        case 'W':
+        case 'cycle_wireframe':
           // Cycle wireframe mode
           wireframeMode = (wireframeMode + 1) % 3;
           applyWireframeModeToAll();
           const modes = ['Solid', 'Solid + Wireframe', 'Wireframe'];
           console.log(`Wireframe mode: ${modes[wireframeMode]}`);
           break;
-        case 'g':
-        case 'G':
+        case 'larger_points':
+          scalePoints(1.25);
+          break;
+        case 'smaller_points':
+          scalePoints(0.8);
+          break;
+        case 'toggle_grid':
           // Toggle grid visibility
           gridHelper.visible = !gridHelper.visible;
           console.log(`Grid ${gridHelper.visible ? 'shown' : 'hidden'}`);
           break;
+        case 'save_snapshot': {
+          const name = prompt('Save view as snapshot:');
+          if (name) {
+            saveSnapshot(name)
+              .catch((error) => console.error('Snapshot failed:', error));
+          }
+          break;
+        }
       }
+      reportView();
     });
 
     // Track mouse down position to distinguish clicks from drags
@@ -494,6 +1129,7 @@ pub const HTML: &str = r#"<!DOCTYPE html>
         selectedObject = null;
         updateFileList();
       }
+      reportView();
     });
 
     // Helper function to get filename for a loaded object
@@ -541,6 +1177,7 @@ pub const HTML: &str = r#"<!DOCTYPE html>
 
     // Apply wireframe mode to a single object
     function applyWireframeToObject(object) {
+      const color = fileColor(getObjectFilename(object) || '');
       // Remove existing wireframe overlays if present
       if (wireframeOverlays.has(object)) {
         const overlays = wireframeOverlays.get(object);
@@ -552,17 +1189,33 @@ pub const HTML: &str = r#"<!DOCTYPE html>
         wireframeOverlays.delete(object);
       }
 
+      // The material set's colour, a glTF file's or scan's own
+      // colours, or the file's colour
+      const restoreColor = (child) => {
+        if (child.userData.overrideColor) {
+          child.material.color.copy(child.userData.overrideColor);
+        } else if (child.userData.originalColor) {
+          child.material.color.copy(child.userData.originalColor);
+        } else if (!hasOwnMaterials(getObjectFilename(object))) {
+          child.material.color.set(color);
+        }
+      };
       object.traverse((child) => {
-        if (child.isMesh) {
+        if (child.isPoints) {
+          // No faces to draw wireframes of; the palette or point size
+          // may have just loaded
+          restoreColor(child);
+          child.material.size = currentPointSize();
+        } else if (child.isMesh) {
           if (wireframeMode === 0) {
             // Solid only
             child.material.wireframe = false;
             // Restore original color
-            child.material.color.setHex(0xcccccc);
+            restoreColor(child);
           } else if (wireframeMode === 1) {
             // Solid + wireframe overlay
             child.material.wireframe = false;
-            child.material.color.setHex(0xcccccc);
+            restoreColor(child);
             // Create wireframe overlay
             const wireframeGeo = new THREE.EdgesGeometry(child.geometry);
             const wireframeMat = new THREE.LineBasicMaterial(
@@ -580,14 +1233,128 @@ pub const HTML: &str = r#"<!DOCTYPE html>
           } else if (wireframeMode === 2) {
             // Wireframe only - use light uniform color
             child.material.wireframe = true;
-            child.material.color.setHex(0xdddddd);
+            child.material.color?.setHex(0xdddddd);
             // Add slight glow for uniform appearance
-            child.material.emissive.setHex(0x333333);
+            child.material.emissive?.setHex(0x333333);
           }
         }
       });
     }
 
+    // Shading shared by every viewer, from the material set in use on
+    // the server; null for the files' own materials
+    let materialSet = null;
+    let checkerTexture = null;
+    let matcapTexture = null;
+
+    // A checkerboard, for spotting stretched or flipped UVs
+    function makeCheckerTexture() {
+      const canvas = document.createElement('canvas');
+      canvas.width = canvas.height = 256;
+      const context = canvas.getContext('2d');
+      for (let y = 0; y < 8; y++) {
+        for (let x = 0; x < 8; x++) {
+          context.fillStyle = (x + y) % 2 ? '#555555' : '#dddddd';
+          context.fillRect(x * 32, y * 32, 32, 32);
+        }
+      }
+      const texture = new THREE.CanvasTexture(canvas);
+      texture.wrapS = texture.wrapT = THREE.RepeatWrapping;
+      texture.repeat.set(4, 4);
+      texture.colorSpace = THREE.SRGBColorSpace;
+      return texture;
+    }
+
+    // A lit sphere drawn on a canvas, so MatCap needs no image file
+    function makeMatcapTexture() {
+      const canvas = document.createElement('canvas');
+      canvas.width = canvas.height = 256;
+      const context = canvas.getContext('2d');
+      const gradient =
+        context.createRadialGradient(96, 80, 8, 128, 128, 128);
+      gradient.addColorStop(0, '#ffffff');
+      gradient.addColorStop(0.4, '#b0b0b0');
+      gradient.addColorStop(1, '#202020');
+      context.fillStyle = gradient;
+      context.fillRect(0, 0, 256, 256);
+      const texture = new THREE.CanvasTexture(canvas);
+      texture.colorSpace = THREE.SRGBColorSpace;
+      return texture;
+    }
+
+    // The material a file's meshes get from the set, or null to keep
+    // their own
+    function setMaterial(filename) {
+      const color = materialSet.colors[filename];
+      const side = THREE.DoubleSide;
+      switch (materialSet.shading) {
+        case 'clay':
+          return new THREE.MeshStandardMaterial({
+            color: color || '#b5a89a', roughness: 0.9, metalness: 0, side
+          });
+        case 'matcap':
+          matcapTexture = matcapTexture || makeMatcapTexture();
+          return new THREE.MeshMatcapMaterial({
+            matcap: matcapTexture, color: color || '#ffffff', side
+          });
+        case 'uv_checker':
+          checkerTexture = checkerTexture || makeCheckerTexture();
+          return new THREE.MeshBasicMaterial({
+            map: checkerTexture, color: color || '#ffffff', side
+          });
+        default:
+          return color ? new THREE.MeshPhongMaterial({ color, side }) : null;
+      }
+    }
+
+    // Swap a file's meshes to the set's materials, or back to their own
+    function applyMaterialSetToObject(object) {
+      const filename = getObjectFilename(object) || '';
+      // The glow goes with the material, so it's moved over
+      const selected = object === selectedObject;
+      if (selected) {
+        unhighlightObject(object);
+      }
+      object.traverse((child) => {
+        if (!child.isMesh) return;
+        if (child.userData.ownMaterial) {
+          child.material.dispose();
+          child.material = child.userData.ownMaterial;
+          delete child.userData.ownMaterial;
+          delete child.userData.overrideColor;
+        }
+        const material = materialSet && setMaterial(filename);
+        if (material) {
+          child.userData.ownMaterial = child.material;
+          child.userData.overrideColor = material.color.clone();
+          child.material = material;
+        }
+      });
+      applyWireframeToObject(object);
+      if (selected) {
+        highlightObject(object);
+      }
+    }
+
+    function showMaterialSet(set) {
+      materialSet = set || null;
+      loadedMeshes.forEach(applyMaterialSetToObject);
+      console.log(`Material set: ${set ? set.name : 'own materials'}`);
+    }
+
+    async function loadMaterialSet() {
+      try {
+        const response = await fetch('/api/material-sets');
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        const { active, sets } = await response.json();
+        showMaterialSet(sets.find((set) => set.name === active));
+      } catch (error) {
+        console.warn('Could not load the material set:', error);
+      }
+    }
+
     // Apply wireframe mode to all loaded objects
     function applyWireframeModeToAll() {
       loadedMeshes.forEach((object) => {
@@ -600,14 +1367,15 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       if (!object) return;
 
       object.traverse((child) => {
-        if (child.isMesh) {
+        // Unlit glTF materials have no emissive to glow with
+        if (child.isMesh && child.material.emissive) {
           // Store original emissive for later restoration
           if (!child.userData.originalEmissive) {
             child.userData.originalEmissive = 
               child.material.emissive.clone();
           }
           // Set highlight glow (subtle blue)
-          child.material.emissive.setHex(0x224488);
+          child.material.emissive.set(access.palette.selection);
         }
       });
     }
@@ -632,17 +1400,24 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       // Collect all filenames (loaded and failed)
       const allFilenames = new Set([
         ...loadedMeshes.keys(),
-        ...failedFiles.keys()
+        ...failedFiles.keys(),
+        ...serverErrors.keys(),
+        ...busyFiles,
+        ...convertingFiles.keys()
       ]);
 
-      if (allFilenames.size === 0) {
+      if (allFilenames.size === 0 && ignoredFiles.size === 0) {
         fileListContent.innerHTML =
           '<div style="color: #888; font-style: italic;">'
           + 'No files loaded</div>';
         return;
       }
 
-      const filenames = Array.from(allFilenames).sort();
+      document.getElementById('file-list-header').textContent =
+        'Files (Tab to toggle)' + (gitBranch ? ` \u2014 ${gitBranch}` : '')
+        + (access.user ? ` \u2014 ${access.user.name} (${access.user.role})` : '');
+
+      const filenames = Array.from(allFilenames).sort(compareFiles);
       const selectedFilename = selectedObject ?
         getObjectFilename(selectedObject) : null;
 
@@ -655,6 +1430,10 @@ pub const HTML: &str = r#"<!DOCTYPE html>
         if (filename === selectedFilename) {
           item.classList.add('selected');
         }
+        const changed = recentChanges.get(filename);
+        if (changed !== undefined && Date.now() - changed < RECENT_MS) {
+          item.classList.add('recent');
+        }
 
         // Add visibility status or error status
         if (failedInfo) {
@@ -673,11 +1452,69 @@ pub const HTML: &str = r#"<!DOCTYPE html>
           icon.textContent = (object && object.visible) ? '●' : '○';
         }
 
-        const text = document.createTextNode(filename);
+        const text = document.createTextNode(displayName(filename));
+        if (displayName(filename) !== filename) {
+          item.title = filename;
+        }
 
         item.appendChild(icon);
         item.appendChild(text);
 
+        const status = gitStatus.get(filename);
+        if (status) {
+          const marker = document.createElement('span');
+          marker.className = `git-status ${status}`;
+          marker.textContent =
+            { modified: 'M', untracked: 'U', ignored: 'I' }[status];
+          marker.title = `git: ${status}`;
+          item.appendChild(marker);
+        }
+
+        if (busyFiles.has(filename)) {
+          const busy = document.createElement('span');
+          busy.className = 'busy';
+          busy.textContent = ' exporting\u2026';
+          busy.title = 'Still being written; it loads once finished';
+          item.appendChild(busy);
+        }
+
+        if (vertexColorFiles.has(filename)) {
+          const colors = document.createElement('span');
+          colors.className = 'vertex-colors';
+          colors.textContent = ' \u25d1';
+          colors.title = 'Has vertex colours';
+          item.appendChild(colors);
+        }
+
+        if (convertingFiles.has(filename)) {
+          const progress = convertingFiles.get(filename);
+          const converting = document.createElement('span');
+          converting.className = 'busy';
+          converting.textContent = progress === null ? ' converting\u2026'
+            : ` converting ${Math.round(progress * 100)}%\u2026`;
+          converting.title = 'Being converted for display; it loads once '
+            + 'finished';
+          item.appendChild(converting);
+        }
+
+        if (serverErrors.has(filename)) {
+          const error = document.createElement('span');
+          error.className = 'server-error';
+          error.textContent = ' \u2716';
+          error.title = serverErrors.get(filename);
+          item.appendChild(error);
+        }
+
+        if (scaleWarnings.has(filename)) {
+          const warning = document.createElement('span');
+          warning.className = 'scale-warning';
+          warning.textContent = ' ⚠';
+          warning.title = scaleWarnings.get(filename);
+          item.appendChild(warning);
+        }
+
+        addIgnoreToggle(item, filename, true);
+
         // Add click handler to select the object
         item.addEventListener('click', () => {
           if (object) {
@@ -689,38 +1526,448 @@ pub const HTML: &str = r#"<!DOCTYPE html>
             highlightObject(selectedObject);
             console.log(`Selected: ${filename}`);
             updateFileList();
+            reportView();
           }
         });
 
         fileListContent.appendChild(item);
       });
+
+      // Listed last, so they can be brought back
+      Array.from(ignoredFiles).sort(compareFiles).forEach((filename) => {
+        const item = document.createElement('div');
+        item.className = 'file-list-item ignored';
+        item.title = 'Ignored: hidden from every viewer';
+        item.appendChild(document.createTextNode(displayName(filename)));
+        addIgnoreToggle(item, filename, false);
+        fileListContent.appendChild(item);
+      });
+    }
+
+    // A button that ignores a file for every viewer, or un-ignores it
+    function addIgnoreToggle(item, filename, ignore) {
+      if (staticScene || !access.allowed.includes('mutate')) {
+        return;
+      }
+      const toggle = document.createElement('span');
+      toggle.className = 'ignore-toggle';
+      toggle.textContent = ignore ? '\u2298' : '\u21ba';
+      toggle.title = ignore ?
+        'Ignore: hide from every viewer without deleting it' :
+        'Stop ignoring';
+      toggle.addEventListener('click', async (event) => {
+        event.stopPropagation();
+        try {
+          const response = await fetch(
+            `/api/files/${encodeURIComponent(filename)}/ignore`, {
+              method: ignore ? 'POST' : 'DELETE',
+              headers: { 'X-CSRF-Token': access.csrf_token },
+            });
+          if (!response.ok) {
+            throw await apiError(response);
+          }
+        } catch (error) {
+          console.error(`Could not change whether ${filename} is ignored:`,
+            error);
+        }
+      });
+      item.appendChild(toggle);
     }
 
-    // Initial load of all files
-    loadAllFiles();
+    async function loadIgnored() {
+      try {
+        const response = await fetch('/api/ignored');
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        ignoredFiles.clear();
+        for (const filename of await response.json()) {
+          ignoredFiles.add(filename);
+        }
+        updateFileList();
+      } catch (error) {
+        console.warn('Could not load the ignored files:', error);
+      }
+    }
+
+    // Remove a file's object from the scene, if it is loaded
+    function removeFile(filename) {
+      busyFiles.delete(filename);
+      convertingFiles.delete(filename);
+      fileSizes.delete(filename);
+      scaleWarnings.delete(filename);
+      serverErrors.delete(filename);
+      failedFiles.delete(filename);
+      if (queuedLoads.delete(filename)) {
+        loadingFiles.delete(filename);
+      }
+      if (loadedMeshes.has(filename)) {
+        const object = loadedMeshes.get(filename);
+
+        // Clear selection and highlight if this object was selected
+        if (selectedObject === object) {
+          unhighlightObject(selectedObject);
+          selectedObject = null;
+        }
+
+        // Dispose of geometries and materials
+        object.traverse((child) => {
+          if (child.isMesh) {
+            if (child.geometry) child.geometry.dispose();
+            if (child.material) child.material.dispose();
+          }
+        });
+
+        scene.remove(object);
+        loadedMeshes.delete(filename);
+        placeGround();
+      }
+      updateFileList();
+    }
+
+    // Bring the scene in line with the snapshot the server sends on
+    // (re)connect: drop files that are gone, load new ones and reload
+    // ones whose contents changed while we weren't listening
+    async function applySnapshot(files, branch) {
+      await loadManifest();
+      gitBranch = branch || null;
+      gitStatus.clear();
+      for (const info of files) {
+        if (info.mtime) {
+          fileTimes.set(info.name, info.mtime);
+        }
+        if (info.bytes !== undefined) {
+          fileBytes.set(info.name, info.bytes);
+        }
+        if (info.bounds) {
+          fileExtents.set(info.name, info.bounds);
+        }
+        if (info.git && info.git !== 'clean') {
+          gitStatus.set(info.name, info.git);
+        }
+        // Shown before trying to load it
+        if (info.error) {
+          serverErrors.set(info.name, `parse_failed: ${info.error}`);
+        }
+      }
+      const names = new Set(files.map((info) => info.name));
+      for (const filename of [...loadedMeshes.keys(), ...failedFiles.keys()]) {
+        if (!names.has(filename)) {
+          fileHashes.delete(filename);
+          removeFile(filename);
+        }
+      }
+      console.log(`Snapshot: ${files.length} OBJ file(s)`);
+      busyFiles.clear();
+      vertexColorFiles.clear();
+      for (const info of files) {
+        if (info.vertex_colors) {
+          vertexColorFiles.add(info.name);
+        }
+        // Loaded by the modified event once it's written
+        if (info.busy) {
+          busyFiles.add(info.name);
+          continue;
+        }
+        const known = fileHashes.get(info.name);
+        if (known !== undefined && known !== info.hash) {
+          removeFile(info.name);
+        }
+        fileHashes.set(info.name, info.hash);
+        loadOBJ(info.name);
+      }
+    }
+
+    // WebSocket message schema this page was written against, and the
+    // optional features it understands
+    const PROTOCOL_VERSION = 1;
+    const CAPABILITIES = [
+      'snapshot', 'subscribe', 'compress:deflate-raw', 'scale_warning',
+      'manifest_changed', 'control', 'git_status', 'busy', 'error',
+      'change_details', 'lock', 'batch', 'converting', 'view_state',
+    ];
+
+    const STANDARD_VIEWS = {
+      front: new THREE.Vector3(0, 0, 1),
+      back: new THREE.Vector3(0, 0, -1),
+      right: new THREE.Vector3(1, 0, 0),
+      left: new THREE.Vector3(-1, 0, 0),
+      top: new THREE.Vector3(0, 1, 0),
+      bottom: new THREE.Vector3(0, -1, 0),
+    };
+
+    // Render the current view and upload it as a PNG
+    async function uploadScreenshot(name) {
+      renderer.render(scene, camera);
+      // Read the canvas straight after rendering, before it's cleared
+      const dataUrl = renderer.domElement.toDataURL('image/png');
+      const blob = await (await fetch(dataUrl)).blob();
+      const query = name ? `?name=${encodeURIComponent(name)}` : '';
+      const response =
+        await fetch(`/api/screenshots${query}`, {
+          method: 'POST',
+          headers: {
+            'X-CSRF-Token': access.csrf_token,
+            // What was shown, for the gallery
+            'X-View-State': encodeURIComponent(JSON.stringify(viewState())),
+          },
+          body: blob,
+        });
+      if (!response.ok) {
+        throw await apiError(response);
+      }
+      console.log('Screenshot saved:', (await response.json()).file);
+    }
+
+    // What this viewer shows, in the server's snapshot format
+    function viewState() {
+      const selected = selectedObject ? getObjectFilename(selectedObject) : null;
+      return {
+        hidden: Array.from(loadedMeshes.entries())
+          .filter(([, object]) => !object.visible)
+          .map(([filename]) => filename),
+        selected,
+        camera: {
+          position: camera.position.toArray(),
+          target: controls.target.toArray(),
+        },
+        clipping_planes: renderer.clippingPlanes.map((plane) => ({
+          normal: plane.normal.toArray(),
+          constant: plane.constant,
+        })),
+      };
+    }
+
+    // Tell the server what this viewer shows after its user changes it,
+    // so recorded sessions have it. Reports are coalesced, and only sent
+    // when something changed.
+    let reportTimer = null;
+    let lastReport = null;
+    function reportView() {
+      if (staticScene || !access.allowed.includes('control')) {
+        return;
+      }
+      clearTimeout(reportTimer);
+      reportTimer = setTimeout(() => {
+        const report = JSON.stringify({ type: 'view_state', ...viewState() });
+        if (report !== lastReport && socket
+            && socket.readyState === WebSocket.OPEN) {
+          socket.send(report);
+          lastReport = report;
+        }
+      }, 250);
+    }
+
+    async function saveSnapshot(name) {
+      const response = await fetch('/api/snapshots', {
+        method: 'POST',
+        headers: {
+          'Content-Type': 'application/json',
+          'X-CSRF-Token': access.csrf_token,
+        },
+        body: JSON.stringify({ name, view: viewState() }),
+      });
+      if (!response.ok) {
+        throw await apiError(response);
+      }
+      console.log('Snapshot saved:', name);
+    }
+
+    function restoreView(view) {
+      const hidden = new Set(view.hidden);
+      loadedMeshes.forEach((object, filename) => {
+        object.visible = !hidden.has(filename);
+      });
+      if (selectedObject) unhighlightObject(selectedObject);
+      selectedObject = view.selected ?
+        loadedMeshes.get(view.selected) || null : null;
+      highlightObject(selectedObject);
+      if (view.camera) {
+        camera.position.set(...view.camera.position);
+        controls.target.set(...view.camera.target);
+        controls.update();
+      }
+      renderer.clippingPlanes = view.clipping_planes.map((plane) =>
+        new THREE.Plane(new THREE.Vector3(...plane.normal), plane.constant));
+      updateFileList();
+    }
+
+    // Carry out a command sent through the server's control API
+    function runControlCommand(msg) {
+      const objectsFor = (files) => files
+        .map((filename) => loadedMeshes.get(filename))
+        .filter((object) => object);
+      const currentDirection = () =>
+        camera.position.clone().sub(controls.target).normalize();
+
+      switch (msg.command) {
+        case 'select': {
+          if (selectedObject) unhighlightObject(selectedObject);
+          selectedObject = msg.file ? loadedMeshes.get(msg.file) || null : null;
+          highlightObject(selectedObject);
+          updateFileList();
+          break;
+        }
+        case 'frame': {
+          const objects = msg.files.length > 0 ? objectsFor(msg.files) :
+            Array.from(loadedMeshes.values()).filter(obj => obj.visible);
+          frameObjects(objects, currentDirection());
+          break;
+        }
+        case 'hide':
+          objectsFor(msg.files).forEach((object) => {
+            object.visible = !msg.hidden;
+          });
+          updateFileList();
+          break;
+        case 'set_view':
+          if (msg.view) {
+            setStandardView(STANDARD_VIEWS[msg.view], `${msg.view} view`);
+          } else {
+            if (msg.position) camera.position.set(...msg.position);
+            if (msg.target) controls.target.set(...msg.target);
+            controls.update();
+          }
+          break;
+        case 'screenshot':
+          uploadScreenshot(msg.name)
+            .catch((error) => console.error('Screenshot failed:', error));
+          break;
+        case 'restore':
+          restoreView(msg);
+          break;
+        case 'reload_all':
+          reloadAllFiles();
+          break;
+        case 'material_set':
+          showMaterialSet(msg.set);
+          break;
+      }
+      const moved = ['frame', 'set_view'].includes(msg.command) ||
+        (msg.command === 'restore' && msg.camera);
+      if (moved) {
+        standInXr();
+      }
+    }
+
+    // Parse a WebSocket message; binary frames are deflate-compressed JSON
+    async function decodeMessage(data) {
+      if (typeof data === 'string') {
+        return JSON.parse(data);
+      }
+      const stream = new Blob([data]).stream()
+        .pipeThrough(new DecompressionStream('deflate-raw'));
+      return JSON.parse(await new Response(stream).text());
+    }
 
     // WebSocket connection for live updates
+    // Whether the server has sent the scene, or failing that, whether
+    // it has been loaded from the service worker's cache
+    let snapshotLoaded = false;
+    let offlineLoaded = false;
+    // The current connection, for view reports
+    let socket = null;
+
     function connectWebSocket() {
       const protocol =
         window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-      const ws =
-        new WebSocket(`${protocol}//${window.location.host}/ws`);
+      // The filter applies to both the initial snapshot and the events.
+      // Large messages (snapshots of big scenes) arrive compressed when
+      // the browser can decompress them.
+      const query = new URLSearchParams(fileFilter);
+      if (typeof DecompressionStream !== 'undefined') {
+        query.set('compress', 'deflate-raw');
+      }
+      if (xrMode) {
+        query.set('xr', 'true');
+      }
+      query.set('client', clientId);
+      const ws = new WebSocket(
+        `${protocol}//${window.location.host}/ws?${query}`);
+      ws.binaryType = 'arraybuffer';
+      socket = ws;
+
+      // Decoding binary frames is async; chain it so messages are still
+      // handled in the order they arrived
+      let received = Promise.resolve();
+      // Events up to this ID are already applied (or were in the
+      // snapshot), so a repeat of one is dropped
+      let lastEventId = 0;
 
       ws.onopen = () => {
         console.log('WebSocket connected - live file updates enabled');
       };
 
       ws.onmessage = (event) => {
-        const msg = JSON.parse(event.data);
+        received = received
+          .then(() => decodeMessage(event.data))
+          .then(handleMessage)
+          .catch((error) => console.error('Bad WebSocket message:', error));
+      };
+
+      function handleMessage(msg) {
         console.log('File change event:', msg);
+        if (msg.id !== undefined) {
+          if (msg.id <= lastEventId) {
+            console.log(`Skipping event ${msg.id}, already applied`);
+            return;
+          }
+          lastEventId = msg.id;
+        }
 
         switch(msg.type) {
+          case 'hello':
+            if (msg.protocol_version !== PROTOCOL_VERSION) {
+              console.warn(`Server speaks protocol ${msg.protocol_version}, ` +
+                `this page ${PROTOCOL_VERSION} - reload if things misbehave`);
+            }
+            ws.send(JSON.stringify({
+              type: 'hello',
+              protocol_version: PROTOCOL_VERSION,
+              capabilities: CAPABILITIES,
+            }));
+            break;
+          case 'snapshot':
+            snapshotLoaded = true;
+            lastEventId = msg.seq || 0;
+            applySnapshot(msg.files, msg.branch);
+            // Lock and material set changes while disconnected weren't
+            // heard
+            loadLock();
+            loadMaterialSet();
+            loadIgnored();
+            break;
+          case 'busy':
+            console.log(`${msg.filename} is still being written`);
+            busyFiles.add(msg.filename);
+            updateFileList();
+            break;
           case 'added':
             console.log(`Auto-loading new file: ${msg.filename}`);
+            noteChange(msg);
+            if (msg.hash) {
+              fileHashes.set(msg.filename, msg.hash);
+            }
+            busyFiles.delete(msg.filename);
+            serverErrors.delete(msg.filename);
             // loadOBJ handles duplicate checking internally
             loadOBJ(msg.filename);
             break;
           case 'modified':
+            noteChange(msg);
+            busyFiles.delete(msg.filename);
+            scaleWarnings.delete(msg.filename);
+            serverErrors.delete(msg.filename);
+            // Touched or rewritten with the same contents
+            if (msg.hash && msg.hash === fileHashes.get(msg.filename)
+                && loadedMeshes.has(msg.filename)) {
+              console.log(`${msg.filename} is unchanged, not reloading`);
+              updateFileList();
+              break;
+            }
+            if (msg.hash) {
+              fileHashes.set(msg.filename, msg.hash);
+            }
             console.log(`Auto-reloading modified file: ${msg.filename}`);
             // Remove old version if it exists
             if (loadedMeshes.has(msg.filename)) {
@@ -738,42 +1985,266 @@ pub const HTML: &str = r#"<!DOCTYPE html>
             break;
           case 'removed':
             console.log(`Removing deleted file: ${msg.filename}`);
-            if (loadedMeshes.has(msg.filename)) {
-              const object = loadedMeshes.get(msg.filename);
-
-              // Clear selection and highlight if this object was selected
-              if (selectedObject === object) {
-                unhighlightObject(selectedObject);
-                selectedObject = null;
-              }
-
-              // Dispose of geometries and materials
-              object.traverse((child) => {
-                if (child.isMesh) {
-                  if (child.geometry) child.geometry.dispose();
-                  if (child.material) child.material.dispose();
-                }
-              });
-
-              scene.remove(object);
-              loadedMeshes.delete(msg.filename);
+            fileHashes.delete(msg.filename);
+            removeFile(msg.filename);
+            break;
+          case 'ignore_changed':
+            if (msg.ignored) {
+              console.log(`Ignoring ${msg.filename}`);
+              ignoredFiles.add(msg.filename);
+              removeFile(msg.filename);
+            } else {
+              console.log(`No longer ignoring ${msg.filename}`);
+              ignoredFiles.delete(msg.filename);
+              loadOBJ(msg.filename);
+            }
+            break;
+          case 'converting':
+            convertingFiles.set(msg.filename, msg.progress ?? null);
+            updateFileList();
+            break;
+          case 'converted':
+            console.log(`${msg.filename} converted in ${msg.duration_ms} ms`);
+            convertingFiles.delete(msg.filename);
+            serverErrors.delete(msg.filename);
+            // A load that gave up waiting for the conversion is retried
+            if (failedFiles.has(msg.filename)) {
+              loadOBJ(msg.filename);
+            }
+            updateFileList();
+            break;
+          case 'scale_warning': {
+            const scales = msg.suggested_scales.length > 0 ?
+              ` - try scaling by ${msg.suggested_scales.join(' or ')}` : '';
+            const warning =
+              `${msg.filename} is ${msg.size.toPrecision(3)} units across${scales}`;
+            console.warn(`Scale warning: ${warning}`);
+            scaleWarnings.set(msg.filename, warning);
+            updateFileList();
+            break;
+          }
+          case 'error': {
+            const where = msg.filename ? `${msg.filename}: ` : '';
+            console.error(`Server error (${msg.code}): ${where}${msg.message}`);
+            if (msg.filename) {
+              convertingFiles.delete(msg.filename);
+              serverErrors.set(msg.filename, `${msg.code}: ${msg.message}`);
               updateFileList();
             }
             break;
+          }
+          case 'manifest_changed':
+            console.log('Scene manifest changed, re-placing objects');
+            loadManifest();
+            placeGround();
+            break;
+          case 'control':
+            runControlCommand(msg);
+            break;
+          case 'batch':
+            // Applied in order, under the batch's own ID
+            msg.events.forEach(handleMessage);
+            break;
+          case 'lock_changed':
+            showLock(msg.lock);
+            break;
+          case 'git_status':
+            gitBranch = msg.branch;
+            for (const [filename, status] of Object.entries(msg.files)) {
+              if (status === 'clean') {
+                gitStatus.delete(filename);
+              } else {
+                gitStatus.set(filename, status);
+              }
+            }
+            updateFileList();
+            break;
         }
-      };
+      }
 
       ws.onerror = (error) => {
         console.error('WebSocket error:', error);
       };
 
       ws.onclose = () => {
+        // Offline, the service worker still has the files from last time
+        if (!snapshotLoaded && !offlineLoaded) {
+          offlineLoaded = true;
+          console.log('Server unreachable - showing the cached scene');
+          loadAllFiles();
+        }
         console.log('WebSocket disconnected - reconnecting in 2s...');
         setTimeout(connectWebSocket, 2000);
       };
     }
 
-    connectWebSocket();
+    // Who holds the scene lock, if anyone. Changes from anyone else are
+    // refused until it's released or runs out.
+    let lockTimer = null;
+
+    function showLock(lock) {
+      const banner = document.getElementById('lock-banner');
+      clearTimeout(lockTimer);
+      if (!lock || lock.expires <= Date.now()) {
+        banner.classList.add('hidden');
+        return;
+      }
+      const mine = access.user && access.user.name === lock.holder;
+      const until = new Date(lock.expires).toLocaleTimeString();
+      banner.textContent = `Locked by ${lock.holder}` +
+        `${mine ? ' (you)' : ''} until ${until}` +
+        (lock.note ? `: ${lock.note}` : '');
+      banner.title = `Since ${new Date(lock.since).toLocaleString()}`;
+      banner.classList.remove('hidden');
+      // Running out isn't announced
+      lockTimer = setTimeout(() => showLock(null), lock.expires - Date.now());
+    }
+
+    async function loadLock() {
+      try {
+        const response = await fetch('/api/lock');
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        showLock(await response.json());
+      } catch (error) {
+        console.warn('Could not load the scene lock:', error);
+      }
+    }
+
+    async function loadAccess() {
+      try {
+        const response = await fetch('/api/config');
+        if (response.ok) {
+          access = await response.json();
+          // Files may have loaded before the palette was known
+          applyWireframeModeToAll();
+        }
+      } catch (error) {
+        console.warn('Could not load server config:', error);
+      }
+      updateFileList();
+    }
+
+    // Panel layout, kept in the server-side prefs of this user or
+    // browser session so it survives reloads
+    let prefs = {};
+    let saveLayoutTimer = null;
+
+    async function loadPrefs() {
+      try {
+        const response = await fetch('/api/prefs');
+        if (!response.ok) {
+          throw await apiError(response);
+        }
+        prefs = await response.json();
+      } catch (error) {
+        console.warn('Could not load prefs:', error);
+      }
+      applyLayout(prefs.layout || {});
+    }
+
+    function applyLayout(layout) {
+      const overlay = document.getElementById('file-list-overlay');
+      const fileList = layout.file_list || {};
+      overlay.classList.toggle('hidden', fileList.visible === false);
+      if (fileList.width) overlay.style.width = `${fileList.width}px`;
+      if (fileList.left !== undefined && fileList.top !== undefined) {
+        placeOverlay(overlay, fileList.left, fileList.top);
+      }
+    }
+
+    // Keeps the header on screen, so the panel can't be lost
+    function placeOverlay(overlay, left, top) {
+      overlay.style.right = 'auto';
+      overlay.style.left =
+        `${Math.min(Math.max(left, 0), window.innerWidth - 40)}px`;
+      overlay.style.top =
+        `${Math.min(Math.max(top, 0), window.innerHeight - 40)}px`;
+    }
+
+    // Saved shortly after the last change, so drags and resizes don't
+    // send a request per frame
+    function saveLayout() {
+      if (staticScene) {
+        return;
+      }
+      clearTimeout(saveLayoutTimer);
+      saveLayoutTimer = setTimeout(async () => {
+        const overlay = document.getElementById('file-list-overlay');
+        const fileList = { visible: !overlay.classList.contains('hidden') };
+        if (overlay.style.width) fileList.width = overlay.offsetWidth;
+        if (overlay.style.left) {
+          fileList.left = overlay.offsetLeft;
+          fileList.top = overlay.offsetTop;
+        }
+        prefs.layout = { ...prefs.layout, file_list: fileList };
+        try {
+          const response = await fetch('/api/prefs', {
+            method: 'PUT',
+            headers: {
+              'Content-Type': 'application/json',
+              'X-CSRF-Token': access.csrf_token,
+            },
+            body: JSON.stringify(prefs),
+          });
+          if (!response.ok) {
+            throw await apiError(response);
+          }
+        } catch (error) {
+          console.warn('Could not save prefs:', error);
+        }
+      }, 500);
+    }
+
+    // Drag the file list by its header
+    document.getElementById('file-list-header')
+      .addEventListener('mousedown', (event) => {
+        const overlay = document.getElementById('file-list-overlay');
+        const offsetX = event.clientX - overlay.offsetLeft;
+        const offsetY = event.clientY - overlay.offsetTop;
+        const move = (moveEvent) => placeOverlay(overlay,
+          moveEvent.clientX - offsetX, moveEvent.clientY - offsetY);
+        const stop = () => {
+          window.removeEventListener('mousemove', move);
+          window.removeEventListener('mouseup', stop);
+          saveLayout();
+        };
+        window.addEventListener('mousemove', move);
+        window.addEventListener('mouseup', stop);
+        event.preventDefault();
+      });
+
+    // The CSS resize handle only changes the inline width. Height
+    // changes as files come and go, and hiding the list zeroes it;
+    // neither is worth saving.
+    let savedWidth = null;
+    const fileListOverlay = document.getElementById('file-list-overlay');
+    new ResizeObserver(() => {
+      const width = fileListOverlay.offsetWidth;
+      if (savedWidth !== null && width > 0 && width !== savedWidth) {
+        savedWidth = width;
+        saveLayout();
+      }
+    }).observe(fileListOverlay);
+
+    if (staticScene) {
+      // An exported copy: nothing to ask the server or listen for
+      access = { ...access, ...staticScene.config };
+      applyLayout({});
+      applySnapshot(staticScene.files, null);
+    } else {
+      loadAccess()
+        .then(loadPrefs)
+        .then(() => { savedWidth = fileListOverlay.offsetWidth; });
+      connectWebSocket();
+      // Keeps the page and scene files for opening offline
+      if ('serviceWorker' in navigator) {
+        navigator.serviceWorker.register('/sw.js').catch((error) => {
+          console.log('No offline support:', error.message);
+        });
+      }
+    }
 
     // Handle window resize
     window.addEventListener('resize', () => {
@@ -782,14 +2253,13 @@ pub const HTML: &str = r#"<!DOCTYPE html>
       renderer.setSize(window.innerWidth, window.innerHeight);
     });
 
-    // Animation loop
-    function animate() {
-      requestAnimationFrame(animate);
-      controls.update();
+    // Animation loop; a headset drives it while presenting
+    renderer.setAnimationLoop(() => {
+      if (!renderer.xr.isPresenting) {
+        controls.update();
+      }
       renderer.render(scene, camera);
-    }
-
-    animate();
+    });
 
     console.log('Kitbash Viewer initialized');
   </script>
@@ -0,0 +1,494 @@
+//! WebDAV access to the scene directory under `/dav`, so DCC tools and
+//! file managers can mount it. Saves made through a mount reach viewers
+//! through the watcher like any other edit.
+//!
+//! Hidden entries (such as the history store) aren't exposed, and
+//! symlinks follow the same rules as listings (see `links`): without
+//! `--follow-symlinks`, paths through them are refused. Files the
+//! mount overwrites or deletes are kept in history first. Locks are
+//! granted but not enforced; they only exist because some clients
+//! (Finder, Office) refuse to write without them.
+//!
+//! Only scene files and the files they load (see
+//! `formats::is_scene_asset`) can be written, since `/scene/` serves
+//! them on the viewer's origin; anything else, such as an HTML page,
+//! could script the viewer with the rights of whoever opened it.
+
+use axum::body::Bytes;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use kitbash_viewer::history::History;
+use kitbash_viewer::{formats, http, links, rewrite};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the mount lives in the server's URL space.
+pub const PREFIX: &str = "/dav";
+
+const ALLOW: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, \
+  MKCOL, COPY, MOVE, LOCK, UNLOCK";
+
+pub struct Dav<'a> {
+  pub root: &'a Path,
+  pub history: &'a History,
+  /// Why writes are refused, if they are
+  pub read_only: Option<&'a str>,
+  /// Whether symlinks in the scene directory are followed
+  pub follow_symlinks: bool,
+}
+
+impl Dav<'_> {
+  /// Answer one request. `path` is the request path below `PREFIX`,
+  /// still percent-encoded.
+  pub fn handle(
+      &self,
+      method: &Method,
+      path: &str,
+      headers: &HeaderMap,
+      body: &Bytes) -> Response {
+    let Some(relative) = resource_path(path)
+      .filter(|relative| !self.through_symlink(relative)) else {
+      return (StatusCode::FORBIDDEN, "hidden or invalid path")
+        .into_response();
+    };
+    let writes = !matches!(method.as_str(),
+      "OPTIONS" | "GET" | "HEAD" | "PROPFIND" | "LOCK" | "UNLOCK");
+    if let (true, Some(reason)) = (writes, self.read_only) {
+      return (StatusCode::FORBIDDEN, reason.to_string()).into_response();
+    }
+
+    let result = match method.as_str() {
+      "OPTIONS" => Ok(options()),
+      "GET" | "HEAD" => self.get(&relative, method == Method::HEAD),
+      "PROPFIND" => self.propfind(&relative, headers),
+      "PROPPATCH" => Ok(multistatus(
+        format!("<D:response><D:href>{}</D:href>{}</D:response>",
+          href(&relative, self.root.join(&relative).is_dir()),
+          "<D:propstat><D:prop/>\
+           <D:status>HTTP/1.1 200 OK</D:status></D:propstat>"))),
+      "PUT" => self.put(&relative, body),
+      "DELETE" => self.delete(&relative),
+      "MKCOL" => self.mkcol(&relative),
+      "COPY" | "MOVE" =>
+        self.transfer(&relative, headers, method.as_str() == "MOVE"),
+      "LOCK" => Ok(lock(&relative)),
+      "UNLOCK" => Ok(StatusCode::NO_CONTENT.into_response()),
+      _ => Ok((StatusCode::METHOD_NOT_ALLOWED,
+        [(header::ALLOW, ALLOW)]).into_response()),
+    };
+    result.unwrap_or_else(|e| match e.kind() {
+      io::ErrorKind::NotFound => StatusCode::NOT_FOUND.into_response(),
+      _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        .into_response(),
+    })
+  }
+
+  fn get(&self, relative: &str, head_only: bool) -> io::Result<Response> {
+    let path = self.root.join(relative);
+    if path.is_dir() {
+      return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+    let contents = fs::read(&path)?;
+    let mut response = if head_only {
+      let mut response = ().into_response();
+      response.headers_mut().insert(header::CONTENT_LENGTH,
+        HeaderValue::from(contents.len()));
+      response
+    } else {
+      contents.into_response()
+    };
+    response.headers_mut().insert(header::CONTENT_TYPE,
+      HeaderValue::from_static(content_type(relative)));
+    guard_content(response.headers_mut());
+    Ok(response)
+  }
+
+  fn propfind(&self, relative: &str, headers: &HeaderMap)
+      -> io::Result<Response> {
+    let path = self.root.join(relative);
+    let metadata = fs::metadata(&path)?;
+    let mut responses = prop_response(relative, &metadata);
+    // "infinity" is treated as 1; nobody needs the whole tree at once
+    let depth = headers.get("depth").and_then(|d| d.to_str().ok());
+    if metadata.is_dir() && depth != Some("0") {
+      let mut entries: Vec<(String, fs::Metadata)> = fs::read_dir(&path)?
+        .flatten()
+        .filter(|entry| links::is_file(entry, self.follow_symlinks)
+          || links::is_dir(entry, self.follow_symlinks))
+        .filter_map(|entry| {
+          let name = entry.file_name().to_str()?.to_string();
+          // The target's, for a followed link
+          let metadata = fs::metadata(entry.path()).ok()?;
+          (!name.starts_with('.')).then_some((name, metadata))
+        })
+        .collect();
+      entries.sort_by(|a, b| a.0.cmp(&b.0));
+      for (name, metadata) in entries {
+        responses += &prop_response(&join(relative, &name), &metadata);
+      }
+    }
+    Ok(multistatus(responses))
+  }
+
+  fn put(&self, relative: &str, body: &Bytes) -> io::Result<Response> {
+    let path = self.root.join(relative);
+    if relative.is_empty() || path.is_dir() {
+      return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+    if !formats::is_scene_asset(relative) {
+      return Ok(not_scene_asset(relative));
+    }
+    if !path.parent().is_some_and(Path::is_dir) {
+      return Ok(StatusCode::CONFLICT.into_response());
+    }
+    let existed = self.back_up(relative)?;
+    rewrite::write_atomic(&path, body)?;
+    println!("WebDAV: wrote {} ({} bytes)", relative, body.len());
+    Ok(if existed { StatusCode::NO_CONTENT } else { StatusCode::CREATED }
+      .into_response())
+  }
+
+  fn delete(&self, relative: &str) -> io::Result<Response> {
+    let path = self.root.join(relative);
+    if relative.is_empty() {
+      return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    if path.is_dir() {
+      self.back_up_tree(relative)?;
+      fs::remove_dir_all(&path)?;
+    } else {
+      if !self.back_up(relative)? {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+      }
+      fs::remove_file(&path)?;
+    }
+    println!("WebDAV: deleted {}", relative);
+    Ok(StatusCode::NO_CONTENT.into_response())
+  }
+
+  fn mkcol(&self, relative: &str) -> io::Result<Response> {
+    let path = self.root.join(relative);
+    if path.exists() {
+      return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+    if !path.parent().is_some_and(Path::is_dir) {
+      return Ok(StatusCode::CONFLICT.into_response());
+    }
+    fs::create_dir(&path)?;
+    Ok(StatusCode::CREATED.into_response())
+  }
+
+  fn transfer(&self, relative: &str, headers: &HeaderMap, moving: bool)
+      -> io::Result<Response> {
+    let destination = headers.get("destination")
+      .and_then(|d| d.to_str().ok())
+      .map(|d| http::split_url(d).map_or(d, |(_, path)| path))
+      .and_then(|d| d.strip_prefix(PREFIX))
+      .and_then(resource_path)
+      .filter(|destination| !self.through_symlink(destination));
+    let Some(destination) = destination else {
+      return Ok(StatusCode::BAD_REQUEST.into_response());
+    };
+    let from = self.root.join(relative);
+    let to = self.root.join(&destination);
+    if relative.is_empty() || destination.is_empty() || from == to {
+      return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+    if !from.exists() {
+      return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    if !from.is_dir() && !formats::is_scene_asset(&destination) {
+      return Ok(not_scene_asset(&destination));
+    }
+    if !to.parent().is_some_and(Path::is_dir) {
+      return Ok(StatusCode::CONFLICT.into_response());
+    }
+
+    let overwrite = headers.get("overwrite")
+      .is_none_or(|o| o.as_bytes() != b"F");
+    let existed = to.exists();
+    if existed {
+      if !overwrite {
+        return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+      }
+      if to.is_dir() {
+        self.back_up_tree(&destination)?;
+        fs::remove_dir_all(&to)?;
+      } else {
+        self.back_up(&destination)?;
+      }
+    }
+
+    if moving {
+      fs::rename(&from, &to)?;
+    } else {
+      copy_tree(&from, &to)?;
+    }
+    println!("WebDAV: {} {} to {}",
+      if moving { "moved" } else { "copied" }, relative, destination);
+    Ok(if existed { StatusCode::NO_CONTENT } else { StatusCode::CREATED }
+      .into_response())
+  }
+
+  // Whether any part of the path is a symlink that isn't followed, which
+  // could lead out of the scene directory
+  fn through_symlink(&self, relative: &str) -> bool {
+    !self.follow_symlinks && links::through_symlink(self.root, relative)
+  }
+
+  // Keep a file's current contents in history; false if there's no such
+  // file
+  fn back_up(&self, relative: &str) -> io::Result<bool> {
+    match fs::read(self.root.join(relative)) {
+      Ok(contents) => {
+        self.history.record(relative, &contents, "webdav")?;
+        Ok(true)
+      }
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn back_up_tree(&self, relative: &str) -> io::Result<()> {
+    for entry in fs::read_dir(self.root.join(relative))?.flatten() {
+      let Some(name) = entry.file_name().to_str().map(str::to_string)
+      else { continue };
+      let child = join(relative, &name);
+      if entry.file_type()?.is_dir() {
+        self.back_up_tree(&child)?;
+      } else {
+        self.back_up(&child)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The decoded path below the scene directory ("" for the root), or
+/// None if it would leave it or touch a hidden entry.
+pub fn resource_path(path: &str) -> Option<String> {
+  let decoded = http::decode_path(path);
+  let segments: Vec<&str> = decoded.split('/')
+    .filter(|s| !s.is_empty())
+    .collect();
+  let valid = segments.iter()
+    .all(|s| !s.starts_with('.') && !s.contains('\\'));
+  valid.then(|| segments.join("/"))
+}
+
+/// Keep a scene file's contents from running as a page on the viewer's
+/// origin: browsers neither guess a type other than the one sent nor
+/// let it run scripts or reach the origin's cookies.
+pub fn guard_content(headers: &mut HeaderMap) {
+  headers.insert(header::X_CONTENT_TYPE_OPTIONS,
+    HeaderValue::from_static("nosniff"));
+  headers.insert(header::CONTENT_SECURITY_POLICY,
+    HeaderValue::from_static("sandbox"));
+}
+
+fn not_scene_asset(relative: &str) -> Response {
+  (StatusCode::FORBIDDEN, format!("{} is not a scene file, material \
+    library or texture", relative)).into_response()
+}
+
+fn join(dir: &str, name: &str) -> String {
+  if dir.is_empty() { name.to_string() } else { format!("{}/{}", dir, name) }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+  if !from.is_dir() {
+    return rewrite::write_atomic(to, &fs::read(from)?);
+  }
+  fs::create_dir(to)?;
+  for entry in fs::read_dir(from)?.flatten() {
+    let name = entry.file_name();
+    if !name.to_string_lossy().starts_with('.') {
+      copy_tree(&entry.path(), &to.join(name))?;
+    }
+  }
+  Ok(())
+}
+
+fn options() -> Response {
+  ([
+    (header::ALLOW, ALLOW),
+    (header::HeaderName::from_static("dav"), "1, 2"),
+    (header::HeaderName::from_static("ms-author-via"), "DAV"),
+  ]).into_response()
+}
+
+fn href(relative: &str, is_dir: bool) -> String {
+  let mut href = PREFIX.to_string();
+  for segment in relative.split('/').filter(|s| !s.is_empty()) {
+    href.push('/');
+    href.push_str(&http::encode_path(segment));
+  }
+  if is_dir {
+    href.push('/');
+  }
+  href
+}
+
+fn prop_response(relative: &str, metadata: &fs::Metadata) -> String {
+  let name = relative.rsplit('/').next().unwrap_or("");
+  let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+  let mut props = format!(
+    "<D:displayname>{}</D:displayname>\
+     <D:getlastmodified>{}</D:getlastmodified>",
+    escape(name), http_date(modified));
+  if metadata.is_dir() {
+    props += "<D:resourcetype><D:collection/></D:resourcetype>";
+  } else {
+    let seconds = modified.duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(0);
+    props += &format!(
+      "<D:resourcetype/>\
+       <D:getcontentlength>{}</D:getcontentlength>\
+       <D:getcontenttype>{}</D:getcontenttype>\
+       <D:getetag>\"{:x}-{:x}\"</D:getetag>",
+      metadata.len(), content_type(name), metadata.len(), seconds);
+  }
+  format!(
+    "<D:response><D:href>{}</D:href>\
+     <D:propstat><D:prop>{}</D:prop>\
+     <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+    href(relative, metadata.is_dir()), props)
+}
+
+fn multistatus(responses: String) -> Response {
+  let body = format!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+     <D:multistatus xmlns:D=\"DAV:\">{}</D:multistatus>", responses);
+  (StatusCode::MULTI_STATUS,
+    [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+    body).into_response()
+}
+
+// A lock nobody else has to respect
+fn lock(relative: &str) -> Response {
+  let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  let token = format!("opaquelocktoken:kitbash-{:x}", nanos);
+  let body = format!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+     <D:prop xmlns:D=\"DAV:\"><D:lockdiscovery><D:activelock>\
+     <D:locktype><D:write/></D:locktype>\
+     <D:lockscope><D:exclusive/></D:lockscope>\
+     <D:depth>0</D:depth><D:timeout>Second-3600</D:timeout>\
+     <D:locktoken><D:href>{}</D:href></D:locktoken>\
+     <D:lockroot><D:href>{}</D:href></D:lockroot>\
+     </D:activelock></D:lockdiscovery></D:prop>",
+    token, href(relative, false));
+  (StatusCode::OK,
+    [
+      (header::CONTENT_TYPE, "application/xml; charset=utf-8".to_string()),
+      (header::HeaderName::from_static("lock-token"), format!("<{}>", token)),
+    ],
+    body).into_response()
+}
+
+fn content_type(name: &str) -> &'static str {
+  match name.rsplit('.').next() {
+    Some("json") => "application/json",
+    Some("png") => "image/png",
+    Some("obj" | "mtl" | "txt") => "text/plain",
+    Some("stl") => "model/stl",
+    Some("glb") => "model/gltf-binary",
+    Some("gltf") => "model/gltf+json",
+    Some("ply") => "application/ply",
+    Some("fbx") => "application/vnd.autodesk.fbx",
+    Some("3mf") => "model/3mf",
+    Some("dae") => "model/vnd.collada+xml",
+    Some("usdz") => "model/vnd.usdz+zip",
+    Some("dxf") => "image/vnd.dxf",
+    Some("xyz") => "text/plain",
+    Some("pcd") => "application/octet-stream",
+    Some("las") => "application/vnd.las",
+    Some("laz") => "application/vnd.laszip",
+    Some("step" | "stp") => "model/step",
+    Some("iges" | "igs") => "model/iges",
+    _ => "application/octet-stream",
+  }
+}
+
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// RFC 1123 date, as WebDAV's getlastmodified wants
+fn http_date(time: SystemTime) -> String {
+  const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+  const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+  let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+  let days = (secs / 86_400) as i64;
+  let (year, month, day) = civil_from_days(days);
+  format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+    DAYS[(days % 7) as usize], day, MONTHS[month as usize - 1], year,
+    secs % 86_400 / 3600, secs % 3600 / 60, secs % 60)
+}
+
+// Days since 1970-01-01 to (year, month, day), after Howard Hinnant's
+// civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+/// The mount's path for a request path, None if it isn't under
+/// `PREFIX`.
+pub fn strip_prefix(path: &str) -> Option<&str> {
+  let rest = path.strip_prefix(PREFIX)?;
+  (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn writes_only_scene_assets() {
+    let root = std::env::temp_dir()
+      .join(format!("kitbash-dav-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let history = History::new(root.join(".history"));
+    let dav = Dav { root: &root, history: &history, read_only: None,
+      follow_symlinks: false };
+    let request = |method: &str, path: &str, destination: Option<&str>| {
+      let mut headers = HeaderMap::new();
+      if let Some(destination) = destination {
+        headers.insert("destination", HeaderValue::from_str(destination)
+          .unwrap());
+      }
+      dav.handle(&Method::from_bytes(method.as_bytes()).unwrap(), path,
+        &headers, &Bytes::from_static(b"v 0 0 0\n"))
+    };
+
+    assert_eq!(request("PUT", "/part.obj", None).status(),
+      StatusCode::CREATED);
+    assert_eq!(request("PUT", "/paint.PNG", None).status(),
+      StatusCode::CREATED);
+    assert_eq!(request("PUT", "/evil.html", None).status(),
+      StatusCode::FORBIDDEN);
+    assert_eq!(request("MOVE", "/part.obj", Some("/dav/evil.html"))
+      .status(), StatusCode::FORBIDDEN);
+    assert!(!root.join("evil.html").exists());
+
+    let response = request("GET", "/part.obj", None);
+    assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(response.headers()[header::CONTENT_SECURITY_POLICY], "sandbox");
+    fs::remove_dir_all(root).unwrap();
+  }
+}
@@ -0,0 +1,132 @@
+//! A minimal XML reader for the XML-based formats (3MF models,
+//! COLLADA): start and end tags with their attributes, and the text
+//! between them. There's no DTD handling, and only the predefined
+//! entities are unescaped, on request.
+
+/// An XML start or end tag. Names lose their namespace prefix; values
+/// are as written, entities and all.
+pub struct Tag<'a> {
+  pub name: &'a str,
+  pub attributes: Vec<(&'a str, &'a str)>,
+  pub closing: bool,
+  /// `<.../>`, with no end tag to follow
+  pub empty: bool,
+}
+
+impl<'a> Tag<'a> {
+  pub fn get(&self, name: &str) -> Option<&'a str> {
+    self.attributes.iter().find(|(key, _)| *key == name)
+      .map(|(_, value)| *value)
+  }
+
+  pub fn require(&self, name: &str) -> Result<&'a str, String> {
+    self.get(name)
+      .ok_or_else(|| format!("<{}> has no {}", self.name, name))
+  }
+
+  pub fn parse<T: std::str::FromStr>(&self, name: &str) -> Result<T, String> {
+    let value = self.require(name)?;
+    value.trim().parse()
+      .map_err(|_| format!("<{}> has invalid {} '{}'", self.name, name, value))
+  }
+}
+
+/// The tags of an XML document in order, skipping text, comments and
+/// declarations.
+pub struct Tags<'a> {
+  xml: &'a str,
+  at: usize,
+}
+
+impl<'a> Tags<'a> {
+  pub fn new(xml: &'a str) -> Self {
+    Tags { xml, at: 0 }
+  }
+
+  /// The text after the last tag, up to the next one.
+  pub fn text(&self) -> &'a str {
+    let xml: &'a str = self.xml;
+    let rest = &xml[self.at..];
+    &rest[..rest.find('<').unwrap_or(rest.len())]
+  }
+
+  fn skip_past(&mut self, end: &str) -> Result<(), String> {
+    let found = self.xml[self.at..].find(end)
+      .ok_or_else(|| format!("XML ends before '{}'", end))?;
+    self.at += found + end.len();
+    Ok(())
+  }
+}
+
+impl<'a> Iterator for Tags<'a> {
+  type Item = Result<Tag<'a>, String>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      self.at += self.xml[self.at..].find('<')?;
+      let rest = &self.xml[self.at..];
+      let skipped = if rest.starts_with("<?") {
+        self.skip_past("?>")
+      } else if rest.starts_with("<!--") {
+        self.skip_past("-->")
+      } else if rest.starts_with("<![CDATA[") {
+        self.skip_past("]]>")
+      } else if rest.starts_with("<!") {
+        self.skip_past(">")
+      } else {
+        break;
+      };
+      if let Err(e) = skipped {
+        self.at = self.xml.len();
+        return Some(Err(e));
+      }
+    }
+    let xml: &'a str = self.xml;
+    let start = self.at + 1;
+    let Some(length) = xml[start..].find('>') else {
+      self.at = xml.len();
+      return Some(Err("XML ends inside a tag".to_string()));
+    };
+    self.at = start + length + 1;
+    Some(parse_tag(&xml[start..start + length]))
+  }
+}
+
+// The inside of `<...>`
+fn parse_tag(text: &str) -> Result<Tag<'_>, String> {
+  let (closing, text) = match text.strip_prefix('/') {
+    Some(text) => (true, text),
+    None => (false, text),
+  };
+  let (empty, mut text) = match text.strip_suffix('/') {
+    Some(text) => (true, text),
+    None => (false, text),
+  };
+  let name_end = text.find(|c: char| c.is_whitespace()).unwrap_or(text.len());
+  let name = local_name(&text[..name_end]);
+  text = text[name_end..].trim_start();
+  let mut attributes = Vec::new();
+  while !text.is_empty() {
+    let (key, rest) = text.split_once('=')
+      .ok_or_else(|| format!("<{}> has an attribute without a value", name))?;
+    let rest = rest.trim_start();
+    let quote = rest.chars().next()
+      .filter(|&c| c == '"' || c == '\'')
+      .ok_or_else(|| format!("<{}> has an unquoted attribute", name))?;
+    let (value, rest) = rest[1..].split_once(quote)
+      .ok_or_else(|| format!("<{}> has an unterminated attribute", name))?;
+    attributes.push((local_name(key.trim()), value));
+    text = rest.trim_start();
+  }
+  Ok(Tag { name, attributes, closing, empty })
+}
+
+fn local_name(name: &str) -> &str {
+  name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Replace the predefined entities with the characters they stand for.
+pub fn unescape(text: &str) -> String {
+  text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+    .replace("&apos;", "'").replace("&amp;", "&")
+}
@@ -0,0 +1,141 @@
+//! Reading zip archives, which 3MF and USDZ packages are. Entries may be
+//! stored or deflated; ZIP64 archives aren't read.
+
+use crate::deflate;
+use std::collections::HashMap;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+/// Whether the contents start like a zip archive.
+pub fn is_zip(bytes: &[u8]) -> bool {
+  bytes.starts_with(&LOCAL_HEADER.to_le_bytes())
+}
+
+/// The name of the archive's first file, from its local header.
+pub fn first_name(bytes: &[u8]) -> Option<String> {
+  if !is_zip(bytes) {
+    return None;
+  }
+  let length = u16_at(bytes, 26).ok()? as usize;
+  bytes.get(30..30 + length)
+    .map(|name| String::from_utf8_lossy(name).into_owned())
+}
+
+/// A file in the zip, as its central directory describes it.
+pub struct Entry {
+  method: u16,
+  compressed: usize,
+  size: usize,
+  offset: usize,
+}
+
+impl Entry {
+  /// The file's contents, inflated if need be.
+  pub fn read(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if u32_at(bytes, self.offset)? != LOCAL_HEADER {
+      return Err("zip entry has no local header".to_string());
+    }
+    let start = self.offset + 30 + u16_at(bytes, self.offset + 26)? as usize
+      + u16_at(bytes, self.offset + 28)? as usize;
+    let data = bytes.get(start..start + self.compressed)
+      .ok_or_else(|| "zip entry runs past the end".to_string())?;
+    // Inflating stops at the declared size, which itself can't be more
+    // than DEFLATE could make of the data
+    if self.size > self.compressed.saturating_mul(deflate::MAX_RATIO) + 8 {
+      return Err(format!("zip entry claims {} bytes from {}", self.size,
+        self.compressed));
+    }
+    let contents = match self.method {
+      0 => data.to_vec(),
+      8 => deflate::decompress(data, self.size)?,
+      method => return Err(format!(
+        "zip compression method {} isn't supported", method)),
+    };
+    if contents.len() != self.size {
+      return Err(format!("zip entry is {} bytes, expected {}",
+        contents.len(), self.size));
+    }
+    Ok(contents)
+  }
+}
+
+/// Every file in the zip by name, from its central directory.
+pub fn entries(bytes: &[u8]) -> Result<HashMap<String, Entry>, String> {
+  let last = bytes.len().checked_sub(22)
+    .ok_or_else(|| "zip file is truncated".to_string())?;
+  // The directory's end record is followed by a comment of up to 64 KB
+  let end = (last.saturating_sub(0xffff)..=last).rev()
+    .find(|&at| u32_at(bytes, at) == Ok(END_OF_DIRECTORY))
+    .ok_or_else(|| "zip file has no central directory".to_string())?;
+  let count = u16_at(bytes, end + 10)? as usize;
+  let mut at = u32_at(bytes, end + 16)? as usize;
+  if at == 0xffff_ffff {
+    return Err("ZIP64 files aren't supported".to_string());
+  }
+  let mut entries = HashMap::new();
+  for _ in 0..count {
+    if u32_at(bytes, at)? != CENTRAL_HEADER {
+      return Err("zip central directory is corrupt".to_string());
+    }
+    let name_length = u16_at(bytes, at + 28)? as usize;
+    let name = bytes.get(at + 46..at + 46 + name_length)
+      .ok_or_else(|| "zip central directory is truncated".to_string())?;
+    entries.insert(String::from_utf8_lossy(name).into_owned(), Entry {
+      method: u16_at(bytes, at + 10)?,
+      compressed: u32_at(bytes, at + 20)? as usize,
+      size: u32_at(bytes, at + 24)? as usize,
+      offset: u32_at(bytes, at + 42)? as usize,
+    });
+    at += 46 + name_length + u16_at(bytes, at + 30)? as usize
+      + u16_at(bytes, at + 32)? as usize;
+  }
+  Ok(entries)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, String> {
+  bytes.get(at..at + 2)
+    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    .ok_or_else(|| "zip file is truncated".to_string())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, String> {
+  bytes.get(at..at + 4)
+    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    .ok_or_else(|| "zip file is truncated".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing;
+
+  #[test]
+  fn reads_stored_and_deflated_entries() {
+    let model = b"<model>".repeat(50);
+    let archive = testing::zip(&[("a.txt", b"stored", false),
+      ("3D/model.xml", &model, true)]);
+    assert!(is_zip(&archive));
+    assert_eq!(first_name(&archive).as_deref(), Some("a.txt"));
+    let entries = entries(&archive).unwrap();
+    assert_eq!(entries["a.txt"].read(&archive).unwrap(), b"stored");
+    assert_eq!(entries["3D/model.xml"].read(&archive).unwrap(), model);
+  }
+
+  #[test]
+  fn rejects_malformed_archives() {
+    let archive = testing::zip(&[("a.txt", b"contents", true)]);
+    assert!(entries(&archive[..archive.len() - 30]).is_err());
+    assert!(entries(b"PK\x03\x04").is_err());
+
+    // An entry claiming far more than its data could inflate to
+    let mut bomb = archive.clone();
+    let directory = bomb.len() - 22 - 46 - 5;
+    bomb[directory + 24..directory + 28]
+      .copy_from_slice(&u32::MAX.to_le_bytes());
+    let entries = entries(&bomb).unwrap();
+    let error = entries["a.txt"].read(&bomb).err().unwrap();
+    assert!(error.contains("claims"), "{}", error);
+  }
+}